@@ -0,0 +1,101 @@
+//! Benchmarks for reading, writing and checksumming PNG files. Run them with
+//! `cargo bench`, and compare against a baseline with `cargo bench -- --save-baseline
+//! before` and `--baseline before`.
+
+use std::convert::TryFrom;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use pngme::generate::{self, Style};
+use pngme::png::{Chunk, ChunkType};
+use pngme::Png;
+
+/// A photo-sized image, so almost all of the file is one large IDAT chunk
+fn large_idat() -> Png {
+    // Noise doesn't compress, which keeps the IDAT chunk as large as the pixel data
+    generate::generate("1024x1024".parse().unwrap(), Style::Noise).unwrap()
+}
+
+/// A tiny image followed by many short chunks, like a file stuffed with metadata
+fn many_small_chunks() -> Png {
+    let mut png = generate::generate("8x8".parse().unwrap(), Style::Noise).unwrap();
+    for i in 0..10_000 {
+        png.insert_chunk(Chunk::from_strings("tEXt", &format!("Comment\0{}", i)).unwrap());
+    }
+    png
+}
+
+fn files() -> Vec<(&'static str, Png)> {
+    vec![
+        ("large_idat", large_idat()),
+        ("many_small_chunks", many_small_chunks()),
+    ]
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for (name, png) in files() {
+        let bytes = png.as_bytes();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("try_from", name), &bytes, |b, bytes| {
+            b.iter(|| Png::try_from(black_box(bytes.as_slice())).unwrap())
+        });
+        group.bench_with_input(
+            BenchmarkId::new("from_bytes_preserving", name),
+            &bytes,
+            |b, bytes| b.iter(|| Png::from_bytes_preserving(black_box(bytes)).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn serialize(c: &mut Criterion) {
+    let mut group = c.benchmark_group("serialize");
+    for (name, png) in files() {
+        group.throughput(Throughput::Bytes(png.as_bytes().len() as u64));
+        group.bench_with_input(BenchmarkId::new("as_bytes", name), &png, |b, png| {
+            b.iter(|| black_box(png).as_bytes())
+        });
+    }
+    group.finish();
+}
+
+fn round_trip(c: &mut Criterion) {
+    let mut group = c.benchmark_group("round_trip");
+    let path = std::env::temp_dir().join(format!("pngme-bench-{}.png", std::process::id()));
+    for (name, png) in files() {
+        let bytes = png.as_bytes();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::new("in_memory", name), &bytes, |b, bytes| {
+            b.iter(|| {
+                Png::try_from(black_box(bytes.as_slice()))
+                    .unwrap()
+                    .as_bytes()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("save", name), &bytes, |b, bytes| {
+            b.iter(|| {
+                let png = Png::try_from(black_box(bytes.as_slice())).unwrap();
+                png.save(&path).unwrap()
+            })
+        });
+    }
+    group.finish();
+    let _ = std::fs::remove_file(&path);
+}
+
+fn crc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("crc");
+    let chunk_type = ChunkType::new(*b"IDAT");
+    for length in [16, 64 * 1024, 4 * 1024 * 1024] {
+        let data = vec![0x5a; length];
+        group.throughput(Throughput::Bytes(length as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(length), &data, |b, data| {
+            b.iter(|| Chunk::calculate_crc(&chunk_type, black_box(data)))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parse, serialize, round_trip, crc);
+criterion_main!(benches);
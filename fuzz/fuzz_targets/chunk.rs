@@ -0,0 +1,14 @@
+#![no_main]
+
+use std::convert::TryFrom;
+
+use libfuzzer_sys::fuzz_target;
+use pngme::png::Chunk;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(chunk) = Chunk::try_from(data) {
+        // A chunk writes back out as exactly the bytes it was read from
+        let bytes = chunk.as_bytes();
+        assert_eq!(bytes, &data[..bytes.len()]);
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use std::str::FromStr;
+
+use libfuzzer_sys::fuzz_target;
+use pngme::png::ChunkType;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        if let Ok(chunk_type) = ChunkType::from_str(text) {
+            assert_eq!(chunk_type.to_string(), text);
+        }
+    }
+});
@@ -0,0 +1,19 @@
+#![no_main]
+
+use std::convert::TryFrom;
+
+use libfuzzer_sys::fuzz_target;
+use pngme::Png;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(png) = Png::try_from(data) {
+        // Anything that parses has to write out to bytes that parse the same way
+        let bytes = png.as_bytes();
+        let again = Png::try_from(bytes.as_slice()).expect("A written PNG failed to parse");
+        assert_eq!(again.as_bytes(), bytes);
+    }
+
+    if let Ok(png) = Png::from_bytes_preserving(data) {
+        let _ = png.as_bytes();
+    }
+});
@@ -0,0 +1,153 @@
+//! Writes seed inputs for the fuzz targets to `fuzz/corpus/<target>`. Random bytes almost
+//! never get past the PNG signature, so the seeds are real files with the kinds of damage
+//! an attacker would try: lengths that don't match the data, bad CRCs, chunks claiming
+//! gigabytes, truncation at every boundary, and huge numbers of tiny chunks.
+//!
+//! Run it with `cargo run --bin generate_corpus` from the `fuzz` directory.
+
+use std::fs;
+use std::path::Path;
+
+use pngme::generate::{self, Style};
+use pngme::png::{Chunk, ChunkType};
+use pngme::Png;
+
+fn main() -> anyhow::Result<()> {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("corpus");
+
+    let mut count = 0;
+    for (target, seeds) in [
+        ("png", png_seeds()?),
+        ("chunk", chunk_seeds()),
+        ("chunk_type", chunk_type_seeds()),
+    ] {
+        let directory = corpus.join(target);
+        fs::create_dir_all(&directory)?;
+        for (name, bytes) in seeds {
+            fs::write(directory.join(name), bytes)?;
+            count += 1;
+        }
+    }
+
+    println!("Wrote {} seeds to: {:?}", count, corpus);
+    Ok(())
+}
+
+fn png_seeds() -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let mut image = generate::generate("8x8".parse()?, Style::Gradient)?;
+    image.insert_chunk(Chunk::from_strings("tEXt", "Comment\0seed")?);
+    image.insert_chunk(Chunk::from_strings("ruSt", "This is a secret message!")?);
+    let valid = image.as_bytes();
+    // Where the chunk after IHDR starts: the signature, then IHDR's length, type, data and CRC
+    let second_chunk = 8 + 12 + 13;
+
+    let mut seeds = vec![
+        ("valid".to_string(), valid.clone()),
+        ("minimal".to_string(), Png::new_minimal().as_bytes()),
+        ("signature_only".to_string(), Png::SIGNATURE.to_vec()),
+        ("bad_signature".to_string(), with_byte(&valid, 1, b'p')),
+        ("bad_crc".to_string(), with_byte(&valid, valid.len() - 1, 0)),
+        (
+            "length_too_short".to_string(),
+            with_length(&valid, second_chunk, 1),
+        ),
+        (
+            "length_past_end".to_string(),
+            with_length(&valid, second_chunk, valid.len() as u32),
+        ),
+        (
+            "giant_chunk".to_string(),
+            with_length(&valid, second_chunk, 0x7fff_ffff),
+        ),
+        (
+            "length_over_limit".to_string(),
+            with_length(&valid, second_chunk, u32::MAX),
+        ),
+        (
+            "invalid_chunk_type".to_string(),
+            with_byte(&valid, second_chunk + 6, b'5'),
+        ),
+        (
+            "trailing_data".to_string(),
+            [&valid[..], b"garbage"].concat(),
+        ),
+        (
+            "chunk_after_iend".to_string(),
+            [
+                &valid[..],
+                &Chunk::from_strings("tEXt", "Comment\0late")?.as_bytes(),
+            ]
+            .concat(),
+        ),
+        (
+            "missing_iend".to_string(),
+            valid[..valid.len() - 12].to_vec(),
+        ),
+    ];
+
+    // Cut off at each chunk boundary and in the middle of each field
+    let mut offset = 8;
+    while offset < valid.len() {
+        let length = u32::from_be_bytes([
+            valid[offset],
+            valid[offset + 1],
+            valid[offset + 2],
+            valid[offset + 3],
+        ]) as usize;
+        for cut in [offset, offset + 2, offset + 6, offset + 8 + length / 2] {
+            if cut < valid.len() {
+                seeds.push((format!("truncated_{}", cut), valid[..cut].to_vec()));
+            }
+        }
+        offset += 12 + length;
+    }
+
+    let mut many = Png::new_minimal();
+    for _ in 0..10_000 {
+        many.insert_chunk(Chunk::new(ChunkType::new(*b"ruSt"), Vec::new()));
+    }
+    seeds.push(("many_small_chunks".to_string(), many.as_bytes()));
+
+    Ok(seeds)
+}
+
+fn chunk_seeds() -> Vec<(String, Vec<u8>)> {
+    let valid = Chunk::new(ChunkType::new(*b"ruSt"), b"Hello".to_vec()).as_bytes();
+    vec![
+        ("valid".to_string(), valid.clone()),
+        (
+            "empty".to_string(),
+            Chunk::new(ChunkType::new(*b"IEND"), Vec::new()).as_bytes(),
+        ),
+        ("bad_crc".to_string(), with_byte(&valid, valid.len() - 1, 0)),
+        ("length_too_short".to_string(), with_length(&valid, 0, 1)),
+        ("length_past_end".to_string(), with_length(&valid, 0, 100)),
+        ("giant_chunk".to_string(), with_length(&valid, 0, u32::MAX)),
+        ("invalid_chunk_type".to_string(), with_byte(&valid, 6, b'5')),
+        ("truncated".to_string(), valid[..valid.len() - 2].to_vec()),
+        ("header_only".to_string(), valid[..8].to_vec()),
+    ]
+}
+
+fn chunk_type_seeds() -> Vec<(String, Vec<u8>)> {
+    ["ruSt", "IHDR", "RUST", "ru5t", "rus", "ruStt", "rüSt", ""]
+        .iter()
+        .enumerate()
+        .map(|(index, text)| (format!("type_{}", index), text.as_bytes().to_vec()))
+        .collect()
+}
+
+/// Returns a copy of `bytes` with the byte at `index` replaced
+fn with_byte(bytes: &[u8], index: usize, value: u8) -> Vec<u8> {
+    let mut bytes = bytes.to_vec();
+    bytes[index] = value;
+    bytes
+}
+
+/// Returns a copy of `bytes` with the length of the chunk starting at `offset` replaced,
+/// leaving its data and CRC as they were
+fn with_length(bytes: &[u8], offset: usize, length: u32) -> Vec<u8> {
+    let mut bytes = bytes.to_vec();
+    bytes[offset..offset + 4].copy_from_slice(&length.to_be_bytes());
+    bytes
+}
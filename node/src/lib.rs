@@ -0,0 +1,104 @@
+//! Node.js bindings for pngme, so JavaScript can read and write messages in image
+//! buffers without running the command line tool. Images are passed in as `Buffer`s and
+//! edited copies are returned, so nothing here touches the file system.
+
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use napi::bindgen_prelude::Buffer;
+use napi_derive::napi;
+
+use pngme::chunk_type::check_message_chunk_type;
+use pngme::container::Container;
+use pngme::envelope;
+use pngme::png::ChunkType;
+
+/// A chunk of a PNG file, or a message stored in an image of another format
+#[napi(object)]
+pub struct ChunkInfo {
+    pub chunk_type: String,
+    /// Offset of the chunk from the start of the file. Only known for PNG files.
+    pub offset: Option<i64>,
+    /// The length of the chunk's data
+    pub length: i64,
+}
+
+/// Lists the chunks of a PNG file, or the messages in a JPEG, GIF or TIFF file
+#[napi]
+pub fn parse(image: Buffer) -> napi::Result<Vec<ChunkInfo>> {
+    let container = read(&image)?;
+    let chunks = match &container {
+        Container::Png(png) => png
+            .chunks()
+            .iter()
+            .map(|chunk| ChunkInfo {
+                chunk_type: chunk.chunk_type().to_string(),
+                offset: chunk.offset().map(|offset| offset as i64),
+                length: chunk.data().len() as i64,
+            })
+            .collect(),
+        container => {
+            let mut chunks = Vec::new();
+            for chunk_type in container.message_types().map_err(to_napi)? {
+                let name = chunk_type.to_string();
+                for message in container.messages(&name).map_err(to_napi)? {
+                    chunks.push(ChunkInfo {
+                        chunk_type: name.clone(),
+                        offset: None,
+                        length: message.len() as i64,
+                    });
+                }
+            }
+            chunks
+        }
+    };
+    Ok(chunks)
+}
+
+/// Returns a copy of `image` with `message` stored under `chunkType`. Critical and
+/// standard chunk types are refused unless `force` is true, since they break the image.
+#[napi]
+pub fn encode(
+    image: Buffer,
+    chunk_type: String,
+    message: Buffer,
+    force: Option<bool>,
+) -> napi::Result<Buffer> {
+    let mut container = read(&image)?;
+    let chunk_type = ChunkType::from_str(&chunk_type).map_err(to_napi)?;
+    if !force.unwrap_or(false) {
+        check_message_chunk_type(&chunk_type).map_err(to_napi)?;
+    }
+
+    container
+        .append_message(&chunk_type, envelope::wrap(&message))
+        .map_err(to_napi)?;
+    Ok(container.as_bytes().into())
+}
+
+/// Returns the first message stored under `chunkType`, or null if there is none
+#[napi]
+pub fn decode(image: Buffer, chunk_type: String) -> napi::Result<Option<Buffer>> {
+    let container = read(&image)?;
+    match container.message(&chunk_type).map_err(to_napi)? {
+        Some(message) => Ok(Some(envelope::unwrap(&message).map_err(to_napi)?.into())),
+        None => Ok(None),
+    }
+}
+
+/// Returns a copy of `image` without the first message stored under `chunkType`
+#[napi]
+pub fn remove(image: Buffer, chunk_type: String) -> napi::Result<Buffer> {
+    let mut container = read(&image)?;
+    container.remove_message(&chunk_type).map_err(to_napi)?;
+    Ok(container.as_bytes().into())
+}
+
+fn read(image: &[u8]) -> napi::Result<Container> {
+    Container::try_from(image).map_err(to_napi)
+}
+
+/// Turns an error into a JavaScript `Error` whose message includes its causes
+fn to_napi(error: anyhow::Error) -> napi::Error {
+    napi::Error::from_reason(format!("{:#}", error))
+}
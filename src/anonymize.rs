@@ -0,0 +1,120 @@
+use crate::png::{Chunk, ChunkType, Png};
+
+/// Standard chunk types that carry free-form or identifying metadata
+pub const METADATA_CHUNK_TYPES: [&str; 5] = ["tEXt", "zTXt", "iTXt", "eXIf", "tIME"];
+
+/// What `anonymize` removed from a `Png`
+#[derive(Debug, Default)]
+pub struct AnonymizeReport {
+    /// Chunks that were dropped entirely
+    pub removed: Vec<ChunkType>,
+    /// Chunks that were kept but had trailing bytes cut off, with the number of bytes removed
+    pub scrubbed: Vec<(ChunkType, usize)>,
+}
+
+impl AnonymizeReport {
+    /// Returns true if nothing needed to be removed
+    pub fn is_clean(&self) -> bool {
+        self.removed.is_empty() && self.scrubbed.is_empty()
+    }
+}
+
+/// Returns a copy of `png` without metadata chunks and private chunks, unless their type
+/// is in `keep`. Standard chunks with a fixed size have any bytes beyond that size removed,
+/// since readers ignore them and they are a convenient place to hide data.
+pub fn anonymize(png: &Png, keep: &[ChunkType]) -> (Png, AnonymizeReport) {
+    let mut report = AnonymizeReport::default();
+    let mut chunks = Vec::with_capacity(png.chunks().len());
+
+    for chunk in png.chunks() {
+        let chunk_type = chunk.chunk_type();
+        let name = chunk_type.to_string();
+
+        let is_metadata = METADATA_CHUNK_TYPES.contains(&name.as_str());
+        if (is_metadata || !chunk_type.is_public()) && !keep.contains(chunk_type) {
+            report.removed.push(*chunk_type);
+            continue;
+        }
+
+        match allowed_length(&name, chunk.data().len()) {
+            Some(length) if length < chunk.data().len() => {
+                let removed = chunk.data().len() - length;
+                report.scrubbed.push((*chunk_type, removed));
+                chunks.push(Chunk::new(*chunk_type, chunk.data()[..length].to_vec()));
+            }
+            _ => chunks.push(chunk.clone()),
+        }
+    }
+
+    (Png::from_chunks(chunks), report)
+}
+
+/// The number of meaningful bytes in a standard chunk, if its size is fixed by the spec
+pub fn allowed_length(chunk_type: &str, length: usize) -> Option<usize> {
+    match chunk_type {
+        "IHDR" => Some(13),
+        "PLTE" => Some((length - length % 3).min(256 * 3)),
+        "IEND" => Some(0),
+        "cHRM" => Some(32),
+        "gAMA" => Some(4),
+        "sRGB" => Some(1),
+        "pHYs" => Some(9),
+        "oFFs" => Some(9),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 13]),
+            Chunk::new(
+                ChunkType::from_str("gAMA").unwrap(),
+                b"\0\0\xb1\x8fhidden".to_vec(),
+            ),
+            Chunk::from_strings("tEXt", "Author\0Someone").unwrap(),
+            Chunk::from_strings("tIME", "1234567").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("ruSt", "This is a secret message!").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_anonymize() {
+        let (png, report) = anonymize(&testing_png(), &[]);
+
+        assert_eq!(chunk_types(&png), vec!["IHDR", "gAMA", "IDAT", "IEND"]);
+        assert_eq!(report.removed.len(), 3);
+        assert_eq!(report.scrubbed.len(), 1);
+        assert_eq!(report.scrubbed[0].1, 6);
+        assert_eq!(png.chunk_by_type("gAMA").unwrap().data(), b"\0\0\xb1\x8f");
+    }
+
+    #[test]
+    fn test_anonymize_keep() {
+        let keep = vec![ChunkType::from_str("tEXt").unwrap()];
+        let (png, _) = anonymize(&testing_png(), &keep);
+
+        assert!(png.chunk_by_type("tEXt").is_some());
+        assert!(png.chunk_by_type("ruSt").is_none());
+    }
+
+    #[test]
+    fn test_already_clean() {
+        let (png, _) = anonymize(&testing_png(), &[]);
+        let (_, report) = anonymize(&png, &[]);
+        assert!(report.is_clean());
+    }
+}
@@ -0,0 +1,119 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+
+const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// Packs the contents of `directory` into a tar archive, gzipped if `compress` is set.
+/// Paths in the archive are relative to `directory`.
+pub fn pack(directory: &Path, compress: bool) -> anyhow::Result<Vec<u8>> {
+    if !directory.is_dir() {
+        anyhow::bail!("{:?} is not a directory", directory);
+    }
+
+    let mut builder = tar::Builder::new(Vec::new());
+    builder.follow_symlinks(false);
+    builder.append_dir_all(".", directory)?;
+    let archive = builder.into_inner()?;
+
+    if !compress {
+        return Ok(archive);
+    }
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&archive)?;
+    Ok(encoder.finish()?)
+}
+
+/// Returns true if `data` looks like an archive made by `pack`
+pub fn is_archive(data: &[u8]) -> bool {
+    data.starts_with(&GZIP_MAGIC)
+        || data
+            .get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5)
+            .is_some_and(|magic| magic == b"ustar")
+}
+
+/// Extracts an archive made by `pack` into `destination`, creating it if needed.
+/// Entries that would end up outside `destination` are skipped. Returns the number of
+/// files extracted.
+pub fn unpack(data: &[u8], destination: &Path) -> anyhow::Result<usize> {
+    fs::create_dir_all(destination)?;
+
+    let reader: Box<dyn Read + '_> = if data.starts_with(&GZIP_MAGIC) {
+        Box::new(GzDecoder::new(data))
+    } else {
+        Box::new(data)
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    let mut files = 0;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let is_file = entry.header().entry_type().is_file();
+        let path = entry.path()?.into_owned();
+
+        if !entry.unpack_in(destination)? {
+            eprintln!("Skipping {:?}, which is outside the destination", path);
+        } else if is_file {
+            files += 1;
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn testing_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pngme-archive-{}-{}", std::process::id(), name))
+    }
+
+    fn round_trip(compress: bool) {
+        let source = testing_path(&format!("source-{}", compress));
+        let destination = testing_path(&format!("destination-{}", compress));
+        fs::create_dir_all(source.join("nested")).unwrap();
+        fs::write(source.join("note.txt"), b"top level").unwrap();
+        fs::write(source.join("nested").join("deep.txt"), b"nested").unwrap();
+
+        let data = pack(&source, compress).unwrap();
+        assert!(is_archive(&data));
+        assert_eq!(unpack(&data, &destination).unwrap(), 2);
+
+        assert_eq!(
+            fs::read(destination.join("note.txt")).unwrap(),
+            b"top level"
+        );
+        assert_eq!(
+            fs::read(destination.join("nested").join("deep.txt")).unwrap(),
+            b"nested"
+        );
+
+        fs::remove_dir_all(&source).unwrap();
+        fs::remove_dir_all(&destination).unwrap();
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip(false);
+    }
+
+    #[test]
+    fn test_compressed_round_trip() {
+        round_trip(true);
+    }
+
+    #[test]
+    fn test_not_an_archive() {
+        assert!(!is_archive(b"Hello, world"));
+        assert!(pack(Path::new("Cargo.toml"), false).is_err());
+    }
+}
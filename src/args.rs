@@ -1,36 +1,1185 @@
+use std::num::{NonZeroU64, NonZeroUsize};
 use std::path::PathBuf;
+use std::str::FromStr;
 
-use clap::Clap;
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
-#[derive(Clap, Debug)]
+use pngme::config::ByteSize;
+use pngme::error::ErrorFormat;
+use pngme::generate::{Size, Style};
+use pngme::output::OutputFormat;
+use pngme::png::ChunkType;
+
+/// The chunk argument that stands for a chunk type derived from a passphrase
+pub const SECRET_CHUNK_TYPE: &str = "secret";
+
+#[derive(Parser, Debug)]
+#[command(
+    version,
+    about = "Hides messages in PNG files, and in JPEG, GIF and TIFF files",
+    after_long_help = "Examples:
+  pngme encode dice.png \"This is a secret message!\"
+  pngme encode dice.png ruSt \"This is a secret message!\"
+  pngme decode dice.png ruSt
+  pngme remove dice.png ruSt
+  pngme print dice.png"
+)]
+pub struct Cli {
+    /// How to print errors: text, or json for one JSON object on stderr with the error's
+    /// code, message, file, offset and chunk type
+    #[arg(long, global = true, default_value = "text")]
+    pub error_format: ErrorFormat,
+    /// How to print results: text, or json, csv or yaml with a record for each result,
+    /// like a chunk or a problem found, and a message record for other lines of output
+    #[arg(long, global = true, default_value = "text")]
+    pub format: OutputFormat,
+    #[command(subcommand)]
+    pub command: PngMeArgs,
+}
+
+#[derive(Subcommand, Debug)]
 pub enum PngMeArgs {
+    /// Store a message in a chunk
+    #[command(
+        visible_alias = "e",
+        after_long_help = "Examples:
+  pngme encode dice.png ruSt \"This is a secret message!\"
+  pngme encode dice.png ruSt \"Secret\" out.png --encrypt
+  pngme encode dice.png secret \"Secret\"
+  pngme encode dice.png ruSt \"Secret\" --in-place --backup
+  pngme encode dice.png ruSt \"Secret\" --decoy \"Shopping list\"
+  pngme encode dice.png ruSt \"Not for casual eyes\" --obfuscate key
+  pngme encode dice.png ruSt --input-dir notes --compress
+  pngme encode dice.png ruSt \"Secret\" --mode trailer
+  pngme encode dice.png tIME --hex \"07E4 0C 1F 17 3B 3B\" --force
+  pngme encode dice.png ruSt \"Secret\" --emit-data-url > dice.url
+  pngme encode clipboard: ruSt \"Secret\"
+  pngme encode dice.png tEXt \"Secret\" --encrypt --camouflage --keyword Software"
+    )]
     Encode(EncodeArgs),
+    /// Print the message stored in a chunk
+    #[command(
+        visible_alias = "d",
+        after_long_help = "Examples:
+  pngme decode dice.png
+  pngme decode dice.png ruSt
+  pngme decode dice.png secret
+  pngme decode dice.png ruSt --head 64
+  pngme decode dice.png ruSt --extract-to notes
+  pngme decode dice.png ruSt --mode trailer
+  pngme decode clipboard: ruSt
+  pngme decode dice.png tEXt --camouflage --keyword Software"
+    )]
     Decode(DecodeArgs),
+    /// Remove the first chunk of a type
+    #[command(
+        visible_alias = "rm",
+        after_long_help = "Examples:
+  pngme remove dice.png ruSt
+  pngme remove dice.png ruSt clean.png
+  pngme remove dice.png ruSt --in-place --backup --history"
+    )]
     Remove(RemoveArgs),
+    /// Edit the message stored in a chunk with $EDITOR
+    #[command(after_long_help = "Examples:
+  pngme edit dice.png ruSt
+  EDITOR=nano pngme edit dice.png ruSt --backup")]
+    Edit(EditArgs),
+    /// Add text to the end of the message stored in a chunk
+    #[command(after_long_help = "Examples:
+  pngme append dice.png loGs \"$(date): backed up\"")]
+    Append(AppendArgs),
+    /// Frame messages written by older versions of pngme the way new ones are written
+    #[command(after_long_help = "Examples:
+  pngme migrate dice.png
+  pngme migrate dice.png --dry-run")]
+    Migrate(MigrateArgs),
+    /// List the chunks of a file
+    #[command(
+        visible_alias = "p",
+        after_long_help = "Examples:
+  pngme print dice.png
+  pngme print dice.png --color never
+  pngme print dice.png --explain"
+    )]
     Print(PrintArgs),
+    /// Describe what each chunk of a PNG file is for, with its decoded contents
+    #[command(after_long_help = "Examples:
+  pngme explain dice.png")]
+    Explain(ExplainArgs),
+    /// Check files for damage and spec violations
+    Check(CheckArgs),
+    /// Run named rules over a file and list what they find
+    #[command(after_long_help = "Examples:
+  pngme lint dice.png
+  pngme lint dice.png --disable missing-color-space
+  pngme lint dice.png --only duplicate-chunk --only text-keyword
+  pngme lint dice.png --severity private-chunk=error --severity oversized-text=off
+  pngme lint --list-rules")]
+    Lint(LintArgs),
+    /// Correct the problems lint finds that can be fixed automatically
+    #[command(after_long_help = "Examples:
+  pngme fix dice.png
+  pngme fix assets/ --rules crc,order,trailer
+  pngme fix assets/ --dry-run
+  pngme fix dice.png --rules trailer --backup")]
+    Fix(FixArgs),
+    /// Generate a key pair for encrypting or signing messages
+    Keygen(KeygenArgs),
+    /// Sign a file's chunks with an Ed25519 key
+    Sign(SignArgs),
+    /// Verify the signatures made with `sign`
+    Verify(VerifyArgs),
+    /// Record a digest of the critical chunks, to detect later changes to the image
+    Seal(SealArgs),
+    /// Check that the image hasn't changed since it was sealed
+    VerifySeal(VerifySealArgs),
+    /// Print a digest of the image content that ignores metadata and messages
+    Pixelhash(PixelhashArgs),
+    /// Reorder the chunks into the order recommended by the PNG spec
+    Canonicalize(CanonicalizeArgs),
+    /// Fix bad CRCs and structural problems
+    Repair(RepairArgs),
+    /// Remove duplicate chunks
+    Dedupe(DedupeArgs),
+    /// Recompress the image data to make the file smaller
+    Optimize(OptimizeArgs),
+    /// Show how many bytes don't contribute to the image
+    Report(ReportArgs),
+    /// Write each chunk to its own file in a directory
+    Explode(ExplodeArgs),
+    /// Rebuild a file from a directory written by `explode`
+    Implode(ImplodeArgs),
+    /// Apply the steps of a plan file to many files at once
+    Apply(ApplyArgs),
+    /// Generate an image to hide messages in
+    Generate(GenerateArgs),
+    /// Remove identifying metadata and private chunks
+    Anonymize(AnonymizeArgs),
+    /// Copy text, time and color space metadata from one file to another
+    CloneMeta(CloneMetaArgs),
+    /// Read and write text, XMP and EXIF metadata
+    Meta(MetaArgs),
+    /// Export or import an embedded ICC profile
+    Icc(IccArgs),
+    /// Revert the last edit made with --history
+    Undo(UndoArgs),
+    /// Show the audit trail of edits
+    History(HistoryArgs),
+    /// List the named messages stored in a chunk
+    ListMessages(ListMessagesArgs),
+    /// Search the chunk data of files
+    Grep(GrepArgs),
+    /// List the runs of readable text in the data of every chunk
+    #[command(after_long_help = "Examples:
+  pngme strings dice.png
+  pngme strings dice.png --min-len 10 --decompress")]
+    Strings(StringsArgs),
+    /// Look for places in files where data could be hidden
+    Scan(ScanArgs),
+    /// Count the chunks in many files, or compare them with --aggregate
+    #[command(after_long_help = "Examples:
+  pngme stats photos/
+  pngme stats photos/ --aggregate")]
+    Stats(StatsArgs),
+    /// Look for messages hidden in the low bits of the pixels
+    AnalyzeBitplanes(AnalyzeBitplanesArgs),
+    /// Combine an image and a ZIP archive into one file that opens as either
+    #[command(after_long_help = "Examples:
+  pngme polyglot dice.png notes.zip --out combined.png
+  unzip -l combined.png")]
+    Polyglot(PolyglotArgs),
+    /// Open a window for viewing and editing messages
+    #[cfg(feature = "gui")]
+    Gui(GuiArgs),
+}
+
+/// Where encode stores a message and decode looks for it
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// In a chunk of the given type
+    Chunk,
+    /// After IEND, labeled with the chunk type
+    Trailer,
 }
 
-#[derive(Clap, Debug)]
+#[derive(Args, Debug)]
 pub struct EncodeArgs {
+    /// The image to store the message in. May also be a `data:` URL, with --out or
+    /// --emit-data-url saying where the result goes, or `clipboard:` for the image on the
+    /// clipboard, which gets the result unless --out is given
     pub file: PathBuf,
-    pub chunk: String,
-    pub message: String,
+    /// The chunk type to store the message in, or `secret` to encrypt the message with a
+    /// passphrase and store it in a private chunk type derived from that passphrase, so
+    /// only someone who knows it can tell which chunk holds the message. Defaults to
+    /// `chunk_type` in the config file, or puNk, but can only be left out when nothing
+    /// but the message follows FILE. A message that is itself a valid chunk type needs the
+    /// chunk type given before it
+    pub chunk: Option<String>,
+    pub message: Option<String>,
+    /// Where to write the result. Defaults to a new file next to FILE, like
+    /// dice.pngme.png for dice.png
     pub out: Option<PathBuf>,
+    /// Write the result over FILE instead of to a new file
+    #[arg(long, conflicts_with_all = ["out", "emit_data_url"])]
+    pub in_place: bool,
+    /// Store this directory as a tar archive instead of a text message
+    #[arg(long)]
+    pub input_dir: Option<PathBuf>,
+    /// Gzip the archive made with --input-dir
+    #[arg(long)]
+    pub compress: bool,
+    /// Store these bytes, written in hex like `DEADBEEF`, instead of a text message
+    #[arg(long)]
+    pub hex: Option<String>,
+    /// Store these bytes, written in base64, instead of a text message
+    #[arg(long)]
+    pub base64: Option<String>,
+    /// Encrypt the message with a passphrase
+    #[arg(long)]
+    pub encrypt: bool,
+    /// Encrypt the message with a passphrase in a format with room for a second message,
+    /// which is filled with random bytes unless --decoy is given
+    #[arg(long)]
+    pub deniable: bool,
+    /// Also store this decoy message, which a second passphrase decrypts instead of the
+    /// real one. Implies --deniable
+    #[arg(long)]
+    pub decoy: Option<String>,
+    /// Encrypt the message with a 32 byte key read from this file instead of a passphrase
+    #[arg(long)]
+    pub key_file: Option<PathBuf>,
+    /// Scramble the message with this key so it isn't plainly readable. This is
+    /// obfuscation, not encryption: nothing detects tampering and weak keys are easily
+    /// guessed. Needs a pngme built with --features obfuscate
+    #[arg(long, value_name = "KEY")]
+    pub obfuscate: Option<String>,
+    /// Encrypt the message to an age recipient (`age1...` or an SSH public key). May be repeated
+    #[arg(long)]
+    pub age_recipient: Vec<String>,
+    /// Encrypt the message to the X25519 public key in this file. May be repeated
+    #[arg(long)]
+    pub recipient: Vec<PathBuf>,
+    /// Sign the message with the Ed25519 private key in this file
+    #[arg(long)]
+    pub sign_key: Option<PathBuf>,
+    /// Allow critical or standard chunk types, which will usually break the image
+    #[arg(long)]
+    pub force: bool,
+    /// Overwrite the output file if it already exists
+    #[arg(long)]
+    pub overwrite: bool,
+    /// Store the message as the text of an ordinary looking tEXt or iTXt chunk, given as
+    /// the chunk type, so that chunk listings show nothing unusual
+    #[arg(long)]
+    pub camouflage: bool,
+    /// The keyword of the camouflaged text chunk, like Software. Defaults to Comment
+    #[arg(long, requires = "camouflage")]
+    pub keyword: Option<String>,
+    /// Store the message under this name, alongside other named messages in the same chunk
+    #[arg(long)]
+    pub name: Option<String>,
+    /// Add the chunk without rewriting the rest of the file. Only works with --in-place
+    #[arg(long, requires = "in_place")]
+    pub fast_append: bool,
+    /// Convert a JPEG, BMP, TIFF, GIF or WebP image to PNG first, keeping its pixels. The
+    /// result is written next to it with a .png extension unless --out is given
+    #[arg(long, conflicts_with = "fast_append")]
+    pub convert: bool,
+    /// Print the result as a base64 `data:` URL instead of writing a file
+    #[arg(long, conflicts_with = "out")]
+    pub emit_data_url: bool,
+    /// Also put the result on the clipboard as a copied file, ready to paste into a chat
+    /// or a file manager
+    #[arg(long, conflicts_with = "emit_data_url")]
+    pub clipboard: bool,
+    /// Split the message into chunks of at most this many bytes, plus a manifest chunk
+    /// listing the parts
+    #[arg(long)]
+    pub split: Option<usize>,
+    /// Where to store the message: in a chunk, or after IEND, where viewers never look,
+    /// labeled with the chunk type
+    #[arg(long, value_enum, default_value_t = Mode::Chunk)]
+    pub mode: Mode,
+    /// Refuse to write the file if it would grow by more than this, like 4KiB. Defaults
+    /// to `max_growth` in the `[encode]` table of the config file
+    #[arg(long)]
+    pub max_growth: Option<ByteSize>,
+    /// Create the file as a 1x1 transparent PNG if it doesn't exist yet
+    #[arg(long)]
+    pub create: bool,
+    /// Decode the pixels before and after adding the message and refuse to write the
+    /// file unless they are identical. Only works with PNG files
+    #[arg(long)]
+    pub verify_pixels: bool,
+    /// Argon2id memory cost in KiB used when encrypting, at most 1048576 (1 GiB)
+    #[arg(long, default_value = "19456")]
+    pub kdf_memory: u32,
+    /// Argon2id iteration count used when encrypting, at most 64
+    #[arg(long, default_value = "2")]
+    pub kdf_iterations: u32,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+impl EncodeArgs {
+    /// Returns the chunk type to store the message in, which is `default` if none was
+    /// given. The arguments after FILE fill CHUNK, MESSAGE and OUT in order, so a lone
+    /// argument that isn't a chunk type is taken as the message. With two or more, the
+    /// first must be a chunk type, so that a typo in it can't turn the message into an
+    /// output path.
+    pub fn resolve_chunk(&mut self, default: &str) -> anyhow::Result<String> {
+        match self.chunk.take() {
+            Some(chunk) if chunk_type_or_secret(&chunk).is_ok() => Ok(chunk),
+            Some(message) if self.message.is_none() => {
+                self.message = Some(message);
+                Ok(default.to_string())
+            }
+            Some(chunk) => chunk_type_or_secret(&chunk)
+                .map_err(|error| anyhow::anyhow!("Invalid chunk type {:?}: {}", chunk, error)),
+            None => Ok(default.to_string()),
+        }
+    }
 }
 
-#[derive(Clap, Debug)]
+#[derive(Args, Debug)]
 pub struct DecodeArgs {
+    /// The image to read the message from, or `clipboard:` for the image on the clipboard
     pub file: PathBuf,
-    pub chunk: String,
+    /// The chunk type the message is in, or `secret` to find a message stored with
+    /// `encode ... secret` from its passphrase. Without it, every message that can be
+    /// found is listed with the chunk it is in: the data of chunk types the PNG spec
+    /// doesn't define, the text of text chunks, split messages and messages after IEND
+    #[arg(value_parser = chunk_type_or_secret)]
+    pub chunk: Option<String>,
+    /// Decrypt the message with a 32 byte key read from this file instead of a passphrase.
+    /// For messages encrypted to recipients, this is the recipient's private key
+    #[arg(long)]
+    pub key_file: Option<PathBuf>,
+    /// Unscramble a message stored with `encode --obfuscate` with this key
+    #[arg(long, value_name = "KEY")]
+    pub obfuscate: Option<String>,
+    /// Decrypt an age encrypted message with this age identity file or SSH private key
+    #[arg(long)]
+    pub age_identity: Option<PathBuf>,
+    /// Read a message stored with `encode --camouflage` from the text chunk of this type
+    #[arg(long)]
+    pub camouflage: bool,
+    /// Where to look for the message: in a chunk, or after IEND. Data another tool put
+    /// after IEND is read as the message, whatever the chunk type
+    #[arg(long, value_enum, default_value_t = Mode::Chunk)]
+    pub mode: Mode,
+    /// The keyword of the camouflaged text chunk. Defaults to Comment
+    #[arg(long, requires = "camouflage")]
+    pub keyword: Option<String>,
+    /// Decode the message stored under this name
+    #[arg(long)]
+    pub name: Option<String>,
+    /// Extract a message stored with `encode --input-dir` into this directory
+    #[arg(long)]
+    pub extract_to: Option<PathBuf>,
+    /// Print only the first this many bytes of the message, escaped, with its length and
+    /// what kind of data it looks like. Encrypted messages are shown as stored
+    #[arg(long)]
+    pub head: Option<usize>,
+    /// Print a message that isn't valid UTF-8 with replacement characters instead of
+    /// failing
+    #[arg(long)]
+    pub lossy: bool,
 }
 
-#[derive(Clap, Debug)]
+#[derive(Args, Debug)]
 pub struct RemoveArgs {
     pub file: PathBuf,
+    #[arg(value_parser = chunk_type)]
+    pub chunk: String,
+    /// Where to write the result. Defaults to a new file next to FILE, like
+    /// dice.pngme.png for dice.png
+    pub out: Option<PathBuf>,
+    /// Write the result over FILE instead of to a new file
+    #[arg(long, conflicts_with = "out")]
+    pub in_place: bool,
+    /// Overwrite the output file if it already exists
+    #[arg(long)]
+    pub overwrite: bool,
+    /// Allow removing IHDR, PLTE, IDAT or IEND, which breaks the image
+    #[arg(long)]
+    pub force: bool,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct EditArgs {
+    pub file: PathBuf,
+    #[arg(value_parser = chunk_type)]
+    pub chunk: String,
+    /// Allow critical or standard chunk types, which will usually break the image
+    #[arg(long)]
+    pub force: bool,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct MigrateArgs {
+    pub file: PathBuf,
+    /// Only list the chunk types that would be migrated
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AppendArgs {
+    pub file: PathBuf,
+    #[arg(value_parser = chunk_type)]
     pub chunk: String,
+    pub message: String,
+    /// Allow critical or standard chunk types, which will usually break the image
+    #[arg(long)]
+    pub force: bool,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+/// When print colors its output
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// When printing to a terminal and NO_COLOR isn't set
+    Auto,
+    Always,
+    Never,
 }
 
-#[derive(Clap, Debug)]
+#[derive(Args, Debug)]
 pub struct PrintArgs {
     pub file: PathBuf,
+    /// When to color the output
+    #[arg(long, value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+    /// Describe what each chunk is for instead, like the `explain` command
+    #[arg(long)]
+    pub explain: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ExplainArgs {
+    pub file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct CheckArgs {
+    /// PNG files or directories to check recursively
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+    /// How many files to process at once. Defaults to the number of CPUs
+    #[arg(long)]
+    pub jobs: Option<NonZeroUsize>,
+    /// Read the files on a tokio runtime with many reads in flight at once, instead of
+    /// one file per CPU. Faster for many small files or files on a network drive
+    #[arg(long = "async", conflicts_with = "jobs")]
+    pub async_io: bool,
+    /// How many files --async reads at once. Defaults to 32
+    #[arg(long, requires = "async_io")]
+    pub concurrency: Option<NonZeroUsize>,
+    /// Wait to read a file until the files already being processed leave room for it
+    /// under this much memory, like 512M. Files bigger than a quarter of the limit are
+    /// copied to a temporary file and read from disk as they're parsed
+    #[arg(long, conflicts_with = "async_io")]
+    pub max_memory: Option<ByteSize>,
+}
+
+#[derive(Args, Debug)]
+pub struct KeygenArgs {
+    /// Where to write the private key. The public key is written next to it with a `.pub` extension
+    pub out: PathBuf,
+    /// Generate an Ed25519 signing key pair instead of an X25519 encryption key pair
+    #[arg(long)]
+    pub signing: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct SignArgs {
+    pub file: PathBuf,
+    /// The Ed25519 private key to sign with
+    #[arg(long)]
+    pub key_file: PathBuf,
+    /// Write a detached signature to this file instead of adding one to the PNG, which
+    /// is left untouched
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct VerifyArgs {
+    pub file: PathBuf,
+    /// A detached signature file written by `sign --out`. Signatures embedded in the
+    /// PNG are checked if this is left out
+    pub signature: Option<PathBuf>,
+    /// The Ed25519 public key the signatures must have been made with
+    #[arg(long)]
+    pub pubkey: PathBuf,
+    /// Only verify the signature over this chunk type
+    #[arg(long, value_parser = chunk_type)]
+    pub chunk: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct SealArgs {
+    pub file: PathBuf,
+    pub out: Option<PathBuf>,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct VerifySealArgs {
+    pub file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct PixelhashArgs {
+    pub file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct CanonicalizeArgs {
+    pub file: PathBuf,
+    pub out: Option<PathBuf>,
+    /// Also merge consecutive IDAT chunks into one
+    #[arg(long)]
+    pub merge_idat: bool,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct RepairArgs {
+    pub file: PathBuf,
+    pub out: Option<PathBuf>,
+    /// Also merge consecutive IDAT chunks into one
+    #[arg(long)]
+    pub merge_idat: bool,
+    /// Also drop the bytes after IEND, which are kept by default since viewers ignore
+    /// them
+    #[arg(long)]
+    pub strip_trailer: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct DedupeArgs {
+    pub file: PathBuf,
+    pub out: Option<PathBuf>,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct OptimizeArgs {
+    pub file: PathBuf,
+    pub out: Option<PathBuf>,
+    /// Also try each filter strategy and keep the one that compresses best
+    #[arg(long)]
+    pub filters: bool,
+    /// Remove ancillary chunks that don't change how the image is displayed, including
+    /// messages
+    #[arg(long)]
+    pub strip: bool,
+    /// Number of zopfli iterations. More iterations are slower but may compress better
+    #[arg(long, default_value = "15")]
+    pub iterations: NonZeroU64,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct ReportArgs {
+    pub file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct ExplodeArgs {
+    pub file: PathBuf,
+    /// The directory to write one file per chunk and a manifest to. It is created if it
+    /// doesn't exist, and must be empty if it does
+    pub dir: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct ApplyArgs {
+    /// A TOML file listing the files to edit and the steps to apply to each of them
+    pub plan: PathBuf,
+    /// Print what would change without writing any files
+    #[arg(long)]
+    pub dry_run: bool,
+    /// How many files to process at once. Defaults to the number of CPUs
+    #[arg(long)]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+#[derive(Args, Debug)]
+pub struct GenerateArgs {
+    /// Where to write the image
+    #[arg(long)]
+    pub out: PathBuf,
+    /// The image dimensions, like 512x512
+    #[arg(long, default_value = "512x512")]
+    pub size: Size,
+    /// Either noise, or gradient for a smoother image that still has random low bits
+    #[arg(long, default_value = "noise")]
+    pub style: Style,
+}
+
+#[derive(Args, Debug)]
+pub struct ImplodeArgs {
+    /// A directory written by `explode`
+    pub dir: PathBuf,
+    /// Where to write the rebuilt PNG
+    pub out: PathBuf,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AnonymizeArgs {
+    pub file: PathBuf,
+    pub out: Option<PathBuf>,
+    /// Keep chunks of this type even though they would normally be removed. May be repeated
+    #[arg(long, value_parser = chunk_type)]
+    pub keep: Vec<String>,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CloneMetaArgs {
+    /// The file to copy metadata from
+    #[arg(long)]
+    pub from: PathBuf,
+    /// The file to copy metadata to
+    #[arg(long)]
+    pub to: PathBuf,
+    /// Where to write the result instead of overwriting --to
+    pub out: Option<PathBuf>,
+    /// Copy chunks marked unsafe to copy even though the images' critical chunks differ
+    #[arg(long)]
+    pub force: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct MetaArgs {
+    #[command(subcommand)]
+    pub command: MetaCommand,
+}
+
+#[derive(Subcommand, Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum MetaCommand {
+    /// Print the text metadata stored in a PNG file, or the comments of a GIF file
+    List(MetaListArgs),
+    /// Set the text metadata keywords predefined by the PNG spec
+    SetStandard(SetStandardArgs),
+    /// Save the XMP packet stored in a PNG file
+    XmpExtract(XmpExtractArgs),
+    /// Store an XMP packet in a PNG file, replacing any existing one
+    XmpInject(XmpInjectArgs),
+    /// Print XMP properties such as dc:creator or xmp:CreatorTool
+    XmpGet(XmpGetArgs),
+    /// Print the EXIF tags stored in a PNG eXIf chunk, or the tags of a TIFF file
+    Exif(ExifArgs),
+    /// Add a comment extension to a GIF file, or remove its comments with --remove
+    Comment(CommentArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct MetaListArgs {
+    pub file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct SetStandardArgs {
+    pub file: PathBuf,
+    pub out: Option<PathBuf>,
+    #[arg(long)]
+    pub title: Option<String>,
+    #[arg(long)]
+    pub author: Option<String>,
+    #[arg(long)]
+    pub description: Option<String>,
+    #[arg(long)]
+    pub copyright: Option<String>,
+    #[arg(long)]
+    pub creation_time: Option<String>,
+    #[arg(long)]
+    pub software: Option<String>,
+    #[arg(long)]
+    pub disclaimer: Option<String>,
+    #[arg(long)]
+    pub warning: Option<String>,
+    #[arg(long)]
+    pub source: Option<String>,
+    #[arg(long)]
+    pub comment: Option<String>,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct CommentArgs {
+    pub file: PathBuf,
+    /// The text of the comment to add
+    #[arg(required_unless_present = "remove")]
+    pub text: Option<String>,
+    /// Where to write the result. Defaults to FILE
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+    /// Remove every comment instead of adding one
+    #[arg(long, conflicts_with = "text")]
+    pub remove: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct XmpExtractArgs {
+    pub file: PathBuf,
+    pub xmp: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct XmpInjectArgs {
+    pub file: PathBuf,
+    pub xmp: PathBuf,
+    pub out: Option<PathBuf>,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct XmpGetArgs {
+    pub file: PathBuf,
+    pub properties: Vec<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct ExifArgs {
+    pub file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct IccArgs {
+    #[command(subcommand)]
+    pub command: IccCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IccCommand {
+    /// Save the ICC profile embedded in a PNG file
+    Export(IccExportArgs),
+    /// Embed an ICC profile in a PNG file, replacing any existing one
+    Import(IccImportArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct IccExportArgs {
+    pub file: PathBuf,
+    pub profile: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct IccImportArgs {
+    pub file: PathBuf,
+    pub profile: PathBuf,
+    pub out: Option<PathBuf>,
+    #[arg(long, default_value = "ICC Profile")]
+    pub name: String,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Keep the replaced chunks so that this edit can be reverted with `undo`
+    #[arg(long)]
+    pub history: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct UndoArgs {
+    pub file: PathBuf,
+    pub out: Option<PathBuf>,
+    /// Keep chunks with bad CRCs and unreadable trailing bytes as they are instead of
+    /// refusing the file
+    #[arg(long)]
+    pub preserve: bool,
+    /// Keep a copy of the original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct HistoryArgs {
+    pub file: PathBuf,
+}
+
+#[derive(Args, Debug)]
+pub struct ListMessagesArgs {
+    pub file: PathBuf,
+    #[arg(value_parser = chunk_type)]
+    pub chunk: String,
+}
+
+#[derive(Args, Debug)]
+pub struct ScanArgs {
+    /// PNG files or directories to scan recursively
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+    /// Print the entropy and chi-square statistic of every chunk's data, and flag chunks
+    /// that look compressed or encrypted when they shouldn't
+    #[arg(long)]
+    pub entropy: bool,
+    /// Only report what pngme itself leaves in files: its messages, whether in chunks,
+    /// after IEND or camouflaged as text, the manifests of split messages, its own
+    /// records, and the chunk types its examples use. Ends with how many files have any
+    #[arg(long)]
+    pub pngme: bool,
+    /// Write the data after IEND of each file that has some to this directory, in a file
+    /// named after the image with a .trailer extension. Images in subfolders of a scanned
+    /// folder get the same subfolders here
+    #[arg(long)]
+    pub extract_trailers: Option<PathBuf>,
+    /// Write the ZIP, PDF, RAR and 7-Zip files found inside images to this directory, in
+    /// files named after the image and the offset each was found at. Images in subfolders
+    /// of a scanned folder get the same subfolders here
+    #[arg(long)]
+    pub extract_embedded: Option<PathBuf>,
+    /// How many files to process at once. Defaults to the number of CPUs
+    #[arg(long)]
+    pub jobs: Option<NonZeroUsize>,
+    /// Read the files on a tokio runtime with many reads in flight at once, instead of
+    /// one file per CPU. Faster for many small files or files on a network drive
+    #[arg(long = "async", conflicts_with = "jobs")]
+    pub async_io: bool,
+    /// How many files --async reads at once. Defaults to 32
+    #[arg(long, requires = "async_io")]
+    pub concurrency: Option<NonZeroUsize>,
+    /// Wait to read a file until the files already being processed leave room for it
+    /// under this much memory, like 512M. Files bigger than a quarter of the limit are
+    /// copied to a temporary file and read from disk as they're parsed
+    #[arg(long, conflicts_with = "async_io")]
+    pub max_memory: Option<ByteSize>,
+}
+
+#[derive(Args, Debug)]
+pub struct StatsArgs {
+    /// PNG files or directories to read recursively
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+    /// Print one report for all the files instead of a line for each: how many files
+    /// have each chunk type, the average length of its chunks, how many files have
+    /// metadata, and the files that stand out from the rest
+    #[arg(long)]
+    pub aggregate: bool,
+    /// How many files to process at once. Defaults to the number of CPUs
+    #[arg(long)]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+#[derive(Args, Debug)]
+pub struct PolyglotArgs {
+    pub image: PathBuf,
+    pub archive: PathBuf,
+    /// Where to write the combined file. Defaults to a new file next to IMAGE, like
+    /// dice.pngme.png for dice.png
+    #[arg(long)]
+    pub out: Option<PathBuf>,
+    /// Overwrite the output file if it already exists
+    #[arg(long)]
+    pub overwrite: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct AnalyzeBitplanesArgs {
+    pub file: PathBuf,
+    /// Also draw each bit plane as a black and white PNG in this directory
+    #[arg(long)]
+    pub render: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct GrepArgs {
+    pub pattern: String,
+    /// PNG files or directories to search recursively
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+    /// Treat the pattern as a regular expression
+    #[arg(long)]
+    pub regex: bool,
+    /// Comma separated chunk types to search, or `all`. Image data is skipped by default
+    #[arg(long)]
+    pub types: Option<String>,
+    /// How many files to process at once. Defaults to the number of CPUs
+    #[arg(long)]
+    pub jobs: Option<NonZeroUsize>,
+    /// Read the files on a tokio runtime with many reads in flight at once, instead of
+    /// one file per CPU. Faster for many small files or files on a network drive
+    #[arg(long = "async", conflicts_with = "jobs")]
+    pub async_io: bool,
+    /// How many files --async reads at once. Defaults to 32
+    #[arg(long, requires = "async_io")]
+    pub concurrency: Option<NonZeroUsize>,
+    /// Wait to read a file until the files already being processed leave room for it
+    /// under this much memory, like 512M. Files bigger than a quarter of the limit are
+    /// copied to a temporary file and read from disk as they're parsed
+    #[arg(long, conflicts_with = "async_io")]
+    pub max_memory: Option<ByteSize>,
+}
+
+#[derive(Args, Debug)]
+pub struct LintArgs {
+    #[arg(required_unless_present = "list_rules")]
+    pub file: Option<PathBuf>,
+    /// Don't run this rule. Can be given more than once
+    #[arg(long, value_name = "RULE")]
+    pub disable: Vec<String>,
+    /// Run only this rule. Can be given more than once
+    #[arg(long, value_name = "RULE", conflicts_with = "disable")]
+    pub only: Vec<String>,
+    /// Change a rule's severity, like private-chunk=error. The levels are error, warning
+    /// and off. Can be given more than once
+    #[arg(long, value_name = "RULE=LEVEL")]
+    pub severity: Vec<String>,
+    /// List the rules with their severity and what they look for
+    #[arg(long)]
+    pub list_rules: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct FixArgs {
+    /// PNG files or directories to fix recursively
+    #[arg(required = true)]
+    pub paths: Vec<PathBuf>,
+    /// The lint rules whose problems to fix, separated by commas: crc recomputes bad
+    /// CRCs, order moves chunks into the order the PNG spec recommends, and trailer drops
+    /// bytes after IEND that pngme didn't store. Defaults to crc and order, since the
+    /// bytes after IEND are often kept on purpose, like the archive in a polyglot
+    #[arg(long, value_name = "RULES", value_delimiter = ',')]
+    pub rules: Vec<String>,
+    /// Only print what would change, without writing any files
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Keep a copy of each original file with a .bak extension
+    #[arg(long)]
+    pub backup: bool,
+    /// Add an entry for this edit to the audit trail shown by the `history` command.
+    /// Files that already have an audit trail always get a new entry
+    #[arg(long)]
+    pub audit: bool,
+    /// How many files to process at once. Defaults to the number of CPUs
+    #[arg(long)]
+    pub jobs: Option<NonZeroUsize>,
+}
+
+#[derive(Args, Debug)]
+pub struct StringsArgs {
+    pub file: PathBuf,
+    /// The fewest characters a run of text must have to be listed
+    #[arg(long, default_value_t = 6)]
+    pub min_len: usize,
+    /// Also look inside compressed data: zTXt, iCCP and compressed iTXt chunks, and the
+    /// image data of all IDAT chunks together
+    #[arg(long)]
+    pub decompress: bool,
+}
+
+#[cfg(feature = "gui")]
+#[derive(Args, Debug)]
+pub struct GuiArgs {
+    /// A file to open at the start. Others can be dropped on the window.
+    pub file: Option<PathBuf>,
+}
+
+/// Checks that a chunk type argument is valid while the arguments are parsed, so that
+/// a typo is reported with the usage instead of after the file has been read
+fn chunk_type(name: &str) -> anyhow::Result<String> {
+    ChunkType::from_str(name)?;
+    Ok(name.to_string())
+}
+
+/// Like `chunk_type`, but also accepts `secret`
+fn chunk_type_or_secret(name: &str) -> anyhow::Result<String> {
+    match name {
+        SECRET_CHUNK_TYPE => Ok(name.to_string()),
+        _ => chunk_type(name),
+    }
 }
@@ -0,0 +1,147 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Writes `bytes` to `path` so that a crash or full disk never leaves a half-written
+/// file behind. The bytes go to a temporary file in the same directory, which is then
+/// renamed over `path`. If `path` already exists its permissions are kept.
+pub fn write<P: AsRef<Path>>(path: P, bytes: &[u8]) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let temp_path = temp_path(path)?;
+
+    let result = write_temp(path, &temp_path, bytes);
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Writes several files with `write`, so that either all of them are written or none
+/// are. If one write fails, the files already written are put back the way they were.
+pub fn write_all(files: &[(PathBuf, Vec<u8>)]) -> anyhow::Result<()> {
+    let mut written: Vec<(&Path, Option<Vec<u8>>)> = Vec::with_capacity(files.len());
+    for (path, bytes) in files {
+        let previous = match fs::read(path) {
+            Ok(previous) => Some(previous),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into()),
+        };
+
+        if let Err(error) = write(path, bytes) {
+            for (path, previous) in written.into_iter().rev() {
+                let _ = match previous {
+                    Some(previous) => write(path, &previous),
+                    None => fs::remove_file(path).map_err(Into::into),
+                };
+            }
+            return Err(error.context(format!("Couldn't write {:?}", path)));
+        }
+        written.push((path, previous));
+    }
+    Ok(())
+}
+
+/// Copies `path` to `path.bak`, replacing any previous backup. Returns the backup's path,
+/// or `None` if there was nothing to back up.
+pub fn backup<P: AsRef<Path>>(path: P) -> anyhow::Result<Option<PathBuf>> {
+    let path = path.as_ref();
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let mut backup_path = path.as_os_str().to_owned();
+    backup_path.push(".bak");
+    let backup_path = PathBuf::from(backup_path);
+
+    fs::copy(path, &backup_path)?;
+    Ok(Some(backup_path))
+}
+
+fn write_temp(path: &Path, temp_path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(temp_path)?;
+    file.write_all(bytes)?;
+
+    if let Ok(metadata) = fs::metadata(path) {
+        file.set_permissions(metadata.permissions())?;
+    }
+
+    file.sync_all()?;
+    fs::rename(temp_path, path)?;
+    Ok(())
+}
+
+/// A hidden file next to `path` that no other process should be using
+fn temp_path(path: &Path) -> anyhow::Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{:?} is not a file path", path))?;
+
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+
+    Ok(path.with_file_name(temp_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pngme-atomic-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn test_write_replaces_file() {
+        let path = testing_path("write");
+        fs::write(&path, b"old").unwrap();
+
+        write(&path, b"new").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"new");
+        assert!(!temp_path(&path).unwrap().exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_write_all_rolls_back() {
+        let existing = testing_path("write-all-existing");
+        let created = testing_path("write-all-created");
+        fs::write(&existing, b"old").unwrap();
+
+        let missing_directory = testing_path("write-all-missing").join("file");
+        let files = vec![
+            (existing.clone(), b"new".to_vec()),
+            (created.clone(), b"new".to_vec()),
+            (missing_directory, b"new".to_vec()),
+        ];
+        assert!(write_all(&files).is_err());
+        assert_eq!(fs::read(&existing).unwrap(), b"old");
+        assert!(!created.exists());
+
+        write_all(&files[..2]).unwrap();
+        assert_eq!(fs::read(&created).unwrap(), b"new");
+
+        fs::remove_file(&existing).unwrap();
+        fs::remove_file(&created).unwrap();
+    }
+
+    #[test]
+    fn test_backup() {
+        let path = testing_path("backup");
+        assert_eq!(backup(&path).unwrap(), None);
+
+        fs::write(&path, b"original").unwrap();
+        let backup_path = backup(&path).unwrap().unwrap();
+        write(&path, b"changed").unwrap();
+
+        assert_eq!(backup_path.extension().unwrap(), "bak");
+        assert_eq!(fs::read(&backup_path).unwrap(), b"original");
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&backup_path).unwrap();
+    }
+}
@@ -0,0 +1,138 @@
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::png::{Chunk, ChunkType, Png};
+
+/// The chunk type used to store the audit trail
+pub const AUDIT_CHUNK_TYPE: &str = "pnAu";
+
+/// One edit made to a file by pngme
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Seconds since the UNIX epoch when the edit was made
+    pub timestamp: u64,
+    /// The pngme version that made the edit
+    pub version: String,
+    /// The command that made the edit, like `encode`
+    pub operation: String,
+    /// The chunk type the edit was about, if it was about a single one
+    pub chunk_type: Option<String>,
+}
+
+impl Entry {
+    /// Creates an entry for an edit made now by this version of pngme
+    pub fn new(operation: &str, chunk_type: Option<&ChunkType>) -> anyhow::Result<Self> {
+        Ok(Self {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            operation: operation.to_string(),
+            chunk_type: chunk_type.map(ChunkType::to_string),
+        })
+    }
+}
+
+impl FromStr for Entry {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> anyhow::Result<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 4 {
+            anyhow::bail!("Invalid audit entry: {:?}", line);
+        }
+
+        Ok(Self {
+            timestamp: fields[0].parse()?,
+            version: fields[1].to_string(),
+            operation: fields[2].to_string(),
+            chunk_type: match fields[3] {
+                "-" => None,
+                chunk_type => Some(chunk_type.to_string()),
+            },
+        })
+    }
+}
+
+/// Returns true if `png` has an audit trail
+pub fn has_trail(png: &Png) -> bool {
+    png.chunk_by_type(AUDIT_CHUNK_TYPE).is_some()
+}
+
+/// Returns every entry in the audit trail of `png`, oldest first
+pub fn entries(png: &Png) -> anyhow::Result<Vec<Entry>> {
+    match png.chunk_by_type(AUDIT_CHUNK_TYPE) {
+        Some(chunk) => chunk
+            .data_as_string()?
+            .lines()
+            .map(Entry::from_str)
+            .collect(),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Adds `entry` to the audit trail of `png`, starting one if there isn't one yet.
+///
+/// The trail is a single chunk of UTF-8 text with one line per entry, holding the
+/// timestamp, version, operation and chunk type (or `-`) separated by tabs.
+pub fn record(png: &mut Png, entry: &Entry) -> anyhow::Result<()> {
+    let mut data = png
+        .chunk_by_type(AUDIT_CHUNK_TYPE)
+        .map(|chunk| chunk.data().to_vec())
+        .unwrap_or_default();
+    data.extend_from_slice(
+        format!(
+            "{}\t{}\t{}\t{}\n",
+            entry.timestamp,
+            entry.version,
+            entry.operation,
+            entry.chunk_type.as_deref().unwrap_or("-")
+        )
+        .as_bytes(),
+    );
+
+    png.retain_chunks(|chunk| chunk.chunk_type().to_string() != AUDIT_CHUNK_TYPE);
+    png.insert_chunk(Chunk::new(ChunkType::from_str(AUDIT_CHUNK_TYPE)?, data));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_record_and_read() {
+        let mut png = testing_png();
+        assert!(!has_trail(&png));
+
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let first = Entry::new("encode", Some(&chunk_type)).unwrap();
+        let second = Entry::new("canonicalize", None).unwrap();
+        record(&mut png, &first).unwrap();
+        record(&mut png, &second).unwrap();
+
+        assert!(has_trail(&png));
+        assert_eq!(entries(&png).unwrap(), vec![first, second]);
+        assert_eq!(png.chunks().len(), 3);
+        assert_eq!(png.chunks()[2].chunk_type().to_string(), "IEND");
+    }
+
+    #[test]
+    fn test_parse_entry() {
+        let entry = Entry::from_str("1700000000\t0.1.0\tremove\truSt").unwrap();
+        assert_eq!(entry.timestamp, 1_700_000_000);
+        assert_eq!(entry.chunk_type.as_deref(), Some("ruSt"));
+        assert_eq!(
+            Entry::from_str("1700000000\t0.1.0\tseal\t-")
+                .unwrap()
+                .chunk_type,
+            None
+        );
+        assert!(Entry::from_str("not an entry").is_err());
+    }
+}
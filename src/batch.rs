@@ -0,0 +1,145 @@
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+
+#[cfg(feature = "async")]
+use crate::container;
+#[cfg(feature = "async")]
+use crate::error::Locate;
+
+/// Calls `process` on every item with a pool of `jobs` threads, or one thread per CPU if
+/// `jobs` is `None`, and returns the results in the same order as `items`
+pub fn map<T, R, F>(items: &[T], jobs: Option<NonZeroUsize>, process: F) -> anyhow::Result<Vec<R>>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync + Send,
+{
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs.map_or(0, NonZeroUsize::get))
+        .build()?;
+    Ok(pool.install(|| items.par_iter().map(process).collect()))
+}
+
+/// Reads `files` on a tokio runtime with up to `concurrency` reads in flight, and calls
+/// `process` with each file's contents, or the error reading it, in the order the reads
+/// finish. Returns the results in the same order as `files`.
+///
+/// Unlike `map`, which keeps one thread per CPU busy, this is for many files that each
+/// take little work, on network drives or in wide directories, where waiting on reads
+/// takes longer than processing them.
+#[cfg(feature = "async")]
+pub fn map_files_async<R, F>(
+    files: &[PathBuf],
+    concurrency: NonZeroUsize,
+    mut process: F,
+) -> anyhow::Result<Vec<R>>
+where
+    F: FnMut(&Path, anyhow::Result<Vec<u8>>) -> R,
+{
+    use std::sync::Arc;
+    use tokio::sync::{mpsc, Semaphore};
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .max_blocking_threads(concurrency.get())
+        .build()?;
+
+    runtime.block_on(async {
+        // A read holds its permit until its contents are handed over, so no more than
+        // `concurrency` files are in memory at once
+        let permits = Arc::new(Semaphore::new(concurrency.get()));
+        let (sender, mut receiver) = mpsc::channel(concurrency.get());
+        for (index, file) in files.iter().enumerate() {
+            let permits = Arc::clone(&permits);
+            let sender = sender.clone();
+            let file = file.clone();
+            tokio::spawn(async move {
+                let Ok(_permit) = permits.acquire_owned().await else {
+                    return;
+                };
+                let bytes = read(file).await;
+                let _ = sender.send((index, bytes)).await;
+            });
+        }
+        drop(sender);
+
+        let mut results: Vec<Option<R>> = files.iter().map(|_| None).collect();
+        while let Some((index, bytes)) = receiver.recv().await {
+            results[index] = Some(process(&files[index], bytes));
+        }
+        results
+            .into_iter()
+            .zip(files)
+            .map(|(result, file)| {
+                result.ok_or_else(|| anyhow::anyhow!("Reading {:?} was cancelled", file))
+            })
+            .collect()
+    })
+}
+
+#[cfg(not(feature = "async"))]
+pub fn map_files_async<R, F>(
+    _files: &[PathBuf],
+    _concurrency: NonZeroUsize,
+    _process: F,
+) -> anyhow::Result<Vec<R>>
+where
+    F: FnMut(&Path, anyhow::Result<Vec<u8>>) -> R,
+{
+    anyhow::bail!("This pngme was built without --async support. Rebuild it with --features async")
+}
+
+/// Reads a file with tokio, or on a blocking thread for the paths `container::read_file`
+/// understands that aren't files on disk, like images inside ZIP archives
+#[cfg(feature = "async")]
+async fn read(file: PathBuf) -> anyhow::Result<Vec<u8>> {
+    match file.is_file() {
+        true => tokio::fs::read(&file).await.in_file(&file),
+        false => tokio::task::spawn_blocking(move || container::read_file(&file)).await?,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_map_keeps_order() {
+        let items: Vec<u64> = (0..32).collect();
+        // Later items finish first, so this checks that map puts the results back in order
+        let results = map(&items, NonZeroUsize::new(4), |&item| {
+            thread::sleep(Duration::from_millis(32 - item));
+            item * 2
+        })
+        .unwrap();
+        assert_eq!(results, (0..32).map(|item| item * 2).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_map_files_async() {
+        let directory = std::env::temp_dir().join(format!("pngme-async-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let mut files: Vec<PathBuf> = (0..64)
+            .map(|index| {
+                let file = directory.join(format!("{}.txt", index));
+                std::fs::write(&file, index.to_string()).unwrap();
+                file
+            })
+            .collect();
+        files.push(directory.join("missing.txt"));
+
+        let results = map_files_async(&files, NonZeroUsize::new(8).unwrap(), |_, bytes| {
+            bytes.map(|bytes| String::from_utf8(bytes).unwrap())
+        })
+        .unwrap();
+        for (index, result) in results.iter().take(64).enumerate() {
+            assert_eq!(result.as_ref().unwrap(), &index.to_string());
+        }
+        assert!(results[64].is_err());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,289 @@
+use std::io::Write;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::builder::PngBuilder;
+use crate::pixels;
+use crate::png::{Chunk, ChunkType, Png};
+
+/// How far from an even split a plane can be and still count as noise
+const NOISE_TOLERANCE: f64 = 0.02;
+
+/// The shortest run of printable characters at the start of the LSB data reported as text
+const MIN_TEXT_LENGTH: usize = 8;
+
+/// The most LSB text that is returned
+const MAX_TEXT_LENGTH: usize = 256;
+
+/// The samples of one channel of an image, in row order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Channel {
+    pub name: &'static str,
+    pub samples: Vec<u16>,
+}
+
+/// A decoded image split into channels, so each bit of each channel can be looked at as
+/// an image of its own
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Planes {
+    pub width: usize,
+    pub height: usize,
+    pub bit_depth: u8,
+    pub channels: Vec<Channel>,
+}
+
+/// How the bits of one bit plane are distributed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlaneStatistics {
+    pub channel: &'static str,
+    /// 0 is the least significant bit
+    pub bit: u8,
+    /// The fraction of pixels with the bit set
+    pub ones: f64,
+    /// The fraction of pixels whose bit matches the pixel to their left. Planes showing
+    /// the picture are well above one half, while noise and hidden data sit right at it.
+    pub same_as_left: f64,
+}
+
+impl PlaneStatistics {
+    /// Returns true if the plane has no visible structure, as is normal for the lowest
+    /// bits of a photo and for bits replaced by encrypted or compressed data
+    pub fn is_noise(&self) -> bool {
+        (self.ones - 0.5).abs() < NOISE_TOLERANCE
+            && (self.same_as_left - 0.5).abs() < NOISE_TOLERANCE
+    }
+}
+
+impl Planes {
+    /// Decodes the pixels of `png`. Palette images are split into their palette indices
+    /// rather than colors, since that is where hidden data would be.
+    pub fn from_png(png: &Png) -> anyhow::Result<Self> {
+        let (header, raw) = pixels::decode(png)?;
+        let names: &[&'static str] = match header.color_type {
+            0 => &["gray"],
+            2 => &["red", "green", "blue"],
+            3 => &["index"],
+            4 => &["gray", "alpha"],
+            6 => &["red", "green", "blue", "alpha"],
+            color_type => anyhow::bail!("Invalid color type {}", color_type),
+        };
+
+        let (width, height) = (header.width as usize, header.height as usize);
+        let depth = header.bit_depth as usize;
+        let mut channels: Vec<Channel> = names
+            .iter()
+            .map(|&name| Channel {
+                name,
+                samples: Vec::with_capacity(width * height),
+            })
+            .collect();
+        for line in raw.chunks(header.row_length()?) {
+            for x in 0..width {
+                for (index, channel) in channels.iter_mut().enumerate() {
+                    let bit = (x * names.len() + index) * depth;
+                    channel.samples.push(read_sample(line, bit, depth));
+                }
+            }
+        }
+
+        Ok(Self {
+            width,
+            height,
+            bit_depth: header.bit_depth,
+            channels,
+        })
+    }
+
+    /// Returns statistics for every bit of every channel, most significant bit first
+    pub fn statistics(&self) -> Vec<PlaneStatistics> {
+        let pixels = (self.width * self.height) as f64;
+        let pairs = ((self.width - 1) * self.height) as f64;
+
+        let mut statistics = Vec::new();
+        for channel in &self.channels {
+            for bit in (0..self.bit_depth).rev() {
+                let value = |sample: &u16| (sample >> bit) & 1;
+                let ones = channel.samples.iter().filter(|&s| value(s) == 1).count();
+                let same = channel
+                    .samples
+                    .chunks(self.width)
+                    .flat_map(|row| row.windows(2))
+                    .filter(|pair| value(&pair[0]) == value(&pair[1]))
+                    .count();
+                statistics.push(PlaneStatistics {
+                    channel: channel.name,
+                    bit,
+                    ones: ones as f64 / pixels,
+                    same_as_left: if pairs > 0.0 {
+                        same as f64 / pairs
+                    } else {
+                        1.0
+                    },
+                });
+            }
+        }
+
+        statistics
+    }
+
+    /// Draws one bit plane as a black and white image, with set bits in white
+    pub fn render(&self, channel: usize, bit: u8) -> anyhow::Result<Png> {
+        let samples = &self.channels[channel].samples;
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        for row in samples.chunks(self.width) {
+            // Filter type None, then the bits packed eight pixels to a byte
+            let mut line = vec![0; 1 + self.width.div_ceil(8)];
+            for (x, sample) in row.iter().enumerate() {
+                line[1 + x / 8] |= (((sample >> bit) & 1) as u8) << (7 - x % 8);
+            }
+            encoder.write_all(&line)?;
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&(self.width as u32).to_be_bytes());
+        header.extend_from_slice(&(self.height as u32).to_be_bytes());
+        // 1 bit grayscale, default compression and filtering, no interlacing
+        header.extend_from_slice(&[1, 0, 0, 0, 0]);
+
+        PngBuilder::new(Chunk::new(ChunkType::new(*b"IHDR"), header))?
+            .chunk(Chunk::new(ChunkType::new(*b"IDAT"), encoder.finish()?))?
+            .build()
+    }
+
+    /// Returns the printable text at the start of the data hidden the most common way:
+    /// in the least significant bit of each color sample, pixel by pixel, packed most
+    /// significant bit first. Returns `None` if there isn't enough text to stand out.
+    pub fn lsb_text(&self) -> Option<String> {
+        let colors: Vec<&Channel> = self
+            .channels
+            .iter()
+            .filter(|channel| channel.name != "alpha")
+            .collect();
+        let bits = (0..self.width * self.height)
+            .flat_map(|pixel| colors.iter().map(move |channel| channel.samples[pixel] & 1));
+
+        let mut text = String::new();
+        let mut byte = 0u8;
+        for (index, bit) in bits.enumerate() {
+            byte = (byte << 1) | bit as u8;
+            if index % 8 < 7 {
+                continue;
+            }
+            if !(byte.is_ascii_graphic() || byte == b' ') || text.len() == MAX_TEXT_LENGTH {
+                break;
+            }
+            text.push(byte as char);
+        }
+
+        Some(text).filter(|text| text.len() >= MIN_TEXT_LENGTH)
+    }
+}
+
+/// Reads a sample of `depth` bits starting at bit `bit` of `line`
+fn read_sample(line: &[u8], bit: usize, depth: usize) -> u16 {
+    match depth {
+        16 => u16::from_be_bytes([line[bit / 8], line[bit / 8 + 1]]),
+        8 => line[bit / 8] as u16,
+        _ => ((line[bit / 8] >> (8 - depth - bit % 8)) & ((1 << depth) - 1)) as u16,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 16x8 RGB gradient with `message` hidden in the lowest bit of each sample
+    fn testing_png(message: &[u8]) -> Png {
+        let bits: Vec<u8> = message
+            .iter()
+            .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) & 1))
+            .collect();
+
+        let mut raw = Vec::new();
+        for y in 0..8u8 {
+            raw.push(0);
+            for x in 0..16u8 {
+                let pixel = (y as usize * 16 + x as usize) * 3;
+                for channel in 0..3 {
+                    let value = (x * 15) & 0xfe;
+                    raw.push(value | bits.get(pixel + channel).copied().unwrap_or(0));
+                }
+            }
+        }
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&raw).unwrap();
+        let mut header = Vec::new();
+        header.extend_from_slice(&16u32.to_be_bytes());
+        header.extend_from_slice(&8u32.to_be_bytes());
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+        PngBuilder::new(Chunk::new(ChunkType::new(*b"IHDR"), header))
+            .unwrap()
+            .chunk(Chunk::new(
+                ChunkType::new(*b"IDAT"),
+                encoder.finish().unwrap(),
+            ))
+            .unwrap()
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_from_png() {
+        let planes = Planes::from_png(&testing_png(b"")).unwrap();
+        assert_eq!((planes.width, planes.height, planes.bit_depth), (16, 8, 8));
+        let names: Vec<&str> = planes.channels.iter().map(|c| c.name).collect();
+        assert_eq!(names, vec!["red", "green", "blue"]);
+        assert_eq!(planes.channels[0].samples[..3], [0, 14, 30]);
+    }
+
+    #[test]
+    fn test_invalid_bit_depth() {
+        let png = testing_png(b"");
+        for bit_depth in [0, 3] {
+            let mut header = png.chunks()[0].data().to_vec();
+            header[8..10].copy_from_slice(&[bit_depth, 0]);
+            let mut chunks = png.chunks().to_vec();
+            chunks[0] = Chunk::new(ChunkType::new(*b"IHDR"), header);
+            assert!(Planes::from_png(&Png::from_chunks(chunks)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_statistics() {
+        let planes = Planes::from_png(&testing_png(b"")).unwrap();
+        let statistics = planes.statistics();
+        assert_eq!(statistics.len(), 3 * 8);
+        assert_eq!((statistics[0].channel, statistics[0].bit), ("red", 7));
+
+        let lowest = statistics[7];
+        assert_eq!(lowest.bit, 0);
+        assert_eq!(lowest.ones, 0.0);
+        assert_eq!(lowest.same_as_left, 1.0);
+        assert!(!lowest.is_noise());
+    }
+
+    #[test]
+    fn test_lsb_text() {
+        let planes = Planes::from_png(&testing_png(b"Hidden in plain sight")).unwrap();
+        assert_eq!(planes.lsb_text().unwrap(), "Hidden in plain sight");
+        assert_eq!(
+            Planes::from_png(&testing_png(b"")).unwrap().lsb_text(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let planes = Planes::from_png(&testing_png(b"")).unwrap();
+        let rendered = Planes::from_png(&planes.render(0, 5).unwrap()).unwrap();
+        assert_eq!((rendered.width, rendered.height), (16, 8));
+        let expected: Vec<u16> = planes.channels[0]
+            .samples
+            .iter()
+            .map(|sample| (sample >> 5) & 1)
+            .collect();
+        assert_eq!(rendered.channels[0].samples, expected);
+    }
+}
@@ -0,0 +1,271 @@
+use std::convert::{TryFrom, TryInto};
+use std::str::FromStr;
+
+use crate::pixels;
+use crate::png::{Chunk, ChunkType, Png};
+use crate::text::TextChunk;
+
+/// Chunk types the PNG spec allows at most once per file
+const SINGLE_CHUNK_TYPES: [&str; 12] = [
+    "PLTE", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "bKGD", "hIST", "tRNS", "pHYs", "tIME", "eXIf",
+];
+
+/// Builds a `Png` one chunk at a time, so callers don't need to know where each chunk
+/// belongs. Every call checks its chunk straight away, and `build` puts the chunks in
+/// the order the PNG spec requires and ends the file with IEND.
+///
+/// ```
+/// # use pngme::builder::PngBuilder;
+/// # use pngme::generate::{self, Style};
+/// # fn main() -> anyhow::Result<()> {
+/// # let image = generate::generate("4x4".parse()?, Style::Noise)?;
+/// # let header = image.chunk_by_type("IHDR").unwrap().clone();
+/// # let image_data = image.chunk_by_type("IDAT").unwrap().clone();
+/// let png = PngBuilder::new(header)?
+///     .chunk(image_data)?
+///     .text("Author", "Ferris")?
+///     .payload("ruSt", b"Hello".to_vec())?
+///     .build()?;
+/// # assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"Hello");
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PngBuilder {
+    chunks: Vec<Chunk>,
+    /// Chunks found after IEND in the file the builder started from, kept where they were
+    after_iend: Vec<Chunk>,
+}
+
+impl PngBuilder {
+    /// Starts a PNG from its IHDR chunk, which must describe a valid image
+    pub fn new(header: Chunk) -> anyhow::Result<Self> {
+        if header.chunk_type().to_string() != "IHDR" {
+            anyhow::bail!(
+                "The header must be an IHDR chunk, not {}",
+                header.chunk_type()
+            );
+        }
+        let data = header.data();
+        if data.len() != 13 {
+            anyhow::bail!("IHDR chunk must be 13 bytes long, not {}", data.len());
+        }
+
+        let width = u32::from_be_bytes(data[0..4].try_into()?);
+        let height = u32::from_be_bytes(data[4..8].try_into()?);
+        if width == 0 || height == 0 || width > i32::MAX as u32 || height > i32::MAX as u32 {
+            anyhow::bail!("Invalid image size {}x{}", width, height);
+        }
+
+        pixels::check_bit_depth(data[8], data[9])?;
+        if data[10] != 0 || data[11] != 0 {
+            anyhow::bail!("Unknown compression or filter method");
+        }
+        if data[12] > 1 {
+            anyhow::bail!("Invalid interlace method {}", data[12]);
+        }
+
+        Ok(Self {
+            chunks: vec![header],
+            after_iend: Vec::new(),
+        })
+    }
+
+    /// Starts from the chunks of an existing PNG. Each chunk is checked as if it were
+    /// added with `chunk`, and chunks after IEND stay after it.
+    pub fn from_png(png: &Png) -> anyhow::Result<Self> {
+        let (header, rest) = png
+            .chunks()
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("The PNG has no chunks"))?;
+        let iend = rest
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == "IEND")
+            .unwrap_or(rest.len());
+
+        let mut builder = Self::new(header.clone())?;
+        for chunk in &rest[..iend] {
+            builder = builder.chunk(chunk.clone())?;
+        }
+        builder.after_iend = rest.get(iend + 1..).unwrap_or_default().to_vec();
+        Ok(builder)
+    }
+
+    /// Adds a chunk. IHDR and IEND are refused since the builder writes those itself, as
+    /// are a second copy of a chunk type that may only appear once and a PLTE chunk in a
+    /// grayscale image.
+    pub fn chunk(mut self, chunk: Chunk) -> anyhow::Result<Self> {
+        let chunk_type = chunk.chunk_type().to_string();
+        if !chunk.chunk_type().is_reserved_bit_valid() {
+            anyhow::bail!("{} chunk type has the reserved bit set", chunk_type);
+        }
+
+        match chunk_type.as_str() {
+            "IHDR" => anyhow::bail!("The PNG already has an IHDR chunk"),
+            "IEND" => anyhow::bail!("IEND is added by build"),
+            "PLTE" if matches!(self.color_type(), 0 | 4) => {
+                anyhow::bail!("Grayscale images can't have a PLTE chunk")
+            }
+            t if SINGLE_CHUNK_TYPES.contains(&t) && self.has(t) => {
+                anyhow::bail!("The PNG already has a {} chunk", t)
+            }
+            _ => {}
+        }
+
+        self.chunks.push(chunk);
+        Ok(self)
+    }
+
+    /// Adds a tEXt chunk, replacing any earlier text with the same keyword
+    pub fn text(mut self, keyword: &str, text: &str) -> anyhow::Result<Self> {
+        let text = TextChunk::new(keyword, text)?;
+        self.chunks
+            .retain(|chunk| TextChunk::try_from(chunk).map_or(true, |t| t.keyword() != keyword));
+        self.chunk(text.to_chunk())
+    }
+
+    /// Adds a message in a chunk of type `chunk_type`. The type must be ancillary and not
+    /// defined by the PNG spec, so that viewers skip it.
+    pub fn payload(self, chunk_type: &str, data: Vec<u8>) -> anyhow::Result<Self> {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        if chunk_type.is_critical() || chunk_type.is_registered() {
+            anyhow::bail!(
+                "{} can't hold a payload. Use a private ancillary type like ruSt",
+                chunk_type
+            );
+        }
+        self.chunk(Chunk::new(chunk_type, data))
+    }
+
+    /// Checks that the image is complete and returns it with its chunks in spec order
+    pub fn build(self) -> anyhow::Result<Png> {
+        if !self.has("IDAT") {
+            anyhow::bail!("The PNG has no IDAT chunk");
+        }
+        if self.color_type() == 3 && !self.has("PLTE") {
+            anyhow::bail!("Palette images need a PLTE chunk");
+        }
+
+        let mut png = Png::from_chunks(self.chunks);
+        png.canonicalize();
+        png.append_chunk(Chunk::new(ChunkType::new(*b"IEND"), Vec::new()));
+        for chunk in self.after_iend {
+            png.append_chunk(chunk);
+        }
+        Ok(png)
+    }
+
+    fn color_type(&self) -> u8 {
+        self.chunks[0].data()[9]
+    }
+
+    fn has(&self, chunk_type: &str) -> bool {
+        self.chunks
+            .iter()
+            .any(|chunk| chunk.chunk_type().to_string() == chunk_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(bit_depth: u8, color_type: u8) -> Chunk {
+        let mut data = Vec::new();
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&1u32.to_be_bytes());
+        data.extend_from_slice(&[bit_depth, color_type, 0, 0, 0]);
+        Chunk::new(ChunkType::from_str("IHDR").unwrap(), data)
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_build_in_spec_order() {
+        let png = PngBuilder::new(header(8, 6))
+            .unwrap()
+            .chunk(Chunk::from_strings("IDAT", "pixels").unwrap())
+            .unwrap()
+            .text("Author", "Ferris")
+            .unwrap()
+            .chunk(Chunk::from_strings("gAMA", "gama").unwrap())
+            .unwrap()
+            .payload("ruSt", b"Hello".to_vec())
+            .unwrap()
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "gAMA", "IDAT", "tEXt", "ruSt", "IEND"]
+        );
+        assert!(Png::try_from(png.as_bytes().as_slice()).is_ok());
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        assert!(PngBuilder::new(header(16, 3)).is_err());
+        assert!(PngBuilder::new(header(8, 5)).is_err());
+        assert!(PngBuilder::new(Chunk::from_strings("IHDR", "short").unwrap()).is_err());
+        assert!(PngBuilder::new(Chunk::from_strings("IDAT", "pixels").unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_checks_each_step() {
+        let builder = PngBuilder::new(header(8, 0)).unwrap();
+        assert!(builder.clone().chunk(header(8, 0)).is_err());
+        assert!(builder
+            .clone()
+            .chunk(Chunk::from_strings("IEND", "").unwrap())
+            .is_err());
+        assert!(builder
+            .clone()
+            .chunk(Chunk::from_strings("PLTE", "rgb").unwrap())
+            .is_err());
+        assert!(builder.clone().payload("IDAT", Vec::new()).is_err());
+        assert!(builder.clone().payload("tEXt", Vec::new()).is_err());
+        assert!(builder.clone().text("", "no keyword").is_err());
+        assert!(builder.clone().build().is_err());
+
+        let gamma = Chunk::from_strings("gAMA", "gama").unwrap();
+        let builder = builder.chunk(gamma.clone()).unwrap();
+        assert!(builder.chunk(gamma).is_err());
+
+        let palette = PngBuilder::new(header(8, 3))
+            .unwrap()
+            .chunk(Chunk::from_strings("IDAT", "pixels").unwrap())
+            .unwrap();
+        assert!(palette.build().is_err());
+    }
+
+    #[test]
+    fn test_from_png() {
+        let png = Png::from_chunks(vec![
+            header(8, 2),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("tEXt", "Author\0Ferris").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+            Chunk::from_strings("ruSt", "after the end").unwrap(),
+        ]);
+
+        let rebuilt = PngBuilder::from_png(&png).unwrap().build().unwrap();
+        assert_eq!(rebuilt.as_bytes(), png.as_bytes());
+
+        let edited = PngBuilder::from_png(&png)
+            .unwrap()
+            .text("Author", "Corro")
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(
+            chunk_types(&edited),
+            vec!["IHDR", "IDAT", "tEXt", "IEND", "ruSt"]
+        );
+        assert_eq!(edited.chunks()[2].data(), b"Author\0Corro");
+    }
+}
@@ -0,0 +1,150 @@
+use std::convert::TryFrom;
+
+use base64::Engine;
+
+use crate::archive;
+use crate::crypto;
+use crate::deniable;
+use crate::messages::MessageStore;
+use crate::obfuscate;
+use crate::png::{Chunk, ChunkType, Png};
+use crate::recipients;
+use crate::text::{InternationalTextChunk, TextChunk};
+
+/// The keyword used when none is given. Plenty of software leaves comments behind, so
+/// one more doesn't stand out.
+pub const DEFAULT_KEYWORD: &str = "Comment";
+
+/// Returns a tEXt or iTXt chunk, depending on `chunk_type`, that stores `payload` as the
+/// text for `keyword`. Text is stored as it is. Encrypted messages, archives and named
+/// messages are stored in base64, which `reveal` recognizes by what it decodes to. Other
+/// binary data can't be told apart from text that happens to be valid base64, so it is
+/// refused.
+pub fn hide(chunk_type: &ChunkType, keyword: &str, payload: &[u8]) -> anyhow::Result<Chunk> {
+    let base64 = &base64::engine::general_purpose::STANDARD;
+    let text = match std::str::from_utf8(payload) {
+        Ok(text) if !text.contains('\0') => text.to_string(),
+        _ if is_binary_message(payload) => base64.encode(payload),
+        _ => anyhow::bail!(
+            "Only text, encrypted messages, directory archives and named messages can be \
+             camouflaged"
+        ),
+    };
+
+    match chunk_type.to_string().as_str() {
+        "tEXt" => Ok(TextChunk::new(keyword, &text)
+            .map_err(|error| error.context("Use iTXt for text that isn't Latin-1"))?
+            .to_chunk()),
+        "iTXt" => Ok(InternationalTextChunk::new(keyword, &text)?.to_chunk()),
+        other => anyhow::bail!(
+            "Camouflaged messages go in tEXt or iTXt chunks, not {}",
+            other
+        ),
+    }
+}
+
+/// Returns the payload stored with `hide` in the last `chunk_type` chunk with `keyword`,
+/// since a message is added after any text the image already had
+pub fn reveal(png: &Png, chunk_type: &ChunkType, keyword: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let text = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type() == chunk_type)
+        .filter_map(|chunk| match chunk_type.to_string().as_str() {
+            "tEXt" => TextChunk::try_from(chunk)
+                .ok()
+                .filter(|text| text.keyword() == keyword)
+                .map(|text| text.text().to_string()),
+            "iTXt" => InternationalTextChunk::try_from(chunk)
+                .ok()
+                .filter(|text| text.keyword() == keyword)
+                .map(|text| text.text().to_string()),
+            _ => None,
+        })
+        .last();
+
+    let base64 = &base64::engine::general_purpose::STANDARD;
+    Ok(text.map(|text| match base64.decode(&text) {
+        Ok(decoded) if is_binary_message(&decoded) => decoded,
+        _ => text.into_bytes(),
+    }))
+}
+
+/// Returns true for the kinds of binary data pngme writes, which all start with a
+/// recognizable header
+pub(crate) fn is_binary_message(data: &[u8]) -> bool {
+    crypto::is_encrypted(data)
+        || crypto::is_age_encrypted(data)
+        || deniable::is_deniable(data)
+        || recipients::is_multi_recipient(data)
+        || obfuscate::is_obfuscated(data)
+        || archive::is_archive(data)
+        || MessageStore::is_message_store(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{KdfParams, Secret};
+
+    fn text_type() -> ChunkType {
+        ChunkType::new(*b"tEXt")
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            TextChunk::new("Comment", "A real comment")
+                .unwrap()
+                .to_chunk(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_hide_text() {
+        let mut png = testing_png();
+        png.insert_chunk(hide(&text_type(), "Comment", b"secret").unwrap());
+
+        let chunk = &png.chunks()[2];
+        assert_eq!(chunk.chunk_type(), &text_type());
+        assert_eq!(chunk.data(), b"Comment\0secret");
+        assert_eq!(
+            reveal(&png, &text_type(), "Comment").unwrap().unwrap(),
+            b"secret"
+        );
+        assert!(reveal(&png, &text_type(), "Software").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_hide_encrypted() {
+        let secret = Secret::Key([7; 32]);
+        let encrypted = crypto::encrypt(b"secret", &secret, KdfParams::default()).unwrap();
+
+        let mut png = testing_png();
+        png.insert_chunk(hide(&text_type(), "Software", &encrypted).unwrap());
+        assert_eq!(
+            reveal(&png, &text_type(), "Software").unwrap().unwrap(),
+            encrypted
+        );
+    }
+
+    #[test]
+    fn test_hide_international_text() {
+        let itxt = ChunkType::new(*b"iTXt");
+        assert!(hide(&text_type(), "Comment", "秘密".as_bytes()).is_err());
+
+        let mut png = testing_png();
+        png.insert_chunk(hide(&itxt, "Comment", "秘密".as_bytes()).unwrap());
+        assert_eq!(
+            reveal(&png, &itxt, "Comment").unwrap().unwrap(),
+            "秘密".as_bytes()
+        );
+    }
+
+    #[test]
+    fn test_refuse_binary() {
+        assert!(hide(&text_type(), "Comment", &[0xde, 0xad, 0xbe, 0xef]).is_err());
+        assert!(hide(&ChunkType::new(*b"ruSt"), "Comment", b"text").is_err());
+    }
+}
@@ -1,23 +1,32 @@
-use std::convert::TryFrom;
+use std::convert::{TryFrom, TryInto};
 use std::fmt;
-use std::io::{BufReader, Read};
+use std::io::Read;
 
+use bytes::Bytes;
+use crc::{crc32, Hasher32};
+
+use crate::error::Locate;
 use crate::png::ChunkType;
 
 /// A validated PNG chunk. See the PNG Spec for more details
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
+///
+/// The data is reference counted, so cloning a chunk doesn't copy it, and chunks parsed
+/// from the same bytes share one buffer.
 #[derive(Debug, Clone)]
 pub struct Chunk {
     length: u32,
     chunk_type: ChunkType,
-    data: Vec<u8>,
+    data: Bytes,
     crc: u32,
+    offset: Option<usize>,
 }
 
 impl Chunk {
     /// Creates a new chunk from a validated `ChunkType` and some data.
     /// The length and CRC will be computed automatically.
-    pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Self {
+    pub fn new<D: Into<Bytes>>(chunk_type: ChunkType, data: D) -> Self {
+        let data = data.into();
         let crc = Self::calculate_crc(&chunk_type, &data);
 
         Self {
@@ -25,6 +34,20 @@ impl Chunk {
             chunk_type,
             data,
             crc,
+            offset: None,
+        }
+    }
+
+    /// Creates a chunk that keeps `crc` even if it doesn't match the data, so that a
+    /// damaged chunk can be written back exactly as it was read
+    pub fn with_crc<D: Into<Bytes>>(chunk_type: ChunkType, data: D, crc: u32) -> Self {
+        let data = data.into();
+        Self {
+            length: data.len() as u32,
+            chunk_type,
+            data,
+            crc,
+            offset: None,
         }
     }
 
@@ -34,11 +57,13 @@ impl Chunk {
     /// # Example
     ///
     /// ```
-    /// # fn main() -> Result<(), Box<dyn std::error::Error> {
+    /// # use pngme::png::Chunk;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
     /// let chunk = Chunk::from_strings("RuSt", "This is a secret message!")?;
     ///
     /// assert_eq!(&chunk.chunk_type().to_string(), "RuSt");
     /// assert_eq!(&chunk.data_as_string()?, "This is a secret message!");
+    /// # Ok(())
     /// # }
     /// ```
     pub fn from_strings(chunk_type: &str, data: &str) -> anyhow::Result<Self> {
@@ -50,6 +75,25 @@ impl Chunk {
         Ok(Chunk::new(chunk_type, data))
     }
 
+    /// Reads one chunk from `reader`, leaving it positioned just after the chunk's CRC.
+    /// Fails like `TryFrom<&[u8]>` if the chunk is invalid or the stream ends early.
+    pub fn from_reader<R: Read>(mut reader: R) -> anyhow::Result<Self> {
+        let mut fields = [0; 8];
+        reader
+            .read_exact(&mut fields)
+            .map_err(|_| anyhow::anyhow!("Stream ended before the chunk's length and type"))
+            .code("truncated")?;
+        let length = u32::from_be_bytes(fields[..4].try_into()?);
+        let type_bytes: [u8; 4] = fields[4..].try_into()?;
+        ChunkType::try_from(type_bytes).code("invalid_chunk_type")?;
+
+        // The length comes from untrusted input, so read through `take` rather than
+        // allocating that many bytes up front
+        let mut bytes = fields.to_vec();
+        reader.take(u64::from(length) + 4).read_to_end(&mut bytes)?;
+        Self::try_from(Bytes::from(bytes))
+    }
+
     /// The length of the data portion of this chunk.
     pub fn length(&self) -> u32 {
         self.length
@@ -65,6 +109,17 @@ impl Chunk {
         &self.data
     }
 
+    /// The data as shared `Bytes`, which can be kept or sliced without copying it
+    pub fn data_bytes(&self) -> Bytes {
+        self.data.clone()
+    }
+
+    /// Takes the data out of this chunk. It is only copied if it is shared with a clone
+    /// of this chunk or with other chunks parsed from the same bytes.
+    pub fn into_data(self) -> Vec<u8> {
+        self.data.into()
+    }
+
     /// The CRC of this chunk. If this chunk was created with `new` or `from_strings`,
     /// the CRC is computed based on the data. If this chunk was created with `TryFrom<&[u8]>`,
     /// the CRC was read from the bytes per the PNG spec and validated against the data
@@ -73,6 +128,46 @@ impl Chunk {
         self.crc
     }
 
+    /// Where this chunk started in the file it was read from, counting from the start of
+    /// the file. `None` for chunks that were created rather than read. Together with
+    /// `encoded_length`, this gives the bytes the chunk took up on disk.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// Records that this chunk was read from `offset` in a file
+    pub fn at_offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    /// Replaces the data of this chunk, recomputing its length and CRC
+    pub fn set_data<D: Into<Bytes>>(&mut self, data: D) {
+        self.data = data.into();
+        self.length = self.data.len() as u32;
+        self.crc = Self::calculate_crc(&self.chunk_type, &self.data);
+    }
+
+    /// Adds `data` to the end of this chunk's data, recomputing its length and CRC
+    pub fn append_data(&mut self, data: &[u8]) {
+        let mut appended = Vec::with_capacity(self.data.len() + data.len());
+        appended.extend_from_slice(&self.data);
+        appended.extend_from_slice(data);
+        self.set_data(appended);
+    }
+
+    /// Changes the type of this chunk, recomputing its CRC since it covers the type too
+    pub fn set_chunk_type(&mut self, chunk_type: ChunkType) {
+        self.chunk_type = chunk_type;
+        self.crc = Self::calculate_crc(&self.chunk_type, &self.data);
+    }
+
+    /// Compares the type and data of two chunks, but not their CRCs, so a chunk with a
+    /// damaged CRC equals the same chunk with the correct one
+    pub fn eq_ignoring_crc(&self, other: &Chunk) -> bool {
+        self.chunk_type == other.chunk_type && self.data == other.data
+    }
+
     /// Calculates a new CRC based on the data stored in this chunk. Returns true if the calculated
     /// CRC matches the stored CRC.
     pub fn is_crc_valid(&self) -> bool {
@@ -82,7 +177,7 @@ impl Chunk {
     /// Returns the data stored in this chunk as a `String`. This function will return an error
     /// if the stored data is not valid UTF-8.
     pub fn data_as_string(&self) -> anyhow::Result<String> {
-        Ok(String::from_utf8(self.data.clone())?)
+        Ok(String::from_utf8(self.data.to_vec())?)
     }
 
     /// Returns this chunk as a byte sequences described by the PNG spec.
@@ -92,26 +187,33 @@ impl Chunk {
     /// 3. The data itself *(`length` bytes)*
     /// 4. The CRC of the chunk type and data *(4 bytes)*
     pub fn as_bytes(&self) -> Vec<u8> {
-        self.length
-            .to_be_bytes()
-            .iter()
-            .copied()
-            .chain(self.chunk_type().bytes().iter().cloned())
-            .chain(self.data.iter().cloned())
-            .chain(self.crc.to_be_bytes().iter().cloned())
-            .collect()
+        let mut result = Vec::with_capacity(self.encoded_length());
+        self.write_bytes(&mut result);
+        result
+    }
+
+    /// Appends the byte sequence returned by `as_bytes` to `buffer` without
+    /// allocating an intermediate `Vec`.
+    pub fn write_bytes(&self, buffer: &mut Vec<u8>) {
+        buffer.reserve(self.encoded_length());
+        buffer.extend_from_slice(&self.length.to_be_bytes());
+        buffer.extend_from_slice(&self.chunk_type.bytes());
+        buffer.extend_from_slice(&self.data);
+        buffer.extend_from_slice(&self.crc.to_be_bytes());
+    }
+
+    /// The number of bytes in this chunk's byte sequence, including the length, chunk type
+    /// and CRC.
+    pub fn encoded_length(&self) -> usize {
+        12 + self.data.len()
     }
 
     /// Calculates the CRC of a `ChunkType` followed by some data
     pub fn calculate_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
-        let crc_data: Vec<u8> = chunk_type
-            .bytes()
-            .iter()
-            .copied()
-            .chain(data.iter().copied())
-            .collect();
-
-        crc::crc32::checksum_ieee(&crc_data)
+        let mut digest = crc32::Digest::new(crc32::IEEE);
+        digest.write(&chunk_type.bytes());
+        digest.write(data);
+        digest.sum32()
     }
 }
 
@@ -119,28 +221,42 @@ impl TryFrom<&[u8]> for Chunk {
     type Error = anyhow::Error;
 
     fn try_from(bytes: &[u8]) -> anyhow::Result<Self> {
+        Self::try_from(Bytes::copy_from_slice(bytes))
+    }
+}
+
+/// Reads a chunk from the start of `bytes`, keeping its data as a slice of them
+impl TryFrom<Bytes> for Chunk {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: Bytes) -> anyhow::Result<Self> {
         if bytes.len() < 8 {
             anyhow::bail!("Invalid chunk")
         }
 
-        let mut reader = BufReader::new(bytes);
-        let mut buffer: [u8; 4] = [0, 0, 0, 0];
-
-        reader.read_exact(&mut buffer)?;
-        let data_length = u32::from_be_bytes(buffer);
-
-        reader.read_exact(&mut buffer)?;
-        let chunk_type = ChunkType::try_from(buffer)?;
-
-        let mut data: Vec<u8> = vec![0; data_length as usize];
-        reader.read_exact(&mut data)?;
+        let data_length = u32::from_be_bytes(bytes[0..4].try_into()?);
+        let type_bytes: [u8; 4] = bytes[4..8].try_into()?;
+        let chunk_type = ChunkType::try_from(type_bytes).code("invalid_chunk_type")?;
+
+        // Check the length before using it, since it comes from untrusted input
+        let data_end = (data_length as usize).saturating_add(8);
+        if data_end.saturating_add(4) > bytes.len() {
+            return Err(anyhow::anyhow!(
+                "Chunk length {} is longer than the {} bytes of data left",
+                data_length,
+                bytes.len().saturating_sub(12)
+            ))
+            .code("truncated");
+        }
 
-        reader.read_exact(&mut buffer)?;
-        let crc = u32::from_be_bytes(buffer);
+        let data = bytes.slice(8..data_end);
+        let crc = u32::from_be_bytes(bytes[data_end..data_end + 4].try_into()?);
 
         let computed_crc = Chunk::calculate_crc(&chunk_type, &data);
         if crc != computed_crc {
-            anyhow::bail!("CRC check failed");
+            return Err(anyhow::anyhow!("CRC check failed"))
+                .code("crc_mismatch")
+                .in_chunk(&chunk_type.to_string());
         }
 
         Ok(Self {
@@ -148,10 +264,21 @@ impl TryFrom<&[u8]> for Chunk {
             chunk_type,
             data,
             crc,
+            offset: None,
         })
     }
 }
 
+/// Chunks are equal if they have the same type, data and CRC. Where they were read from
+/// doesn't matter.
+impl PartialEq for Chunk {
+    fn eq(&self, other: &Chunk) -> bool {
+        self.eq_ignoring_crc(other) && self.crc == other.crc
+    }
+}
+
+impl Eq for Chunk {}
+
 impl fmt::Display for Chunk {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         writeln!(f, "Chunk {{",)?;
@@ -167,6 +294,7 @@ impl fmt::Display for Chunk {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::ErrorReport;
     use std::str::FromStr;
 
     fn testing_chunk() -> Chunk {
@@ -221,6 +349,19 @@ mod tests {
         assert_eq!(chunk.crc(), 2882656334);
     }
 
+    #[test]
+    fn test_chunk_as_bytes() {
+        let chunk = testing_chunk();
+        let bytes = chunk.as_bytes();
+        assert_eq!(bytes.len(), chunk.encoded_length());
+        assert_eq!(Chunk::try_from(bytes.as_ref()).unwrap().crc(), chunk.crc());
+
+        let mut buffer = vec![1, 2, 3];
+        chunk.write_bytes(&mut buffer);
+        assert_eq!(&buffer[..3], &[1, 2, 3]);
+        assert_eq!(&buffer[3..], bytes.as_slice());
+    }
+
     #[test]
     fn test_valid_chunk_from_bytes() {
         let data_length: u32 = 42;
@@ -269,6 +410,106 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_into_data() {
+        let chunk = testing_chunk();
+        assert_eq!(
+            chunk.into_data(),
+            b"This is where your secret message will be!"
+        );
+    }
+
+    #[test]
+    fn test_set_data() {
+        let mut chunk = testing_chunk();
+        chunk.set_data(b"Edited".to_vec());
+        assert_eq!(chunk.length(), 6);
+        assert_eq!(chunk.data(), b"Edited");
+        assert!(chunk.is_crc_valid());
+    }
+
+    #[test]
+    fn test_append_data() {
+        let mut chunk = Chunk::from_strings("ruSt", "first").unwrap();
+        chunk.append_data(b" second");
+        assert_eq!(chunk, Chunk::from_strings("ruSt", "first second").unwrap());
+    }
+
+    #[test]
+    fn test_set_chunk_type() {
+        let mut chunk = testing_chunk();
+        chunk.set_chunk_type(ChunkType::new(*b"ruSt"));
+        assert_eq!(chunk.chunk_type().to_string(), "ruSt");
+        assert!(chunk.is_crc_valid());
+    }
+
+    #[test]
+    fn test_chunk_equality() {
+        let chunk = testing_chunk();
+        let parsed = Chunk::try_from(chunk.as_bytes().as_ref())
+            .unwrap()
+            .at_offset(8);
+        assert_eq!(parsed, chunk);
+
+        let damaged = Chunk::with_crc(*chunk.chunk_type(), chunk.data().to_vec(), 0);
+        assert_ne!(damaged, chunk);
+        assert!(damaged.eq_ignoring_crc(&chunk));
+        assert_ne!(Chunk::from_strings("RuSt", "Other").unwrap(), chunk);
+    }
+
+    #[test]
+    fn test_chunk_equality_ignores_offset() {
+        let chunk = testing_chunk();
+        assert_eq!(chunk.clone().at_offset(8), chunk.clone().at_offset(100));
+
+        let retyped = Chunk::new(ChunkType::from_str("ruSt").unwrap(), chunk.data().to_vec());
+        assert_ne!(retyped, chunk);
+        assert!(!retyped.eq_ignoring_crc(&chunk));
+    }
+
+    #[test]
+    fn test_chunk_from_reader() {
+        let first = testing_chunk();
+        let second = Chunk::from_strings("ruSt", "Second").unwrap();
+        let bytes = [first.as_bytes(), second.as_bytes()].concat();
+
+        let mut reader = std::io::Cursor::new(bytes);
+        assert_eq!(Chunk::from_reader(&mut reader).unwrap(), first);
+        assert_eq!(Chunk::from_reader(&mut reader).unwrap(), second);
+
+        let error = Chunk::from_reader(&mut reader).unwrap_err();
+        assert_eq!(ErrorReport::new(&error).code, "truncated");
+    }
+
+    #[test]
+    fn test_chunk_from_reader_truncated() {
+        let bytes = testing_chunk().as_bytes();
+        let error = Chunk::from_reader(&bytes[..bytes.len() - 2]).unwrap_err();
+        assert_eq!(ErrorReport::new(&error).code, "truncated");
+    }
+
+    #[test]
+    fn test_chunk_data_is_shared() {
+        let chunk = Chunk::from_strings("RuSt", "This is a secret message!").unwrap();
+        assert_eq!(chunk.clone().data().as_ptr(), chunk.data().as_ptr());
+
+        let bytes = Bytes::from(chunk.as_bytes());
+        let parsed = Chunk::try_from(bytes.clone()).unwrap();
+        assert_eq!(parsed.data().as_ptr(), bytes[8..].as_ptr());
+        assert_eq!(parsed.data_bytes().slice(..4), "This");
+    }
+
+    #[test]
+    fn test_chunk_length_past_end() {
+        let mut chunk_data = u32::MAX.to_be_bytes().to_vec();
+        chunk_data.extend_from_slice(b"RuSt");
+        chunk_data.extend_from_slice(&[0; 6]);
+
+        let chunk = Chunk::try_from(chunk_data.as_ref());
+
+        assert!(chunk.is_err());
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
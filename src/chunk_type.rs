@@ -2,17 +2,51 @@ use std::convert::TryFrom;
 use std::fmt;
 use std::str::FromStr;
 
+use crate::crypto::KdfParams;
+use crate::envelope::BOOKKEEPING_CHUNK_TYPES;
+
+/// Chunk types defined by the PNG spec and its registered extensions
+pub const REGISTERED_CHUNK_TYPES: [&str; 34] = [
+    "IHDR", "PLTE", "IDAT", "IEND", "acTL", "bKGD", "cHRM", "cICP", "cLLi", "dSIG", "eXIf", "fcTL",
+    "fdAT", "fRAc", "gAMA", "gIFg", "gIFt", "gIFx", "hIST", "iCCP", "iTXt", "mDCv", "oFFs", "pCAL",
+    "pHYs", "sBIT", "sCAL", "sPLT", "sRGB", "sTER", "tEXt", "tIME", "tRNS", "zTXt",
+];
+
+/// The Argon2id salt for `from_passphrase`. The type has to be found again from the
+/// passphrase alone, so the salt can't be random, but it keeps these keys apart from any
+/// other use of Argon2 on the same passphrase.
+const PASSPHRASE_SALT: &[u8] = b"pngme chunk type";
+
+/// How far a chunk type name can be from one that exists and still be suggested for it.
+/// See `edit_distance`.
+const MAX_SUGGESTION_DISTANCE: usize = 4;
+
 /// A validated PNG chunk type. See the PNG spec for more details.
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct ChunkType {
     bytes: [u8; 4],
 }
 
 impl ChunkType {
+    /// Creates a chunk type from bytes known ahead of time, so that chunk types can be
+    /// defined as constants. Panics if any byte is not an ASCII letter, which fails the
+    /// build when used in a const context. Use `TryFrom` for bytes from elsewhere.
+    pub const fn new(bytes: [u8; 4]) -> Self {
+        let mut i = 0;
+        while i < bytes.len() {
+            if !bytes[i].is_ascii_alphabetic() {
+                panic!("Chunk type bytes must be ASCII letters");
+            }
+            i += 1;
+        }
+
+        Self { bytes }
+    }
+
     /// Returns the raw bytes contained in this chunk
     pub fn bytes(&self) -> [u8; 4] {
-        self.bytes.clone()
+        self.bytes
     }
 
     /// Returns the property state of the first byte as described in the PNG spec
@@ -35,6 +69,13 @@ impl ChunkType {
         self.bytes[3].is_ascii_lowercase()
     }
 
+    /// Returns true if this chunk type is defined by the PNG spec or a registered extension
+    pub fn is_registered(&self) -> bool {
+        REGISTERED_CHUNK_TYPES
+            .iter()
+            .any(|registered| registered.as_bytes() == self.bytes)
+    }
+
     /// Returns true if the reserved byte is valid and all four bytes are represented
     /// by the characters A-Z or a-z.
     /// Note that this chunk type should always be valid as it is validated during construction.
@@ -50,8 +91,8 @@ impl ChunkType {
     /// Valid bytes are represented by the characters A-Z or a-z
     #[rustfmt::skip]
     pub fn is_valid_byte(byte: u8) -> bool {
-        (byte >= 65 && byte <= 90) ||
-        (byte >= 97 && byte <= 122)
+        (65..=90).contains(&byte) ||
+        (97..=122).contains(&byte)
     }
 }
 
@@ -96,6 +137,113 @@ impl FromStr for ChunkType {
     }
 }
 
+/// Chunk types that hold the image itself. A file missing any of them, or with extra
+/// ones holding something else, is one that nothing can open.
+pub const STRUCTURAL_CHUNK_TYPES: [&str; 4] = ["IHDR", "PLTE", "IDAT", "IEND"];
+
+/// Refuses the chunk types that hold the image itself, saying that `action`, like
+/// `removing it`, would break the file
+pub fn check_structural_chunk_type(chunk_type: &ChunkType, action: &str) -> anyhow::Result<()> {
+    if STRUCTURAL_CHUNK_TYPES.contains(&chunk_type.to_string().as_str()) {
+        anyhow::bail!(
+            "{} holds part of the image itself, and {} leaves a file that nothing can open. \
+             Pass --force to do it anyway",
+            chunk_type,
+            action
+        );
+    }
+    Ok(())
+}
+
+/// Refuses chunk types that viewers will try to interpret, with an explanation
+pub fn check_message_chunk_type(chunk_type: &ChunkType) -> anyhow::Result<()> {
+    check_structural_chunk_type(chunk_type, "storing a message in it")?;
+    if chunk_type.is_critical() {
+        anyhow::bail!(
+            "{} is a critical chunk type. Viewers must understand every critical chunk, so \
+             a message stored in one will make most of them reject the image. Use a type \
+             starting with a lowercase letter, like ruSt, or pass --force to do it anyway",
+            chunk_type
+        );
+    }
+
+    if chunk_type.is_registered() {
+        anyhow::bail!(
+            "{} is a standard chunk type. Viewers will try to read the message as {} data \
+             and may misbehave or discard it. Use a private type with a lowercase second \
+             letter, like ruSt, or pass --force to do it anyway",
+            chunk_type,
+            chunk_type
+        );
+    }
+
+    Ok(())
+}
+
+/// Derives a chunk type from a passphrase, so that a message can be stored under a type
+/// only the people who know the passphrase can find. The type is ancillary, private and
+/// safe to copy, like ruSt, and never one of the types pngme keeps its own records in.
+///
+/// The type narrows down which passphrases could have made it, so it is derived with
+/// Argon2id rather than a plain hash. Checking a guess against it then costs as much as
+/// trying the guess on the encrypted message.
+pub fn from_passphrase(passphrase: &str) -> anyhow::Result<ChunkType> {
+    let key = KdfParams::default().derive_key(passphrase.as_bytes(), PASSPHRASE_SALT)?;
+    let letter = |byte: u8, base: u8| base + byte % 26;
+    // Each four bytes of the key make a candidate, in case one is a type pngme uses
+    key.chunks(4)
+        .map(|bytes| {
+            ChunkType::new([
+                letter(bytes[0], b'a'),
+                letter(bytes[1], b'a'),
+                letter(bytes[2], b'A'),
+                letter(bytes[3], b'a'),
+            ])
+        })
+        .find(|chunk_type| !BOOKKEEPING_CHUNK_TYPES.contains(&chunk_type.to_string().as_str()))
+        .ok_or_else(|| anyhow::anyhow!("This passphrase can't be used for a secret chunk type"))
+}
+
+/// Returns the chunk type in `candidates` that `name` was most likely meant to be, for
+/// "did you mean" hints when no chunk of type `name` exists
+pub fn closest<'a, I>(name: &str, candidates: I) -> Option<&'a ChunkType>
+where
+    I: IntoIterator<Item = &'a ChunkType>,
+{
+    candidates
+        .into_iter()
+        .map(|candidate| (edit_distance(name, &candidate.to_string()), candidate))
+        .filter(|&(distance, _)| distance > 0 && distance <= MAX_SUGGESTION_DISTANCE)
+        .min_by_key(|&(distance, _)| distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// The Levenshtein distance between two names, with every edit costing 2 except changing
+/// the case of a letter, which costs 1. Chunk type names are case sensitive, and getting
+/// the case wrong is by far the most common mistake.
+fn edit_distance(first: &str, second: &str) -> usize {
+    let second: Vec<char> = second.chars().collect();
+    let mut previous: Vec<usize> = (0..=second.len()).map(|j| 2 * j).collect();
+    for (i, a) in first.chars().enumerate() {
+        let mut current = vec![2 * (i + 1)];
+        for (j, &b) in second.iter().enumerate() {
+            let substitution = if a == b {
+                0
+            } else if a.eq_ignore_ascii_case(&b) {
+                1
+            } else {
+                2
+            };
+            let cost = (previous[j] + substitution)
+                .min(previous[j + 1] + 2)
+                .min(current[j] + 2);
+            current.push(cost);
+        }
+        previous = current;
+    }
+    previous[second.len()]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -163,6 +311,39 @@ mod tests {
         assert!(!chunk.is_safe_to_copy());
     }
 
+    #[test]
+    pub fn test_chunk_type_const_new() {
+        const RUST: ChunkType = ChunkType::new(*b"RuSt");
+        assert_eq!(RUST, ChunkType::from_str("RuSt").unwrap());
+    }
+
+    #[test]
+    #[should_panic]
+    pub fn test_chunk_type_const_new_invalid() {
+        ChunkType::new(*b"Ru1t");
+    }
+
+    #[test]
+    pub fn test_chunk_type_ord_and_hash() {
+        let mut chunk_types: Vec<ChunkType> = ["tEXt", "IDAT", "IHDR"]
+            .iter()
+            .map(|s| ChunkType::from_str(s).unwrap())
+            .collect();
+        chunk_types.sort();
+        assert_eq!(chunk_types[0].to_string(), "IDAT");
+
+        let set: std::collections::HashSet<ChunkType> = chunk_types.iter().copied().collect();
+        assert!(set.contains(&ChunkType::new(*b"IHDR")));
+    }
+
+    #[test]
+    pub fn test_chunk_type_is_registered() {
+        assert!(ChunkType::from_str("IDAT").unwrap().is_registered());
+        assert!(ChunkType::from_str("tEXt").unwrap().is_registered());
+        assert!(!ChunkType::from_str("ruSt").unwrap().is_registered());
+        assert!(!ChunkType::from_str("TEXT").unwrap().is_registered());
+    }
+
     #[test]
     pub fn test_valid_chunk_is_valid() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
@@ -201,4 +382,50 @@ mod tests {
         let _chunk_string = format!("{}", chunk_type_1);
         let _are_chunks_equal = chunk_type_1 == chunk_type_2;
     }
+
+    #[test]
+    pub fn test_from_passphrase() {
+        let chunk_type = from_passphrase("correct horse battery staple").unwrap();
+        assert_eq!(
+            chunk_type,
+            from_passphrase("correct horse battery staple").unwrap()
+        );
+        assert_ne!(
+            chunk_type,
+            from_passphrase("correct horse battery stapler").unwrap()
+        );
+        assert!(chunk_type.is_valid());
+        assert!(!chunk_type.is_critical());
+        assert!(!chunk_type.is_public());
+        assert!(chunk_type.is_safe_to_copy());
+        assert!(check_message_chunk_type(&chunk_type).is_ok());
+    }
+
+    #[test]
+    pub fn test_check_structural_chunk_type() {
+        let check = |name| check_structural_chunk_type(&ChunkType::from_str(name).unwrap(), "it");
+        assert!(check("IHDR").is_err());
+        assert!(check("IDAT").is_err());
+        assert!(check("tEXt").is_ok());
+        assert!(check("ruSt").is_ok());
+
+        let error = check_message_chunk_type(&ChunkType::from_str("IEND").unwrap()).unwrap_err();
+        assert!(error.to_string().contains("holds part of the image itself"));
+    }
+
+    #[test]
+    pub fn test_closest() {
+        let present: Vec<ChunkType> = ["IHDR", "ruSt", "tEXt", "IEND"]
+            .iter()
+            .map(|name| ChunkType::from_str(name).unwrap())
+            .collect();
+        let closest = |name| closest(name, &present).map(ChunkType::to_string);
+
+        assert_eq!(closest("rust").as_deref(), Some("ruSt"));
+        assert_eq!(closest("RUST").as_deref(), Some("ruSt"));
+        assert_eq!(closest("ruSx").as_deref(), Some("ruSt"));
+        assert_eq!(closest("text").as_deref(), Some("tEXt"));
+        assert_eq!(closest("ruSt"), None);
+        assert_eq!(closest("abcd"), None);
+    }
 }
@@ -0,0 +1,121 @@
+#[cfg(feature = "clipboard")]
+use std::fs;
+#[cfg(feature = "clipboard")]
+use std::io::{self, Write};
+use std::path::Path;
+#[cfg(not(feature = "clipboard"))]
+use std::path::PathBuf;
+
+#[cfg(feature = "clipboard")]
+use arboard::Clipboard;
+
+#[cfg(feature = "clipboard")]
+use pngme::error::Locate;
+#[cfg(feature = "clipboard")]
+use pngme::generate::{self, Size};
+
+/// Given as the file, stands for the image on the clipboard
+pub const PATH: &str = "clipboard:";
+
+/// The copy of the clipboard's image made by `image_file`, which is deleted when dropped
+#[cfg(feature = "clipboard")]
+pub type ImageFile = tempfile::TempPath;
+#[cfg(not(feature = "clipboard"))]
+pub type ImageFile = PathBuf;
+
+/// Returns true if `path` stands for the image on the clipboard
+pub fn is_clipboard(path: &Path) -> bool {
+    path == Path::new(PATH)
+}
+
+/// Saves the image on the clipboard to a new file in the temporary directory that only
+/// the current user can read. A copied image file is copied as it is, while pixels, like
+/// a screenshot, are saved as a PNG file. Either way, edits don't touch anything but the
+/// copy, which is deleted when the returned path is dropped.
+#[cfg(feature = "clipboard")]
+pub fn image_file() -> anyhow::Result<ImageFile> {
+    let mut clipboard = open()?;
+    let copied = clipboard
+        .get()
+        .file_list()
+        .ok()
+        .and_then(|files| files.into_iter().find(|file| file.is_file()));
+
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("pngme-clipboard-");
+    if let Some(file) = copied {
+        let extension = file.extension().and_then(|extension| extension.to_str());
+        let suffix = extension.map(|extension| format!(".{}", extension));
+        if let Some(suffix) = &suffix {
+            builder.suffix(suffix);
+        }
+        let mut temp = builder.tempfile()?;
+        // Not fs::copy, which would give the copy the original file's permissions
+        io::copy(
+            &mut fs::File::open(&file).in_file(&file)?,
+            temp.as_file_mut(),
+        )?;
+        return Ok(temp.into_temp_path());
+    }
+
+    let image = clipboard
+        .get_image()
+        .map_err(|error| anyhow::anyhow!("The clipboard doesn't hold an image: {}", error))?;
+    let size = Size {
+        width: image.width as u32,
+        height: image.height as u32,
+    };
+    let png = generate::from_rgba(size, &image.bytes)?;
+    let mut temp = builder.suffix(".png").tempfile()?;
+    temp.as_file_mut().write_all(&png.as_bytes())?;
+    Ok(temp.into_temp_path())
+}
+
+/// Puts `file` on the clipboard as a copied file. Copying the image itself would only
+/// keep its pixels, losing every chunk along with the message.
+#[cfg(feature = "clipboard")]
+pub fn copy_file(file: &Path) -> anyhow::Result<()> {
+    let file = file.canonicalize()?;
+    let mut clipboard = open()?;
+    let set = clipboard.set();
+
+    // Only a running process can hand the clipboard to other programs on X11 and
+    // Wayland, so stay until something else is copied
+    #[cfg(all(
+        unix,
+        not(any(target_os = "macos", target_os = "android", target_os = "emscripten"))
+    ))]
+    let set = {
+        use arboard::SetExtLinux;
+        outln!(
+            "Keeping {:?} on the clipboard until something else is copied",
+            file
+        );
+        set.wait()
+    };
+
+    set.file_list(&[file])
+        .map_err(|error| anyhow::anyhow!("Couldn't copy to the clipboard: {}", error))
+}
+
+#[cfg(feature = "clipboard")]
+fn open() -> anyhow::Result<Clipboard> {
+    Clipboard::new().map_err(|error| anyhow::anyhow!("Couldn't open the clipboard: {}", error))
+}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn image_file() -> anyhow::Result<ImageFile> {
+    Err(not_built())
+}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn copy_file(_file: &Path) -> anyhow::Result<()> {
+    Err(not_built())
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn not_built() -> anyhow::Error {
+    anyhow::anyhow!(
+        "This pngme was built without clipboard support. Rebuild it with --features clipboard"
+    )
+}
@@ -0,0 +1,165 @@
+use crate::png::{Chunk, ChunkType, Png};
+
+/// The chunk types `clone_meta` copies, in groups of types that describe the same thing.
+/// Chunks that only make sense for particular pixel data, like PLTE, tRNS, bKGD and
+/// sBIT, aren't copied since the target image may use a different color type.
+const METADATA_GROUPS: [&[&str]; 4] = [
+    // Text
+    &["tEXt", "zTXt", "iTXt"],
+    // Time of last modification
+    &["tIME"],
+    // Physical size
+    &["pHYs", "sCAL"],
+    // Color space
+    &["gAMA", "cHRM", "sRGB", "iCCP", "cICP", "mDCv", "cLLi"],
+];
+
+/// What `clone_meta` copied
+#[derive(Debug, Default)]
+pub struct CloneReport {
+    pub copied: Vec<ChunkType>,
+    /// Unsafe-to-copy chunks left out because the images' critical chunks differ
+    pub skipped: Vec<ChunkType>,
+}
+
+/// Returns a copy of `to` with the text, time, physical size and color space chunks of
+/// `from`. For each group of metadata that `from` has, the chunks of that group already
+/// in `to` are replaced rather than added to, so that `to` never ends up with both an
+/// sRGB and an iCCP chunk, for example.
+///
+/// The PNG spec only allows chunks marked unsafe to copy to be copied into an image
+/// with the same critical chunks, since they may depend on them. Unless `force` is set,
+/// those chunks are skipped when the critical chunks differ.
+pub fn clone_meta(from: &Png, to: &Png, force: bool) -> anyhow::Result<(Png, CloneReport)> {
+    let group_of = |chunk: &Chunk| {
+        let name = chunk.chunk_type().to_string();
+        METADATA_GROUPS
+            .iter()
+            .position(|group| group.contains(&name.as_str()))
+    };
+    let same_image = critical_chunks(from).eq(critical_chunks(to));
+
+    let mut report = CloneReport::default();
+    let mut copied: Vec<&Chunk> = Vec::new();
+    for chunk in from
+        .chunks()
+        .iter()
+        .filter(|chunk| group_of(chunk).is_some())
+    {
+        if chunk.chunk_type().is_safe_to_copy() || same_image || force {
+            report.copied.push(*chunk.chunk_type());
+            copied.push(chunk);
+        } else {
+            report.skipped.push(*chunk.chunk_type());
+        }
+    }
+    let replaced: Vec<usize> = copied.iter().filter_map(|chunk| group_of(chunk)).collect();
+
+    let mut png = to.clone();
+    png.retain_chunks(|chunk| group_of(chunk).is_none_or(|group| !replaced.contains(&group)));
+
+    // Color space and physical size chunks have to come before PLTE and IDAT, and text
+    // can go anywhere, so everything goes just before the first of them
+    let anchor = ["PLTE", "IDAT"]
+        .iter()
+        .find(|anchor| png.chunk_by_type(anchor).is_some());
+    for chunk in copied {
+        match anchor {
+            Some(anchor) => png.insert_chunk_before(anchor, chunk.clone())?,
+            None => png.insert_chunk(chunk.clone()),
+        }
+    }
+
+    Ok((png, report))
+}
+
+/// The type and data of each critical chunk, in order
+fn critical_chunks(png: &Png) -> impl Iterator<Item = (&ChunkType, &[u8])> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().is_critical())
+        .map(|chunk| (chunk.chunk_type(), chunk.data()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_clone_meta() {
+        let from = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("sRGB", "\0").unwrap(),
+            Chunk::from_strings("tEXt", "Author\0Someone").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("tIME", "1234567").unwrap(),
+            Chunk::from_strings("ruSt", "hidden").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ]);
+        let to = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "resized").unwrap(),
+            Chunk::from_strings("gAMA", "gama").unwrap(),
+            Chunk::from_strings("pHYs", "physical").unwrap(),
+            Chunk::from_strings("IDAT", "smaller pixels").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ]);
+
+        let (png, report) = clone_meta(&from, &to, true).unwrap();
+        let copied: Vec<String> = report.copied.iter().map(ChunkType::to_string).collect();
+        assert_eq!(copied, vec!["sRGB", "tEXt", "tIME"]);
+        // gAMA is replaced by the sRGB chunk, while pHYs has nothing to replace it
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "pHYs", "sRGB", "tEXt", "tIME", "IDAT", "IEND"]
+        );
+        assert_eq!(png.chunk_by_type("IDAT").unwrap().data(), b"smaller pixels");
+    }
+
+    #[test]
+    fn test_clone_nothing() {
+        let from = Png::from_chunks(vec![Chunk::from_strings("IEND", "").unwrap()]);
+        let to = Png::from_chunks(vec![
+            Chunk::from_strings("tEXt", "Title\0Kept").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ]);
+
+        let (png, report) = clone_meta(&from, &to, false).unwrap();
+        assert!(report.copied.is_empty());
+        assert_eq!(png.as_bytes(), to.as_bytes());
+    }
+
+    #[test]
+    fn test_skip_unsafe_to_copy() {
+        let from = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("gAMA", "gama").unwrap(),
+            Chunk::from_strings("pHYs", "physical").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ]);
+        let resized = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "resized").unwrap(),
+            Chunk::from_strings("IDAT", "smaller pixels").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ]);
+
+        let (png, report) = clone_meta(&from, &resized, false).unwrap();
+        assert_eq!(report.copied, vec![ChunkType::new(*b"pHYs")]);
+        assert_eq!(report.skipped, vec![ChunkType::new(*b"gAMA")]);
+        assert!(png.chunk_by_type("gAMA").is_none());
+
+        // Both are copied into an image with the same critical chunks
+        let mut stripped = from.clone();
+        stripped.retain_chunks(|chunk| chunk.chunk_type().is_critical());
+        let (png, report) = clone_meta(&from, &stripped, false).unwrap();
+        assert!(report.skipped.is_empty());
+        assert_eq!(png.as_bytes(), from.as_bytes());
+    }
+}
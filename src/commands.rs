@@ -1,61 +1,3160 @@
 use std::convert::TryFrom;
-use std::fs;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, IsTerminal, Read, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::process;
 use std::str::FromStr;
+use std::time::{Duration, UNIX_EPOCH};
 
-use crate::args::{DecodeArgs, EncodeArgs, PrintArgs, RemoveArgs};
-use crate::png::{Chunk, ChunkType, Png};
+use base64::Engine;
+use bytes::Bytes;
 
-/// Encodes a message into a PNG file and saves the result
-pub fn encode(args: EncodeArgs) -> anyhow::Result<()> {
-    let mut png = Png::from_file(&args.file)?;
+#[cfg(feature = "gui")]
+use crate::args::GuiArgs;
+use crate::args::{
+    AnalyzeBitplanesArgs, AnonymizeArgs, AppendArgs, ApplyArgs, CanonicalizeArgs, CheckArgs,
+    CloneMetaArgs, ColorChoice, CommentArgs, DecodeArgs, DedupeArgs, EditArgs, EncodeArgs,
+    ExifArgs, ExplainArgs, ExplodeArgs, FixArgs, GenerateArgs, GrepArgs, HistoryArgs, IccArgs,
+    IccCommand, IccExportArgs, IccImportArgs, ImplodeArgs, KeygenArgs, LintArgs, ListMessagesArgs,
+    MetaArgs, MetaCommand, MetaListArgs, MigrateArgs, Mode, OptimizeArgs, PixelhashArgs,
+    PolyglotArgs, PrintArgs, RemoveArgs, RepairArgs, ReportArgs, ScanArgs, SealArgs,
+    SetStandardArgs, SignArgs, StatsArgs, StringsArgs, UndoArgs, VerifyArgs, VerifySealArgs,
+    XmpExtractArgs, XmpGetArgs, XmpInjectArgs, SECRET_CHUNK_TYPE,
+};
+use crate::clipboard;
+use crate::printer;
+use pngme::archive;
+use pngme::atomic;
+use pngme::audit;
+use pngme::batch;
+use pngme::bitplanes::Planes;
+use pngme::camouflage;
+use pngme::chunk_type::{self, check_message_chunk_type, check_structural_chunk_type};
+use pngme::config::{ByteSize, Config};
+use pngme::conformance;
+use pngme::container::{self, Container, Format};
+use pngme::crypto::{self, KdfParams, Secret};
+use pngme::data_url;
+use pngme::deniable;
+use pngme::diagnostics::{self, Severity};
+use pngme::envelope;
+use pngme::error::Locate;
+use pngme::explain;
+use pngme::explode::{self, MANIFEST_FILE};
+use pngme::generate::{self};
+use pngme::gif::Gif;
+use pngme::hidden;
+use pngme::history;
+use pngme::icc::IccProfile;
+use pngme::jpeg::Jpeg;
+use pngme::keys;
+use pngme::layout;
+use pngme::lint::{self, RuleSet};
+use pngme::memory::{self, MemoryLimit};
+use pngme::messages::MessageStore;
+use pngme::multipart::{Manifest, MANIFEST_CHUNK_TYPE};
+use pngme::obfuscate;
+use pngme::optimize::OptimizeOptions;
+use pngme::output::Record;
+use pngme::pixels;
+use pngme::plan::Plan;
+use pngme::png::{Chunk, ChunkType, Normalization, Png};
+use pngme::polyglot;
+use pngme::recipients::{self, KeyPair};
+use pngme::report::{self, ChunkUsage};
+use pngme::scan::{self, ByteStatistics};
+use pngme::seal::{Seal, SEAL_CHUNK_TYPE};
+use pngme::search::{self, Pattern};
+use pngme::signature::{self, EmbeddedSignature};
+use pngme::stats::{self, FileStats};
+use pngme::text::{TextChunk, STANDARD_KEYWORDS};
+use pngme::tiff::Tiff;
+use pngme::trailer;
+use pngme::xmp;
+use pngme::zip_path::ZipPath;
 
+/// Encodes a message into an image file and saves the result
+pub fn encode(mut args: EncodeArgs) -> anyhow::Result<()> {
+    // The clipboard's image is edited as a copy, which then goes back on the clipboard
+    let from_clipboard = clipboard::is_clipboard(&args.file);
+    let _clipboard_file = match from_clipboard {
+        true => {
+            let file = clipboard::image_file()?;
+            args.file = file.to_path_buf();
+            Some(file)
+        }
+        false => None,
+    };
+    let config = Config::load()?;
+    let mut chunk = args.resolve_chunk(config.default_chunk_type())?;
+    let to_clipboard =
+        args.clipboard || (from_clipboard && args.out.is_none() && !args.emit_data_url);
+
+    if args.fast_append
+        && (args.sign_key.is_some()
+            || args.history
+            || args.audit
+            || args.create
+            || args.verify_pixels
+            || args.emit_data_url
+            || args.name.is_some())
+    {
+        anyhow::bail!(
+            "--fast-append only works in place and without --sign-key, --history, --audit, \
+             --create, --verify-pixels, --emit-data-url or --name"
+        );
+    }
+
+    if is_data_url(&args.file) && args.out.is_none() && !args.emit_data_url {
+        anyhow::bail!("An image given as a data: URL needs --out or --emit-data-url");
+    }
+
+    if args.split.is_some() && (args.fast_append || args.name.is_some()) {
+        anyhow::bail!("--split can't be combined with --fast-append or --name");
+    }
+
+    if args.camouflage && (args.fast_append || args.name.is_some() || args.split.is_some()) {
+        anyhow::bail!("--camouflage can't be combined with --fast-append, --name or --split");
+    }
+
+    let to_trailer = args.mode == Mode::Trailer;
+    if to_trailer
+        && (args.fast_append
+            || args.name.is_some()
+            || args.split.is_some()
+            || args.camouflage
+            || args.sign_key.is_some())
+    {
+        anyhow::bail!(
+            "--mode trailer can't be combined with --fast-append, --name, --split, \
+             --camouflage or --sign-key"
+        );
+    }
+
+    // The passphrase picks the chunk type, so it's asked for before anything else, and
+    // encrypts the message too
+    let mut secret_passphrase = None;
+    if chunk == SECRET_CHUNK_TYPE {
+        if args.camouflage
+            || args.deniable
+            || args.decoy.is_some()
+            || args.key_file.is_some()
+            || !args.age_recipient.is_empty()
+            || !args.recipient.is_empty()
+            || args.obfuscate.is_some()
+        {
+            anyhow::bail!(
+                "A secret chunk type is encrypted with its passphrase, so it can't be combined \
+                 with --camouflage, --deniable, --decoy, --key-file, --age-recipient, \
+                 --recipient or --obfuscate"
+            );
+        }
+        let passphrase = prompt_new_passphrase("Passphrase")?;
+        chunk = chunk_type::from_passphrase(&passphrase)?.to_string();
+        args.encrypt = true;
+        secret_passphrase = Some(passphrase);
+    }
+
+    let chunk_type = ChunkType::from_str(&chunk)?;
+    // Camouflaged messages go in standard text chunks on purpose
+    if !args.force && !args.camouflage {
+        check_message_chunk_type(&chunk_type)?;
+    }
+    let max_growth = match args.max_growth {
+        Some(budget) => Some(budget),
+        None => config.encode.max_growth,
+    };
+    let mut data = match (&args.message, &args.input_dir, &args.hex, &args.base64) {
+        (Some(message), None, None, None) => message.as_bytes().to_vec(),
+        (None, Some(directory), None, None) => archive::pack(directory, args.compress)?,
+        // Whitespace is allowed so that data can be pasted from a hex dump or a spec
+        (None, None, Some(hex), None) => hex::decode(without_whitespace(hex))
+            .map_err(|error| anyhow::anyhow!("Invalid --hex data: {}", error))?,
+        (None, None, None, Some(text)) => base64::engine::general_purpose::STANDARD
+            .decode(without_whitespace(text))
+            .map_err(|error| anyhow::anyhow!("Invalid --base64 data: {}", error))?,
+        (None, None, None, None) => anyhow::bail!(
+            "Pass a message, a directory with --input-dir, or data with --hex or --base64"
+        ),
+        _ => anyhow::bail!("Pass only one of a message, --input-dir, --hex or --base64"),
+    };
+
+    let deniable = args.deniable || args.decoy.is_some();
+    let methods = [
+        args.encrypt,
+        deniable,
+        args.key_file.is_some(),
+        !args.age_recipient.is_empty(),
+        !args.recipient.is_empty(),
+        args.obfuscate.is_some(),
+    ];
+    if methods.iter().filter(|&&enabled| enabled).count() > 1 {
+        anyhow::bail!(
+            "Choose only one of --encrypt, --deniable, --key-file, --age-recipient, --recipient \
+             or --obfuscate"
+        );
+    }
+
+    if let Some(key) = &args.obfuscate {
+        data = obfuscate::obfuscate(&data, key)?;
+    }
+
+    if !args.age_recipient.is_empty() {
+        data = crypto::encrypt_to_age_recipients(&data, &args.age_recipient)?;
+    }
+
+    if !args.recipient.is_empty() {
+        let public_keys = args
+            .recipient
+            .iter()
+            .map(keys::read_key_file)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        data = recipients::encrypt(&data, &public_keys)?;
+    }
+
+    let params = KdfParams {
+        memory_kib: args.kdf_memory,
+        iterations: args.kdf_iterations,
+        ..KdfParams::default()
+    };
+    let secret = match (&args.key_file, secret_passphrase) {
+        (Some(key_file), _) => Some(Secret::Key(keys::read_key_file(key_file)?)),
+        (None, Some(passphrase)) => Some(Secret::Passphrase(passphrase)),
+        (None, None) if args.encrypt => {
+            Some(Secret::Passphrase(prompt_new_passphrase("Passphrase")?))
+        }
+        (None, None) => None,
+    };
+
+    if let Some(secret) = secret {
+        data = crypto::encrypt(&data, &secret, params)?;
+    }
+
+    if deniable {
+        let passphrase = prompt_new_passphrase("Passphrase")?;
+        let decoy = match &args.decoy {
+            Some(decoy) => Some((decoy.as_bytes(), prompt_new_passphrase("Decoy passphrase")?)),
+            None => None,
+        };
+        let decoy = decoy
+            .as_ref()
+            .map(|(decoy, passphrase)| (*decoy, passphrase.as_str()));
+        data = deniable::encrypt(&data, &passphrase, decoy, params)?;
+    }
+
+    if args.fast_append {
+        if ZipPath::parse(&args.file).is_some() {
+            anyhow::bail!("--fast-append doesn't work on files inside ZIP archives");
+        }
+        if Format::detect(&file_header(&args.file)?) != Some(Format::Png) {
+            anyhow::bail!("--fast-append only works with PNG files");
+        }
+        let chunk = Chunk::new(chunk_type, envelope::frame(&chunk_type, &data));
+        warn_if_nonconforming(&chunk);
+        let length = fs::metadata(&args.file)?.len();
+        check_growth(length, length + chunk.encoded_length() as u64, max_growth)?;
+        if args.backup {
+            backup(&args.file)?;
+        }
+        Png::append_chunk_to_file(&args.file, &chunk)?;
+        outln!("Wrote message to: {:?}", &args.file);
+        if to_clipboard {
+            clipboard::copy_file(&args.file)?;
+        }
+        return Ok(());
+    }
+
+    let created = args.create && !args.file.exists();
+    let to_convert = match args.convert && !created {
+        true => Some(container::read_file(&args.file)?)
+            .filter(|bytes| Format::detect(bytes) != Some(Format::Png)),
+        false => None,
+    };
+    // A converted image is a new file, so it is measured and saved as one
+    let converted = to_convert.is_some();
+    let original = match (created, to_convert) {
+        (true, _) => {
+            outln!("Creating {:?} as a 1x1 transparent PNG", &args.file);
+            Container::Png(Png::new_minimal())
+        }
+        (false, Some(bytes)) => {
+            let png = convert_to_png(&bytes)?;
+            outln!("Converted {:?} to PNG", &args.file);
+            Container::Png(png)
+        }
+        (false, None) => read_container(&args.file, args.preserve)?,
+    };
+    let mut container = original.clone();
+
+    if let Some(name) = &args.name {
+        let existing = container.message(&chunk)?;
+        let existing = existing.as_deref().map(envelope::unwrap).transpose()?;
+        data = add_named_message(existing, &chunk_type, name, data)?;
+        container.remove_messages(&chunk_type)?;
+    }
+
+    // Camouflaged messages have to pass for ordinary text
+    if !args.camouflage {
+        data = envelope::frame(&chunk_type, &data);
+        if args.split.is_none() && !to_trailer {
+            warn_if_nonconforming(&Chunk::new(chunk_type, data.clone()));
+        }
+    }
+
+    // A split message is signed through its manifest, which holds the digest of every part
+    let signed_type = match args.split {
+        None if to_trailer => {
+            match &mut container {
+                Container::Png(png) => append_to_trailer(png, &chunk_type, &data)?,
+                _ => anyhow::bail!(
+                    "--mode trailer only works with PNG files, not {}",
+                    container.format()
+                ),
+            }
+            chunk_type
+        }
+        None if args.camouflage => {
+            let keyword = args
+                .keyword
+                .as_deref()
+                .unwrap_or(camouflage::DEFAULT_KEYWORD);
+            let chunk = camouflage::hide(&chunk_type, keyword, &data)?;
+            match &mut container {
+                // Text after IEND would give it away
+                Container::Png(png) => png.insert_chunk(chunk),
+                _ => anyhow::bail!(
+                    "--camouflage only works with PNG files, not {}",
+                    container.format()
+                ),
+            }
+            chunk_type
+        }
+        Some(part_size) => {
+            remove_split_message(&mut container, &chunk)?;
+            container.remove_messages(&chunk_type)?;
+
+            let (manifest, parts) = Manifest::split(chunk_type, &data, part_size)?;
+            for part in parts {
+                container.append_message(&chunk_type, part)?;
+            }
+            let manifest_type = ChunkType::from_str(MANIFEST_CHUNK_TYPE)?;
+            container.append_message(&manifest_type, manifest.as_bytes())?;
+            manifest_type
+        }
+        None => {
+            container.append_message(&chunk_type, data)?;
+            chunk_type
+        }
+    };
+    // The chunk a signature covers is the one just added, not an older one of its type:
+    // camouflaged chunks go just before IEND, and everything else goes last
+    let signed_index = match &container {
+        Container::Png(png) if args.camouflage => png
+            .chunks()
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == "IEND")
+            .unwrap_or(png.chunks().len())
+            .checked_sub(1),
+        Container::Png(png) => png.chunks().len().checked_sub(1),
+        _ => None,
+    };
+
+    record_container_audit(&mut container, args.audit, "encode", &chunk_type)?;
+
+    if args.sign_key.is_some() || args.history {
+        let (original, png) = match (&original, &mut container) {
+            (Container::Png(original), Container::Png(png)) => (original, png),
+            _ => anyhow::bail!(
+                "--sign-key and --history only work with PNG files, not {}",
+                container.format()
+            ),
+        };
+
+        if let Some(sign_key) = &args.sign_key {
+            let signing_key = keys::read_key_file(sign_key)?;
+            let signed = signed_index
+                .map(|index| &png.chunks()[index])
+                .filter(|chunk| chunk.chunk_type() == &signed_type)
+                .ok_or_else(|| anyhow::anyhow!("No {} chunk to sign", signed_type))?;
+            let signature = EmbeddedSignature::sign(png, Some(signed), &signing_key)?;
+            png.append_chunk(signature.to_chunk());
+        }
+
+        if args.history {
+            history::record(original, png)?;
+        }
+    }
+
+    let bytes = container.checked_bytes()?;
+    let original_length = match created || converted {
+        true => original.as_bytes().len() as u64,
+        false => file_length(&args.file)?,
+    };
+    check_growth(original_length, bytes.len() as u64, max_growth)?;
+
+    if args.verify_pixels {
+        let original = match &original {
+            Container::Png(png) => png,
+            _ => anyhow::bail!(
+                "--verify-pixels only works with PNG files, not {}",
+                original.format()
+            ),
+        };
+        // Read back the bytes about to be written, not the edited `Png`
+        if !pixels::same_pixels(original, &Png::from_bytes_preserving(&bytes)?)? {
+            anyhow::bail!("The pixel data changed, so the file was not written");
+        }
+        outln!("Verified that the pixel data is unchanged");
+    }
+
+    if args.emit_data_url {
+        outln!("{}", data_url::encode(&bytes));
+        return Ok(());
+    }
+
+    // The clipboard's image is already a copy, and a created file is new
+    let in_place = args.in_place || (from_clipboard && args.out.is_none()) || created;
+    let file_path = match args.out {
+        None if converted => output_path(
+            &args.file,
+            Some(args.file.with_extension("png")),
+            false,
+            args.overwrite,
+        )?,
+        out => output_path(&args.file, out, in_place, args.overwrite)?,
+    };
+
+    save_file(&bytes, &file_path, args.backup)?;
+
+    outln!("Wrote message to: {:?}", &file_path);
+    if to_clipboard {
+        clipboard::copy_file(&file_path)?;
+    }
+
+    Ok(())
+}
+
+/// Stores `data` after IEND, after any messages already there
+fn append_to_trailer(png: &mut Png, chunk_type: &ChunkType, data: &[u8]) -> anyhow::Result<()> {
+    if png.chunk_by_type("IEND").is_none() {
+        anyhow::bail!(
+            "This image has no IEND chunk to store the message after. Fix it with `repair`"
+        );
+    }
+    let existing = png.trailer();
+    if !existing.is_empty() && !trailer::is_pngme_trailer(existing) {
+        anyhow::bail!(
+            "This image already has {} bytes after IEND ({}) that pngme didn't write. \
+             Remove them with `repair --strip-trailer` first",
+            existing.len(),
+            trailer::describe(existing)
+        );
+    }
+    let mut bytes = existing.to_vec();
+    bytes.extend_from_slice(&trailer::wrap(chunk_type, data));
+    png.set_trailer(bytes);
+    Ok(())
+}
+
+/// Decodes an image in another format and returns its pixels as a PNG
+#[cfg(feature = "convert")]
+fn convert_to_png(bytes: &[u8]) -> anyhow::Result<Png> {
+    pngme::convert::to_png(bytes)
+}
+
+#[cfg(not(feature = "convert"))]
+fn convert_to_png(_bytes: &[u8]) -> anyhow::Result<Png> {
+    anyhow::bail!(
+        "This pngme was built without image conversion. Rebuild it with --features convert"
+    )
+}
+
+/// Adds `data` under `name` to the named messages in `existing`, or to a new message
+/// store, and returns the store's bytes
+fn add_named_message(
+    existing: Option<&[u8]>,
+    chunk_type: &ChunkType,
+    name: &str,
+    data: Vec<u8>,
+) -> anyhow::Result<Vec<u8>> {
+    let mut store = match existing {
+        Some(existing) => MessageStore::from_bytes(existing).map_err(|_| {
+            anyhow::anyhow!(
+                "The existing {} chunk does not hold named messages",
+                chunk_type
+            )
+        })?,
+        None => MessageStore::default(),
+    };
+    store.insert(name, data)?;
+    Ok(store.as_bytes())
+}
+
+/// Searches for a message hidden in an image file and prints the message if one is found
+pub fn decode(args: DecodeArgs) -> anyhow::Result<()> {
+    let container = match clipboard::is_clipboard(&args.file) {
+        true => Container::from_file(clipboard::image_file()?)?,
+        false => Container::from_file(&args.file)?,
+    };
+    let chunk = match &args.chunk {
+        Some(chunk) => chunk.clone(),
+        None => return decode_all(&container, &args),
+    };
+    let passphrase = match chunk == SECRET_CHUNK_TYPE {
+        true if args.camouflage || args.key_file.is_some() || args.age_identity.is_some() => {
+            anyhow::bail!(
+                "A secret chunk type is found with its passphrase, so it can't be combined \
+                 with --camouflage, --key-file or --age-identity"
+            )
+        }
+        true => Some(rpassword::prompt_password("Passphrase: ")?),
+        false => None,
+    };
+    let chunk = match &passphrase {
+        Some(passphrase) => chunk_type::from_passphrase(passphrase)?.to_string(),
+        None => chunk,
+    };
+    let payload = match (&container, args.camouflage) {
+        (_, _) if args.mode == Mode::Trailer => match read_trailer(&container, &chunk)? {
+            Some(payload) => Some(payload),
+            None => return Ok(()),
+        },
+        (_, false) => read_payload(&container, &chunk)?,
+        (Container::Png(png), true) => {
+            let keyword = args
+                .keyword
+                .as_deref()
+                .unwrap_or(camouflage::DEFAULT_KEYWORD);
+            let payload = camouflage::reveal(png, &ChunkType::from_str(&chunk)?, keyword)?;
+            if payload.is_none() {
+                outln!("Error: No {} chunk with the keyword {:?}", chunk, keyword);
+                return Ok(());
+            }
+            payload
+        }
+        (_, true) => anyhow::bail!(
+            "--camouflage only works with PNG files, not {}",
+            container.format()
+        ),
+    };
+    match payload {
+        Some(data) => {
+            let data = match &args.name {
+                Some(name) => MessageStore::from_bytes(&data)?
+                    .get(name)
+                    .ok_or_else(|| anyhow::anyhow!("No message named {:?}", name))?
+                    .to_vec(),
+                None if MessageStore::is_message_store(&data) && args.head.is_none() => {
+                    anyhow::bail!(
+                        "This chunk holds named messages. Pick one with --name, see list-messages"
+                    )
+                }
+                None => data,
+            };
+
+            if let Some(length) = args.head {
+                print_head(&data, length);
+                return Ok(());
+            }
+
+            let message = decrypt_message(&data, &args, passphrase.as_deref())?;
+
+            if let Some(directory) = &args.extract_to {
+                if !archive::is_archive(&message) {
+                    anyhow::bail!("This message is not a directory archive");
+                }
+                let files = archive::unpack(&message, directory)?;
+                outln!("Extracted {} files to: {:?}", files, directory);
+            } else if archive::is_archive(&message) {
+                anyhow::bail!("This message is a directory archive. Extract it with --extract-to");
+            } else if args.lossy {
+                if std::str::from_utf8(&message).is_err() {
+                    eprintln!("Warning: the message isn't valid UTF-8. Invalid bytes are shown as \u{FFFD}");
+                }
+                outln!("{}", String::from_utf8_lossy(&message));
+            } else {
+                let text = String::from_utf8(message).map_err(|_| {
+                    anyhow::anyhow!(
+                        "The message isn't valid UTF-8. Print it anyway with --lossy, or look at it with --head"
+                    )
+                })?;
+                outln!("{}", text);
+            }
+        }
+        // Which chunk types are in the file says nothing about a wrong passphrase
+        None if passphrase.is_some() => outln!("Error: No message for this passphrase"),
+        None => outln!("Error: {}", missing_chunk(&container, &chunk)?),
+    }
+
+    Ok(())
+}
+
+/// Returns the message of type `chunk_type` stored after IEND, or all of the data after
+/// IEND if another tool put it there. Prints why there is nothing to decode and returns
+/// `None` otherwise.
+fn read_trailer(container: &Container, chunk_type: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let png = match container {
+        Container::Png(png) => png,
+        _ => anyhow::bail!(
+            "--mode trailer only works with PNG files, not {}",
+            container.format()
+        ),
+    };
+    let bytes = png.trailer();
+    if bytes.is_empty() {
+        outln!("Error: No data after IEND");
+        return Ok(None);
+    }
+    if !trailer::is_pngme_trailer(bytes) {
+        eprintln!(
+            "Note: the {} bytes after IEND weren't stored by pngme. They look like {}",
+            bytes.len(),
+            trailer::describe(bytes)
+        );
+        return Ok(Some(bytes.to_vec()));
+    }
+    match trailer::message(bytes, &ChunkType::from_str(chunk_type)?) {
+        Some(message) => Ok(Some(envelope::unwrap(message)?.to_vec())),
+        None => {
+            outln!("Error: No message of type {} after IEND", chunk_type);
+            Ok(None)
+        }
+    }
+}
+
+/// Prints every message that can be found without knowing its chunk type, with the
+/// chunk it is in. Text is printed on one line, escaped, and anything else is described.
+fn decode_all(container: &Container, args: &DecodeArgs) -> anyhow::Result<()> {
+    if args.camouflage
+        || args.mode != Mode::Chunk
+        || args.key_file.is_some()
+        || args.age_identity.is_some()
+        || args.obfuscate.is_some()
+        || args.name.is_some()
+        || args.extract_to.is_some()
+        || args.head.is_some()
+    {
+        anyhow::bail!(
+            "Give the chunk type of the message to use --camouflage, --mode, --key-file, \
+             --age-identity, --obfuscate, --name, --extract-to or --head"
+        );
+    }
+    let png = match container {
+        Container::Png(png) => png,
+        _ => anyhow::bail!(
+            "Give the chunk type of the message to decode it from a {} file",
+            container.format()
+        ),
+    };
+
+    let messages = hidden::find(png);
+    if messages.is_empty() {
+        outln!("No messages found");
+    }
+    for message in messages {
+        let source = match &message.keyword {
+            Some(keyword) => format!("{} {:?}", message.chunk_type, keyword),
+            None => message.chunk_type.to_string(),
+        };
+        let content_type = content_type(&message.data);
+        let mut record = Record::new()
+            .with("offset", message.offset)
+            .with("type", message.chunk_type.to_string())
+            .with("keyword", message.keyword.clone())
+            .with("content_type", content_type.as_str())
+            .with("length", message.data.len());
+        let line = match std::str::from_utf8(&message.data) {
+            Ok(text) if content_type == "UTF-8 text" => {
+                record = record.with("text", text);
+                format!(
+                    "{:>10}  {}: {}",
+                    message.offset,
+                    source,
+                    text.escape_debug()
+                )
+            }
+            _ => format!(
+                "{:>10}  {}: {}, {} bytes",
+                message.offset,
+                source,
+                content_type,
+                message.data.len()
+            ),
+        };
+        printer::record(line, record);
+    }
+    Ok(())
+}
+
+/// Says what kind of data a message holds
+fn content_type(data: &[u8]) -> String {
+    if crypto::is_age_encrypted(data) {
+        "encrypted to age recipients".to_string()
+    } else if recipients::is_multi_recipient(data) {
+        "encrypted to recipients".to_string()
+    } else if crypto::is_passphrase_encrypted(data) || deniable::is_deniable(data) {
+        "encrypted with a passphrase".to_string()
+    } else if crypto::is_encrypted(data) {
+        "encrypted with a key".to_string()
+    } else if obfuscate::is_obfuscated(data) {
+        "obfuscated".to_string()
+    } else if MessageStore::is_message_store(data) {
+        "named messages, see list-messages".to_string()
+    } else if archive::is_archive(data) {
+        "directory archive, see --extract-to".to_string()
+    } else if let Some(format) = Format::detect(data) {
+        format!("{} image", format)
+    } else if std::str::from_utf8(data).is_ok() {
+        "UTF-8 text".to_string()
+    } else {
+        "binary data".to_string()
+    }
+}
+
+/// Prints what kind of data a message holds, its length and its first `length` bytes
+fn print_head(data: &[u8], length: usize) {
+    let content_type = content_type(data);
+    let head: String = data[..length.min(data.len())]
+        .iter()
+        .flat_map(|&byte| std::ascii::escape_default(byte))
+        .map(char::from)
+        .collect();
+
+    outln!("Type: {}", content_type);
+    outln!("Length: {} bytes", data.len());
+    if length < data.len() {
+        outln!("First {} bytes: {}", length, head);
+    } else {
+        outln!("Contents: {}", head);
+    }
+}
+
+/// Prints the names of the messages stored in a chunk
+pub fn list_messages(args: ListMessagesArgs) -> anyhow::Result<()> {
+    let container = Container::from_file(&args.file)?;
+    let data = match read_payload(&container, &args.chunk)? {
+        Some(data) => data,
+        None => {
+            return Err(anyhow::anyhow!(missing_chunk(&container, &args.chunk)?))
+                .code("chunk_not_found")
+                .in_chunk(&args.chunk)
+                .in_file(&args.file)
+        }
+    };
+
+    for (name, message) in MessageStore::from_bytes(&data)?.iter() {
+        outln!("{} ({} bytes)", name, message.len());
+    }
+
+    Ok(())
+}
+
+/// Searches the chunk data of PNG files and prints every match with its file, chunk
+/// type and offset
+pub fn grep(args: GrepArgs) -> anyhow::Result<()> {
+    let pattern = Pattern::new(&args.pattern, args.regex)?;
+    // Without --types, skip the compressed image data since it only produces noise
+    let types = match args.types.as_deref() {
+        Some("all") => None,
+        Some(types) => Some(
+            types
+                .split(',')
+                .map(|chunk_type| ChunkType::from_str(chunk_type.trim()))
+                .collect::<anyhow::Result<Vec<_>>>()?,
+        ),
+        None => Some(Vec::new()),
+    };
+    let include = |chunk_type: &ChunkType| match &types {
+        Some(types) if types.is_empty() => chunk_type.to_string() != "IDAT",
+        Some(types) => types.contains(chunk_type),
+        None => true,
+    };
+
+    let files = png_files(&args.paths)?;
+    let batch = Batch::new(args.jobs, args.async_io, args.concurrency, args.max_memory);
+    let results = batch.map_files(&files, |file, bytes| {
+        let png = Png::try_from(bytes)?;
+        let lines: Vec<(String, Option<Record>)> = search::search(&png, &pattern, include)
+            .iter()
+            .map(|found| {
+                let text = String::from_utf8_lossy(&found.bytes);
+                let line = format!(
+                    "{}:{}:{}: {}",
+                    file.display(),
+                    found.chunk_type,
+                    found.offset,
+                    text.escape_debug()
+                );
+                let record = Record::new()
+                    .with("file", file.display().to_string())
+                    .with("type", found.chunk_type.to_string())
+                    .with("offset", found.offset)
+                    .with("match", text.as_ref());
+                (line, Some(record))
+            })
+            .collect();
+        Ok(lines)
+    })?;
+
+    for (file, result) in files.iter().zip(results) {
+        print_lines_or_skip(file, result);
+    }
+
+    Ok(())
+}
+
+/// Lists the runs of readable text in the chunk data of a PNG file
+pub fn strings(args: StringsArgs) -> anyhow::Result<()> {
+    let bytes = container::read_file(&args.file)?;
+    let png = Png::from_bytes_preserving(&bytes).in_file(&args.file)?;
+    for found in search::strings(&png, args.min_len, args.decompress) {
+        let text = String::from_utf8_lossy(&found.bytes);
+        let line = format!(
+            "{}:{}: {}",
+            found.chunk_type,
+            found.offset,
+            text.escape_debug()
+        );
+        let record = Record::new()
+            .with("type", found.chunk_type.to_string())
+            .with("offset", found.offset)
+            .with("text", text.as_ref());
+        printer::record(line, record);
+    }
+    Ok(())
+}
+
+/// Returns every PNG file in or under `paths`, in the order given
+fn png_files(paths: &[PathBuf]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for path in paths {
+        files.extend(search::png_files(path)?);
+    }
+    Ok(files)
+}
+
+/// Returns where scan writes data extracted from `file`: in `directory`, at the path of
+/// `file` relative to the scanned path it was found under, with `suffix` added to the
+/// name, so that files with the same name in different folders are kept apart
+fn extraction_path(directory: &Path, roots: &[PathBuf], file: &Path, suffix: &str) -> PathBuf {
+    let relative = roots
+        .iter()
+        .filter_map(|root| file.strip_prefix(root).ok())
+        .find(|relative| !relative.as_os_str().is_empty())
+        .unwrap_or_else(|| Path::new(file.file_name().unwrap_or_default()));
+    let mut name = relative.as_os_str().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    directory.join(name)
+}
+
+/// How to spread work over many files: over a pool of threads, one per CPU unless told
+/// otherwise and holding no more file data than `--max-memory` allows, or with `--async`
+/// over reads in flight on a tokio runtime
+enum Batch {
+    Threads(Option<NonZeroUsize>, Option<MemoryLimit>),
+    Async(NonZeroUsize),
+}
+
+impl Batch {
+    /// How many reads `--async` keeps in flight unless told otherwise
+    const DEFAULT_CONCURRENCY: usize = 32;
+
+    fn new(
+        jobs: Option<NonZeroUsize>,
+        async_io: bool,
+        concurrency: Option<NonZeroUsize>,
+        max_memory: Option<ByteSize>,
+    ) -> Self {
+        match async_io {
+            true => Batch::Async(concurrency.unwrap_or(
+                NonZeroUsize::new(Self::DEFAULT_CONCURRENCY).expect("the default is not zero"),
+            )),
+            false => Batch::Threads(jobs, max_memory.map(|size| MemoryLimit::new(size.0))),
+        }
+    }
+
+    /// Reads every file and calls `process` with its contents, returning the results in
+    /// the same order as `files`. Files that can't be read get the read error instead.
+    fn map_files<R, F>(
+        &self,
+        files: &[PathBuf],
+        process: F,
+    ) -> anyhow::Result<Vec<anyhow::Result<R>>>
+    where
+        R: Send,
+        F: Fn(&Path, Bytes) -> anyhow::Result<R> + Sync + Send,
+    {
+        match self {
+            Batch::Threads(jobs, None) => batch::map(files, *jobs, |file| {
+                process(file, Bytes::from(container::read_file(file)?))
+            }),
+            Batch::Threads(jobs, Some(limit)) => batch::map(files, *jobs, |file| {
+                // The length of a file inside an archive isn't known until it's read
+                let (_reservation, bytes) = match file.is_file() {
+                    true => {
+                        let length = fs::metadata(file).in_file(file)?.len();
+                        if length > limit.spill_threshold() {
+                            let reservation = limit.reserve(limit.spill_threshold());
+                            let file_handle = File::open(file).in_file(file)?;
+                            (reservation, memory::spill(file_handle).in_file(file)?)
+                        } else {
+                            let reservation = limit.reserve(memory::estimate(length));
+                            (reservation, Bytes::from(container::read_file(file)?))
+                        }
+                    }
+                    false => {
+                        let bytes = container::read_file(file)?;
+                        let reservation = limit.reserve(memory::estimate(bytes.len() as u64));
+                        (reservation, Bytes::from(bytes))
+                    }
+                };
+                process(file, bytes)
+            }),
+            Batch::Async(concurrency) => {
+                batch::map_files_async(files, *concurrency, |file, bytes| {
+                    process(file, Bytes::from(bytes?))
+                })
+            }
+        }
+    }
+}
+
+/// Prints the lines produced for `file`, or why it was skipped. Lines without a record
+/// only label the lines after them in text output.
+fn print_lines_or_skip(file: &Path, result: anyhow::Result<Vec<(String, Option<Record>)>>) {
+    match result {
+        Ok(lines) => {
+            for (line, record) in lines {
+                match record {
+                    Some(record) => printer::record(line, record),
+                    None => printer::heading(line),
+                }
+            }
+        }
+        Err(error) => eprintln!("Skipping {:?}: {}", file, error),
+    }
+}
+
+/// Counts the chunks in PNG files, for each file or across all of them
+pub fn stats(args: StatsArgs) -> anyhow::Result<()> {
+    let files = png_files(&args.paths)?;
+    let batch = Batch::new(args.jobs, false, None, None);
+    let results = batch.map_files(&files, |_, bytes| {
+        let length = bytes.len() as u64;
+        let png = Png::from_shared_preserving(bytes)?;
+        Ok(FileStats::of(&png, length))
+    })?;
+
+    let mut read = Vec::new();
+    let mut stats = Vec::new();
+    for (file, result) in files.iter().zip(results) {
+        match result {
+            Ok(file_stats) => {
+                read.push(file);
+                stats.push(file_stats);
+            }
+            Err(error) => eprintln!("Skipping {:?}: {}", file, error),
+        }
+    }
+
+    if !args.aggregate {
+        for (file, file_stats) in read.iter().zip(&stats) {
+            let line = format!(
+                "{}: {} chunks, {} bytes, {} bytes of metadata",
+                file.display(),
+                file_stats.chunks.len(),
+                file_stats.length,
+                file_stats.metadata_bytes()
+            );
+            let record = Record::new()
+                .with("file", file.display().to_string())
+                .with("chunks", file_stats.chunks.len())
+                .with("length", file_stats.length)
+                .with("metadata_length", file_stats.metadata_bytes());
+            printer::record(line, record);
+        }
+        return Ok(());
+    }
+
+    let aggregate = stats::aggregate(&stats);
+    let percent = |count: usize| 100.0 * count as f64 / aggregate.files.max(1) as f64;
+    printer::heading(format!(
+        "{} files, {} ({:.0}%) with metadata",
+        aggregate.files,
+        aggregate.with_metadata,
+        percent(aggregate.with_metadata)
+    ));
+    printer::heading(format!(
+        "{:<4}  {:>6}  {:>4}  {:>7}  {:>10}",
+        "TYPE", "FILES", "%", "CHUNKS", "AVG LENGTH"
+    ));
+    for type_stats in &aggregate.types {
+        let line = format!(
+            "{:<4}  {:>6}  {:>3.0}%  {:>7}  {:>10.1}",
+            type_stats.chunk_type,
+            type_stats.files,
+            percent(type_stats.files),
+            type_stats.chunks,
+            type_stats.average_length()
+        );
+        let record = Record::new()
+            .with("type", type_stats.chunk_type.to_string())
+            .with("files", type_stats.files)
+            .with("chunks", type_stats.chunks)
+            .with("average_length", type_stats.average_length());
+        printer::record(line, record);
+    }
+    if !aggregate.outliers.is_empty() {
+        printer::heading("Outliers:".to_string());
+        for outlier in &aggregate.outliers {
+            let file = read[outlier.index];
+            let record = Record::new()
+                .with("file", file.display().to_string())
+                .with("outlier", outlier.reason.as_str());
+            printer::record(format!("{}: {}", file.display(), outlier.reason), record);
+        }
+    }
+    Ok(())
+}
+
+/// Looks for places in PNG files where data could be hidden
+pub fn scan(args: ScanArgs) -> anyhow::Result<()> {
+    let files = png_files(&args.paths)?;
+    let batch = Batch::new(args.jobs, args.async_io, args.concurrency, args.max_memory);
+    let results = batch.map_files(&files, |file, bytes| {
+        let png = Png::from_shared_preserving(bytes)?;
+        let findings = match args.pngme {
+            true => scan::pngme_findings(&png),
+            false => scan::findings(&png),
+        };
+        let mut lines: Vec<(String, Option<Record>)> = findings
+            .iter()
+            .map(|finding| {
+                let record = Record::new()
+                    .with("file", file.display().to_string())
+                    .with("offset", finding.offset)
+                    .with("message", finding.message.as_str());
+                (format!("{}:{}", file.display(), finding), Some(record))
+            })
+            .collect();
+        if args.entropy {
+            lines.extend(entropy_table(file, &png));
+        }
+        if let Some(directory) = args
+            .extract_trailers
+            .as_ref()
+            .filter(|_| !png.trailer().is_empty())
+        {
+            let path = extraction_path(directory, &args.paths, file, "trailer");
+            let parent = path.parent().unwrap_or(directory);
+            fs::create_dir_all(parent).in_file(parent)?;
+            fs::write(&path, png.trailer()).in_file(&path)?;
+            let record = Record::new()
+                .with("file", file.display().to_string())
+                .with("extracted_to", path.display().to_string())
+                .with("length", png.trailer().len());
+            let line = format!(
+                "{}: wrote the {} bytes after IEND to {}",
+                file.display(),
+                png.trailer().len(),
+                path.display()
+            );
+            lines.push((line, Some(record)));
+        }
+        if let Some(directory) = &args.extract_embedded {
+            for embedded in polyglot::find(&png) {
+                let suffix = format!("{}.{}", embedded.offset, embedded.extension);
+                let path = extraction_path(directory, &args.paths, file, &suffix);
+                let parent = path.parent().unwrap_or(directory);
+                fs::create_dir_all(parent).in_file(parent)?;
+                fs::write(&path, &embedded.bytes).in_file(&path)?;
+                let record = Record::new()
+                    .with("file", file.display().to_string())
+                    .with("offset", embedded.offset)
+                    .with("kind", embedded.kind)
+                    .with("extracted_to", path.display().to_string())
+                    .with("length", embedded.bytes.len());
+                let line = format!(
+                    "{}:{}: wrote the {} to {}",
+                    file.display(),
+                    embedded.offset,
+                    embedded.kind,
+                    path.display()
+                );
+                lines.push((line, Some(record)));
+            }
+        }
+        Ok((lines, !findings.is_empty()))
+    })?;
+
+    let mut carrying = 0;
+    for (file, result) in files.iter().zip(results) {
+        let lines = result.map(|(lines, found)| {
+            carrying += found as usize;
+            lines
+        });
+        print_lines_or_skip(file, lines);
+    }
+    if args.pngme {
+        printer::heading(format!(
+            "{} of {} files carry data left by pngme",
+            carrying,
+            files.len()
+        ));
+    }
+
+    Ok(())
+}
+
+fn entropy_table(file: &Path, png: &Png) -> Vec<(String, Option<Record>)> {
+    let mut lines = vec![
+        (format!("{}:", file.display()), None),
+        (
+            format!(
+                "{:>10}  {:<4}  {:>10}  {:>7}  {:>10}  ASSESSMENT",
+                "OFFSET", "TYPE", "LENGTH", "ENTROPY", "CHI-SQUARE"
+            ),
+            None,
+        ),
+    ];
+    for chunk in png.chunks() {
+        let stats = ByteStatistics::of(chunk.data());
+        let assessment = stats.assess();
+        let suspicious = scan::is_suspicious(chunk.chunk_type(), assessment);
+        let flag = match suspicious {
+            true => " (suspicious)",
+            false => "",
+        };
+        let line = format!(
+            "{:>10}  {:<4}  {:>10}  {:>7.3}  {:>10.1}  {}{}",
+            chunk.offset().unwrap_or_default(),
+            chunk.chunk_type(),
+            stats.length,
+            stats.entropy,
+            stats.chi_square,
+            assessment,
+            flag
+        );
+        let record = Record::new()
+            .with("file", file.display().to_string())
+            .with("offset", chunk.offset().unwrap_or_default())
+            .with("type", chunk.chunk_type().to_string())
+            .with("length", stats.length)
+            .with("entropy", stats.entropy)
+            .with("chi_square", stats.chi_square)
+            .with("assessment", assessment.to_string())
+            .with("suspicious", suspicious);
+        lines.push((line, Some(record)));
+    }
+    lines
+}
+
+/// Writes a file that is both the PNG image and the ZIP archive, so that viewers show
+/// the image and archive tools list the archive's files
+pub fn polyglot(args: PolyglotArgs) -> anyhow::Result<()> {
+    let mut png = match read_container(&args.image, false)? {
+        Container::Png(png) => png,
+        container => anyhow::bail!(
+            "Polyglots can only be made from PNG files, not {}",
+            container.format()
+        ),
+    };
+    let archive = container::read_file(&args.archive)?;
+    polyglot::append_zip(&mut png, &archive).in_file(&args.archive)?;
+
+    let file_path = output_path(&args.image, args.out, false, args.overwrite)?;
+    save_file(&png.checked_bytes()?, &file_path, false)?;
+    outln!(
+        "Wrote {:?}, which is both a PNG image and a ZIP archive",
+        &file_path
+    );
+    Ok(())
+}
+
+/// Prints statistics for each bit plane of the pixel data and any text hidden in the
+/// lowest bits, optionally drawing every plane as an image
+pub fn analyze_bitplanes(args: AnalyzeBitplanesArgs) -> anyhow::Result<()> {
+    let planes = Planes::from_png(&Png::from_file(&args.file)?)?;
+
+    outln!(
+        "{:<7}  {:>3}  {:>6}  {:>12}  PATTERN",
+        "CHANNEL",
+        "BIT",
+        "ONES",
+        "SAME AS LEFT"
+    );
+    for plane in planes.statistics() {
+        outln!(
+            "{:<7}  {:>3}  {:>5.1}%  {:>11.1}%  {}",
+            plane.channel,
+            plane.bit,
+            plane.ones * 100.0,
+            plane.same_as_left * 100.0,
+            if plane.is_noise() {
+                "noise"
+            } else {
+                "structured"
+            }
+        );
+    }
+    if let Some(text) = planes.lsb_text() {
+        outln!("The lowest bits start with text: {:?}", text);
+    }
+
+    if let Some(directory) = &args.render {
+        fs::create_dir_all(directory)?;
+        for (index, channel) in planes.channels.iter().enumerate() {
+            for bit in 0..planes.bit_depth {
+                let path = directory.join(format!("{}_{}.png", channel.name, bit));
+                save_png(&planes.render(index, bit)?, &path, false)?;
+            }
+        }
+        outln!(
+            "Wrote {} bit plane images to: {:?}",
+            planes.channels.len() * planes.bit_depth as usize,
+            directory
+        );
+    }
+
+    Ok(())
+}
+
+/// Removes a message from an image file and saves the result
+pub fn remove(args: RemoveArgs) -> anyhow::Result<()> {
+    let chunk_type = ChunkType::from_str(&args.chunk)?;
+    if !args.force {
+        check_structural_chunk_type(&chunk_type, "removing it")
+            .in_chunk(&args.chunk)
+            .in_file(&args.file)?;
+    }
+
+    let original = read_container(&args.file, args.preserve)?;
+    let mut container = original.clone();
+    if !remove_split_message(&mut container, &args.chunk)? {
+        if container.message(&args.chunk)?.is_none() {
+            return Err(anyhow::anyhow!(missing_chunk(&container, &args.chunk)?))
+                .code("chunk_not_found")
+                .in_chunk(&args.chunk)
+                .in_file(&args.file);
+        }
+        container.remove_message(&args.chunk).in_file(&args.file)?;
+    }
+
+    record_container_audit(&mut container, args.audit, "remove", &chunk_type)?;
+
+    record_container_history(&original, &mut container, args.history)?;
+
+    let file_path = output_path(&args.file, args.out, args.in_place, args.overwrite)?;
+    save_file(&container.checked_bytes()?, &file_path, args.backup)?;
+    outln!("Wrote {:?} without the {} chunk", &file_path, chunk_type);
+
+    Ok(())
+}
+
+/// Opens the message stored in a chunk in the user's editor, and saves what they write
+/// as the chunk's new data. A chunk is added if there was none.
+pub fn edit(args: EditArgs) -> anyhow::Result<()> {
+    let chunk_type = ChunkType::from_str(&args.chunk)?;
+    if !args.force {
+        check_message_chunk_type(&chunk_type)?;
+    }
+
+    let original = read_container(&args.file, args.preserve)?;
+    let message = original
+        .message(&args.chunk)
+        .in_file(&args.file)?
+        .unwrap_or_default();
+    let message = envelope::unwrap(&message).in_file(&args.file)?;
+    let edited = edit_in_editor(message, &args.chunk)?;
+    if edited == message {
+        outln!("The message is unchanged, so nothing was written");
+        return Ok(());
+    }
+
+    let mut container = original.clone();
+    let data = envelope::frame(&chunk_type, &edited);
+    warn_if_nonconforming(&Chunk::new(chunk_type, data.clone()));
+    container
+        .replace_message(&chunk_type, data)
+        .in_file(&args.file)?;
+    record_container_audit(&mut container, args.audit, "edit", &chunk_type)?;
+    record_container_history(&original, &mut container, args.history)?;
+
+    save_file(&container.checked_bytes()?, &args.file, args.backup)?;
+    outln!("Wrote edited message to: {:?}", &args.file);
+
+    Ok(())
+}
+
+/// Adds text to the end of the message stored in a chunk, or stores it as a new message
+/// if there is none
+pub fn append(args: AppendArgs) -> anyhow::Result<()> {
     let chunk_type = ChunkType::from_str(&args.chunk)?;
-    let data = args.message.into_bytes();
+    if !args.force {
+        check_message_chunk_type(&chunk_type)?;
+    }
+
+    let original = read_container(&args.file, args.preserve)?;
+    let existed = original.message(&args.chunk).in_file(&args.file)?.is_some();
+    let mut container = original.clone();
+    // The envelope is only a header, so text can go straight on the end of a message
+    match existed {
+        true => container.append_to_message(&chunk_type, args.message.as_bytes()),
+        false => container.append_message(
+            &chunk_type,
+            envelope::frame(&chunk_type, args.message.as_bytes()),
+        ),
+    }
+    .in_file(&args.file)?;
+    if let Some(data) = container.message(&args.chunk).in_file(&args.file)? {
+        warn_if_nonconforming(&Chunk::new(chunk_type, data));
+    }
+    record_container_audit(&mut container, args.audit, "append", &chunk_type)?;
+    record_container_history(&original, &mut container, args.history)?;
+
+    save_file(&container.checked_bytes()?, &args.file, args.backup)?;
+    match existed {
+        true => outln!("Appended to the {} chunk in: {:?}", chunk_type, &args.file),
+        false => outln!("Added a {} chunk to: {:?}", chunk_type, &args.file),
+    }
+
+    Ok(())
+}
+
+/// Frames the messages in an image file that were written before pngme framed its
+/// messages, so that every message in the file is read the same way
+pub fn migrate(args: MigrateArgs) -> anyhow::Result<()> {
+    let original = read_container(&args.file, args.preserve)?;
+    let mut container = original.clone();
+    let migrated = envelope::migrate(&mut container).in_file(&args.file)?;
+    if migrated.is_empty() {
+        outln!("Every message is already up to date");
+        return Ok(());
+    }
+
+    let names: Vec<String> = migrated.iter().map(ChunkType::to_string).collect();
+    if args.dry_run {
+        outln!("Would migrate the {} chunks", names.join(", "));
+        return Ok(());
+    }
+
+    match &mut container {
+        Container::Png(png) => record_audit(png, args.audit, "migrate", None)?,
+        _ if args.audit => anyhow::bail!(
+            "--audit only works with PNG files, not {}",
+            container.format()
+        ),
+        _ => {}
+    }
+    record_container_history(&original, &mut container, args.history)?;
+
+    save_file(&container.checked_bytes()?, &args.file, args.backup)?;
+    outln!(
+        "Migrated the {} chunks in: {:?}",
+        names.join(", "),
+        &args.file
+    );
+
+    Ok(())
+}
+
+/// Writes `message` to a temporary file, opens it in `$VISUAL` or `$EDITOR` (or vi if
+/// neither is set) and returns the file's contents once the editor exits
+fn edit_in_editor(message: &[u8], chunk_type: &str) -> anyhow::Result<Vec<u8>> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // The editor may come with arguments, like `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("$EDITOR is empty"))?;
+
+    let path = env::temp_dir().join(format!(
+        "pngme-edit-{}-{}.txt",
+        std::process::id(),
+        chunk_type
+    ));
+    write_private(&path, message)?;
+
+    let result = process::Command::new(program)
+        .args(words)
+        .arg(&path)
+        .status()
+        .map_err(|error| anyhow::anyhow!("Couldn't run {}: {}", program, error))
+        .and_then(|status| match status.success() {
+            true => Ok(fs::read(&path)?),
+            false => Err(anyhow::anyhow!(
+                "{} exited with {}, so the message wasn't changed",
+                program,
+                status
+            )),
+        });
+    let _ = fs::remove_file(&path);
+    result
+}
+
+/// Creates a file that only the current user can read, since it may hold a secret
+fn write_private(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(bytes)?;
+    Ok(())
+}
+
+/// Prints all of the chunks in a PNG file, the segments or blocks of a JPEG or GIF file,
+/// or the tags of a TIFF file
+pub fn print_chunks(args: PrintArgs) -> anyhow::Result<()> {
+    if args.explain {
+        return explain_chunks(ExplainArgs { file: args.file });
+    }
+    let bytes = container::read_file(&args.file)?;
+    match Format::detect(&bytes) {
+        Some(Format::Png) => {}
+        Some(_) => {
+            return match Container::try_from(bytes.as_ref()).in_file(&args.file)? {
+                Container::Jpeg(jpeg) => print_jpeg_segments(&jpeg),
+                Container::Gif(gif) => print_gif_blocks(&gif),
+                Container::Tiff(tiff) => print_tiff_tags(&tiff),
+                Container::Png(_) => unreachable!(),
+            };
+        }
+        None => {
+            return Err(anyhow::anyhow!("Unrecognized file format"))
+                .code("unsupported_format")
+                .in_file(&args.file)
+        }
+    }
+    let layout = layout::chunk_layout(&bytes).in_file(&args.file)?;
+    let trailer = layout::trailer_offset(&bytes, &layout);
+
+    let color = match args.color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+    };
+    let paint = |text: String, code: &str| {
+        if color && !code.is_empty() {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text
+        }
+    };
+
+    printer::heading(format!(
+        "{:>10}  {:<4}  {:>10}  {:<3}  FLAGS",
+        "OFFSET", "TYPE", "LENGTH", "CRC"
+    ));
+
+    for info in &layout {
+        let (name, name_style, flags) = match info.chunk_type() {
+            Some(chunk_type) => {
+                let style = if !chunk_type.is_public() {
+                    YELLOW
+                } else if chunk_type.is_critical() {
+                    BOLD
+                } else {
+                    ""
+                };
+                (chunk_type.to_string(), style, describe_flags(&chunk_type))
+            }
+            None => (
+                String::from_utf8_lossy(&info.type_bytes)
+                    .escape_debug()
+                    .to_string(),
+                RED,
+                "invalid chunk type".to_string(),
+            ),
+        };
+
+        let crc = if info.crc_valid {
+            paint(format!("{:<3}", "ok"), "")
+        } else {
+            paint(format!("{:<3}", "BAD"), RED)
+        };
+
+        let line = format!(
+            "{:>10}  {}  {:>10}  {}  {}",
+            info.offset,
+            paint(format!("{:<4}", name), name_style),
+            info.length,
+            crc,
+            flags
+        );
+        let record = Record::new()
+            .with("offset", info.offset)
+            .with("type", name)
+            .with("length", info.length)
+            .with("crc_valid", info.crc_valid)
+            .with("flags", flags);
+        printer::record(line, record);
+    }
+
+    if let Some(offset) = trailer {
+        let length = bytes.len() - offset;
+        let line = format!(
+            "{:>10}  {}  {:>10}  {:<3}  trailing data after IEND",
+            offset,
+            paint(format!("{:<4}", "-"), YELLOW),
+            length,
+            "-"
+        );
+        let record = Record::new()
+            .with("offset", offset)
+            .with("type", "trailer")
+            .with("length", length)
+            .with("flags", "trailing data after IEND");
+        printer::record(line, record);
+    }
+
+    Ok(())
+}
+
+/// Prints the offset of every chunk in a PNG file along with what the chunk is for and
+/// a summary of its contents
+pub fn explain_chunks(args: ExplainArgs) -> anyhow::Result<()> {
+    let bytes = container::read_file(&args.file)?;
+    if Format::detect(&bytes) != Some(Format::Png) {
+        return Err(anyhow::anyhow!("Only PNG files can be explained"))
+            .code("unsupported_format")
+            .in_file(&args.file);
+    }
+
+    for info in layout::chunk_layout(&bytes).in_file(&args.file)? {
+        let name = String::from_utf8_lossy(&info.type_bytes)
+            .escape_debug()
+            .to_string();
+        let explanation = match info.chunk_type() {
+            Some(chunk_type) => {
+                let start = info.offset + 8;
+                let data = bytes
+                    .get(start..start + info.length as usize)
+                    .unwrap_or_default();
+                explain::explain(&Chunk::new(chunk_type, data.to_vec()))
+            }
+            None => format!("{}: invalid chunk type", name),
+        };
+        let line = match info.crc_valid {
+            true => format!("{:>10}  {}", info.offset, explanation),
+            false => format!("{:>10}  {} (bad CRC)", info.offset, explanation),
+        };
+        let record = Record::new()
+            .with("offset", info.offset)
+            .with("type", name)
+            .with("crc_valid", info.crc_valid)
+            .with("explanation", explanation);
+        printer::record(line, record);
+    }
+    Ok(())
+}
+
+/// Checks PNG files for damage and spec violations, printing every problem with its
+/// offset. Returns an error if any file has errors.
+pub fn check(args: CheckArgs) -> anyhow::Result<()> {
+    let files = png_files(&args.paths)?;
+    let batch = Batch::new(args.jobs, args.async_io, args.concurrency, args.max_memory);
+    let results = batch.map_files(&files, |_, bytes| Ok(diagnostics::diagnose(&bytes)))?;
+
+    let mut failed = 0;
+    for (file, found) in files.iter().zip(results) {
+        let found = found?;
+        for diagnostic in &found {
+            let record = Record::new()
+                .with("file", file.display().to_string())
+                .with("offset", diagnostic.offset)
+                .with("severity", diagnostic.severity.to_string())
+                .with("message", diagnostic.message.as_str());
+            printer::record(format!("{}:{}", file.display(), diagnostic), record);
+        }
+        if found
+            .iter()
+            .any(|diagnostic| diagnostic.severity == Severity::Error)
+        {
+            failed += 1;
+        } else if found.is_empty() {
+            let record = Record::new()
+                .with("file", file.display().to_string())
+                .with("message", "ok");
+            printer::record(format!("{}: ok", file.display()), record);
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} file(s) have errors", failed);
+    }
+    Ok(())
+}
+
+/// Checks a PNG file against the lint rules and prints every finding. Returns an error if
+/// any finding is an error.
+pub fn lint(args: LintArgs) -> anyhow::Result<()> {
+    if args.list_rules {
+        for rule in &lint::RULES {
+            let record = Record::new()
+                .with("rule", rule.name)
+                .with("severity", rule.severity.to_string())
+                .with("description", rule.description)
+                .with("fixable", rule.is_fixable());
+            let fixable = match rule.is_fixable() {
+                true => " (fixable with `fix`)",
+                false => "",
+            };
+            let line = format!(
+                "{:<20} {:<8} {}{}",
+                rule.name,
+                rule.severity.to_string(),
+                rule.description,
+                fixable
+            );
+            printer::record(line, record);
+        }
+        return Ok(());
+    }
+
+    let mut rules = RuleSet::default();
+    if !args.only.is_empty() {
+        rules.only(&args.only)?;
+    }
+    for name in &args.disable {
+        rules.disable(name)?;
+    }
+    for setting in &args.severity {
+        rules.configure(setting)?;
+    }
+
+    let file = match &args.file {
+        Some(file) => file,
+        None => anyhow::bail!("Give a file to lint, or --list-rules"),
+    };
+    let png = Png::from_bytes_preserving(&container::read_file(file)?).in_file(file)?;
+    let found = rules.lint(&png);
+    for finding in &found {
+        let record = Record::new()
+            .with("rule", finding.rule)
+            .with("offset", finding.offset)
+            .with("severity", finding.severity.to_string())
+            .with("message", finding.message.as_str());
+        printer::record(finding.to_string(), record);
+    }
+    if found.is_empty() {
+        outln!("No problems found");
+    }
+
+    let errors = found
+        .iter()
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        anyhow::bail!("Lint found {} error(s)", errors);
+    }
+    Ok(())
+}
+
+/// Applies the fixes of lint rules to PNG files and prints what each one changed
+pub fn fix(args: FixArgs) -> anyhow::Result<()> {
+    let rules = RuleSet::fixable(&args.rules)?;
+    let files = png_files(&args.paths)?;
+    let batch = Batch::new(args.jobs, false, None, None);
+    let results = batch.map_files(&files, |file, bytes| {
+        let mut png = Png::from_shared_preserving(bytes)?;
+        let changes = rules.fix(&mut png);
+        if !changes.is_empty() && !args.dry_run {
+            record_audit(&mut png, args.audit, "fix", None)?;
+            save_file(&png.checked_bytes()?, file, args.backup)?;
+        }
+        let lines: Vec<(String, Option<Record>)> = changes
+            .iter()
+            .map(|change| {
+                let record = Record::new()
+                    .with("file", file.display().to_string())
+                    .with("rule", change.rule)
+                    .with("change", change.message.as_str());
+                (format!("{}: {}", file.display(), change), Some(record))
+            })
+            .collect();
+        Ok(lines)
+    })?;
+
+    let mut fixed = 0;
+    for (file, result) in files.iter().zip(results) {
+        if matches!(&result, Ok(lines) if !lines.is_empty()) {
+            fixed += 1;
+        }
+        print_lines_or_skip(file, result);
+    }
+    let verb = match args.dry_run {
+        true => "Would fix",
+        false => "Fixed",
+    };
+    printer::heading(format!("{} {} of {} files", verb, fixed, files.len()));
+    Ok(())
+}
+
+/// Prints the offset, marker and length of every segment before the image data
+fn print_jpeg_segments(jpeg: &Jpeg) -> anyhow::Result<()> {
+    printer::heading(format!(
+        "{:>10}  {:<6}  {:>10}  CONTENTS",
+        "OFFSET", "MARKER", "LENGTH"
+    ));
+
+    let mut offset = Jpeg::EXPECTED_HEADER.len();
+    for segment in jpeg.segments() {
+        let contents = match segment.as_message() {
+            Some((chunk_type, message)) => {
+                format!("message of type {} ({} bytes)", chunk_type, message.len())
+            }
+            None => segment.label().unwrap_or_default(),
+        };
+        let line = format!(
+            "{:>10}  {:<6}  {:>10}  {}",
+            offset,
+            segment.name(),
+            segment.data().len(),
+            contents
+        );
+        outln!("{}", line.trim_end());
+        offset += segment.encoded_length();
+    }
+
+    outln!(
+        "{:>10}  {:<6}  {:>10}  image data",
+        offset,
+        "SOS",
+        jpeg.image_data_length()
+    );
+
+    Ok(())
+}
+
+/// Prints the offset, kind and length of every block after the header
+fn print_gif_blocks(gif: &Gif) -> anyhow::Result<()> {
+    printer::heading(format!(
+        "{:>10}  {:<16}  {:>10}  CONTENTS",
+        "OFFSET", "BLOCK", "LENGTH"
+    ));
+
+    let mut offset = gif.header_length();
+    for block in gif.blocks() {
+        let contents = match (block.as_message(), block.comment(), block.application()) {
+            (Some((chunk_type, message)), _, _) => {
+                format!("message of type {} ({} bytes)", chunk_type, message.len())
+            }
+            (None, Some(comment), _) => {
+                String::from_utf8_lossy(&comment).escape_debug().to_string()
+            }
+            (None, None, Some(application)) => String::from_utf8_lossy(application)
+                .escape_debug()
+                .to_string(),
+            (None, None, None) => String::new(),
+        };
+        let line = format!(
+            "{:>10}  {:<16}  {:>10}  {}",
+            offset,
+            block.name(),
+            block.encoded_length(),
+            contents
+        );
+        outln!("{}", line.trim_end());
+        offset += block.encoded_length();
+    }
+
+    Ok(())
+}
+
+/// Prints the number, name and value of every tag in the first image file directory
+fn print_tiff_tags(tiff: &Tiff) -> anyhow::Result<()> {
+    printer::heading(format!("{:>6}  {:<26}  VALUE", "TAG", "NAME"));
+
+    for entry in tiff.entries()? {
+        let value = match entry.as_message() {
+            Some((chunk_type, message)) => {
+                format!("message of type {} ({} bytes)", chunk_type, message.len())
+            }
+            None => entry.display(tiff.byte_order()),
+        };
+        outln!("{:>6}  {:<26}  {}", entry.tag, entry.name(), value);
+    }
+
+    Ok(())
+}
+
+/// The properties encoded in the case of each letter of a chunk type
+fn describe_flags(chunk_type: &ChunkType) -> String {
+    let critical = match chunk_type.is_critical() {
+        true => "critical",
+        false => "ancillary",
+    };
+    let public = match chunk_type.is_public() {
+        true => "public",
+        false => "private",
+    };
+    let safe_to_copy = match chunk_type.is_safe_to_copy() {
+        true => "safe to copy",
+        false => "unsafe to copy",
+    };
+
+    format!("{}, {}, {}", critical, public, safe_to_copy)
+}
+
+const BOLD: &str = "1";
+const RED: &str = "31";
+const YELLOW: &str = "33";
+
+/// Generates an X25519 key pair for receiving messages encrypted with `--recipient`,
+/// or an Ed25519 key pair for signing
+pub fn keygen(args: KeygenArgs) -> anyhow::Result<()> {
+    let mut public_path = args.out.clone().into_os_string();
+    public_path.push(".pub");
+
+    let (private_pem, public_pem) = if args.signing {
+        let (private_key, public_key) = signature::generate_signing_key();
+        (
+            keys::to_pem("PRIVATE KEY", keys::PKCS8_ED25519_PREFIX, &private_key),
+            keys::to_pem("PUBLIC KEY", keys::SPKI_ED25519_PREFIX, &public_key),
+        )
+    } else {
+        let key_pair = KeyPair::generate();
+        (
+            keys::to_pem(
+                "PRIVATE KEY",
+                keys::PKCS8_X25519_PREFIX,
+                &key_pair.private_key,
+            ),
+            keys::to_pem("PUBLIC KEY", keys::SPKI_X25519_PREFIX, &key_pair.public_key),
+        )
+    };
+
+    // An existing key may be the only copy, and the pair must match
+    for path in [args.out.as_path(), Path::new(&public_path)] {
+        if path.exists() {
+            return Err(anyhow::anyhow!(
+                "{:?} already exists. Move it away or choose another path",
+                path
+            ))
+            .code("output_exists");
+        }
+    }
+    write_private(&args.out, private_pem.as_bytes()).in_file(&args.out)?;
+    fs::write(&public_path, public_pem)?;
+
+    outln!("Wrote private key to: {:?}", &args.out);
+    outln!("Wrote public key to: {:?}", &public_path);
+
+    Ok(())
+}
+
+/// Adds a signature covering every chunk in a PNG file and saves the result
+pub fn sign(args: SignArgs) -> anyhow::Result<()> {
+    let mut png = read_png(&args.file, args.preserve)?;
+    let signing_key = keys::read_key_file(&args.key_file)?;
+
+    if let Some(out) = &args.out {
+        if args.audit || args.backup {
+            anyhow::bail!(
+                "--audit and --backup can't be used with --out, which leaves the PNG untouched"
+            );
+        }
+        let signature = EmbeddedSignature::sign(&png, None, &signing_key)?;
+        atomic::write(out, signature.to_pem().as_bytes())?;
+        outln!(
+            "Wrote detached signature for {:?} to: {:?}",
+            &args.file,
+            out
+        );
+        return Ok(());
+    }
+
+    record_audit(&mut png, args.audit, "sign", None)?;
+    let signature = EmbeddedSignature::sign(&png, None, &signing_key)?;
+    png.append_chunk(signature.to_chunk());
+    save_png(&png, &args.file, args.backup)?;
+
+    outln!("Signed: {:?}", &args.file);
+
+    Ok(())
+}
+
+/// Checks the signatures embedded in a PNG file, or a detached signature made for it.
+/// Returns an error unless every checked signature was made with the expected key and
+/// nothing it covers has changed.
+pub fn verify(args: VerifyArgs) -> anyhow::Result<()> {
+    let png = Png::from_file(&args.file)?;
+    let expected_signer = keys::read_key_file(&args.pubkey)?;
+
+    let target = match &args.chunk {
+        Some(chunk) => Some(ChunkType::from_str(chunk)?),
+        None => None,
+    };
+
+    let signatures: Vec<EmbeddedSignature> = match &args.signature {
+        Some(path) => {
+            if target.is_some() {
+                anyhow::bail!(
+                    "--chunk can't be used with a detached signature, which covers the whole file"
+                );
+            }
+            let signature = EmbeddedSignature::from_pem(&fs::read(path)?)
+                .map_err(|e| anyhow::anyhow!("Invalid signature file {:?}: {}", path, e))?;
+            vec![signature]
+        }
+        None => signature::signatures(&png)?
+            .into_iter()
+            .filter(|signature| target.is_none() || signature.target == target)
+            .collect(),
+    };
+
+    if signatures.is_empty() {
+        anyhow::bail!("No matching signatures found in {:?}", &args.file);
+    }
+
+    let describe = |unchanged: bool| if unchanged { "unchanged" } else { "CHANGED" };
+    let mut all_passed = true;
+
+    for signature in &signatures {
+        let verification = signature.verify(&png);
+        let signer_matches = signature.signer == expected_signer;
+        let passed = signer_matches && verification.passed();
+        all_passed &= passed;
+
+        let signer = base64::engine::general_purpose::STANDARD.encode(signature.signer);
+        let signer_note = match signer_matches {
+            true => "",
+            false => " (does not match --pubkey)",
+        };
+        let signed_at = UNIX_EPOCH + Duration::from_secs(signature.timestamp);
+        let validity = match verification.signature_valid {
+            true => "valid",
+            false => "INVALID",
+        };
+
+        match &signature.target {
+            Some(chunk_type) => outln!("Signature over chunk {}", chunk_type),
+            None => outln!("Signature over whole file"),
+        }
+        outln!("  Signer: {}{}", signer, signer_note);
+        outln!(
+            "  Signed at: {}",
+            humantime::format_rfc3339_seconds(signed_at)
+        );
+        outln!("  Signature: {}", validity);
+        outln!("  Content: {}", describe(verification.content_unchanged));
+        outln!(
+            "  Critical chunks: {}",
+            describe(verification.critical_unchanged)
+        );
+        outln!("  Result: {}", if passed { "PASS" } else { "FAIL" });
+    }
+
+    if !all_passed {
+        anyhow::bail!("Signature verification failed");
+    }
+
+    Ok(())
+}
+
+/// Records a digest of the critical chunks in a PNG file, replacing any earlier seal,
+/// and saves the result
+pub fn seal(args: SealArgs) -> anyhow::Result<()> {
+    let original = read_png(&args.file, args.preserve)?;
+    let mut png = original.clone();
+
+    png.retain_chunks(|chunk| chunk.chunk_type().to_string() != SEAL_CHUNK_TYPE);
+    png.insert_chunk(Seal::new(&png)?.to_chunk());
+
+    record_audit(&mut png, args.audit, "seal", None)?;
+    if args.history {
+        history::record(&original, &mut png)?;
+    }
+
+    let file_path = match args.out {
+        Some(path) => path,
+        None => args.file,
+    };
+
+    save_png(&png, &file_path, args.backup)?;
+
+    outln!("Sealed: {:?}", &file_path);
+
+    Ok(())
+}
+
+/// Checks that the critical chunks of a PNG file haven't changed since it was sealed.
+/// Returns an error if they have.
+pub fn verify_seal(args: VerifySealArgs) -> anyhow::Result<()> {
+    let png = Png::from_file(&args.file)?;
+    let seal = Seal::from_png(&png)?
+        .ok_or_else(|| anyhow::anyhow!("No seal found in {:?}", &args.file))?;
+
+    let sealed_at = UNIX_EPOCH + Duration::from_secs(seal.timestamp);
+    let unchanged = seal.matches(&png);
+
+    outln!(
+        "Sealed at: {}",
+        humantime::format_rfc3339_seconds(sealed_at)
+    );
+    outln!(
+        "Critical chunks: {}",
+        if unchanged { "unchanged" } else { "CHANGED" }
+    );
+    outln!("Result: {}", if unchanged { "PASS" } else { "FAIL" });
+
+    if !unchanged {
+        anyhow::bail!("The image has changed since it was sealed");
+    }
+
+    Ok(())
+}
+
+/// Prints a digest of the image content which ignores ancillary chunks
+pub fn pixelhash(args: PixelhashArgs) -> anyhow::Result<()> {
+    let png = Png::from_file(&args.file)?;
+    let hash: String = png
+        .content_hash()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    outln!("{}  {}", hash, args.file.display());
+
+    Ok(())
+}
+
+/// Rewrites a PNG file with its chunks in the order recommended by the PNG spec
+pub fn canonicalize(args: CanonicalizeArgs) -> anyhow::Result<()> {
+    let original = read_png(&args.file, args.preserve)?;
+    let mut png = original.clone();
+
+    let moved = png.canonicalize();
+    let normalization = png.normalize(args.merge_idat);
+    if !moved && normalization.is_empty() {
+        outln!("Chunks are already in canonical order: {:?}", &args.file);
+        return Ok(());
+    }
+    print_normalization(&normalization);
+    record_audit(&mut png, args.audit, "canonicalize", None)?;
+    if args.history {
+        history::record(&original, &mut png)?;
+    }
+
+    let file_path = match args.out {
+        Some(path) => path,
+        None => args.file,
+    };
+
+    save_png(&png, &file_path, args.backup)?;
+
+    outln!("Wrote canonical PNG to: {:?}", &file_path);
+
+    Ok(())
+}
+
+/// Fixes what it can in a damaged PNG file and saves the result: bad CRCs are recomputed,
+/// chunks after IEND are moved before it and the chunk structure is normalized. Data
+/// after IEND is kept unless --strip-trailer is given, while leftover bytes in a file
+/// without IEND are always dropped.
+pub fn repair(args: RepairArgs) -> anyhow::Result<()> {
+    let original = Png::from_bytes_preserving(&fs::read(&args.file)?)?;
+    let mut changed = false;
+
+    let mut chunks: Vec<Chunk> = original
+        .chunks()
+        .iter()
+        .map(|chunk| {
+            if chunk.is_crc_valid() {
+                return chunk.clone();
+            }
+            outln!(
+                "Recomputed the CRC of the {} chunk at offset {}. Its data may be damaged",
+                chunk.chunk_type(),
+                chunk.offset().unwrap_or_default()
+            );
+            changed = true;
+            Chunk::new(*chunk.chunk_type(), chunk.data().to_vec())
+        })
+        .collect();
+    // Chunks after IEND are usually messages appended by encode, so they are moved in
+    // front of it rather than dropped by `normalize`
+    if let Some(iend) = chunks
+        .iter()
+        .position(|chunk| chunk.chunk_type().to_string() == "IEND")
+    {
+        let after = chunks.split_off(iend + 1);
+        if !after.is_empty() {
+            outln!(
+                "Moved {} chunk(s) from after IEND to before it",
+                after.len()
+            );
+            changed = true;
+            chunks.splice(iend..iend, after);
+        }
+    }
+    // Without IEND the bytes after the last chunk are what's left of a damaged one, not a
+    // trailer anyone put there
+    let has_iend = original.chunk_by_type("IEND").is_some();
+    let trailer = original.trailer();
+    if !trailer.is_empty() && !has_iend {
+        outln!("Dropped {} unreadable trailing bytes", trailer.len());
+        changed = true;
+    } else if !trailer.is_empty() && args.strip_trailer {
+        outln!("Dropped {} trailing bytes after IEND", trailer.len());
+        changed = true;
+    }
+
+    let mut png = Png::from_chunks(chunks);
+    if has_iend && !args.strip_trailer {
+        png.set_trailer(trailer.to_vec());
+    }
+    let normalization = png.normalize(args.merge_idat);
+    print_normalization(&normalization);
+    if !changed && normalization.is_empty() {
+        outln!("Nothing to repair: {:?}", &args.file);
+        return Ok(());
+    }
+
+    record_audit(&mut png, args.audit, "repair", None)?;
+    if args.history {
+        history::record(&original, &mut png)?;
+    }
+
+    let file_path = match args.out {
+        Some(path) => path,
+        None => args.file,
+    };
+
+    save_png(&png, &file_path, args.backup)?;
+
+    outln!("Wrote repaired PNG to: {:?}", &file_path);
+
+    Ok(())
+}
+
+fn print_normalization(normalization: &Normalization) {
+    if normalization.added_iend {
+        outln!("Added missing IEND chunk");
+    }
+    for chunk in &normalization.removed {
+        outln!(
+            "Removed {} chunk after IEND ({} bytes)",
+            chunk.chunk_type(),
+            chunk.encoded_length()
+        );
+    }
+    if normalization.merged_idat > 0 {
+        outln!(
+            "Merged {} IDAT chunk(s) into the one before them",
+            normalization.merged_idat
+        );
+    }
+}
+
+/// Removes repeated copies of ancillary chunks from a PNG file and saves the result
+pub fn dedupe(args: DedupeArgs) -> anyhow::Result<()> {
+    let original = read_png(&args.file, args.preserve)?;
+    let mut png = original.clone();
+
+    let removed = png.dedupe_chunks();
+    if removed.is_empty() {
+        outln!("No duplicate chunks found: {:?}", &args.file);
+        return Ok(());
+    }
+
+    for chunk in &removed {
+        outln!(
+            "Removed duplicate {} chunk ({} bytes)",
+            chunk.chunk_type(),
+            chunk.encoded_length()
+        );
+    }
+    let saved: usize = removed.iter().map(Chunk::encoded_length).sum();
+    outln!("Saved {} bytes", saved);
+
+    record_audit(&mut png, args.audit, "dedupe", None)?;
+    if args.history {
+        history::record(&original, &mut png)?;
+    }
+
+    let file_path = match args.out {
+        Some(path) => path,
+        None => args.file,
+    };
+
+    save_png(&png, &file_path, args.backup)?;
+
+    outln!("Wrote deduplicated PNG to: {:?}", &file_path);
+
+    Ok(())
+}
+
+/// Recompresses the image data in a PNG file with zopfli, optionally removing chunks that
+/// don't affect how it's displayed, and saves the result
+pub fn optimize(args: OptimizeArgs) -> anyhow::Result<()> {
+    let original = read_png(&args.file, args.preserve)?;
+    let options = OptimizeOptions {
+        try_filters: args.filters,
+        strip: args.strip,
+        iterations: args.iterations,
+    };
+    let (mut png, report) = pngme::optimize::optimize(&original, &options)?;
+
+    for chunk in &report.stripped {
+        outln!(
+            "Stripped {} chunk ({} bytes)",
+            chunk.chunk_type(),
+            chunk.encoded_length()
+        );
+    }
+    outln!(
+        "IDAT: {} -> {} bytes ({} filters)",
+        report.idat_before,
+        report.idat_after,
+        report.filter
+    );
+
+    let before = original.as_bytes().len();
+    let after = png.as_bytes().len();
+    if after >= before {
+        outln!("Could not make the file any smaller: {:?}", &args.file);
+        return Ok(());
+    }
+    outln!(
+        "File: {} -> {} bytes ({:.1}% smaller)",
+        before,
+        after,
+        (before - after) as f64 * 100.0 / before as f64
+    );
+    if Seal::from_png(&png)?.is_some()
+        || png.chunk_by_type(signature::SIGNATURE_CHUNK_TYPE).is_some()
+    {
+        outln!("Note: the image data changed, so existing seals and signatures no longer match");
+    }
+
+    record_audit(&mut png, args.audit, "optimize", None)?;
+    if args.history {
+        history::record(&original, &mut png)?;
+    }
+
+    let file_path = match args.out {
+        Some(path) => path,
+        None => args.file,
+    };
+
+    save_png(&png, &file_path, args.backup)?;
+
+    outln!("Wrote optimized PNG to: {:?}", &file_path);
+
+    Ok(())
+}
+
+/// Prints how many bytes in a PNG file don't contribute to the image, and the commands
+/// that would reclaim them
+pub fn report(args: ReportArgs) -> anyhow::Result<()> {
+    let bytes = fs::read(&args.file)?;
+    let report = report::waste_report(&bytes)?;
+    let file = args.file.display();
+
+    outln!("File size: {} bytes", report.file_size);
+    print_usage(
+        "Non-essential chunks",
+        &report.non_essential,
+        &format!("pngme optimize {} --strip", file),
+    );
+    print_usage(
+        "Padding in standard chunks",
+        &report.padding,
+        &format!("pngme anonymize {}", file),
+    );
+    print_usage(
+        "Duplicate chunks",
+        &report.duplicates,
+        &format!("pngme dedupe {}", file),
+    );
+    if report.idat_overhead > 0 {
+        outln!("Extra IDAT chunk headers: {} bytes", report.idat_overhead);
+    }
+    if report.recompression > 0 {
+        outln!(
+            "Image data recompression: at least {} bytes",
+            report.recompression
+        );
+    }
+    if report.idat_overhead + report.recompression > 0 {
+        outln!("    reclaim with: pngme optimize {}", file);
+    }
+    if report.trailing > 0 {
+        outln!("Trailing data: {} bytes", report.trailing);
+        outln!(
+            "    reclaim with: truncate -s {} {}",
+            report.file_size - report.trailing,
+            file
+        );
+    }
+
+    let total = report.total();
+    if total == 0 {
+        outln!("Nothing to reclaim");
+    } else {
+        outln!(
+            "Reclaimable: {} bytes ({:.1}% of the file)",
+            total,
+            total as f64 * 100.0 / report.file_size as f64
+        );
+    }
+
+    Ok(())
+}
+
+/// Writes every chunk in a PNG file to its own file in a directory, with a manifest
+pub fn explode(args: ExplodeArgs) -> anyhow::Result<()> {
+    let png = Png::from_file(&args.file)?;
+    let manifest = explode::explode(&png, &args.dir)?;
+
+    outln!(
+        "Wrote {} chunks and {} to: {:?}",
+        manifest.chunks.len(),
+        MANIFEST_FILE,
+        &args.dir
+    );
+
+    Ok(())
+}
+
+/// Rebuilds a PNG file from a directory written by `explode`
+pub fn implode(args: ImplodeArgs) -> anyhow::Result<()> {
+    let png = explode::implode(&args.dir)?;
+    save_png(&png, &args.out, args.backup)?;
+
+    outln!(
+        "Wrote PNG with {} chunks to: {:?}",
+        png.chunks().len(),
+        &args.out
+    );
+
+    Ok(())
+}
+
+/// Creates a carrier image filled with random content
+pub fn generate(args: GenerateArgs) -> anyhow::Result<()> {
+    let png = generate::generate(args.size, args.style)?;
+    save_png(&png, &args.out, false)?;
+
+    outln!("Wrote {} image to: {:?}", args.size, &args.out);
+
+    Ok(())
+}
+
+/// Applies every step of a plan to every file it matches. All of the files are edited in
+/// memory first and only written once every step has worked on every file, and if any
+/// write fails the files already written are restored. Either every file is updated or
+/// none are.
+pub fn apply(args: ApplyArgs) -> anyhow::Result<()> {
+    let plan = Plan::load(&args.plan)?;
+    let base = match args.plan.parent() {
+        Some(directory) if !directory.as_os_str().is_empty() => directory,
+        _ => Path::new("."),
+    };
+    let files = plan.matching_files(base)?;
+
+    let edits = batch::map(&files, args.jobs, |file| -> anyhow::Result<_> {
+        let bytes = fs::read(file).map_err(|e| anyhow::anyhow!("Can't read {:?}: {}", file, e))?;
+        let mut container = Container::try_from(bytes.as_slice())
+            .map_err(|e| anyhow::anyhow!("Can't read {:?}: {}", file, e))?;
+
+        let mut changes = Vec::with_capacity(plan.steps.len());
+        for step in &plan.steps {
+            let change = step
+                .apply(&mut container)
+                .map_err(|e| anyhow::anyhow!("Step \"{}\" failed on {:?}: {}", step, file, e))?;
+            changes.push(change);
+        }
+
+        let out = plan.output_path(base, file)?;
+        let edited = container.checked_bytes()?;
+        let line = format!(
+            "{}: {} ({} -> {} bytes)",
+            out.display(),
+            changes.join(", "),
+            bytes.len(),
+            edited.len()
+        );
+        Ok((line, (out, edited)))
+    })?;
+
+    let mut outputs = Vec::with_capacity(files.len());
+    let mut summary = Vec::with_capacity(files.len());
+    for edit in edits {
+        let (line, output) = edit?;
+        summary.push(line);
+        outputs.push(output);
+    }
+
+    for line in &summary {
+        outln!("{}", line);
+    }
+
+    if args.dry_run {
+        outln!("Dry run: {} file(s) left unchanged", outputs.len());
+        return Ok(());
+    }
+
+    for (out, _) in &outputs {
+        if let Some(directory) = out.parent() {
+            fs::create_dir_all(directory)?;
+        }
+    }
+    atomic::write_all(&outputs)?;
+    outln!(
+        "Applied {} step(s) to {} file(s)",
+        plan.steps.len(),
+        outputs.len()
+    );
+
+    Ok(())
+}
+
+fn print_usage(title: &str, usage: &[ChunkUsage], command: &str) {
+    if usage.is_empty() {
+        return;
+    }
+
+    let total: usize = usage.iter().map(|usage| usage.bytes).sum();
+    outln!("{}: {} bytes", title, total);
+    for usage in usage {
+        outln!(
+            "    {}  {:>4} {:<7} {:>8} bytes",
+            usage.chunk_type,
+            usage.count,
+            if usage.count == 1 { "chunk" } else { "chunks" },
+            usage.bytes
+        );
+    }
+    outln!("    reclaim with: {}", command);
+}
+
+/// Removes identifying metadata and private chunks from a PNG file and saves the result
+pub fn anonymize(args: AnonymizeArgs) -> anyhow::Result<()> {
+    let png = Png::from_file(&args.file)?;
+    let keep = args
+        .keep
+        .iter()
+        .map(|chunk_type| ChunkType::from_str(chunk_type))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let (png, report) = pngme::anonymize::anonymize(&png, &keep);
 
-    png.append_chunk(Chunk::new(chunk_type, data));
+    for chunk_type in &report.removed {
+        outln!("Removed {} chunk", chunk_type);
+    }
+    for (chunk_type, bytes) in &report.scrubbed {
+        outln!(
+            "Scrubbed {} trailing bytes from {} chunk",
+            bytes,
+            chunk_type
+        );
+    }
+
+    if report.is_clean() {
+        outln!("Nothing to remove: {:?}", &args.file);
+        return Ok(());
+    }
 
     let file_path = match args.out {
         Some(path) => path,
         None => args.file,
     };
 
-    fs::write(&file_path, &png.as_bytes())?;
+    save_png(&png, &file_path, args.backup)?;
 
-    println!("Wrote message to: {:?}", &file_path);
+    outln!("Wrote anonymized PNG to: {:?}", &file_path);
 
     Ok(())
 }
 
-/// Searches for a message hidden in a PNG file and prints the message if one is found
-pub fn decode(args: DecodeArgs) -> anyhow::Result<()> {
-    let png = Png::from_file(&args.file)?;
+/// Opens the graphical frontend
+#[cfg(feature = "gui")]
+pub fn gui(args: GuiArgs) -> anyhow::Result<()> {
+    crate::gui::run(args.file)
+}
+
+/// Copies metadata chunks from one PNG file to another
+pub fn clone_meta(args: CloneMetaArgs) -> anyhow::Result<()> {
+    let from = Png::from_file(&args.from)?;
+    let to = Png::from_file(&args.to)?;
+
+    let (png, report) = pngme::clone_meta::clone_meta(&from, &to, args.force)?;
+    for chunk_type in &report.skipped {
+        eprintln!(
+            "Warning: skipped {} chunk, which is unsafe to copy into an image with different \
+             critical chunks. Copy it anyway with --force",
+            chunk_type
+        );
+    }
+    if report.copied.is_empty() {
+        outln!("No metadata to copy in: {:?}", &args.from);
+        return Ok(());
+    }
+    for chunk_type in &report.copied {
+        outln!("Copied {} chunk", chunk_type);
+    }
+
+    let file_path = match args.out {
+        Some(path) => path,
+        None => args.to,
+    };
 
-    match png.chunk_by_type(&args.chunk) {
-        Some(message_chunk) => {
-            let message = std::str::from_utf8(message_chunk.data())?;
-            println!("{}", message);
+    save_png(&png, &file_path, args.backup)?;
+
+    outln!("Wrote PNG with copied metadata to: {:?}", &file_path);
+
+    Ok(())
+}
+
+/// Reads or edits text metadata
+pub fn meta(args: MetaArgs) -> anyhow::Result<()> {
+    match args.command {
+        MetaCommand::List(list_args) => meta_list(list_args),
+        MetaCommand::SetStandard(set_args) => meta_set_standard(set_args),
+        MetaCommand::XmpExtract(extract_args) => xmp_extract(extract_args),
+        MetaCommand::XmpInject(inject_args) => xmp_inject(inject_args),
+        MetaCommand::XmpGet(get_args) => xmp_get(get_args),
+        MetaCommand::Exif(exif_args) => exif(exif_args),
+        MetaCommand::Comment(comment_args) => meta_comment(comment_args),
+    }
+}
+
+/// Prints every tEXt chunk in a PNG file, or every comment in a GIF file
+fn meta_list(args: MetaListArgs) -> anyhow::Result<()> {
+    let png = match Container::from_file(&args.file)? {
+        Container::Png(png) => png,
+        Container::Gif(gif) => {
+            for comment in gif.comments() {
+                outln!("Comment: {}", String::from_utf8_lossy(&comment));
+            }
+            return Ok(());
+        }
+        container => anyhow::bail!("{} files have no text metadata", container.format()),
+    };
+
+    for chunk in png.chunks() {
+        if chunk.chunk_type().to_string() == "tEXt" {
+            let text = TextChunk::try_from(chunk)?;
+            outln!("{}: {}", text.keyword(), text.text());
         }
-        None => println!("Error: No chunk of type {}", &args.chunk),
     }
 
     Ok(())
 }
 
-/// Removes a chunk from a PNG file and saves the result
-pub fn remove(args: RemoveArgs) -> anyhow::Result<()> {
-    let mut png = Png::from_file(&args.file)?;
-    png.remove_chunk(&args.chunk)?;
-    fs::write(&args.file, &png.as_bytes())?;
-    println!("Removed message from: {:?}", &args.file);
+/// Writes the predefined text keywords, replacing any existing values, and saves the result
+fn meta_set_standard(args: SetStandardArgs) -> anyhow::Result<()> {
+    let original = read_png(&args.file, args.preserve)?;
+    let mut png = original.clone();
+
+    // In the same order as STANDARD_KEYWORDS
+    let values = [
+        &args.title,
+        &args.author,
+        &args.description,
+        &args.copyright,
+        &args.creation_time,
+        &args.software,
+        &args.disclaimer,
+        &args.warning,
+        &args.source,
+        &args.comment,
+    ];
+
+    let texts = STANDARD_KEYWORDS
+        .iter()
+        .zip(values.iter())
+        .filter_map(|(keyword, value)| value.as_ref().map(|value| TextChunk::new(keyword, value)))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    if texts.is_empty() {
+        anyhow::bail!("Nothing to set. Pass at least one of --title, --author, --comment, ...");
+    }
+
+    for text in &texts {
+        png.retain_chunks(|chunk| match TextChunk::try_from(chunk) {
+            Ok(existing) => existing.keyword() != text.keyword(),
+            Err(_) => true,
+        });
+        png.insert_chunk(text.to_chunk());
+        outln!("Set {}", text);
+    }
+
+    record_audit(&mut png, args.audit, "meta set-standard", None)?;
+    if args.history {
+        history::record(&original, &mut png)?;
+    }
+
+    let file_path = match args.out {
+        Some(path) => path,
+        None => args.file,
+    };
+
+    save_png(&png, &file_path, args.backup)?;
+
+    outln!("Wrote metadata to: {:?}", &file_path);
 
     Ok(())
 }
 
-/// Prints all of the chunks in a PNG file
-pub fn print_chunks(args: PrintArgs) -> anyhow::Result<()> {
+/// Adds a comment extension to a GIF file, or removes all of them, and saves the result
+fn meta_comment(args: CommentArgs) -> anyhow::Result<()> {
+    let mut gif = match Container::from_file(&args.file)? {
+        Container::Gif(gif) => gif,
+        container => anyhow::bail!(
+            "Comments can only be set on GIF files, not {}. Use `meta set-standard --comment` \
+             for PNG files",
+            container.format()
+        ),
+    };
+
+    match &args.text {
+        Some(text) => {
+            gif.append_comment(text.as_bytes());
+            outln!("Added comment");
+        }
+        None => outln!("Removed {} comment(s)", gif.remove_comments()),
+    }
+
+    let file_path = args.out.unwrap_or(args.file);
+    save_file(
+        &Container::Gif(gif).checked_bytes()?,
+        &file_path,
+        args.backup,
+    )?;
+    outln!("Wrote {:?}", &file_path);
+
+    Ok(())
+}
+
+/// Saves the XMP packet in a PNG file to its own file
+fn xmp_extract(args: XmpExtractArgs) -> anyhow::Result<()> {
+    let png = Png::from_file(&args.file)?;
+    let packet = xmp::packet(&png)?.ok_or_else(|| anyhow::anyhow!("No XMP packet found"))?;
+
+    fs::write(&args.xmp, packet)?;
+
+    outln!("Wrote XMP packet to: {:?}", &args.xmp);
+
+    Ok(())
+}
+
+/// Stores the contents of an XMP file in a PNG file and saves the result
+fn xmp_inject(args: XmpInjectArgs) -> anyhow::Result<()> {
+    let original = read_png(&args.file, args.preserve)?;
+    let packet = fs::read_to_string(&args.xmp)?;
+
+    let mut png = original.clone();
+    xmp::set_packet(&mut png, &packet)?;
+    record_audit(
+        &mut png,
+        args.audit,
+        "meta xmp-inject",
+        Some(&ChunkType::from_str("iTXt")?),
+    )?;
+    if args.history {
+        history::record(&original, &mut png)?;
+    }
+
+    let file_path = match args.out {
+        Some(path) => path,
+        None => args.file,
+    };
+
+    save_png(&png, &file_path, args.backup)?;
+
+    outln!("Wrote XMP packet to: {:?}", &file_path);
+
+    Ok(())
+}
+
+/// Prints the requested XMP properties, one value per line
+fn xmp_get(args: XmpGetArgs) -> anyhow::Result<()> {
+    if args.properties.is_empty() {
+        anyhow::bail!("Name at least one property, e.g. dc:creator");
+    }
+
+    let png = Png::from_file(&args.file)?;
+    let packet = xmp::packet(&png)?.ok_or_else(|| anyhow::anyhow!("No XMP packet found"))?;
+
+    for name in &args.properties {
+        for value in xmp::property(&packet, name) {
+            outln!("{}: {}", name, value);
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the name and value of every EXIF tag
+fn exif(args: ExifArgs) -> anyhow::Result<()> {
     let bytes = fs::read(&args.file)?;
-    let png = Png::try_from(bytes.as_ref())?;
-    println!("{}", png);
+    let tiff = if Tiff::is_tiff(&bytes) {
+        Tiff::try_from(bytes.as_ref())?
+    } else {
+        let png = Png::try_from(bytes.as_ref())?;
+        let chunk = png
+            .chunk_by_type("eXIf")
+            .ok_or_else(|| anyhow::anyhow!("No eXIf chunk found"))?;
+        Tiff::try_from(chunk.data())?
+    };
+
+    for entry in tiff.all_entries()? {
+        outln!("{}: {}", entry.name(), entry.display(tiff.byte_order()));
+    }
+
+    Ok(())
+}
+
+/// Exports or imports an embedded ICC profile
+pub fn icc(args: IccArgs) -> anyhow::Result<()> {
+    match args.command {
+        IccCommand::Export(export_args) => icc_export(export_args),
+        IccCommand::Import(import_args) => icc_import(import_args),
+    }
+}
+
+/// Decompresses the iCCP profile in a PNG file and saves it
+fn icc_export(args: IccExportArgs) -> anyhow::Result<()> {
+    let png = Png::from_file(&args.file)?;
+    let profile =
+        IccProfile::from_png(&png)?.ok_or_else(|| anyhow::anyhow!("No ICC profile found"))?;
+
+    fs::write(&args.profile, &profile.data)?;
+
+    outln!(
+        "Wrote ICC profile {:?} ({} bytes) to: {:?}",
+        profile.name,
+        profile.data.len(),
+        &args.profile
+    );
 
     Ok(())
 }
+
+/// Embeds an ICC profile file in a PNG file and saves the result
+fn icc_import(args: IccImportArgs) -> anyhow::Result<()> {
+    let original = read_png(&args.file, args.preserve)?;
+    let profile = IccProfile::new(&args.name, fs::read(&args.profile)?)?;
+
+    if original.chunk_by_type("sRGB").is_some() {
+        outln!("Removing sRGB chunk, which cannot be used together with an ICC profile");
+    }
+    let mut png = pngme::icc::embed(&original, &profile)?;
+    record_audit(
+        &mut png,
+        args.audit,
+        "icc import",
+        Some(&ChunkType::from_str("iCCP")?),
+    )?;
+    if args.history {
+        history::record(&original, &mut png)?;
+    }
+
+    let file_path = match args.out {
+        Some(path) => path,
+        None => args.file,
+    };
+
+    save_png(&png, &file_path, args.backup)?;
+
+    outln!("Wrote ICC profile to: {:?}", &file_path);
+
+    Ok(())
+}
+
+/// Reverts the most recent edit recorded with `--history` and saves the result
+pub fn undo(args: UndoArgs) -> anyhow::Result<()> {
+    let png = read_png(&args.file, args.preserve)?;
+    let mut png = history::undo(&png)?;
+    record_audit(&mut png, args.audit, "undo", None)?;
+
+    let file_path = match args.out {
+        Some(path) => path,
+        None => args.file,
+    };
+
+    save_png(&png, &file_path, args.backup)?;
+
+    outln!(
+        "Undid last edit ({} more can be undone): {:?}",
+        history::depth(&png),
+        &file_path
+    );
+
+    Ok(())
+}
+
+/// Prints the audit trail of a PNG file, oldest entry first
+pub fn print_history(args: HistoryArgs) -> anyhow::Result<()> {
+    let png = Png::from_file(&args.file)?;
+    let entries = audit::entries(&png)?;
+    if entries.is_empty() {
+        outln!("No audit trail found: {:?}", &args.file);
+        return Ok(());
+    }
+
+    for entry in entries {
+        let time = UNIX_EPOCH + Duration::from_secs(entry.timestamp);
+        outln!(
+            "{}  pngme {:<8} {:<18} {}",
+            humantime::format_rfc3339_seconds(time),
+            entry.version,
+            entry.operation,
+            entry.chunk_type.as_deref().unwrap_or("-")
+        );
+    }
+
+    Ok(())
+}
+
+/// Adds an entry for `operation` to the audit trail of `png` if `enabled` is set or the
+/// file already has a trail
+fn record_audit(
+    png: &mut Png,
+    enabled: bool,
+    operation: &str,
+    chunk_type: Option<&ChunkType>,
+) -> anyhow::Result<()> {
+    if enabled || audit::has_trail(png) {
+        audit::record(png, &audit::Entry::new(operation, chunk_type)?)?;
+    }
+    Ok(())
+}
+
+/// Like `record_audit`, for commands that work with any format. Only PNG files have an
+/// audit trail.
+fn record_container_audit(
+    container: &mut Container,
+    enabled: bool,
+    operation: &str,
+    chunk_type: &ChunkType,
+) -> anyhow::Result<()> {
+    match container {
+        Container::Png(png) => record_audit(png, enabled, operation, Some(chunk_type)),
+        _ if enabled => anyhow::bail!(
+            "--audit only works with PNG files, not {}",
+            container.format()
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Keeps the chunks `container` replaced, so that the edit can be reverted with `undo`,
+/// if `enabled` is set
+fn record_container_history(
+    original: &Container,
+    container: &mut Container,
+    enabled: bool,
+) -> anyhow::Result<()> {
+    match (original, container) {
+        _ if !enabled => Ok(()),
+        (Container::Png(original), Container::Png(png)) => history::record(original, png),
+        (_, container) => anyhow::bail!(
+            "--history only works with PNG files, not {}",
+            container.format()
+        ),
+    }
+}
+
+fn without_whitespace(text: &str) -> String {
+    text.split_whitespace().collect()
+}
+
+/// Refuses an edit that grows a file from `original_length` to `new_length` bytes by more
+/// than `budget`
+fn check_growth(
+    original_length: u64,
+    new_length: u64,
+    budget: Option<ByteSize>,
+) -> anyhow::Result<()> {
+    let growth = new_length.saturating_sub(original_length);
+    match budget {
+        Some(budget) if growth > budget.0 => anyhow::bail!(
+            "The file would grow by {} bytes, more than the --max-growth budget of {}",
+            growth,
+            budget
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Reads a PNG file. With `preserve`, damaged chunks and trailing data are kept as they
+/// are instead of failing, and a note says how much was kept.
+fn read_png(path: &Path, preserve: bool) -> anyhow::Result<Png> {
+    if !preserve {
+        return Png::from_file(path);
+    }
+
+    let png = Png::from_bytes_preserving(&fs::read(path).in_file(path)?).in_file(path)?;
+    note_preserved(&png);
+    Ok(png)
+}
+
+/// Like `read_png`, for commands that work with any format
+fn read_container(path: &Path, preserve: bool) -> anyhow::Result<Container> {
+    if !preserve {
+        return Container::from_file(path);
+    }
+
+    let container = Container::from_bytes_preserving(&container::read_file(path)?).in_file(path)?;
+    if let Container::Png(png) = &container {
+        note_preserved(png);
+    }
+    Ok(container)
+}
+
+fn note_preserved(png: &Png) {
+    let damaged = png
+        .chunks()
+        .iter()
+        .filter(|chunk| !chunk.is_crc_valid())
+        .count();
+    if damaged > 0 {
+        outln!("Keeping {} chunk(s) with bad CRCs unchanged", damaged);
+    }
+    if !png.trailer().is_empty() {
+        outln!("Keeping {} trailing bytes unchanged", png.trailer().len());
+    }
+}
+
+/// Saves `png` to `path` without risking a half-written file, first copying the
+/// existing file to `path.bak` if `keep_backup` is set
+fn save_png(png: &Png, path: &Path, keep_backup: bool) -> anyhow::Result<()> {
+    if keep_backup {
+        backup(path)?;
+    }
+    png.save(path)
+}
+
+/// Like `save_png`, for files in any format. Files inside a ZIP archive are written
+/// back into it, and the backup is of the whole archive.
+fn save_file(bytes: &[u8], path: &Path, keep_backup: bool) -> anyhow::Result<()> {
+    if is_data_url(path) {
+        anyhow::bail!("An image given as a data: URL can't be saved in place");
+    }
+    let zip_path = ZipPath::parse(path);
+    if keep_backup {
+        backup(zip_path.as_ref().map_or(path, |zip_path| &zip_path.archive))?;
+    }
+    match zip_path {
+        Some(zip_path) => zip_path.write(bytes),
+        None => atomic::write(path, bytes),
+    }
+}
+
+/// Returns where encode and remove write their result: over `file` when editing in place,
+/// otherwise to `out` or a new file next to `file`, like `dice.pngme.png` for `dice.png`.
+/// Existing files other than `file` itself are only overwritten with --overwrite.
+fn output_path(
+    file: &Path,
+    out: Option<PathBuf>,
+    in_place: bool,
+    overwrite: bool,
+) -> anyhow::Result<PathBuf> {
+    if in_place {
+        return Ok(file.to_path_buf());
+    }
+    let path = match out {
+        Some(out) => out,
+        None if ZipPath::parse(file).is_some() => anyhow::bail!(
+            "Pass --in-place to write the result back into the archive, or an output path"
+        ),
+        None => {
+            let mut name = file.file_stem().unwrap_or_default().to_os_string();
+            name.push(".pngme");
+            if let Some(extension) = file.extension() {
+                name.push(".");
+                name.push(extension);
+            }
+            file.with_file_name(name)
+        }
+    };
+    if path.exists() && !overwrite {
+        return Err(anyhow::anyhow!(
+            "{:?} already exists. Pass --overwrite to overwrite it, or --in-place to write over \
+             the original",
+            path
+        ))
+        .code("output_exists");
+    }
+    Ok(path)
+}
+
+/// Returns the length of the file at `path`, which may be inside a ZIP archive or a
+/// `data:` URL
+fn file_length(path: &Path) -> anyhow::Result<u64> {
+    match path.is_file() {
+        true => Ok(fs::metadata(path).in_file(path)?.len()),
+        false => Ok(container::read_file(path)?.len() as u64),
+    }
+}
+
+/// Warns that `chunk` is a registered chunk type whose data doesn't follow that type's
+/// layout, since software that understands the type may reject or rewrite it
+fn warn_if_nonconforming(chunk: &Chunk) {
+    if let Err(problem) = conformance::check(chunk.chunk_type(), chunk.data()) {
+        eprintln!(
+            "Warning: {} is a registered chunk type and this data {}. Software that \
+             understands {} chunks may reject the image or rewrite the chunk, losing the message",
+            chunk.chunk_type(),
+            problem,
+            chunk.chunk_type()
+        );
+    }
+}
+
+fn is_data_url(path: &Path) -> bool {
+    path.to_str().is_some_and(data_url::is_data_url)
+}
+
+fn backup(path: &Path) -> anyhow::Result<()> {
+    if let Some(backup_path) = atomic::backup(path)? {
+        outln!("Backed up original to: {:?}", &backup_path);
+    }
+    Ok(())
+}
+
+/// Returns the manifest describing a message split across chunks of type `chunk_type`
+fn find_manifest(container: &Container, chunk_type: &str) -> anyhow::Result<Option<Manifest>> {
+    for data in container.messages(MANIFEST_CHUNK_TYPE)? {
+        let manifest = Manifest::try_from(data.as_ref())?;
+        if manifest.describes(chunk_type) {
+            return Ok(Some(manifest));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the message stored under `chunk_type`, putting it back together first if it
+/// was split and taking it out of its envelope
+fn read_payload(container: &Container, chunk_type: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let data = match find_manifest(container, chunk_type)? {
+        Some(manifest) => Some(manifest.assemble(&container.messages(chunk_type)?)?),
+        None => container.message(chunk_type)?,
+    };
+    data.map(|data| Ok(envelope::unwrap(&data)?.to_vec()))
+        .transpose()
+}
+
+/// Says there is no chunk of type `chunk_type`, suggesting the type present in the file
+/// that it is closest to
+fn missing_chunk(container: &Container, chunk_type: &str) -> anyhow::Result<String> {
+    let present = container.message_types()?;
+    Ok(match chunk_type::closest(chunk_type, &present) {
+        Some(closest) => format!(
+            "No chunk of type {}. Did you mean '{}'?",
+            chunk_type, closest
+        ),
+        None => format!("No chunk of type {}", chunk_type),
+    })
+}
+
+/// Removes every part of a message split across chunks of type `chunk_type`, along with
+/// its manifest. Returns false if there was no split message.
+fn remove_split_message(container: &mut Container, chunk_type: &str) -> anyhow::Result<bool> {
+    if find_manifest(container, chunk_type)?.is_none() {
+        return Ok(false);
+    }
+
+    let manifest_type = ChunkType::from_str(MANIFEST_CHUNK_TYPE)?;
+    let manifests = container.messages(MANIFEST_CHUNK_TYPE)?;
+    container.remove_messages(&manifest_type)?;
+    for data in manifests {
+        if !Manifest::try_from(data.as_ref())?.describes(chunk_type) {
+            container.append_message(&manifest_type, data)?;
+        }
+    }
+    container.remove_messages(&ChunkType::from_str(chunk_type)?)?;
+
+    Ok(true)
+}
+
+/// Reads enough of the start of a file to tell which format it is in
+fn file_header(path: &Path) -> anyhow::Result<Vec<u8>> {
+    let mut header = Vec::with_capacity(Format::MAGIC_LENGTH);
+    File::open(path)?
+        .take(Format::MAGIC_LENGTH as u64)
+        .read_to_end(&mut header)?;
+    Ok(header)
+}
+
+/// Decrypts chunk data if it is encrypted, prompting for a passphrase when no
+/// key or identity file or `passphrase` was given. Unencrypted data is returned as is.
+fn decrypt_message(
+    data: &[u8],
+    args: &DecodeArgs,
+    passphrase: Option<&str>,
+) -> anyhow::Result<Vec<u8>> {
+    let prompt_passphrase = || match passphrase {
+        Some(passphrase) => Ok(passphrase.to_string()),
+        None => rpassword::prompt_password("Passphrase: "),
+    };
+
+    if crypto::is_age_encrypted(data) {
+        return match &args.age_identity {
+            Some(identity_file) => {
+                crypto::decrypt_with_age_identity(data, &fs::read(identity_file)?)
+            }
+            None => {
+                anyhow::bail!("This message is encrypted to age recipients. Pass --age-identity")
+            }
+        };
+    }
+
+    if obfuscate::is_obfuscated(data) {
+        return match &args.obfuscate {
+            Some(key) => obfuscate::reveal(data, key),
+            None => anyhow::bail!("This message is obfuscated. Pass its key with --obfuscate"),
+        };
+    }
+
+    if recipients::is_multi_recipient(data) {
+        return match &args.key_file {
+            Some(key_file) => recipients::decrypt(data, &keys::read_key_file(key_file)?),
+            None => anyhow::bail!("This message is encrypted to recipients. Pass --key-file"),
+        };
+    }
+
+    if deniable::is_deniable(data) {
+        if args.key_file.is_some() {
+            anyhow::bail!("This message is encrypted with a passphrase, not a key");
+        }
+        return deniable::decrypt(data, &prompt_passphrase()?);
+    }
+
+    if !crypto::is_encrypted(data) {
+        return Ok(data.to_vec());
+    }
+
+    let secret = match &args.key_file {
+        Some(key_file) => Secret::Key(keys::read_key_file(key_file)?),
+        None if crypto::is_passphrase_encrypted(data) => Secret::Passphrase(prompt_passphrase()?),
+        None => anyhow::bail!("This message is encrypted with a key. Pass --key-file"),
+    };
+
+    crypto::decrypt(data, &secret)
+}
+
+/// Prompts for a new passphrase twice, showing `label`, and returns it if both entries
+/// match
+fn prompt_new_passphrase(label: &str) -> anyhow::Result<String> {
+    let passphrase = rpassword::prompt_password(format!("{}: ", label))?;
+    let confirmation = rpassword::prompt_password(format!("Confirm {}: ", label.to_lowercase()))?;
+
+    if passphrase != confirmation {
+        anyhow::bail!("Passphrases do not match");
+    }
+    if passphrase.is_empty() {
+        anyhow::bail!("Passphrase must not be empty");
+    }
+
+    Ok(passphrase)
+}
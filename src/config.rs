@@ -0,0 +1,168 @@
+use std::convert::TryFrom;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::png::ChunkType;
+
+/// The chunk type encode stores messages in when none is given and the config file
+/// doesn't name one. It is ancillary, private and safe to copy, so viewers ignore it and
+/// editors keep it.
+pub const DEFAULT_CHUNK_TYPE: &str = "puNk";
+
+/// Settings read from the config file, used where no command line option is given.
+///
+/// The file is TOML with settings shared by several commands at the top, and one table
+/// per command, for example
+///
+/// ```toml
+/// chunk_type = "ruSt"
+///
+/// [encode]
+/// max_growth = "4KiB"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The chunk type encode stores messages in when none is given
+    pub chunk_type: Option<String>,
+    pub encode: EncodeConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct EncodeConfig {
+    /// The most an encode may grow a file by
+    pub max_growth: Option<ByteSize>,
+}
+
+impl Config {
+    /// Reads the config file named by `PNGME_CONFIG`, or else `pngme/config.toml` in the
+    /// user's config directory. A missing file gives the default config.
+    pub fn load() -> anyhow::Result<Self> {
+        let path = match env::var_os("PNGME_CONFIG") {
+            Some(path) => PathBuf::from(path),
+            None => match config_dir() {
+                Some(dir) => dir.join("pngme").join("config.toml"),
+                None => return Ok(Self::default()),
+            },
+        };
+
+        match fs::read_to_string(&path) {
+            Ok(text) => Self::from_str(&text)
+                .map_err(|error| anyhow::anyhow!("Invalid config file {:?}: {}", path, error)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    /// The chunk type to use when none is given: the one in the config file, or else
+    /// `DEFAULT_CHUNK_TYPE`
+    pub fn default_chunk_type(&self) -> &str {
+        self.chunk_type.as_deref().unwrap_or(DEFAULT_CHUNK_TYPE)
+    }
+}
+
+impl FromStr for Config {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        let config: Self = toml::from_str(text)?;
+        if let Some(name) = &config.chunk_type {
+            ChunkType::from_str(name)
+                .map_err(|error| anyhow::anyhow!("Invalid chunk_type {:?}: {}", name, error))?;
+        }
+        Ok(config)
+    }
+}
+
+fn config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+/// A number of bytes, written like `512`, `4KiB`, `10kB` or `1.5MiB`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ByteSize(pub u64);
+
+impl FromStr for ByteSize {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        let text = text.trim();
+        let split = text
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+
+        let multiplier: u64 = match unit.trim() {
+            "" | "B" => 1,
+            "kB" | "KB" | "k" | "K" => 1_000,
+            "KiB" => 1 << 10,
+            "MB" | "M" => 1_000_000,
+            "MiB" => 1 << 20,
+            "GB" | "G" => 1_000_000_000,
+            "GiB" => 1 << 30,
+            unit => anyhow::bail!("Unknown size unit {:?}", unit),
+        };
+        let number: f64 = number
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid size {:?}", text))?;
+
+        Ok(Self((number * multiplier as f64).round() as u64))
+    }
+}
+
+impl TryFrom<String> for ByteSize {
+    type Error = anyhow::Error;
+
+    fn try_from(text: String) -> anyhow::Result<Self> {
+        Self::from_str(&text)
+    }
+}
+
+impl fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} bytes", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sizes() {
+        assert_eq!(ByteSize::from_str("512").unwrap(), ByteSize(512));
+        assert_eq!(ByteSize::from_str("4KiB").unwrap(), ByteSize(4096));
+        assert_eq!(ByteSize::from_str("10kB").unwrap(), ByteSize(10_000));
+        assert_eq!(ByteSize::from_str("1.5MiB").unwrap(), ByteSize(1_572_864));
+        assert_eq!(ByteSize::from_str("2 GB").unwrap(), ByteSize(2_000_000_000));
+        assert!(ByteSize::from_str("4 furlongs").is_err());
+        assert!(ByteSize::from_str("KiB").is_err());
+    }
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::from_str("[encode]\nmax_growth = \"4KiB\"\n").unwrap();
+        assert_eq!(config.encode.max_growth, Some(ByteSize(4096)));
+
+        assert_eq!(Config::from_str("").unwrap().encode.max_growth, None);
+        assert!(Config::from_str("[encode]\nmax_groth = \"4KiB\"\n").is_err());
+        assert!(Config::from_str("[encode]\nmax_growth = \"lots\"\n").is_err());
+    }
+
+    #[test]
+    fn test_default_chunk_type() {
+        assert_eq!(Config::default().default_chunk_type(), DEFAULT_CHUNK_TYPE);
+        let config = Config::from_str("chunk_type = \"ruSt\"\n").unwrap();
+        assert_eq!(config.default_chunk_type(), "ruSt");
+        assert!(Config::from_str("chunk_type = \"rust!\"\n").is_err());
+    }
+}
@@ -0,0 +1,134 @@
+use crate::png::ChunkType;
+use crate::text::validate_keyword;
+
+/// Checks `data` against the layout the PNG spec or a registered extension gives chunks
+/// of type `chunk_type`, and returns what is wrong with it. Software that understands
+/// those chunks may reject, rewrite or drop ones that don't conform. Chunk types that
+/// aren't registered, and registered ones whose layout depends on other chunks, like
+/// tRNS, always pass.
+pub fn check(chunk_type: &ChunkType, data: &[u8]) -> Result<(), String> {
+    let length = data.len();
+    let exactly = |expected: usize| match length == expected {
+        true => Ok(()),
+        false => Err(format!("must be {} byte(s) long, not {}", expected, length)),
+    };
+    let one_of = |field: &str, value: Option<&u8>, allowed: &[u8]| match value {
+        Some(value) if allowed.contains(value) => Ok(()),
+        Some(value) => Err(format!("has an invalid {} of {}", field, value)),
+        None => Err(format!("is missing its {}", field)),
+    };
+
+    match chunk_type.to_string().as_str() {
+        "IHDR" => exactly(13),
+        "IEND" => exactly(0),
+        "PLTE" if length == 0 || length > 768 || !length.is_multiple_of(3) => Err(format!(
+            "must hold 1 to 256 three byte colors, not {} bytes",
+            length
+        )),
+        "acTL" | "cLLi" => exactly(8),
+        "fcTL" => exactly(26),
+        "cHRM" => exactly(32),
+        "mDCv" => exactly(24),
+        "cICP" | "gAMA" => exactly(4),
+        "fdAT" if length < 4 => Err("is missing its sequence number".to_string()),
+        "bKGD" if ![1, 2, 6].contains(&length) => {
+            Err(format!("must be 1, 2 or 6 bytes long, not {}", length))
+        }
+        "sBIT" if !(1..=4).contains(&length) => {
+            Err(format!("must be 1 to 4 bytes long, not {}", length))
+        }
+        "hIST" if !length.is_multiple_of(2) => Err("must hold two byte frequencies".to_string()),
+        "oFFs" | "pHYs" => {
+            exactly(9)?;
+            one_of("unit", data.get(8), &[0, 1])
+        }
+        "sRGB" => {
+            exactly(1)?;
+            one_of("rendering intent", data.first(), &[0, 1, 2, 3])
+        }
+        "sTER" => {
+            exactly(1)?;
+            one_of("layout", data.first(), &[0, 1])
+        }
+        "tIME" => {
+            exactly(7)?;
+            let valid = (1..=12).contains(&data[2])
+                && (1..=31).contains(&data[3])
+                && data[4] < 24
+                && data[5] < 60
+                && data[6] <= 60;
+            match valid {
+                true => Ok(()),
+                false => Err("holds an invalid date or time".to_string()),
+            }
+        }
+        "eXIf" if !data.starts_with(b"MM\0*") && !data.starts_with(b"II*\0") => {
+            Err("doesn't start with a TIFF header".to_string())
+        }
+        "tEXt" => keyword(data).map(|_| ()),
+        "zTXt" | "iCCP" => {
+            let rest = keyword(data)?;
+            one_of("compression method", rest.first(), &[0])
+        }
+        "iTXt" => {
+            let rest = keyword(data)?;
+            one_of("compression flag", rest.first(), &[0, 1])?;
+            one_of("compression method", rest.get(1), &[0])
+        }
+        "sPLT" => {
+            let rest = keyword(data)?;
+            one_of("sample depth", rest.first(), &[8, 16])
+        }
+        "sCAL" => one_of("unit", data.first(), &[1, 2]),
+        "pCAL" => keyword(data).map(|_| ()),
+        _ => Ok(()),
+    }
+}
+
+/// Checks the keyword that starts text, profile and palette chunks, and returns the
+/// bytes after the null that ends it
+fn keyword(data: &[u8]) -> Result<&[u8], String> {
+    let end = data
+        .iter()
+        .position(|&byte| byte == 0)
+        .ok_or_else(|| "is missing the null byte after its keyword".to_string())?;
+    // Keywords are Latin-1, where every byte is the character with the same number
+    let keyword: String = data[..end].iter().map(|&byte| char::from(byte)).collect();
+    validate_keyword(&keyword).map_err(|error| format!("has an invalid keyword: {}", error))?;
+    Ok(&data[end + 1..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_str(chunk_type: &str, data: &[u8]) -> Result<(), String> {
+        check(&chunk_type.parse().unwrap(), data)
+    }
+
+    #[test]
+    fn test_conforming_data() {
+        assert!(check_str("sTER", &[1]).is_ok());
+        assert!(check_str("tIME", &[7, 228, 12, 31, 23, 59, 59]).is_ok());
+        assert!(check_str("tEXt", b"Comment\0hello").is_ok());
+        assert!(check_str("eXIf", b"II*\0rest").is_ok());
+        assert!(check_str("tRNS", b"anything").is_ok());
+        assert!(check_str("ruSt", b"anything").is_ok());
+    }
+
+    #[test]
+    fn test_nonconforming_data() {
+        assert_eq!(
+            check_str("sTER", b"secret"),
+            Err("must be 1 byte(s) long, not 6".to_string())
+        );
+        assert_eq!(
+            check_str("oFFs", &[0, 0, 0, 0, 0, 0, 0, 0, 7]),
+            Err("has an invalid unit of 7".to_string())
+        );
+        assert!(check_str("eXIf", b"secret").is_err());
+        assert!(check_str("tEXt", b"no keyword").is_err());
+        assert!(check_str("zTXt", b"Comment\0\x05data").is_err());
+        assert!(check_str("tIME", &[7, 228, 13, 31, 23, 59, 59]).is_err());
+    }
+}
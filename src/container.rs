@@ -0,0 +1,408 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::data_url;
+
+use crate::error::Locate;
+use crate::gif::Gif;
+use crate::jpeg::Jpeg;
+use crate::png::{self_check_failed, Chunk, ChunkType, Png};
+use crate::tiff::Tiff;
+use crate::zip_path::ZipPath;
+
+/// An image file format, recognized by the magic bytes at the start of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Jpeg,
+    Gif,
+    WebP,
+    Tiff,
+}
+
+impl Format {
+    /// The number of bytes `detect` needs to tell every format apart
+    pub const MAGIC_LENGTH: usize = 12;
+
+    /// Returns the format of a file starting with `bytes`
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        if bytes.starts_with(&Png::SIGNATURE) {
+            Some(Format::Png)
+        } else if Jpeg::is_jpeg(bytes) {
+            Some(Format::Jpeg)
+        } else if Gif::is_gif(bytes) {
+            Some(Format::Gif)
+        } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+            Some(Format::WebP)
+        } else if Tiff::is_tiff(bytes) {
+            Some(Format::Tiff)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the media type of this format, like `image/png`
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Format::Png => "image/png",
+            Format::Jpeg => "image/jpeg",
+            Format::Gif => "image/gif",
+            Format::WebP => "image/webp",
+            Format::Tiff => "image/tiff",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Format::Png => "PNG",
+            Format::Jpeg => "JPEG",
+            Format::Gif => "GIF",
+            Format::WebP => "WebP",
+            Format::Tiff => "TIFF",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An image in any supported format, holding messages under four letter chunk types.
+/// PNG files keep each message in a chunk of that type, while other formats use a
+/// segment, extension or tag reserved for messages.
+#[derive(Debug, Clone)]
+pub enum Container {
+    Png(Png),
+    Jpeg(Jpeg),
+    Gif(Gif),
+    Tiff(Tiff),
+}
+
+impl Container {
+    /// Reads an image from a path that `read_file` understands
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let bytes = read_file(path)?;
+        Self::try_from(bytes.as_ref()).in_file(path)
+    }
+
+    /// Like `TryFrom<&[u8]>`, but keeps damaged PNG chunks and trailing data as they are.
+    /// See `Png::from_bytes_preserving`.
+    pub fn from_bytes_preserving(bytes: &[u8]) -> anyhow::Result<Self> {
+        match Format::detect(bytes) {
+            Some(Format::Png) => Ok(Container::Png(Png::from_bytes_preserving(bytes)?)),
+            _ => Self::try_from(bytes),
+        }
+    }
+
+    pub fn format(&self) -> Format {
+        match self {
+            Container::Png(_) => Format::Png,
+            Container::Jpeg(_) => Format::Jpeg,
+            Container::Gif(_) => Format::Gif,
+            Container::Tiff(_) => Format::Tiff,
+        }
+    }
+
+    /// Returns the first message stored under `chunk_type`
+    pub fn message(&self, chunk_type: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(match self {
+            Container::Png(png) => png
+                .chunk_by_type(chunk_type)
+                .map(|chunk| chunk.data().to_vec()),
+            Container::Jpeg(jpeg) => jpeg.message(chunk_type).map(<[u8]>::to_vec),
+            Container::Gif(gif) => gif.message(chunk_type),
+            Container::Tiff(tiff) => tiff.message(chunk_type)?,
+        })
+    }
+
+    /// Returns every message stored under `chunk_type`, in the order they appear
+    pub fn messages(&self, chunk_type: &str) -> anyhow::Result<Vec<Vec<u8>>> {
+        let matches = |found: &ChunkType| found.to_string() == chunk_type;
+        Ok(match self {
+            Container::Png(png) => png
+                .chunks()
+                .iter()
+                .filter(|chunk| matches(chunk.chunk_type()))
+                .map(|chunk| chunk.data().to_vec())
+                .collect(),
+            Container::Jpeg(jpeg) => jpeg
+                .messages()
+                .filter(|(found, _)| matches(found))
+                .map(|(_, message)| message.to_vec())
+                .collect(),
+            Container::Gif(gif) => gif
+                .messages()
+                .filter(|(found, _)| matches(found))
+                .map(|(_, message)| message)
+                .collect(),
+            Container::Tiff(tiff) => tiff
+                .messages()?
+                .into_iter()
+                .filter(|(found, _)| matches(found))
+                .map(|(_, message)| message)
+                .collect(),
+        })
+    }
+
+    /// Returns the chunk types messages are stored under, without repeats. For PNG files
+    /// this is every chunk type in the file.
+    pub fn message_types(&self) -> anyhow::Result<Vec<ChunkType>> {
+        let mut types: Vec<ChunkType> = match self {
+            Container::Png(png) => png.chunks().iter().map(|c| *c.chunk_type()).collect(),
+            Container::Jpeg(jpeg) => jpeg.messages().map(|(found, _)| found).collect(),
+            Container::Gif(gif) => gif.messages().map(|(found, _)| found).collect(),
+            Container::Tiff(tiff) => tiff
+                .messages()?
+                .into_iter()
+                .map(|(found, _)| found)
+                .collect(),
+        };
+        types.sort();
+        types.dedup();
+        Ok(types)
+    }
+
+    /// Adds a message stored under `chunk_type`
+    pub fn append_message(
+        &mut self,
+        chunk_type: &ChunkType,
+        message: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        match self {
+            Container::Png(png) => png.append_chunk(Chunk::new(*chunk_type, message)),
+            Container::Jpeg(jpeg) => jpeg.append_message(chunk_type, &message)?,
+            Container::Gif(gif) => gif.append_message(chunk_type, &message),
+            Container::Tiff(tiff) => tiff.append_message(chunk_type, &message)?,
+        }
+        Ok(())
+    }
+
+    /// Replaces the first message stored under `chunk_type`, or adds one if there is none.
+    /// In PNG files the chunk keeps its position; in other formats the message moves to
+    /// the end.
+    pub fn replace_message(
+        &mut self,
+        chunk_type: &ChunkType,
+        message: Vec<u8>,
+    ) -> anyhow::Result<()> {
+        if let Container::Png(png) = self {
+            let found = png
+                .chunks_mut()
+                .iter_mut()
+                .find(|chunk| chunk.chunk_type() == chunk_type);
+            if let Some(chunk) = found {
+                chunk.set_data(message);
+                return Ok(());
+            }
+        } else if self.message(&chunk_type.to_string())?.is_some() {
+            self.remove_message(&chunk_type.to_string())?;
+        }
+        self.append_message(chunk_type, message)
+    }
+
+    /// Adds `data` to the end of the first message stored under `chunk_type`, or adds a
+    /// message if there is none
+    pub fn append_to_message(&mut self, chunk_type: &ChunkType, data: &[u8]) -> anyhow::Result<()> {
+        if let Container::Png(png) = self {
+            let found = png
+                .chunks_mut()
+                .iter_mut()
+                .find(|chunk| chunk.chunk_type() == chunk_type);
+            if let Some(chunk) = found {
+                chunk.append_data(data);
+                return Ok(());
+            }
+        }
+
+        let mut message = self.message(&chunk_type.to_string())?.unwrap_or_default();
+        message.extend_from_slice(data);
+        self.replace_message(chunk_type, message)
+    }
+
+    /// Removes the first message stored under `chunk_type` and returns its contents
+    pub fn remove_message(&mut self, chunk_type: &str) -> anyhow::Result<Vec<u8>> {
+        match self {
+            Container::Png(png) => Ok(png.remove_chunk(chunk_type)?.into_data()),
+            Container::Jpeg(jpeg) => jpeg.remove_message(chunk_type),
+            Container::Gif(gif) => gif.remove_message(chunk_type),
+            Container::Tiff(tiff) => tiff.remove_message(chunk_type),
+        }
+    }
+
+    /// Removes every message stored under `chunk_type`
+    pub fn remove_messages(&mut self, chunk_type: &ChunkType) -> anyhow::Result<()> {
+        let keep = |found: &ChunkType| found != chunk_type;
+        match self {
+            Container::Png(png) => png.retain_chunks(|chunk| keep(chunk.chunk_type())),
+            Container::Jpeg(jpeg) => jpeg.retain_messages(keep),
+            Container::Gif(gif) => gif.retain_messages(keep),
+            Container::Tiff(tiff) => tiff.retain_messages(keep)?,
+        }
+        Ok(())
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        match self {
+            Container::Png(png) => png.as_bytes(),
+            Container::Jpeg(jpeg) => jpeg.as_bytes(),
+            Container::Gif(gif) => gif.as_bytes(),
+            Container::Tiff(tiff) => tiff.as_bytes().to_vec(),
+        }
+    }
+
+    /// Like `as_bytes`, but reads the bytes back first and checks that they hold the
+    /// same messages, so that a bug in writing the file out fails the save instead of
+    /// replacing a good file with a broken one. PNG files are checked chunk by chunk,
+    /// see `Png::checked_bytes`.
+    pub fn checked_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        if let Container::Png(png) = self {
+            return png.checked_bytes();
+        }
+
+        let bytes = self.as_bytes();
+        let parsed = match Container::try_from(bytes.as_slice()) {
+            Ok(parsed) => parsed,
+            Err(error) => return self_check_failed(error),
+        };
+        let types = self.message_types()?;
+        if parsed.message_types()? != types {
+            return self_check_failed(anyhow::anyhow!("the message types differ"));
+        }
+        for chunk_type in types {
+            let name = chunk_type.to_string();
+            if parsed.messages(&name)? != self.messages(&name)? {
+                return self_check_failed(anyhow::anyhow!("the {} messages differ", name));
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+/// Reads the file at `path`. The path may also name an image inside a ZIP archive, like
+/// `bundle.zip!assets/icon.png`, or be a `data:` URL holding the image itself.
+pub fn read_file(path: &Path) -> anyhow::Result<Vec<u8>> {
+    if let Some(url) = path.to_str().filter(|text| data_url::is_data_url(text)) {
+        return data_url::decode(url);
+    }
+    match ZipPath::parse(path) {
+        Some(zip_path) => zip_path.read(),
+        None => fs::read(path).in_file(path),
+    }
+}
+
+impl TryFrom<&[u8]> for Container {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> anyhow::Result<Self> {
+        match Format::detect(bytes) {
+            Some(Format::Png) => Ok(Container::Png(Png::try_from(bytes)?)),
+            Some(Format::Jpeg) => Ok(Container::Jpeg(Jpeg::try_from(bytes)?)),
+            Some(Format::Gif) => Ok(Container::Gif(Gif::try_from(bytes)?)),
+            Some(Format::Tiff) => Ok(Container::Tiff(Tiff::try_from(bytes)?)),
+            Some(format) => Err(anyhow::anyhow!("{} files are not supported yet", format))
+                .code("unsupported_format"),
+            None => Err(anyhow::anyhow!("Unrecognized file format")).code("unsupported_format"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_png() -> Vec<u8> {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+        .as_bytes()
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(Format::detect(&testing_png()), Some(Format::Png));
+        assert_eq!(
+            Format::detect(&[0xFF, 0xD8, 0xFF, 0xE0]),
+            Some(Format::Jpeg)
+        );
+        assert_eq!(Format::detect(b"GIF89a"), Some(Format::Gif));
+        assert_eq!(Format::detect(b"RIFF\0\0\0\0WEBPVP8 "), Some(Format::WebP));
+        assert_eq!(Format::detect(b"MM\0*\0\0\0\x08"), Some(Format::Tiff));
+        assert_eq!(Format::detect(b"RIFF\0\0\0\0WAVE"), None);
+        assert_eq!(Format::detect(b""), None);
+    }
+
+    #[test]
+    fn test_png_messages() {
+        let mut container = Container::try_from(testing_png().as_ref()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        container
+            .append_message(&chunk_type, b"one".to_vec())
+            .unwrap();
+        container
+            .append_message(&chunk_type, b"two".to_vec())
+            .unwrap();
+
+        assert_eq!(container.format(), Format::Png);
+        assert_eq!(container.message("ruSt").unwrap(), Some(b"one".to_vec()));
+        assert_eq!(
+            container.messages("ruSt").unwrap(),
+            vec![b"one".to_vec(), b"two".to_vec()]
+        );
+        assert_eq!(container.remove_message("ruSt").unwrap(), b"one");
+
+        container.remove_messages(&chunk_type).unwrap();
+        assert_eq!(container.message("ruSt").unwrap(), None);
+    }
+
+    #[test]
+    fn test_replace_png_message() {
+        let mut container = Container::try_from(testing_png().as_ref()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        container
+            .replace_message(&chunk_type, b"first".to_vec())
+            .unwrap();
+        container
+            .append_message(&ChunkType::from_str("teSt").unwrap(), b"after".to_vec())
+            .unwrap();
+        container
+            .replace_message(&chunk_type, b"edited".to_vec())
+            .unwrap();
+
+        assert_eq!(
+            container.messages("ruSt").unwrap(),
+            vec![b"edited".to_vec()]
+        );
+        match &container {
+            Container::Png(png) => {
+                assert_eq!(png.chunks()[2].chunk_type(), &chunk_type);
+                assert!(png.chunks()[2].is_crc_valid());
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn test_append_to_message() {
+        let mut container = Container::try_from(testing_png().as_ref()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        container.append_to_message(&chunk_type, b"first").unwrap();
+        container
+            .append_to_message(&chunk_type, b" second")
+            .unwrap();
+        assert_eq!(
+            container.messages("ruSt").unwrap(),
+            vec![b"first second".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_unsupported_format() {
+        let error = Container::try_from(&b"RIFF\0\0\0\0WEBPVP8 "[..]).unwrap_err();
+        assert_eq!(error.to_string(), "WebP files are not supported yet");
+        assert!(Container::try_from(&b"plain text"[..]).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use std::convert::TryFrom;
+use std::io::Cursor;
+
+use image::{DynamicImage, ImageFormat};
+
+use crate::png::Png;
+
+/// Decodes an image in any format the image crate reads, like JPEG, BMP or TIFF, and
+/// returns its pixels as a PNG. Samples keep their bit depth, except that floating point
+/// images become 16 bits per channel since PNG has no floating point samples. Metadata
+/// isn't carried over.
+pub fn to_png(bytes: &[u8]) -> anyhow::Result<Png> {
+    let image = image::load_from_memory(bytes)
+        .map_err(|error| anyhow::anyhow!("Couldn't read the image to convert it: {}", error))?;
+    let image = match image {
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+            DynamicImage::ImageRgba16(image.to_rgba16())
+        }
+        image => image,
+    };
+
+    let mut encoded = Cursor::new(Vec::new());
+    image.write_to(&mut encoded, ImageFormat::Png)?;
+    Png::try_from(encoded.get_ref().as_slice())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generate::{self, Size};
+    use crate::pixels;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_bmp_to_png() {
+        let original =
+            generate::generate(Size::from_str("5x3").unwrap(), generate::Style::Noise).unwrap();
+        let decoded = image::load_from_memory(&original.as_bytes()).unwrap();
+        let mut bmp = Cursor::new(Vec::new());
+        decoded.write_to(&mut bmp, ImageFormat::Bmp).unwrap();
+
+        let converted = to_png(bmp.get_ref()).unwrap();
+        assert!(pixels::same_pixels(&original, &converted).unwrap());
+    }
+
+    #[test]
+    fn test_not_an_image() {
+        assert!(to_png(b"This is a secret message!").is_err());
+    }
+}
@@ -0,0 +1,443 @@
+use std::convert::TryInto;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+
+/// Marks the start of a passphrase-encrypted payload
+pub const MAGIC: [u8; 4] = *b"PMEC";
+
+/// Marks the start of a payload encrypted directly with a 32 byte key
+pub const KEY_MAGIC: [u8; 4] = *b"PMEK";
+
+/// Marks the start of a payload encrypted to age recipients
+pub const AGE_MAGIC: &[u8] = b"age-encryption.org/v1\n";
+
+const VERSION: u8 = 1;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + 4 + 4 + 4 + SALT_LENGTH + NONCE_LENGTH;
+const KEY_HEADER_LENGTH: usize = KEY_MAGIC.len() + 1 + NONCE_LENGTH;
+
+/// The most Argon2id memory, in KiB, that a payload may ask for. The parameters come
+/// from the payload, so without limits a crafted file could make decrypting it allocate
+/// gigabytes or run for hours.
+pub const MAX_KDF_MEMORY_KIB: u32 = 1024 * 1024;
+/// The most Argon2id passes over the memory that a payload may ask for
+pub const MAX_KDF_ITERATIONS: u32 = 64;
+/// The most Argon2id lanes that a payload may ask for
+pub const MAX_KDF_PARALLELISM: u32 = 16;
+
+/// The secret used to encrypt or decrypt a payload
+pub enum Secret {
+    /// A passphrase which is stretched into a key with Argon2id
+    Passphrase(String),
+    /// A raw 256 bit key, typically read from a key file
+    Key([u8; 32]),
+}
+
+/// Cost parameters for the Argon2id key derivation function.
+/// These are stored in the payload header so decryption always uses the values
+/// the payload was encrypted with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    /// Memory cost in KiB
+    pub memory_kib: u32,
+    /// Number of passes over the memory
+    pub iterations: u32,
+    /// Number of lanes
+    pub parallelism: u32,
+}
+
+impl KdfParams {
+    /// Returns an error if any cost is above its maximum, like `MAX_KDF_MEMORY_KIB`
+    pub fn check(&self) -> anyhow::Result<()> {
+        let limits = [
+            ("memory cost", self.memory_kib, MAX_KDF_MEMORY_KIB, " KiB"),
+            ("iteration count", self.iterations, MAX_KDF_ITERATIONS, ""),
+            ("parallelism", self.parallelism, MAX_KDF_PARALLELISM, ""),
+        ];
+        for (name, value, max, unit) in limits {
+            if value > max {
+                anyhow::bail!(
+                    "Argon2id {} of {}{} is above the maximum of {}{}",
+                    name,
+                    value,
+                    unit,
+                    max,
+                    unit
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Derives a 256 bit key from a passphrase and salt using Argon2id. The parameters
+    /// are checked against their maximums first.
+    pub fn derive_key(&self, passphrase: &[u8], salt: &[u8]) -> anyhow::Result<[u8; 32]> {
+        self.check()?;
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|e| anyhow::anyhow!("Invalid key derivation parameters: {}", e))?;
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+
+        let mut key = [0; 32];
+        argon2
+            .hash_password_into(passphrase, salt, &mut key)
+            .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+
+        Ok(key)
+    }
+}
+
+impl Default for KdfParams {
+    /// The OWASP recommended minimum for Argon2id
+    fn default() -> Self {
+        Self {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+/// Returns true if `data` starts with either encrypted payload header
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC) || data.starts_with(&KEY_MAGIC)
+}
+
+/// Returns true if `data` was encrypted with a passphrase rather than a key
+pub fn is_passphrase_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Encrypts `plaintext` with the given secret. `params` is only used for passphrases.
+///
+/// Passphrase-encrypted payloads are laid out as follows
+/// 1. Magic bytes `PMEC` *(4 bytes)*
+/// 2. Format version *(1 byte)*
+/// 3. Argon2id memory cost in KiB *(4 bytes)*
+/// 4. Argon2id iterations *(4 bytes)*
+/// 5. Argon2id parallelism *(4 bytes)*
+/// 6. Salt *(16 bytes)*
+/// 7. Nonce *(12 bytes)*
+/// 8. ChaCha20-Poly1305 ciphertext and tag
+///
+/// Key-encrypted payloads are laid out as follows
+/// 1. Magic bytes `PMEK` *(4 bytes)*
+/// 2. Format version *(1 byte)*
+/// 3. Nonce *(12 bytes)*
+/// 4. ChaCha20-Poly1305 ciphertext and tag
+///
+/// In both cases the header is authenticated along with the ciphertext.
+pub fn encrypt(plaintext: &[u8], secret: &Secret, params: KdfParams) -> anyhow::Result<Vec<u8>> {
+    let mut nonce = [0; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let mut result = Vec::with_capacity(HEADER_LENGTH + plaintext.len() + 16);
+    let key = match secret {
+        Secret::Passphrase(passphrase) => {
+            let mut salt = [0; SALT_LENGTH];
+            rand::thread_rng().fill_bytes(&mut salt);
+
+            result.extend_from_slice(&MAGIC);
+            result.push(VERSION);
+            result.extend_from_slice(&params.memory_kib.to_be_bytes());
+            result.extend_from_slice(&params.iterations.to_be_bytes());
+            result.extend_from_slice(&params.parallelism.to_be_bytes());
+            result.extend_from_slice(&salt);
+
+            params.derive_key(passphrase.as_bytes(), &salt)?
+        }
+        Secret::Key(key) => {
+            result.extend_from_slice(&KEY_MAGIC);
+            result.push(VERSION);
+            *key
+        }
+    };
+    result.extend_from_slice(&nonce);
+
+    let ciphertext = seal(&key, &nonce, &result, plaintext)?;
+    result.extend(ciphertext);
+    Ok(result)
+}
+
+/// Decrypts a payload created by `encrypt`. This function will return an error if the
+/// secret is wrong, if it is the wrong kind of secret for this payload, or if the payload
+/// has been modified.
+pub fn decrypt(data: &[u8], secret: &Secret) -> anyhow::Result<Vec<u8>> {
+    if !is_encrypted(data) || data.len() < KEY_HEADER_LENGTH {
+        anyhow::bail!("Data is not an encrypted payload");
+    }
+
+    let version = data[4];
+    if version != VERSION {
+        anyhow::bail!("Unsupported encryption format version {}", version);
+    }
+
+    match secret {
+        Secret::Passphrase(passphrase) => {
+            if !is_passphrase_encrypted(data) {
+                anyhow::bail!("This payload was encrypted with a key file, not a passphrase");
+            }
+            if data.len() < HEADER_LENGTH {
+                anyhow::bail!("Encrypted payload is truncated");
+            }
+
+            let read_u32 =
+                |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+            let params = KdfParams {
+                memory_kib: read_u32(5),
+                iterations: read_u32(9),
+                parallelism: read_u32(13),
+            };
+            params.check()?;
+
+            let salt = &data[17..17 + SALT_LENGTH];
+            let nonce = &data[17 + SALT_LENGTH..HEADER_LENGTH];
+            let (header, ciphertext) = data.split_at(HEADER_LENGTH);
+
+            let key = params.derive_key(passphrase.as_bytes(), salt)?;
+            open(&key, nonce, header, ciphertext).map_err(|_| {
+                anyhow::anyhow!("Decryption failed: wrong passphrase or corrupted data")
+            })
+        }
+        Secret::Key(key) => {
+            if is_passphrase_encrypted(data) {
+                anyhow::bail!("This payload was encrypted with a passphrase, not a key file");
+            }
+
+            let nonce = &data[5..KEY_HEADER_LENGTH];
+            let (header, ciphertext) = data.split_at(KEY_HEADER_LENGTH);
+
+            open(key, nonce, header, ciphertext)
+                .map_err(|_| anyhow::anyhow!("Decryption failed: wrong key or corrupted data"))
+        }
+    }
+}
+
+/// Returns true if `data` is an age encrypted file
+pub fn is_age_encrypted(data: &[u8]) -> bool {
+    data.starts_with(AGE_MAGIC)
+}
+
+/// Encrypts `plaintext` to one or more age recipients. Each recipient may be a native
+/// `age1...` public key or an SSH public key such as `ssh-ed25519 AAAA...`.
+pub fn encrypt_to_age_recipients(
+    plaintext: &[u8],
+    recipients: &[String],
+) -> anyhow::Result<Vec<u8>> {
+    let recipients = recipients
+        .iter()
+        .map(|recipient| parse_age_recipient(recipient))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let encryptor = age::Encryptor::with_recipients(recipients.iter().map(|r| r.as_ref()))?;
+
+    let mut result = Vec::new();
+    let mut writer = encryptor.wrap_output(&mut result)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?;
+
+    Ok(result)
+}
+
+/// Decrypts an age payload using the contents of an identity file. The identity file may be
+/// a native age identity file or an unencrypted SSH private key.
+pub fn decrypt_with_age_identity(data: &[u8], identity_file: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let native = age::IdentityFile::from_buffer(identity_file)
+        .ok()
+        .and_then(|file| file.into_identities().ok())
+        .filter(|identities| !identities.is_empty());
+
+    let identities: Vec<Box<dyn age::Identity>> = match native {
+        Some(identities) => identities,
+        None => match age::ssh::Identity::from_buffer(identity_file, None)? {
+            identity @ age::ssh::Identity::Unencrypted(_) => vec![Box::new(identity)],
+            age::ssh::Identity::Encrypted(_) => {
+                anyhow::bail!("Passphrase protected SSH keys are not supported")
+            }
+            age::ssh::Identity::Unsupported(_) => anyhow::bail!("Unsupported SSH key type"),
+        },
+    };
+
+    let decryptor = age::Decryptor::new_buffered(data)?;
+    let mut reader = decryptor.decrypt(identities.iter().map(|i| i.as_ref()))?;
+
+    let mut result = Vec::new();
+    reader.read_to_end(&mut result)?;
+
+    Ok(result)
+}
+
+fn parse_age_recipient(recipient: &str) -> anyhow::Result<Box<dyn age::Recipient>> {
+    if let Ok(recipient) = age::x25519::Recipient::from_str(recipient) {
+        return Ok(Box::new(recipient));
+    }
+
+    match age::ssh::Recipient::from_str(recipient) {
+        Ok(recipient) => Ok(Box::new(recipient)),
+        Err(_) => anyhow::bail!("Invalid age recipient: {}", recipient),
+    }
+}
+
+/// Encrypts and authenticates `plaintext`, additionally authenticating `aad`
+pub(crate) fn seal(
+    key: &[u8; 32],
+    nonce: &[u8],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let payload = Payload {
+        msg: plaintext,
+        aad,
+    };
+
+    cipher
+        .encrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| anyhow::anyhow!("Encryption failed"))
+}
+
+/// Reverses `seal`. Fails if the ciphertext or `aad` were modified or the key is wrong.
+pub(crate) fn open(
+    key: &[u8; 32],
+    nonce: &[u8],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let cipher = ChaCha20Poly1305::new(Key::from_slice(key));
+    let payload = Payload {
+        msg: ciphertext,
+        aad,
+    };
+
+    cipher
+        .decrypt(Nonce::from_slice(nonce), payload)
+        .map_err(|_| anyhow::anyhow!("Decryption failed"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_params() -> KdfParams {
+        KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+
+    fn passphrase(value: &str) -> Secret {
+        Secret::Passphrase(value.to_string())
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let secret = passphrase("hunter2");
+        let encrypted = encrypt(b"This is a secret message!", &secret, testing_params()).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(is_passphrase_encrypted(&encrypted));
+
+        let decrypted = decrypt(&encrypted, &secret).unwrap();
+        assert_eq!(decrypted, b"This is a secret message!");
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let encrypted = encrypt(
+            b"This is a secret message!",
+            &passphrase("hunter2"),
+            testing_params(),
+        )
+        .unwrap();
+        assert!(decrypt(&encrypted, &passphrase("hunter3")).is_err());
+    }
+
+    #[test]
+    fn test_tampered_header() {
+        let secret = passphrase("hunter2");
+        let mut encrypted =
+            encrypt(b"This is a secret message!", &secret, testing_params()).unwrap();
+        // Lower the iteration count stored in the header
+        encrypted[12] = 2;
+        assert!(decrypt(&encrypted, &secret).is_err());
+    }
+
+    #[test]
+    fn test_kdf_limits() {
+        let secret = passphrase("hunter2");
+        let mut encrypted = encrypt(b"message", &secret, testing_params()).unwrap();
+        // Raise the memory cost stored in the header to 4 TiB
+        encrypted[5..9].copy_from_slice(&u32::MAX.to_be_bytes());
+        let error = decrypt(&encrypted, &secret).unwrap_err();
+        assert!(error.to_string().contains("memory cost"));
+
+        let params = KdfParams {
+            iterations: MAX_KDF_ITERATIONS + 1,
+            ..testing_params()
+        };
+        assert!(encrypt(b"message", &secret, params).is_err());
+    }
+
+    #[test]
+    fn test_salt_is_random() {
+        let secret = passphrase("hunter2");
+        let first = encrypt(b"message", &secret, testing_params()).unwrap();
+        let second = encrypt(b"message", &secret, testing_params()).unwrap();
+        assert_ne!(first, second);
+    }
+
+    #[test]
+    fn test_key_round_trip() {
+        let secret = Secret::Key([7; 32]);
+        let encrypted = encrypt(b"This is a secret message!", &secret, testing_params()).unwrap();
+        assert!(is_encrypted(&encrypted));
+        assert!(!is_passphrase_encrypted(&encrypted));
+
+        let decrypted = decrypt(&encrypted, &secret).unwrap();
+        assert_eq!(decrypted, b"This is a secret message!");
+
+        assert!(decrypt(&encrypted, &Secret::Key([8; 32])).is_err());
+        assert!(decrypt(&encrypted, &passphrase("hunter2")).is_err());
+    }
+
+    #[test]
+    fn test_age_round_trip() {
+        use age::secrecy::ExposeSecret;
+
+        let identity = age::x25519::Identity::generate();
+        let recipient = identity.to_public().to_string();
+
+        let encrypted =
+            encrypt_to_age_recipients(b"This is a secret message!", &[recipient]).unwrap();
+        assert!(is_age_encrypted(&encrypted));
+        assert!(!is_encrypted(&encrypted));
+
+        let identity_file = identity.to_string();
+        let decrypted =
+            decrypt_with_age_identity(&encrypted, identity_file.expose_secret().as_bytes())
+                .unwrap();
+        assert_eq!(decrypted, b"This is a secret message!");
+
+        let other_identity = age::x25519::Identity::generate().to_string();
+        let result =
+            decrypt_with_age_identity(&encrypted, other_identity.expose_secret().as_bytes());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_invalid_age_recipient() {
+        let recipients = vec!["age1notarealkey".to_string()];
+        assert!(encrypt_to_age_recipients(b"message", &recipients).is_err());
+    }
+
+    #[test]
+    fn test_not_encrypted() {
+        assert!(!is_encrypted(b"plain text"));
+        assert!(decrypt(b"plain text", &passphrase("hunter2")).is_err());
+    }
+}
@@ -0,0 +1,102 @@
+use base64::Engine;
+
+use crate::container::Format;
+
+const SCHEME: &str = "data:";
+
+/// Returns true if `text` is a `data:` URL rather than a path
+pub fn is_data_url(text: &str) -> bool {
+    text.get(..SCHEME.len())
+        .is_some_and(|scheme| scheme.eq_ignore_ascii_case(SCHEME))
+}
+
+/// Returns the bytes in a URL like `data:image/png;base64,iVBORw0KGgo...`. The media
+/// type isn't checked, since the bytes say what format they are. Data that isn't base64
+/// is percent-decoded.
+pub fn decode(url: &str) -> anyhow::Result<Vec<u8>> {
+    if !is_data_url(url) {
+        anyhow::bail!("Not a data: URL");
+    }
+    let (header, data) = url[SCHEME.len()..]
+        .split_once(',')
+        .ok_or_else(|| anyhow::anyhow!("The data: URL has no comma before its data"))?;
+
+    let is_base64 = header
+        .rsplit(';')
+        .next()
+        .is_some_and(|parameter| parameter.eq_ignore_ascii_case("base64"));
+    match is_base64 {
+        // Line breaks and spaces creep in when URLs are copied out of HTML or CSS
+        true => base64::engine::general_purpose::STANDARD
+            .decode(data.split_whitespace().collect::<String>())
+            .map_err(|error| anyhow::anyhow!("Invalid base64 in the data: URL: {}", error)),
+        false => percent_decode(data),
+    }
+}
+
+/// Returns `bytes` as a base64 `data:` URL, with the media type of the image format the
+/// bytes are in
+pub fn encode(bytes: &[u8]) -> String {
+    let media_type =
+        Format::detect(bytes).map_or("application/octet-stream", |format| format.media_type());
+    format!(
+        "{}{};base64,{}",
+        SCHEME,
+        media_type,
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    )
+}
+
+fn percent_decode(text: &str) -> anyhow::Result<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte != b'%' {
+            bytes.push(byte);
+            rest = tail;
+            continue;
+        }
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid percent escape in the data: URL"))?;
+        bytes.push(escaped);
+        rest = &tail[2..];
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Png;
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = Png::new_minimal().as_bytes();
+        let url = encode(&bytes);
+        assert!(url.starts_with("data:image/png;base64,iVBORw0KGgo"));
+        assert!(is_data_url(&url));
+        assert_eq!(decode(&url).unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_decode_variants() {
+        assert_eq!(decode("DATA:;BASE64,aGk=").unwrap(), b"hi");
+        assert_eq!(decode("data:image/png;base64,aG\n  k=").unwrap(), b"hi");
+        assert_eq!(decode("data:,a%20b%2c").unwrap(), b"a b,");
+        assert_eq!(
+            encode(b"not an image"),
+            "data:application/octet-stream;base64,bm90IGFuIGltYWdl"
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert!(decode("image.png").is_err());
+        assert!(decode("data:image/png;base64").is_err());
+        assert!(decode("data:image/png;base64,!!!").is_err());
+        assert!(decode("data:,%4").is_err());
+    }
+}
@@ -0,0 +1,220 @@
+use std::convert::TryInto;
+
+use rand::{Rng, RngCore};
+
+use crate::crypto::{self, KdfParams};
+
+/// Marks the start of a payload holding two messages, each encrypted with its own
+/// passphrase
+pub const MAGIC: [u8; 4] = *b"PMED";
+
+const VERSION: u8 = 1;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 12;
+const SLOT_HEADER_LENGTH: usize = SALT_LENGTH + NONCE_LENGTH;
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + 12 + 2 * SLOT_HEADER_LENGTH;
+const TAG_LENGTH: usize = 16;
+
+/// Returns true if `data` is a payload created by `encrypt`
+pub fn is_deniable(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Encrypts `message` with `passphrase`, and `decoy` with a second passphrase, into one
+/// payload with two slots. Each passphrase decrypts only its own message. Without a
+/// decoy the second slot is filled with random bytes, which look just like a ciphertext.
+///
+/// Nothing in the payload says which slot holds what: both messages are padded to the
+/// same length, the slots are put in a random order and their ciphertexts are interleaved
+/// byte by byte. Someone forced to give up one passphrase can't be shown that the other
+/// slot is anything but filler.
+///
+/// The result is laid out as follows
+/// 1. Magic bytes `PMED` *(4 bytes)*
+/// 2. Format version *(1 byte)*
+/// 3. Argon2id memory cost in KiB, iterations and parallelism *(4 bytes each)*
+/// 4. The salt and nonce of each slot *(28 bytes each)*
+/// 5. The ChaCha20-Poly1305 ciphertexts and tags of both slots, interleaved
+///
+/// Each slot's plaintext is the length of its message *(4 bytes)*, the message and zero
+/// padding. The whole header is authenticated along with each ciphertext.
+pub fn encrypt(
+    message: &[u8],
+    passphrase: &str,
+    decoy: Option<(&[u8], &str)>,
+    params: KdfParams,
+) -> anyhow::Result<Vec<u8>> {
+    if decoy.is_some_and(|(_, decoy_passphrase)| decoy_passphrase == passphrase) {
+        anyhow::bail!("The decoy and the real message need different passphrases");
+    }
+
+    let mut result = Vec::with_capacity(HEADER_LENGTH);
+    result.extend_from_slice(&MAGIC);
+    result.push(VERSION);
+    result.extend_from_slice(&params.memory_kib.to_be_bytes());
+    result.extend_from_slice(&params.iterations.to_be_bytes());
+    result.extend_from_slice(&params.parallelism.to_be_bytes());
+
+    let mut slot_headers = [[0; SLOT_HEADER_LENGTH]; 2];
+    for header in &mut slot_headers {
+        rand::thread_rng().fill_bytes(header);
+        result.extend_from_slice(header);
+    }
+
+    let padded_length = 4 + message.len().max(decoy.map_or(0, |(decoy, _)| decoy.len()));
+    let mut slots = [Some((message, passphrase)), decoy];
+    if rand::thread_rng().gen() {
+        slots.swap(0, 1);
+    }
+
+    let mut ciphertexts = Vec::with_capacity(2);
+    for (slot, header) in slots.iter().zip(&slot_headers) {
+        let ciphertext = match slot {
+            Some((message, passphrase)) => {
+                let (salt, nonce) = header.split_at(SALT_LENGTH);
+                let key = params.derive_key(passphrase.as_bytes(), salt)?;
+
+                let mut plaintext = Vec::with_capacity(padded_length);
+                plaintext.extend_from_slice(&(message.len() as u32).to_be_bytes());
+                plaintext.extend_from_slice(message);
+                plaintext.resize(padded_length, 0);
+                crypto::seal(&key, nonce, &result, &plaintext)?
+            }
+            None => {
+                let mut filler = vec![0; padded_length + TAG_LENGTH];
+                rand::thread_rng().fill_bytes(&mut filler);
+                filler
+            }
+        };
+        ciphertexts.push(ciphertext);
+    }
+
+    for (first, second) in ciphertexts[0].iter().zip(&ciphertexts[1]) {
+        result.push(*first);
+        result.push(*second);
+    }
+    Ok(result)
+}
+
+/// Decrypts whichever of the two messages in a payload created by `encrypt` was
+/// encrypted with `passphrase`. Both slots are always tried, so how long this takes
+/// doesn't give away which one was opened.
+pub fn decrypt(data: &[u8], passphrase: &str) -> anyhow::Result<Vec<u8>> {
+    if !is_deniable(data) || data.len() < HEADER_LENGTH + 2 * (4 + TAG_LENGTH) {
+        anyhow::bail!("Data is not a deniable payload");
+    }
+    if data[4] != VERSION {
+        anyhow::bail!("Unsupported deniable format version {}", data[4]);
+    }
+    if !(data.len() - HEADER_LENGTH).is_multiple_of(2) {
+        anyhow::bail!("Deniable payload is truncated");
+    }
+
+    let read_u32 = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+    let params = KdfParams {
+        memory_kib: read_u32(5),
+        iterations: read_u32(9),
+        parallelism: read_u32(13),
+    };
+    params.check()?;
+
+    let (header, interleaved) = data.split_at(HEADER_LENGTH);
+    let mut opened = None;
+    for slot in 0..2 {
+        let start = 17 + slot * SLOT_HEADER_LENGTH;
+        let salt = &header[start..start + SALT_LENGTH];
+        let nonce = &header[start + SALT_LENGTH..start + SLOT_HEADER_LENGTH];
+        let ciphertext: Vec<u8> = interleaved.iter().skip(slot).step_by(2).copied().collect();
+
+        let key = params.derive_key(passphrase.as_bytes(), salt)?;
+        if let Ok(plaintext) = crypto::open(&key, nonce, header, &ciphertext) {
+            opened = Some(plaintext);
+        }
+    }
+
+    let plaintext = opened
+        .ok_or_else(|| anyhow::anyhow!("Decryption failed: wrong passphrase or corrupted data"))?;
+    let length = u32::from_be_bytes(plaintext[..4].try_into()?) as usize;
+    plaintext
+        .get(4..4 + length)
+        .map(<[u8]>::to_vec)
+        .ok_or_else(|| anyhow::anyhow!("Deniable payload has an invalid message length"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_params() -> KdfParams {
+        KdfParams {
+            memory_kib: 64,
+            iterations: 1,
+            parallelism: 1,
+        }
+    }
+
+    #[test]
+    fn test_each_passphrase_opens_its_message() {
+        let encrypted = encrypt(
+            b"This is a secret message!",
+            "real",
+            Some((b"Shopping list", "decoy")),
+            testing_params(),
+        )
+        .unwrap();
+        assert!(is_deniable(&encrypted));
+
+        assert_eq!(decrypt(&encrypted, "decoy").unwrap(), b"Shopping list");
+        assert_eq!(
+            decrypt(&encrypted, "real").unwrap(),
+            b"This is a secret message!"
+        );
+        assert!(decrypt(&encrypted, "wrong").is_err());
+    }
+
+    #[test]
+    fn test_without_decoy() {
+        let encrypted = encrypt(b"secret", "real", None, testing_params()).unwrap();
+        assert_eq!(decrypt(&encrypted, "real").unwrap(), b"secret");
+        assert!(decrypt(&encrypted, "decoy").is_err());
+        assert_eq!(
+            encrypted.len(),
+            HEADER_LENGTH + 2 * (4 + b"secret".len() + TAG_LENGTH)
+        );
+    }
+
+    #[test]
+    fn test_messages_are_padded_to_the_same_length() {
+        let long = encrypt(&[0; 100], "real", Some((b"a", "decoy")), testing_params()).unwrap();
+        let short = encrypt(b"b", "real", Some((&[0; 100], "decoy")), testing_params()).unwrap();
+        assert_eq!(long.len(), short.len());
+    }
+
+    #[test]
+    fn test_kdf_limits() {
+        let mut encrypted = encrypt(b"secret", "real", None, testing_params()).unwrap();
+        // Raise the iteration count stored in the header
+        encrypted[9..13].copy_from_slice(&u32::MAX.to_be_bytes());
+        let error = decrypt(&encrypted, "real").unwrap_err();
+        assert!(error.to_string().contains("iteration count"));
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let mut encrypted =
+            encrypt(b"real", "real", Some((b"decoy", "decoy")), testing_params()).unwrap();
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+        // The last byte belongs to the second slot, whichever message that holds
+        let failures = ["decoy", "real"]
+            .iter()
+            .filter(|passphrase| decrypt(&encrypted, passphrase).is_err())
+            .count();
+        assert_eq!(failures, 1);
+    }
+
+    #[test]
+    fn test_same_passphrase_refused() {
+        assert!(encrypt(b"real", "same", Some((b"decoy", "same")), testing_params()).is_err());
+    }
+}
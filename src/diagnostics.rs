@@ -0,0 +1,305 @@
+use std::convert::{TryFrom, TryInto};
+use std::fmt;
+
+use crate::png::{is_chunk_at, Chunk, ChunkType, Png};
+
+/// The largest chunk data length allowed by the PNG spec
+const MAX_CHUNK_LENGTH: usize = (1 << 31) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The file breaks the PNG spec and viewers may reject it
+    Error,
+    /// The file is valid but something about it is unusual
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// One problem found in a PNG file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    /// Offset from the start of the file of the chunk or bytes the problem is about
+    pub offset: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn error(offset: usize, message: String) -> Self {
+        Self {
+            offset,
+            severity: Severity::Error,
+            message,
+        }
+    }
+
+    fn warning(offset: usize, message: String) -> Self {
+        Self {
+            offset,
+            severity: Severity::Warning,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}: {}", self.offset, self.severity, self.message)
+    }
+}
+
+/// Checks every chunk in `bytes` and the order they're in, collecting all the problems
+/// instead of stopping at the first one like `Png::try_from` does. Reading only stops
+/// when the chunk lengths can no longer be trusted.
+pub fn diagnose(bytes: &[u8]) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if !bytes.starts_with(&Png::SIGNATURE) {
+        diagnostics.push(Diagnostic::error(0, "Invalid PNG signature".to_string()));
+        if bytes.len() < Png::SIGNATURE.len() {
+            return diagnostics;
+        }
+    }
+
+    let mut offset = Png::SIGNATURE.len();
+    let mut chunks: Vec<(usize, String)> = Vec::new();
+    let mut iend_end = None;
+
+    while offset < bytes.len() {
+        let remaining = bytes.len() - offset;
+        if iend_end.is_some() && !is_chunk_at(&bytes[offset..]) {
+            diagnostics.push(Diagnostic::warning(
+                offset,
+                format!("{} bytes of trailing data after IEND", remaining),
+            ));
+            break;
+        }
+        if remaining < 12 {
+            diagnostics.push(Diagnostic::error(
+                offset,
+                format!("Truncated chunk: only {} bytes left", remaining),
+            ));
+            break;
+        }
+
+        let length = u32::from_be_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize;
+        let type_bytes: [u8; 4] = bytes[offset + 4..offset + 8].try_into().unwrap();
+        let name = String::from_utf8_lossy(&type_bytes)
+            .escape_debug()
+            .to_string();
+
+        if length > MAX_CHUNK_LENGTH {
+            diagnostics.push(Diagnostic::error(
+                offset,
+                format!("{} chunk length {} is over the PNG limit", name, length),
+            ));
+            break;
+        }
+        if length + 12 > remaining {
+            diagnostics.push(Diagnostic::error(
+                offset,
+                format!(
+                    "Truncated {} chunk: claims {} bytes of data but only {} remain",
+                    name,
+                    length,
+                    remaining.saturating_sub(12)
+                ),
+            ));
+            break;
+        }
+
+        let data = &bytes[offset + 8..offset + 8 + length];
+        let stored_crc = u32::from_be_bytes(
+            bytes[offset + 8 + length..offset + 12 + length]
+                .try_into()
+                .unwrap(),
+        );
+        match ChunkType::try_from(type_bytes) {
+            Ok(chunk_type) => {
+                let crc = Chunk::calculate_crc(&chunk_type, data);
+                if crc != stored_crc {
+                    diagnostics.push(Diagnostic::error(
+                        offset,
+                        format!(
+                            "Bad CRC in {} chunk: stored {:08x}, computed {:08x}",
+                            chunk_type, stored_crc, crc
+                        ),
+                    ));
+                }
+                if !chunk_type.is_reserved_bit_valid() {
+                    diagnostics.push(Diagnostic::warning(
+                        offset,
+                        format!("{} chunk type has the reserved bit set", chunk_type),
+                    ));
+                }
+            }
+            Err(_) => diagnostics.push(Diagnostic::error(
+                offset,
+                format!("Invalid chunk type \"{}\"", name),
+            )),
+        }
+
+        chunks.push((offset, name));
+        offset += 12 + length;
+        if chunks.last().is_some_and(|(_, name)| name == "IEND") && iend_end.is_none() {
+            iend_end = Some(offset);
+        }
+    }
+
+    check_order(&chunks, iend_end, bytes.len(), &mut diagnostics);
+    diagnostics.sort_by_key(|diagnostic| diagnostic.offset);
+    diagnostics
+}
+
+/// Checks where IHDR, IDAT and IEND are among the chunks that could be read
+fn check_order(
+    chunks: &[(usize, String)],
+    iend_end: Option<usize>,
+    file_length: usize,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    match chunks.first() {
+        Some((_, name)) if name == "IHDR" => {}
+        Some((offset, name)) => diagnostics.push(Diagnostic::error(
+            *offset,
+            format!("The first chunk is {}, not IHDR", name),
+        )),
+        None => {}
+    }
+    let ihdr = chunks.iter().filter(|(_, name)| name == "IHDR");
+    for (offset, _) in ihdr.skip(1) {
+        diagnostics.push(Diagnostic::error(
+            *offset,
+            "Repeated IHDR chunk".to_string(),
+        ));
+    }
+
+    let idat: Vec<usize> = chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, name))| name == "IDAT")
+        .map(|(index, _)| index)
+        .collect();
+    match (idat.first(), idat.last()) {
+        (Some(&first), Some(&last)) if last - first + 1 != idat.len() => {
+            let gap = (first..=last).find(|index| !idat.contains(index)).unwrap();
+            diagnostics.push(Diagnostic::error(
+                chunks[gap].0,
+                format!("{} chunk between IDAT chunks", chunks[gap].1),
+            ));
+        }
+        (None, _) => diagnostics.push(Diagnostic::error(
+            Png::SIGNATURE.len(),
+            "No IDAT chunk".to_string(),
+        )),
+        _ => {}
+    }
+
+    match iend_end {
+        Some(end) => {
+            let after = chunks.iter().filter(|(offset, _)| *offset >= end).count();
+            if after > 0 {
+                diagnostics.push(Diagnostic::warning(
+                    end,
+                    format!("{} chunk(s) after IEND, which viewers ignore", after),
+                ));
+            }
+        }
+        None => diagnostics.push(Diagnostic::error(
+            file_length,
+            "Missing IEND chunk".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_bytes() -> Vec<u8> {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+        .as_bytes()
+    }
+
+    fn messages(bytes: &[u8]) -> Vec<String> {
+        diagnose(bytes)
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_file() {
+        assert!(diagnose(&testing_bytes()).is_empty());
+    }
+
+    #[test]
+    fn test_collects_every_problem() {
+        let mut bytes = testing_bytes();
+        // Corrupt the IHDR data and the IDAT chunk type, then cut the IEND chunk short
+        bytes[8 + 8] ^= 1;
+        bytes[26 + 4] = b'1';
+        bytes.truncate(bytes.len() - 2);
+
+        let found: Vec<(usize, Severity)> = diagnose(&bytes)
+            .iter()
+            .map(|diagnostic| (diagnostic.offset, diagnostic.severity))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (8, Severity::Error),
+                (8, Severity::Error),
+                (26, Severity::Error),
+                (44, Severity::Error),
+                (bytes.len(), Severity::Error),
+            ]
+        );
+        assert!(messages(&bytes).contains(&"26: error: Invalid chunk type \"1DAT\"".to_string()));
+        assert!(messages(&bytes).contains(&"8: error: No IDAT chunk".to_string()));
+    }
+
+    #[test]
+    fn test_chunk_order() {
+        let bytes = Png::from_chunks(vec![
+            Chunk::from_strings("tEXt", "Comment\0first").unwrap(),
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("ruSt", "message").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+            Chunk::from_strings("ruSt", "message").unwrap(),
+        ])
+        .as_bytes();
+
+        assert_eq!(
+            messages(&bytes),
+            vec![
+                "8: error: The first chunk is tEXt, not IHDR",
+                "69: error: ruSt chunk between IDAT chunks",
+                "118: warning: 1 chunk(s) after IEND, which viewers ignore",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = testing_bytes();
+        bytes.extend_from_slice(b"garbage");
+        assert_eq!(
+            messages(&bytes),
+            vec!["56: warning: 7 bytes of trailing data after IEND"]
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use std::convert::TryFrom;
+
+use crate::audit::AUDIT_CHUNK_TYPE;
+use crate::chunk_type::check_message_chunk_type;
+use crate::container::Container;
+use crate::history::HISTORY_CHUNK_TYPE;
+use crate::multipart::{Manifest, MANIFEST_CHUNK_TYPE};
+use crate::png::ChunkType;
+use crate::seal::SEAL_CHUNK_TYPE;
+use crate::signature::SIGNATURE_CHUNK_TYPE;
+
+/// Marks a message framed by `wrap`
+pub const MAGIC: [u8; 4] = *b"PMEV";
+
+/// The envelope version this build writes, and the newest one it can read
+pub const VERSION: u8 = 1;
+
+const HEADER_LENGTH: usize = MAGIC.len() + 1;
+
+/// Chunk types pngme keeps its own records in, which never hold messages
+pub const BOOKKEEPING_CHUNK_TYPES: [&str; 5] = [
+    AUDIT_CHUNK_TYPE,
+    HISTORY_CHUNK_TYPE,
+    MANIFEST_CHUNK_TYPE,
+    SEAL_CHUNK_TYPE,
+    SIGNATURE_CHUNK_TYPE,
+];
+
+/// Returns true if `data` is a message framed by `wrap`
+pub fn is_envelope(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Frames `payload` so that readers can tell which version of pngme wrote it.
+/// The result is laid out as follows
+/// 1. Magic bytes `PMEV` *(4 bytes)*
+/// 2. Envelope version *(1 byte)*
+/// 3. The payload
+pub fn wrap(payload: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(HEADER_LENGTH + payload.len());
+    result.extend_from_slice(&MAGIC);
+    result.push(VERSION);
+    result.extend_from_slice(payload);
+    result
+}
+
+/// Frames a message in an envelope, except in registered chunk types. Those are only
+/// written with --force, to store data in the chunk's own layout, which the envelope
+/// would break.
+pub fn frame(chunk_type: &ChunkType, data: &[u8]) -> Vec<u8> {
+    match chunk_type.is_registered() {
+        true => data.to_vec(),
+        false => wrap(data),
+    }
+}
+
+/// Returns the payload of a message framed by `wrap`. Messages written before pngme
+/// framed them are returned as they are. Envelopes from a newer version of pngme are
+/// refused rather than misread.
+pub fn unwrap(data: &[u8]) -> anyhow::Result<&[u8]> {
+    if !is_envelope(data) {
+        return Ok(data);
+    }
+    match data.get(MAGIC.len()) {
+        None | Some(0) => anyhow::bail!("Message envelope is missing its version"),
+        Some(&version) if version > VERSION => anyhow::bail!(
+            "This message was written by a newer version of pngme (envelope version {}). \
+             Upgrade pngme to read it",
+            version
+        ),
+        Some(_) => Ok(&data[HEADER_LENGTH..]),
+    }
+}
+
+/// Frames every message in `container` that was written before pngme framed its
+/// messages, keeping its content, and returns the chunk types that were changed.
+/// Only chunk types that messages can be stored under are looked at, leaving out
+/// pngme's own records. Messages split across chunks are left as they are, since their
+/// manifest holds the digest of each part.
+pub fn migrate(container: &mut Container) -> anyhow::Result<Vec<ChunkType>> {
+    let mut manifests = Vec::new();
+    for data in container.messages(MANIFEST_CHUNK_TYPE)? {
+        manifests.push(Manifest::try_from(data.as_ref())?);
+    }
+
+    let mut migrated = Vec::new();
+    for chunk_type in container.message_types()? {
+        let name = chunk_type.to_string();
+        if check_message_chunk_type(&chunk_type).is_err()
+            || BOOKKEEPING_CHUNK_TYPES.contains(&name.as_str())
+            || manifests.iter().any(|manifest| manifest.describes(&name))
+        {
+            continue;
+        }
+
+        let messages = container.messages(&name)?;
+        if messages.iter().all(|message| is_envelope(message)) {
+            continue;
+        }
+        match &mut *container {
+            // Edit the chunks in place so that they keep their positions
+            Container::Png(png) => {
+                for chunk in png.chunks_mut() {
+                    if chunk.chunk_type() == &chunk_type && !is_envelope(chunk.data()) {
+                        chunk.set_data(wrap(chunk.data()));
+                    }
+                }
+            }
+            _ => {
+                container.remove_messages(&chunk_type)?;
+                for message in messages {
+                    let message = match is_envelope(&message) {
+                        true => message,
+                        false => wrap(&message),
+                    };
+                    container.append_message(&chunk_type, message)?;
+                }
+            }
+        }
+        migrated.push(chunk_type);
+    }
+    Ok(migrated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::{Chunk, Png};
+
+    #[test]
+    fn test_wrap_and_unwrap() {
+        let wrapped = wrap(b"This is a secret message!");
+        assert!(is_envelope(&wrapped));
+        assert_eq!(unwrap(&wrapped).unwrap(), b"This is a secret message!");
+        // Messages from before envelopes existed are read as they are
+        assert_eq!(unwrap(b"legacy").unwrap(), b"legacy");
+    }
+
+    #[test]
+    fn test_newer_version_refused() {
+        let mut wrapped = wrap(b"message");
+        wrapped[MAGIC.len()] = VERSION + 1;
+        assert!(unwrap(&wrapped).is_err());
+        assert!(unwrap(&MAGIC).is_err());
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut container = Container::Png(Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("ruSt", "legacy").unwrap(),
+            Chunk::from_strings("tEXt", "Comment\0text").unwrap(),
+            Chunk::new(ChunkType::new(*b"ruSt"), wrap(b"framed")),
+            Chunk::from_strings("pnAu", "audit").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ]));
+
+        let migrated = migrate(&mut container).unwrap();
+        assert_eq!(migrated, vec![ChunkType::new(*b"ruSt")]);
+        assert_eq!(
+            container.messages("ruSt").unwrap(),
+            vec![wrap(b"legacy"), wrap(b"framed")]
+        );
+        assert_eq!(
+            container.message("tEXt").unwrap().unwrap(),
+            b"Comment\0text"
+        );
+        assert_eq!(container.message("pnAu").unwrap().unwrap(), b"audit");
+
+        // Running it again finds nothing left to do
+        assert!(migrate(&mut container).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,217 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+/// How errors are printed when a command fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    /// The message and its causes, for people
+    Text,
+    /// One JSON object per error, for tools wrapping pngme
+    Json,
+}
+
+impl std::str::FromStr for ErrorFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        match text {
+            "text" => Ok(ErrorFormat::Text),
+            "json" => Ok(ErrorFormat::Json),
+            _ => anyhow::bail!("Unknown error format {:?}. Use text or json", text),
+        }
+    }
+}
+
+/// An error along with where it happened. It displays as the error it wraps, so text
+/// output is the same with or without it, and `ErrorReport` picks up the details.
+#[derive(Debug)]
+pub struct Located {
+    code: Option<&'static str>,
+    file: Option<PathBuf>,
+    offset: Option<usize>,
+    chunk_type: Option<String>,
+    error: anyhow::Error,
+}
+
+impl fmt::Display for Located {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+impl Error for Located {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        // The wrapped error is shown as this one, so skip straight to its cause
+        self.error.source()
+    }
+}
+
+impl Located {
+    /// Wraps `error`, or returns it as it is if it is already a `Located`. Details added
+    /// later don't replace ones that are already set, since the innermost code knows best.
+    pub fn of(error: anyhow::Error) -> Self {
+        // `downcast` would also find a `Located` under added context and drop the context
+        let outermost: &(dyn Error + 'static) = &*error;
+        let error = match outermost.is::<Located>() {
+            true => match error.downcast::<Located>() {
+                Ok(located) => return located,
+                Err(error) => error,
+            },
+            false => error,
+        };
+        Located {
+            code: None,
+            file: None,
+            offset: None,
+            chunk_type: None,
+            error,
+        }
+    }
+
+    /// A short, stable name for the kind of error, like `crc_mismatch`
+    pub fn code(mut self, code: &'static str) -> Self {
+        self.code = self.code.or(Some(code));
+        self
+    }
+
+    pub fn in_file(mut self, file: &Path) -> Self {
+        self.file.get_or_insert_with(|| file.to_path_buf());
+        self
+    }
+
+    pub fn at_offset(mut self, offset: usize) -> Self {
+        self.offset = self.offset.or(Some(offset));
+        self
+    }
+
+    pub fn in_chunk(mut self, chunk_type: &str) -> Self {
+        self.chunk_type
+            .get_or_insert_with(|| chunk_type.to_string());
+        self
+    }
+}
+
+/// Adds details to the error in a `Result`, like `Located`'s methods
+pub trait Locate<T> {
+    fn code(self, code: &'static str) -> anyhow::Result<T>;
+    fn in_file(self, file: &Path) -> anyhow::Result<T>;
+    fn at_offset(self, offset: usize) -> anyhow::Result<T>;
+    fn in_chunk(self, chunk_type: &str) -> anyhow::Result<T>;
+}
+
+impl<T, E: Into<anyhow::Error>> Locate<T> for Result<T, E> {
+    fn code(self, code: &'static str) -> anyhow::Result<T> {
+        self.map_err(|error| Located::of(error.into()).code(code).into())
+    }
+
+    fn in_file(self, file: &Path) -> anyhow::Result<T> {
+        self.map_err(|error| Located::of(error.into()).in_file(file).into())
+    }
+
+    fn at_offset(self, offset: usize) -> anyhow::Result<T> {
+        self.map_err(|error| Located::of(error.into()).at_offset(offset).into())
+    }
+
+    fn in_chunk(self, chunk_type: &str) -> anyhow::Result<T> {
+        self.map_err(|error| Located::of(error.into()).in_chunk(chunk_type).into())
+    }
+}
+
+/// What `--error-format json` prints for an error. Fields that aren't known are null.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ErrorReport {
+    pub code: String,
+    /// The error and its causes, separated by `: `
+    pub message: String,
+    pub file: Option<PathBuf>,
+    pub offset: Option<usize>,
+    pub chunk_type: Option<String>,
+}
+
+impl ErrorReport {
+    /// Collects the details attached anywhere in `error`'s chain of causes. Errors
+    /// without a code get `io` if they were caused by an I/O error, and `error` otherwise.
+    pub fn new(error: &anyhow::Error) -> Self {
+        let mut report = Self {
+            code: String::new(),
+            message: format!("{:#}", error),
+            file: None,
+            offset: None,
+            chunk_type: None,
+        };
+
+        let mut code = None;
+        let mut io = false;
+        for cause in error.chain() {
+            match cause.downcast_ref::<Located>() {
+                Some(located) => {
+                    code = code.or(located.code);
+                    report.file = report.file.or_else(|| located.file.clone());
+                    report.offset = report.offset.or(located.offset);
+                    report.chunk_type = report.chunk_type.or_else(|| located.chunk_type.clone());
+                    io |= located.error.is::<io::Error>();
+                }
+                None => io |= cause.is::<io::Error>(),
+            }
+        }
+        let fallback = if io { "io" } else { "error" };
+        report.code = code.unwrap_or(fallback).to_string();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::{Chunk, Png};
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_located_displays_as_wrapped_error() {
+        let plain = anyhow::anyhow!("Chunk not found");
+        let located: anyhow::Error = Located::of(anyhow::anyhow!("Chunk not found"))
+            .code("chunk_not_found")
+            .in_chunk("ruSt")
+            .into();
+        assert_eq!(format!("{:?}", located), format!("{:?}", plain));
+
+        let report = ErrorReport::new(&located);
+        assert_eq!(report.code, "chunk_not_found");
+        assert_eq!(report.chunk_type.as_deref(), Some("ruSt"));
+        assert_eq!(report.file, None);
+    }
+
+    #[test]
+    fn test_report() {
+        let mut bytes = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+        .as_bytes();
+        // Damage the IEND chunk's CRC
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        let error = Png::try_from(bytes.as_slice())
+            .in_file(Path::new("damaged.png"))
+            .unwrap_err();
+        let report = ErrorReport::new(&error);
+        assert_eq!(
+            report,
+            ErrorReport {
+                code: "crc_mismatch".to_string(),
+                message: "Invalid chunk at offset 26: CRC check failed".to_string(),
+                file: Some(PathBuf::from("damaged.png")),
+                offset: Some(26),
+                chunk_type: Some("IEND".to_string()),
+            }
+        );
+
+        let missing = Png::from_file("/nonexistent/pngme.png").unwrap_err();
+        assert_eq!(ErrorReport::new(&missing).code, "io");
+    }
+}
@@ -0,0 +1,270 @@
+use std::convert::{TryFrom, TryInto};
+
+use crate::envelope;
+use crate::png::{Chunk, ChunkType};
+use crate::text::{InternationalTextChunk, TextChunk};
+
+/// How many characters of a text chunk `explain` shows
+const TEXT_PREVIEW_LENGTH: usize = 60;
+
+/// What each chunk type is for: the chunk types of the PNG spec and its registered
+/// extensions, the ones pngme writes for itself, and a few private ones common enough
+/// to be worth naming
+const DESCRIPTIONS: [(&str, &str); 47] = [
+    ("IHDR", "image header"),
+    ("PLTE", "palette"),
+    ("IDAT", "compressed image data"),
+    ("IEND", "end of the image"),
+    ("acTL", "animation control"),
+    ("bKGD", "background color"),
+    ("cHRM", "primary chromaticities and white point"),
+    (
+        "cICP",
+        "coding-independent code points for video signal type",
+    ),
+    ("cLLi", "content light level"),
+    ("dSIG", "digital signature"),
+    ("eXIf", "EXIF metadata"),
+    ("fcTL", "animation frame control"),
+    ("fdAT", "animation frame data"),
+    ("fRAc", "fractal image parameters"),
+    ("gAMA", "image gamma"),
+    ("gIFg", "GIF graphic control extension"),
+    ("gIFt", "GIF plain text extension"),
+    ("gIFx", "GIF application extension"),
+    ("hIST", "palette histogram"),
+    ("iCCP", "embedded ICC color profile"),
+    ("iTXt", "international text"),
+    ("mDCv", "mastering display color volume"),
+    ("oFFs", "image offset"),
+    ("pCAL", "pixel value calibration"),
+    ("pHYs", "physical pixel dimensions"),
+    ("sBIT", "significant bits"),
+    ("sCAL", "physical scale of the image subject"),
+    ("sPLT", "suggested palette"),
+    ("sRGB", "standard RGB color space"),
+    ("sTER", "stereo image indicator"),
+    ("tEXt", "text"),
+    ("tIME", "last modification time"),
+    ("tRNS", "transparency"),
+    ("zTXt", "compressed text"),
+    ("pnAu", "pngme audit trail"),
+    ("pnGb", "pngme edit history"),
+    ("pnMf", "pngme split message manifest"),
+    ("pnSL", "pngme seal of the image content"),
+    ("pmSG", "pngme signature"),
+    ("CgBI", "Apple's iPhone optimized PNG marker"),
+    ("iDOT", "Apple's parallel decoding hints"),
+    ("vpAg", "ImageMagick virtual page size"),
+    ("caNv", "ImageMagick virtual canvas"),
+    ("orNT", "ImageMagick orientation"),
+    ("mkBF", "Adobe Fireworks data"),
+    ("mkTS", "Adobe Fireworks data"),
+    ("prVW", "Adobe Fireworks preview"),
+];
+
+/// Returns what chunks of type `chunk_type` are for, if it is a type pngme knows
+pub fn describe(chunk_type: &ChunkType) -> Option<&'static str> {
+    let name = chunk_type.to_string();
+    DESCRIPTIONS
+        .iter()
+        .find(|(known, _)| *known == name)
+        .map(|(_, description)| *description)
+}
+
+/// Returns a one line explanation of `chunk`, like `pHYs: physical pixel dimensions —
+/// 2835 px/m ≈ 72 DPI`. The data of standard chunks is decoded into a summary; data that
+/// can't be decoded is described by its length.
+pub fn explain(chunk: &Chunk) -> String {
+    let chunk_type = chunk.chunk_type();
+    let description = match describe(chunk_type) {
+        Some(description) => description.to_string(),
+        None if envelope::is_envelope(chunk.data()) => "message written by pngme".to_string(),
+        None if !chunk_type.is_public() => "private chunk, not part of the PNG spec".to_string(),
+        None => "unknown public chunk".to_string(),
+    };
+
+    let summary = summarize(chunk).unwrap_or_else(|| match chunk.data().len() {
+        1 => "1 byte".to_string(),
+        length => format!("{} bytes", length),
+    });
+    format!("{}: {} — {}", chunk_type, description, summary)
+}
+
+/// Decodes the data of the standard chunks that fit in a line, or returns `None` for
+/// other chunks and data that doesn't have the expected layout
+fn summarize(chunk: &Chunk) -> Option<String> {
+    let data = chunk.data();
+    let u32_at = |offset: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(
+            data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+    let u16_at = |offset: usize| -> Option<u16> {
+        Some(u16::from_be_bytes(
+            data.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    };
+
+    Some(match chunk.chunk_type().to_string().as_str() {
+        "IHDR" if data.len() == 13 => format!(
+            "{}x{}, {} bit {}{}",
+            u32_at(0)?,
+            u32_at(4)?,
+            data[8],
+            color_type_name(data[9])?,
+            if data[12] == 1 { ", interlaced" } else { "" }
+        ),
+        "PLTE" if data.len().is_multiple_of(3) => format!("{} colors", data.len() / 3),
+        "IEND" if data.is_empty() => "no data".to_string(),
+        "gAMA" if data.len() == 4 => format!("{:.5}", u32_at(0)? as f64 / 100_000.0),
+        "sRGB" if data.len() == 1 => format!(
+            "{} rendering intent",
+            [
+                "perceptual",
+                "relative colorimetric",
+                "saturation",
+                "absolute colorimetric"
+            ]
+            .get(data[0] as usize)?
+        ),
+        "pHYs" if data.len() == 9 => {
+            let (x, y) = (u32_at(0)?, u32_at(4)?);
+            let ratio = match x == y {
+                true => format!("{}", x),
+                false => format!("{}x{}", x, y),
+            };
+            match data[8] {
+                // One inch is 0.0254 meters
+                1 => format!(
+                    "{} px/m ≈ {} DPI",
+                    ratio,
+                    (x as f64 * 0.0254).round() as u32
+                ),
+                _ => format!("pixel aspect ratio {}, no unit", ratio),
+            }
+        }
+        "tIME" if data.len() == 7 => format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            u16_at(0)?,
+            data[2],
+            data[3],
+            data[4],
+            data[5],
+            data[6]
+        ),
+        "tEXt" => {
+            let text = TextChunk::try_from(chunk).ok()?;
+            format!("{}: {}", text.keyword(), preview(text.text()))
+        }
+        "iTXt" => {
+            let text = InternationalTextChunk::try_from(chunk).ok()?;
+            format!("{}: {}", text.keyword(), preview(text.text()))
+        }
+        "zTXt" | "iCCP" => {
+            let name = data.split(|&byte| byte == 0).next()?;
+            format!(
+                "{:?}, {} compressed bytes",
+                String::from_utf8_lossy(name),
+                data.len() - name.len()
+            )
+        }
+        "tRNS" => format!("{} bytes of transparency", data.len()),
+        "acTL" if data.len() == 8 => match u32_at(4)? {
+            0 => format!("{} frames, looping forever", u32_at(0)?),
+            plays => format!("{} frames, played {} times", u32_at(0)?, plays),
+        },
+        "fcTL" if data.len() == 26 => format!(
+            "frame {}, {}x{} at {},{}",
+            u32_at(0)?,
+            u32_at(4)?,
+            u32_at(8)?,
+            u32_at(12)?,
+            u32_at(16)?
+        ),
+        "oFFs" if data.len() == 9 => format!(
+            "{},{} {}",
+            u32_at(0)? as i32,
+            u32_at(4)? as i32,
+            if data[8] == 1 {
+                "micrometers"
+            } else {
+                "pixels"
+            }
+        ),
+        _ => return None,
+    })
+}
+
+fn color_type_name(color_type: u8) -> Option<&'static str> {
+    Some(match color_type {
+        0 => "grayscale",
+        2 => "RGB",
+        3 => "palette",
+        4 => "grayscale with alpha",
+        6 => "RGBA",
+        _ => return None,
+    })
+}
+
+/// Returns the first line of `text`, cut short if it is long
+fn preview(text: &str) -> String {
+    let line = text.lines().next().unwrap_or_default();
+    let mut preview: String = line.chars().take(TEXT_PREVIEW_LENGTH).collect();
+    if preview.len() < text.len() {
+        preview.push('…');
+    }
+    format!("{:?}", preview)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(chunk_type: &[u8; 4], data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::new(*chunk_type), data)
+    }
+
+    #[test]
+    fn test_explain_standard_chunks() {
+        let mut phys = Vec::new();
+        phys.extend_from_slice(&2835u32.to_be_bytes());
+        phys.extend_from_slice(&2835u32.to_be_bytes());
+        phys.push(1);
+        assert_eq!(
+            explain(&chunk(b"pHYs", phys)),
+            "pHYs: physical pixel dimensions — 2835 px/m ≈ 72 DPI"
+        );
+
+        let header = [0, 0, 2, 0, 0, 0, 1, 0, 8, 6, 0, 0, 0].to_vec();
+        assert_eq!(
+            explain(&chunk(b"IHDR", header)),
+            "IHDR: image header — 512x256, 8 bit RGBA"
+        );
+        assert_eq!(
+            explain(&chunk(b"tIME", vec![7, 228, 12, 31, 23, 59, 59])),
+            "tIME: last modification time — 2020-12-31 23:59:59 UTC"
+        );
+        assert_eq!(
+            explain(&TextChunk::new("Software", "pngme").unwrap().to_chunk()),
+            "tEXt: text — Software: \"pngme\""
+        );
+    }
+
+    #[test]
+    fn test_explain_other_chunks() {
+        assert_eq!(
+            explain(&chunk(b"ruSt", b"secret".to_vec())),
+            "ruSt: private chunk, not part of the PNG spec — 6 bytes"
+        );
+        assert_eq!(
+            explain(&chunk(b"ruSt", envelope::wrap(b"secret"))),
+            "ruSt: message written by pngme — 11 bytes"
+        );
+        // Malformed data falls back to the length
+        assert_eq!(
+            explain(&chunk(b"gAMA", vec![1])),
+            "gAMA: image gamma — 1 byte"
+        );
+    }
+}
@@ -0,0 +1,230 @@
+use std::fs;
+use std::path::{Component, Path};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::png::{Chunk, ChunkType, Png};
+
+/// The name of the manifest written next to the chunk files
+pub const MANIFEST_FILE: &str = "manifest.toml";
+
+/// Lists the chunk files in an exploded PNG, in the order the chunks appear in the file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    #[serde(rename = "chunk", default)]
+    pub chunks: Vec<ManifestEntry>,
+}
+
+/// One chunk in a `Manifest`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntry {
+    /// The name of the file holding the chunk's data, relative to the manifest
+    pub file: String,
+    #[serde(rename = "type")]
+    pub chunk_type: String,
+}
+
+/// Writes the data of every chunk in `png` to its own file in `directory`, along with a
+/// manifest listing them. Files are named after their position and chunk type, like
+/// `000_IHDR.bin`, so they sort in file order. The directory is created if needed and
+/// must otherwise be empty.
+pub fn explode(png: &Png, directory: &Path) -> anyhow::Result<Manifest> {
+    fs::create_dir_all(directory)?;
+    if fs::read_dir(directory)?.next().is_some() {
+        anyhow::bail!("Output directory {:?} is not empty", directory);
+    }
+
+    let width = png.chunks().len().to_string().len().max(3);
+    let mut manifest = Manifest { chunks: Vec::new() };
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let entry = ManifestEntry {
+            file: format!(
+                "{:0width$}_{}.bin",
+                index,
+                chunk.chunk_type(),
+                width = width
+            ),
+            chunk_type: chunk.chunk_type().to_string(),
+        };
+        fs::write(directory.join(&entry.file), chunk.data())?;
+        manifest.chunks.push(entry);
+    }
+
+    let text = format!(
+        "# Chunks in file order. Edit the chunk files or this list, then rebuild the PNG.\n\n{}",
+        toml::to_string(&manifest)?
+    );
+    fs::write(directory.join(MANIFEST_FILE), text)?;
+
+    Ok(manifest)
+}
+
+/// Rebuilds a PNG from a directory written by `explode`, in the order given by its
+/// manifest. CRCs are computed afresh, so chunk files can be edited with any tool.
+/// Returns an error if a chunk type is invalid, a file is outside the directory, or the
+/// chunks are in an order that viewers would reject.
+pub fn implode(directory: &Path) -> anyhow::Result<Png> {
+    let manifest_path = directory.join(MANIFEST_FILE);
+    let text = fs::read_to_string(&manifest_path)
+        .map_err(|e| anyhow::anyhow!("Can't read {:?}: {}", manifest_path, e))?;
+    let manifest: Manifest = toml::from_str(&text)
+        .map_err(|e| anyhow::anyhow!("Invalid manifest {:?}: {}", manifest_path, e))?;
+    check_order(&manifest)?;
+
+    let mut chunks = Vec::with_capacity(manifest.chunks.len());
+    for entry in &manifest.chunks {
+        let chunk_type = ChunkType::from_str(&entry.chunk_type)
+            .map_err(|e| anyhow::anyhow!("Invalid chunk type for {}: {}", entry.file, e))?;
+        let is_inside = Path::new(&entry.file)
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+        if !is_inside {
+            anyhow::bail!("{} is outside the directory", entry.file);
+        }
+
+        let data = fs::read(directory.join(&entry.file))
+            .map_err(|e| anyhow::anyhow!("Can't read {}: {}", entry.file, e))?;
+        chunks.push(Chunk::new(chunk_type, data));
+    }
+
+    Ok(Png::from_chunks(chunks))
+}
+
+/// Checks that IHDR comes first and only once, the IDAT chunks are together, and there
+/// is an IEND. Chunks after IEND are allowed since messages are often stored there.
+fn check_order(manifest: &Manifest) -> anyhow::Result<()> {
+    let types: Vec<&str> = manifest
+        .chunks
+        .iter()
+        .map(|entry| entry.chunk_type.as_str())
+        .collect();
+
+    if types.first() != Some(&"IHDR") {
+        anyhow::bail!("The first chunk must be IHDR");
+    }
+    if let Some(index) = types.iter().skip(1).position(|&t| t == "IHDR") {
+        anyhow::bail!("{} is a second IHDR chunk", manifest.chunks[index + 1].file);
+    }
+
+    let first_idat = types
+        .iter()
+        .position(|&t| t == "IDAT")
+        .ok_or_else(|| anyhow::anyhow!("There is no IDAT chunk"))?;
+    let last_idat = types.iter().rposition(|&t| t == "IDAT").unwrap();
+    if let Some(gap) = (first_idat..last_idat).find(|&index| types[index] != "IDAT") {
+        anyhow::bail!("{} is between IDAT chunks", manifest.chunks[gap].file);
+    }
+
+    match types.iter().position(|&t| t == "IEND") {
+        Some(iend) if iend < last_idat => anyhow::bail!("IEND comes before the IDAT chunks"),
+        Some(_) => Ok(()),
+        None => anyhow::bail!("There is no IEND chunk"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::path::PathBuf;
+
+    fn testing_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pngme-explode-{}-{}", std::process::id(), name))
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("tEXt", "Comment\0hello").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_explode() {
+        let directory = testing_path("explode");
+        let manifest = explode(&testing_png(), &directory).unwrap();
+
+        let files: Vec<&str> = manifest
+            .chunks
+            .iter()
+            .map(|entry| entry.file.as_str())
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                "000_IHDR.bin",
+                "001_tEXt.bin",
+                "002_IDAT.bin",
+                "003_IEND.bin"
+            ]
+        );
+        assert_eq!(
+            fs::read(directory.join("001_tEXt.bin")).unwrap(),
+            b"Comment\0hello"
+        );
+
+        let text = fs::read_to_string(directory.join(MANIFEST_FILE)).unwrap();
+        assert_eq!(toml::from_str::<Manifest>(&text).unwrap(), manifest);
+
+        assert!(explode(&testing_png(), &directory).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_implode() {
+        let directory = testing_path("implode");
+        explode(&testing_png(), &directory).unwrap();
+        assert_eq!(
+            implode(&directory).unwrap().as_bytes(),
+            testing_png().as_bytes()
+        );
+
+        // Edited data gets a fresh CRC
+        fs::write(directory.join("002_IDAT.bin"), b"new pixels").unwrap();
+        let png = implode(&directory).unwrap();
+        assert_eq!(png.chunks()[2].data(), b"new pixels");
+        assert!(Png::try_from(png.as_bytes().as_slice()).is_ok());
+
+        fs::write(
+            directory.join(MANIFEST_FILE),
+            "[[chunk]]\nfile = \"../000_IHDR.bin\"\ntype = \"IHDR\"\n\n\
+             [[chunk]]\nfile = \"002_IDAT.bin\"\ntype = \"IDAT\"\n\n\
+             [[chunk]]\nfile = \"003_IEND.bin\"\ntype = \"IEND\"\n",
+        )
+        .unwrap();
+        assert!(implode(&directory).is_err());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_check_order() {
+        let manifest = |types: &[&str]| Manifest {
+            chunks: types
+                .iter()
+                .enumerate()
+                .map(|(index, chunk_type)| ManifestEntry {
+                    file: format!("{:03}_{}.bin", index, chunk_type),
+                    chunk_type: chunk_type.to_string(),
+                })
+                .collect(),
+        };
+
+        assert!(check_order(&manifest(&["IHDR", "IDAT", "IDAT", "IEND", "ruSt"])).is_ok());
+        assert!(check_order(&manifest(&["tEXt", "IHDR", "IDAT", "IEND"])).is_err());
+        assert!(check_order(&manifest(&["IHDR", "IHDR", "IDAT", "IEND"])).is_err());
+        assert!(check_order(&manifest(&["IHDR", "IEND"])).is_err());
+        assert!(check_order(&manifest(&["IHDR", "IDAT", "IEND", "IDAT"])).is_err());
+        assert!(check_order(&manifest(&["IHDR", "IDAT"])).is_err());
+
+        let error = check_order(&manifest(&["IHDR", "IDAT", "tEXt", "IDAT", "IEND"]));
+        assert_eq!(
+            error.unwrap_err().to_string(),
+            "002_tEXt.bin is between IDAT chunks"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use rand::Rng;
+
+use crate::builder::PngBuilder;
+use crate::png::{Chunk, ChunkType, Png};
+
+/// The largest image `generate` will make, to keep memory use reasonable
+const MAX_PIXELS: u64 = 1 << 26;
+
+/// How much each channel of a gradient pixel is randomly nudged up or down
+const GRADIENT_NOISE: i16 = 3;
+
+/// The look of a generated carrier image
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    /// Every channel of every pixel is random
+    Noise,
+    /// A diagonal blend between two random colors, with a little noise in each pixel so
+    /// the low bits are as random as in a photo
+    Gradient,
+}
+
+impl FromStr for Style {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        match text {
+            "noise" => Ok(Style::Noise),
+            "gradient" => Ok(Style::Gradient),
+            _ => anyhow::bail!("Unknown style {:?}. Use noise or gradient", text),
+        }
+    }
+}
+
+/// Image dimensions, written like `512x512`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Size {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid size {:?}. Use WIDTHxHEIGHT, like 512x512", text);
+        let (width, height) = text.split_once(['x', 'X']).ok_or_else(invalid)?;
+        let size = Self {
+            width: width.trim().parse().map_err(|_| invalid())?,
+            height: height.trim().parse().map_err(|_| invalid())?,
+        };
+
+        if size.width == 0 || size.height == 0 {
+            return Err(invalid());
+        }
+        if size.width as u64 * size.height as u64 > MAX_PIXELS {
+            anyhow::bail!("{} is too large. The limit is {} pixels", size, MAX_PIXELS);
+        }
+        Ok(size)
+    }
+}
+
+impl fmt::Display for Size {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+/// Creates an 8 bit RGB image of the given size and style with random content
+pub fn generate(size: Size, style: Style) -> anyhow::Result<Png> {
+    let mut rng = rand::thread_rng();
+    let (width, height) = (size.width as usize, size.height as usize);
+    let colors: [[u8; 3]; 2] = rng.gen();
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    let mut row = vec![0; 1 + 3 * width];
+    for y in 0..height {
+        // Filter type None, then the pixels
+        row[0] = 0;
+        match style {
+            Style::Noise => rng.fill(&mut row[1..]),
+            Style::Gradient => {
+                for x in 0..width {
+                    // How far along the diagonal this pixel is, from 0 to 1
+                    let t = (x as f32 / width as f32 + y as f32 / height as f32) / 2.0;
+                    for channel in 0..3 {
+                        let from = colors[0][channel] as f32;
+                        let to = colors[1][channel] as f32;
+                        let value = (from + (to - from) * t) as i16
+                            + rng.gen_range(-GRADIENT_NOISE..=GRADIENT_NOISE);
+                        row[1 + 3 * x + channel] = value.clamp(0, 255) as u8;
+                    }
+                }
+            }
+        }
+        encoder.write_all(&row)?;
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&size.width.to_be_bytes());
+    header.extend_from_slice(&size.height.to_be_bytes());
+    // 8 bits per channel, RGB, default compression and filtering, no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    PngBuilder::new(Chunk::new(ChunkType::new(*b"IHDR"), header))?
+        .chunk(Chunk::new(ChunkType::new(*b"IDAT"), encoder.finish()?))?
+        .build()
+}
+
+/// Creates an 8 bit RGBA image from `pixels`, which hold four bytes for each pixel, row
+/// by row
+pub fn from_rgba(size: Size, pixels: &[u8]) -> anyhow::Result<Png> {
+    let row_length = 4 * size.width as usize;
+    if row_length == 0 || pixels.len() != row_length * size.height as usize {
+        anyhow::bail!(
+            "A {} RGBA image needs {} bytes of pixels, not {}",
+            size,
+            row_length * size.height as usize,
+            pixels.len()
+        );
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in pixels.chunks(row_length) {
+        // Filter type None, then the pixels
+        encoder.write_all(&[0])?;
+        encoder.write_all(row)?;
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&size.width.to_be_bytes());
+    header.extend_from_slice(&size.height.to_be_bytes());
+    // 8 bits per channel, RGBA, default compression and filtering, no interlacing
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    PngBuilder::new(Chunk::new(ChunkType::new(*b"IHDR"), header))?
+        .chunk(Chunk::new(ChunkType::new(*b"IDAT"), encoder.finish()?))?
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    use flate2::read::ZlibDecoder;
+
+    #[test]
+    fn test_parse_size() {
+        let size = Size::from_str("640x480").unwrap();
+        assert_eq!((size.width, size.height), (640, 480));
+        assert_eq!(size.to_string(), "640x480");
+        assert!(Size::from_str("0x10").is_err());
+        assert!(Size::from_str("640").is_err());
+        assert!(Size::from_str("100000x100000").is_err());
+    }
+
+    #[test]
+    fn test_generate() {
+        for style in [Style::Noise, Style::Gradient] {
+            let png = generate(Size::from_str("7x5").unwrap(), style).unwrap();
+            let bytes = png.as_bytes();
+            assert!(crate::diagnostics::diagnose(&bytes).is_empty());
+
+            let mut pixels = Vec::new();
+            ZlibDecoder::new(png.chunk_by_type("IDAT").unwrap().data())
+                .read_to_end(&mut pixels)
+                .unwrap();
+            assert_eq!(pixels.len(), 5 * (1 + 3 * 7));
+            assert!(pixels.chunks(1 + 3 * 7).all(|row| row[0] == 0));
+        }
+    }
+
+    #[test]
+    fn test_from_rgba() {
+        let pixels: Vec<u8> = (0..2 * 3 * 4).collect();
+        let size = Size::from_str("2x3").unwrap();
+        let png = from_rgba(size, &pixels).unwrap();
+        assert!(crate::diagnostics::diagnose(&png.as_bytes()).is_empty());
+
+        let (header, decoded) = crate::pixels::decode(&png).unwrap();
+        assert_eq!(header.channels().unwrap(), 4);
+        assert_eq!(decoded, pixels);
+        assert!(from_rgba(size, &pixels[1..]).is_err());
+    }
+}
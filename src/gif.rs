@@ -0,0 +1,390 @@
+use std::convert::{TryFrom, TryInto};
+
+use crate::error::Locate;
+use crate::png::ChunkType;
+
+const EXTENSION_INTRODUCER: u8 = 0x21;
+const IMAGE_SEPARATOR: u8 = 0x2C;
+const TRAILER: u8 = 0x3B;
+
+const GRAPHIC_CONTROL_LABEL: u8 = 0xF9;
+const COMMENT_LABEL: u8 = 0xFE;
+const PLAIN_TEXT_LABEL: u8 = 0x01;
+const APPLICATION_LABEL: u8 = 0xFF;
+
+/// The application identifier and authentication code of extensions holding a message
+const MESSAGE_APPLICATION: &[u8; 11] = b"PNGME1.0MSG";
+
+/// The largest data sub-block
+const MAX_SUB_BLOCK: usize = 255;
+
+/// An extension or image from the body of a GIF file, kept as its raw bytes so that
+/// blocks this module doesn't understand are written back unchanged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    bytes: Vec<u8>,
+}
+
+impl Block {
+    /// Creates an extension block with `data` split into sub-blocks. Application
+    /// extensions pass their identifier as `header`, which gets a sub-block of its own.
+    pub fn extension(label: u8, header: Option<&[u8]>, data: &[u8]) -> Self {
+        let mut bytes = vec![EXTENSION_INTRODUCER, label];
+        if let Some(header) = header {
+            bytes.push(header.len() as u8);
+            bytes.extend_from_slice(header);
+        }
+        for sub_block in data.chunks(MAX_SUB_BLOCK) {
+            bytes.push(sub_block.len() as u8);
+            bytes.extend_from_slice(sub_block);
+        }
+        bytes.push(0);
+        Self { bytes }
+    }
+
+    /// Creates an application extension holding `message` under `chunk_type`, so that
+    /// messages are found by the same four letter names as in PNG files
+    pub fn message(chunk_type: &ChunkType, message: &[u8]) -> Self {
+        let mut data = chunk_type.bytes().to_vec();
+        data.extend_from_slice(message);
+        Self::extension(APPLICATION_LABEL, Some(MESSAGE_APPLICATION), &data)
+    }
+
+    /// Returns the extension label, or `None` for images
+    pub fn label(&self) -> Option<u8> {
+        match self.bytes[0] {
+            EXTENSION_INTRODUCER => Some(self.bytes[1]),
+            _ => None,
+        }
+    }
+
+    /// The number of bytes this block takes up in a file
+    pub fn encoded_length(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns the contents of each sub-block of an extension
+    pub fn sub_blocks(&self) -> Vec<&[u8]> {
+        let mut sub_blocks = Vec::new();
+        if self.label().is_none() {
+            return sub_blocks;
+        }
+
+        let mut position = 2;
+        while let Some(&length) = self.bytes.get(position) {
+            if length == 0 {
+                break;
+            }
+            sub_blocks.push(&self.bytes[position + 1..position + 1 + length as usize]);
+            position += 1 + length as usize;
+        }
+        sub_blocks
+    }
+
+    /// Returns the identifier and authentication code of an application extension
+    pub fn application(&self) -> Option<&[u8]> {
+        match self.label() {
+            Some(APPLICATION_LABEL) => self.sub_blocks().first().copied(),
+            _ => None,
+        }
+    }
+
+    /// Returns the text of a comment extension
+    pub fn comment(&self) -> Option<Vec<u8>> {
+        match self.label() {
+            Some(COMMENT_LABEL) => Some(self.sub_blocks().concat()),
+            _ => None,
+        }
+    }
+
+    /// Returns the chunk type and contents if this block holds a message
+    pub fn as_message(&self) -> Option<(ChunkType, Vec<u8>)> {
+        if self.application() != Some(&MESSAGE_APPLICATION[..]) {
+            return None;
+        }
+        let data = self.sub_blocks()[1..].concat();
+        let type_bytes: [u8; 4] = data.get(..4)?.try_into().ok()?;
+        let chunk_type = ChunkType::try_from(type_bytes).ok()?;
+        Some((chunk_type, data[4..].to_vec()))
+    }
+
+    /// A short description of the block, like `image 16x16` or `comment`
+    pub fn name(&self) -> String {
+        match self.label() {
+            None => {
+                let width = u16::from_le_bytes([self.bytes[5], self.bytes[6]]);
+                let height = u16::from_le_bytes([self.bytes[7], self.bytes[8]]);
+                format!("image {}x{}", width, height)
+            }
+            Some(GRAPHIC_CONTROL_LABEL) => "graphic control".to_string(),
+            Some(COMMENT_LABEL) => "comment".to_string(),
+            Some(PLAIN_TEXT_LABEL) => "plain text".to_string(),
+            Some(APPLICATION_LABEL) => "application".to_string(),
+            Some(label) => format!("extension 0x{:02X}", label),
+        }
+    }
+}
+
+/// A GIF file split into its header, with the logical screen descriptor and global
+/// color table, the blocks that follow, and anything after the trailer
+#[derive(Debug, Clone)]
+pub struct Gif {
+    header: Vec<u8>,
+    blocks: Vec<Block>,
+    trailing_data: Vec<u8>,
+}
+
+impl Gif {
+    const HEADER_LENGTH: usize = 6 + 7;
+
+    /// Returns true if `bytes` starts like a GIF file
+    pub fn is_gif(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"GIF87a") || bytes.starts_with(b"GIF89a")
+    }
+
+    /// The length of the header, logical screen descriptor and global color table
+    pub fn header_length(&self) -> usize {
+        self.header.len()
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    /// Returns the first message stored under `chunk_type`
+    pub fn message(&self, chunk_type: &str) -> Option<Vec<u8>> {
+        self.messages()
+            .find(|(found, _)| found.to_string() == chunk_type)
+            .map(|(_, message)| message)
+    }
+
+    /// Returns the chunk type and contents of every message
+    pub fn messages(&self) -> impl Iterator<Item = (ChunkType, Vec<u8>)> + '_ {
+        self.blocks.iter().filter_map(Block::as_message)
+    }
+
+    /// Adds a message after the last image. Messages are extensions, which GIF87a doesn't
+    /// have, so older files are marked as GIF89a.
+    pub fn append_message(&mut self, chunk_type: &ChunkType, message: &[u8]) {
+        self.header[3..6].copy_from_slice(b"89a");
+        self.blocks.push(Block::message(chunk_type, message));
+    }
+
+    /// Removes the first message stored under `chunk_type` and returns its contents
+    pub fn remove_message(&mut self, chunk_type: &str) -> anyhow::Result<Vec<u8>> {
+        let (index, message) = self
+            .blocks
+            .iter()
+            .enumerate()
+            .find_map(|(index, block)| match block.as_message() {
+                Some((found, message)) if found.to_string() == chunk_type => Some((index, message)),
+                _ => None,
+            })
+            .ok_or_else(|| anyhow::anyhow!("No message of type {}", chunk_type))
+            .code("chunk_not_found")
+            .in_chunk(chunk_type)?;
+
+        self.blocks.remove(index);
+        Ok(message)
+    }
+
+    /// Returns the text of every comment extension
+    pub fn comments(&self) -> Vec<Vec<u8>> {
+        self.blocks.iter().filter_map(Block::comment).collect()
+    }
+
+    /// Adds a comment extension after the last image, marking GIF87a files as GIF89a
+    /// like `append_message`
+    pub fn append_comment(&mut self, text: &[u8]) {
+        self.header[3..6].copy_from_slice(b"89a");
+        self.blocks
+            .push(Block::extension(COMMENT_LABEL, None, text));
+    }
+
+    /// Removes every comment extension and returns how many there were
+    pub fn remove_comments(&mut self) -> usize {
+        let before = self.blocks.len();
+        self.blocks.retain(|block| block.comment().is_none());
+        before - self.blocks.len()
+    }
+
+    /// Keeps only the messages for which `keep` returns true. Other blocks are untouched.
+    pub fn retain_messages<F: FnMut(&ChunkType) -> bool>(&mut self, mut keep: F) {
+        self.blocks
+            .retain(|block| block.as_message().is_none_or(|(found, _)| keep(&found)));
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let length = self.header.len()
+            + self.blocks.iter().map(Block::encoded_length).sum::<usize>()
+            + 1
+            + self.trailing_data.len();
+
+        let mut bytes = Vec::with_capacity(length);
+        bytes.extend_from_slice(&self.header);
+        for block in &self.blocks {
+            bytes.extend_from_slice(&block.bytes);
+        }
+        bytes.push(TRAILER);
+        bytes.extend_from_slice(&self.trailing_data);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Gif {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> anyhow::Result<Self> {
+        if !Self::is_gif(bytes) {
+            anyhow::bail!("Invalid GIF header");
+        }
+        if bytes.len() < Self::HEADER_LENGTH {
+            anyhow::bail!("GIF is truncated");
+        }
+
+        let header_length = Self::HEADER_LENGTH + color_table_length(bytes[10]);
+        let header = bytes
+            .get(..header_length)
+            .ok_or_else(|| anyhow::anyhow!("GIF is truncated"))?
+            .to_vec();
+
+        let mut blocks = Vec::new();
+        let mut position = header_length;
+
+        loop {
+            let start = position;
+            match bytes.get(position) {
+                Some(&EXTENSION_INTRODUCER) => position += 2,
+                Some(&IMAGE_SEPARATOR) => {
+                    let packed = *bytes
+                        .get(position + 9)
+                        .ok_or_else(|| anyhow::anyhow!("Image at offset {} is truncated", start))?;
+                    // Descriptor, local color table and LZW minimum code size
+                    position += 10 + color_table_length(packed) + 1;
+                }
+                Some(&TRAILER) => {
+                    return Ok(Self {
+                        header,
+                        blocks,
+                        trailing_data: bytes[position + 1..].to_vec(),
+                    });
+                }
+                Some(byte) => anyhow::bail!("Unknown GIF block 0x{:02X} at offset {}", byte, start),
+                None => anyhow::bail!("GIF ends without a trailer"),
+            }
+
+            loop {
+                let length = *bytes
+                    .get(position)
+                    .ok_or_else(|| anyhow::anyhow!("Block at offset {} is truncated", start))?;
+                position += 1 + length as usize;
+                if length == 0 {
+                    break;
+                }
+            }
+
+            let block = bytes
+                .get(start..position)
+                .ok_or_else(|| anyhow::anyhow!("Block at offset {} is truncated", start))?;
+            blocks.push(Block {
+                bytes: block.to_vec(),
+            });
+        }
+    }
+}
+
+/// The length of the color table described by the packed fields of a logical screen or
+/// image descriptor
+fn color_table_length(packed: u8) -> usize {
+    if packed & 0x80 == 0 {
+        return 0;
+    }
+    3 * (1 << ((packed & 0x07) + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let mut bytes = b"GIF87a".to_vec();
+        // 1x1 screen with a two color global color table
+        bytes.extend_from_slice(&[1, 0, 1, 0, 0x80, 0, 0]);
+        bytes.extend_from_slice(&[0, 0, 0, 255, 255, 255]);
+        // Image descriptor, LZW minimum code size and image data
+        bytes.extend_from_slice(&[IMAGE_SEPARATOR, 0, 0, 0, 0, 1, 0, 1, 0, 0]);
+        bytes.extend_from_slice(&[2, 2, 0x4C, 0x01, 0]);
+        bytes.push(TRAILER);
+        bytes
+    }
+
+    #[test]
+    fn test_parse() {
+        let gif = Gif::try_from(testing_bytes().as_ref()).unwrap();
+        assert_eq!(gif.header_length(), 19);
+        assert_eq!(gif.blocks().len(), 1);
+        assert_eq!(gif.blocks()[0].name(), "image 1x1");
+        assert_eq!(gif.as_bytes(), testing_bytes());
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let mut gif = Gif::try_from(testing_bytes().as_ref()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let message = vec![7; 600];
+        gif.append_message(&chunk_type, &message);
+
+        let bytes = gif.as_bytes();
+        assert!(bytes.starts_with(b"GIF89a"));
+
+        let gif = Gif::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(gif.message("ruSt"), Some(message));
+        assert_eq!(gif.message("otHr"), None);
+        assert_eq!(gif.blocks()[1].application(), Some(&b"PNGME1.0MSG"[..]));
+    }
+
+    #[test]
+    fn test_remove_message() {
+        let mut gif = Gif::try_from(testing_bytes().as_ref()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        gif.append_message(&chunk_type, b"secret");
+
+        assert_eq!(gif.remove_message("ruSt").unwrap(), b"secret");
+        assert!(gif.remove_message("ruSt").is_err());
+        assert_eq!(gif.blocks().len(), 1);
+    }
+
+    #[test]
+    fn test_comment() {
+        let block = Block::extension(COMMENT_LABEL, None, b"Made by a crab");
+        assert_eq!(block.comment().unwrap(), b"Made by a crab");
+        assert_eq!(block.as_message(), None);
+    }
+
+    #[test]
+    fn test_comments() {
+        let mut gif = Gif::try_from(testing_bytes().as_ref()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        gif.append_comment(b"first");
+        gif.append_message(&chunk_type, b"secret");
+        gif.append_comment(&[b'x'; 300]);
+
+        let gif = Gif::try_from(gif.as_bytes().as_ref()).unwrap();
+        assert_eq!(gif.comments(), vec![b"first".to_vec(), vec![b'x'; 300]]);
+
+        let mut gif = gif;
+        assert_eq!(gif.remove_comments(), 2);
+        assert!(gif.comments().is_empty());
+        assert_eq!(gif.message("ruSt"), Some(b"secret".to_vec()));
+        assert_eq!(gif.remove_comments(), 0);
+    }
+
+    #[test]
+    fn test_invalid_gif() {
+        let bytes = testing_bytes();
+        assert!(Gif::try_from(&bytes[1..]).is_err());
+        assert!(Gif::try_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Gif::try_from(&bytes[..15]).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+use std::convert::TryFrom;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use eframe::egui;
+
+use pngme::atomic;
+use pngme::chunk_type::check_message_chunk_type;
+use pngme::container::Container;
+use pngme::envelope;
+use pngme::png::ChunkType;
+
+/// Opens a window for looking at and editing the messages in image files, starting with
+/// `file` if there is one
+pub fn run(file: Option<PathBuf>) -> anyhow::Result<()> {
+    let mut app = App::default();
+    if let Some(file) = file {
+        app.open(&file);
+    }
+
+    eframe::run_native(
+        "pngme",
+        eframe::NativeOptions::default(),
+        Box::new(|context| {
+            egui_extras::install_image_loaders(&context.egui_ctx);
+            Ok(Box::new(app))
+        }),
+    )
+    .map_err(|error| anyhow::anyhow!("Couldn't open a window: {}", error))
+}
+
+/// An image file and what it was read as
+struct OpenFile {
+    path: PathBuf,
+    /// Shared with the preview, so showing it doesn't copy the file every frame
+    bytes: egui::load::Bytes,
+    container: Container,
+}
+
+#[derive(Default)]
+struct App {
+    file: Option<OpenFile>,
+    /// Counts the files opened, so that the preview isn't served from the cache of an
+    /// earlier file at the same path
+    generation: usize,
+    chunk_type: String,
+    message: String,
+    force: bool,
+    /// The outcome of the last action, or why it failed
+    status: String,
+}
+
+impl App {
+    fn open(&mut self, path: &Path) {
+        let result = fs::read(path)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| {
+                let container = Container::try_from(bytes.as_slice())?;
+                Ok(OpenFile {
+                    path: path.to_path_buf(),
+                    bytes: bytes.into(),
+                    container,
+                })
+            });
+
+        match result {
+            Ok(file) => {
+                self.status = format!("Opened {}", path.display());
+                self.file = Some(file);
+                self.generation += 1;
+            }
+            Err(error) => self.status = format!("Couldn't open {}: {:#}", path.display(), error),
+        }
+    }
+
+    fn decode(&mut self) -> anyhow::Result<()> {
+        let file = self.file.as_ref().ok_or_else(no_file)?;
+        match file.container.message(&self.chunk_type)? {
+            Some(data) => {
+                let data = envelope::unwrap(&data)?;
+                self.message = String::from_utf8_lossy(data).into_owned();
+                self.status = format!("Decoded {} bytes", data.len());
+            }
+            None => anyhow::bail!("No chunk of type {}", self.chunk_type),
+        }
+        Ok(())
+    }
+
+    fn encode(&mut self) -> anyhow::Result<()> {
+        let file = self.file.as_mut().ok_or_else(no_file)?;
+        let chunk_type = ChunkType::from_str(&self.chunk_type)?;
+        if !self.force {
+            check_message_chunk_type(&chunk_type)?;
+        }
+
+        let mut container = file.container.clone();
+        container.append_message(&chunk_type, envelope::wrap(self.message.as_bytes()))?;
+        let bytes = container.checked_bytes()?;
+        atomic::write(&file.path, &bytes)?;
+
+        file.container = container;
+        file.bytes = bytes.into();
+        self.status = format!("Wrote message to {}", file.path.display());
+        Ok(())
+    }
+
+    fn chunk_list(&self, ui: &mut egui::Ui) {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return,
+        };
+
+        egui::Grid::new("chunks")
+            .striped(true)
+            .show(ui, |ui| match &file.container {
+                Container::Png(png) => {
+                    ui.strong("Offset");
+                    ui.strong("Type");
+                    ui.strong("Length");
+                    ui.end_row();
+                    for chunk in png.chunks() {
+                        ui.label(chunk.offset().unwrap_or_default().to_string());
+                        ui.monospace(chunk.chunk_type().to_string());
+                        ui.label(chunk.data().len().to_string());
+                        ui.end_row();
+                    }
+                }
+                container => {
+                    ui.strong(format!("{} messages", container.format()));
+                    ui.end_row();
+                    for chunk_type in container.message_types().unwrap_or_default() {
+                        ui.monospace(chunk_type.to_string());
+                        ui.end_row();
+                    }
+                }
+            });
+    }
+}
+
+impl eframe::App for App {
+    fn update(&mut self, context: &egui::Context, _frame: &mut eframe::Frame) {
+        let dropped = context.input(|input| {
+            input
+                .raw
+                .dropped_files
+                .iter()
+                .find_map(|file| file.path.clone())
+        });
+        if let Some(path) = dropped {
+            self.open(&path);
+        }
+
+        egui::TopBottomPanel::bottom("status").show(context, |ui| {
+            ui.label(&self.status);
+        });
+
+        egui::SidePanel::left("chunk_list").show(context, |ui| {
+            ui.heading("Chunks");
+            egui::ScrollArea::vertical().show(ui, |ui| self.chunk_list(ui));
+        });
+
+        egui::SidePanel::right("messages").show(context, |ui| {
+            ui.heading("Message");
+            ui.horizontal(|ui| {
+                ui.label("Chunk type");
+                ui.text_edit_singleline(&mut self.chunk_type);
+            });
+            ui.text_edit_multiline(&mut self.message);
+            ui.checkbox(&mut self.force, "Allow critical and standard chunk types");
+            ui.horizontal(|ui| {
+                if ui.button("Decode").clicked() {
+                    if let Err(error) = self.decode() {
+                        self.status = format!("{:#}", error);
+                    }
+                }
+                if ui.button("Encode").clicked() {
+                    if let Err(error) = self.encode() {
+                        self.status = format!("{:#}", error);
+                    }
+                }
+            });
+        });
+
+        egui::CentralPanel::default().show(context, |ui| match &self.file {
+            Some(file) => {
+                let uri = format!("bytes://{}/{}", self.generation, file.path.display());
+                ui.add(egui::Image::from_bytes(uri, file.bytes.clone()).shrink_to_fit());
+            }
+            None => {
+                ui.centered_and_justified(|ui| ui.label("Drop an image file here"));
+            }
+        });
+    }
+}
+
+fn no_file() -> anyhow::Error {
+    anyhow::anyhow!("Open a file first by dropping it on the window")
+}
@@ -0,0 +1,210 @@
+use std::convert::TryFrom;
+
+use base64::Engine;
+
+use crate::camouflage;
+use crate::envelope::{self, BOOKKEEPING_CHUNK_TYPES};
+use crate::multipart::{Manifest, MANIFEST_CHUNK_TYPE};
+use crate::png::{Chunk, ChunkType, Png};
+use crate::search;
+use crate::trailer;
+
+/// Chunk types the PNG spec defines that hold free text, where messages are also found
+const TEXT_CHUNK_TYPES: [&str; 3] = ["tEXt", "zTXt", "iTXt"];
+
+/// A message found by `find`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HiddenMessage {
+    pub chunk_type: ChunkType,
+    /// Offset of the chunk the message is in, or of the trailer, from the start of the
+    /// file. Split messages have the offset of their manifest.
+    pub offset: usize,
+    /// The keyword of the text chunk the message is in
+    pub keyword: Option<String>,
+    /// The message, taken out of its envelope. Encrypted messages stay encrypted.
+    pub data: Vec<u8>,
+}
+
+/// Finds every message in `png` without knowing its chunk type: the data of each chunk
+/// type the PNG spec doesn't define, the text of tEXt, zTXt and iTXt chunks, messages
+/// split across chunks, and messages stored after IEND. Camouflaged messages are found
+/// in their text chunks. pngme's own records are left out.
+pub fn find(png: &Png) -> Vec<HiddenMessage> {
+    let mut found = Vec::new();
+    let manifests: Vec<(usize, Manifest)> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == MANIFEST_CHUNK_TYPE)
+        .filter_map(|chunk| {
+            let manifest = Manifest::try_from(chunk.data()).ok()?;
+            Some((chunk.offset().unwrap_or_default(), manifest))
+        })
+        .collect();
+
+    for chunk in png.chunks() {
+        let chunk_type = chunk.chunk_type();
+        let name = chunk_type.to_string();
+        let split = manifests
+            .iter()
+            .any(|(_, manifest)| &manifest.chunk_type == chunk_type);
+        if split || BOOKKEEPING_CHUNK_TYPES.contains(&name.as_str()) {
+            continue;
+        }
+
+        let (keyword, data) = if TEXT_CHUNK_TYPES.contains(&name.as_str()) {
+            let (keyword, text) = text_fields(chunk);
+            (Some(keyword), text)
+        } else if !chunk_type.is_registered() {
+            (None, chunk.data().to_vec())
+        } else {
+            continue;
+        };
+        found.push(HiddenMessage {
+            chunk_type: *chunk_type,
+            offset: chunk.offset().unwrap_or_default(),
+            keyword,
+            data: unwrap(&data),
+        });
+    }
+
+    for (offset, manifest) in &manifests {
+        let parts: Vec<Vec<u8>> = png
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type() == &manifest.chunk_type)
+            .map(|chunk| chunk.data().to_vec())
+            .collect();
+        if let Ok(payload) = manifest.assemble(&parts) {
+            found.push(HiddenMessage {
+                chunk_type: manifest.chunk_type,
+                offset: *offset,
+                keyword: None,
+                data: unwrap(&payload),
+            });
+        }
+    }
+
+    let trailer_offset = png.as_bytes().len() - png.trailer().len();
+    for (chunk_type, message) in trailer::messages(png.trailer()) {
+        found.push(HiddenMessage {
+            chunk_type,
+            offset: trailer_offset,
+            keyword: None,
+            data: unwrap(message),
+        });
+    }
+
+    found
+}
+
+/// Returns the keyword and text of a tEXt, zTXt or iTXt chunk. The text comes after the
+/// last NUL once any compressed part is inflated, since the text itself can't hold one.
+fn text_fields(chunk: &Chunk) -> (String, Vec<u8>) {
+    let data = search::searchable_data(chunk);
+    let keyword_end = data.iter().position(|&byte| byte == 0);
+    let text_start = data
+        .iter()
+        .rposition(|&byte| byte == 0)
+        .map_or(0, |i| i + 1);
+    let keyword = data[..keyword_end.unwrap_or(0)]
+        .iter()
+        .map(|&byte| byte as char)
+        .collect();
+    (keyword, data[text_start..].to_vec())
+}
+
+/// Takes a message out of its envelope, and binary messages camouflaged as text out of
+/// their base64. Anything else is returned as it is.
+fn unwrap(data: &[u8]) -> Vec<u8> {
+    if let Ok(text) = std::str::from_utf8(data) {
+        if let Ok(decoded) = base64::engine::general_purpose::STANDARD.decode(text.trim()) {
+            if camouflage::is_binary_message(&decoded) {
+                return unwrap(&decoded);
+            }
+        }
+    }
+    envelope::unwrap(data).unwrap_or(data).to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AUDIT_CHUNK_TYPE;
+    use crate::messages::MessageStore;
+    use crate::text::TextChunk;
+    use std::io::Write;
+    use std::str::FromStr;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    #[test]
+    fn test_find() {
+        let split_type = ChunkType::new(*b"spLt");
+        let (manifest, parts) =
+            Manifest::split(split_type, &envelope::wrap(b"split message"), 8).unwrap();
+        let mut chunks = vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            TextChunk::new("Software", "pngme").unwrap().to_chunk(),
+            Chunk::new(ChunkType::new(*b"ruSt"), envelope::wrap(b"hidden")),
+            Chunk::from_strings("gAMA", "gamma").unwrap(),
+            Chunk::from_strings(AUDIT_CHUNK_TYPE, "records").unwrap(),
+            Chunk::new(
+                ChunkType::from_str(MANIFEST_CHUNK_TYPE).unwrap(),
+                manifest.as_bytes(),
+            ),
+        ];
+        chunks.extend(parts.into_iter().map(|part| Chunk::new(split_type, part)));
+        chunks.push(Chunk::from_strings("IEND", "").unwrap());
+        let mut png = Png::from_chunks(chunks);
+        png.set_trailer(trailer::wrap(
+            &ChunkType::new(*b"taIl"),
+            &envelope::wrap(b"after"),
+        ));
+        let png = Png::from_bytes_preserving(&png.as_bytes()).unwrap();
+
+        let found: Vec<(String, Option<String>, Vec<u8>)> = find(&png)
+            .into_iter()
+            .map(|message| {
+                (
+                    message.chunk_type.to_string(),
+                    message.keyword,
+                    message.data,
+                )
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    "tEXt".to_string(),
+                    Some("Software".to_string()),
+                    b"pngme".to_vec()
+                ),
+                ("ruSt".to_string(), None, b"hidden".to_vec()),
+                ("spLt".to_string(), None, b"split message".to_vec()),
+                ("taIl".to_string(), None, b"after".to_vec()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_camouflaged_and_compressed_text() {
+        let message = MessageStore::default().as_bytes();
+        let camouflaged = camouflage::hide(&ChunkType::new(*b"tEXt"), "Comment", &message).unwrap();
+        let mut compressed = b"Title\0\0".to_vec();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"Squeezed").unwrap();
+        compressed.extend(encoder.finish().unwrap());
+        let png = Png::from_chunks(vec![
+            camouflaged,
+            Chunk::new(ChunkType::new(*b"zTXt"), compressed),
+        ]);
+
+        let found = find(&png);
+        assert_eq!(found[0].keyword.as_deref(), Some("Comment"));
+        assert_eq!(found[0].data, message);
+        assert_eq!(found[1].keyword.as_deref(), Some("Title"));
+        assert_eq!(found[1].data, b"Squeezed");
+    }
+}
@@ -0,0 +1,220 @@
+use std::convert::{TryFrom, TryInto};
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::png::{Chunk, ChunkType, Png};
+
+/// The chunk type used to store undo history
+pub const HISTORY_CHUNK_TYPE: &str = "pnGb";
+
+const VERSION: u8 = 1;
+const REFERENCE: u8 = 0;
+const ARCHIVED: u8 = 1;
+
+/// Records how to get from `after` back to `before` in a new history chunk in `after`.
+/// Chunks that still exist in `after` are stored as references, so only removed or
+/// replaced chunks take up space.
+///
+/// Before compression the chunk data is laid out as follows
+/// 1. Format version *(1 byte)*
+/// 2. Number of chunks in the previous state *(4 bytes)*
+/// 3. For each of those chunks, either a zero byte and the index of the same chunk in
+///    the new state *(5 bytes)*, or a one byte and the whole archived chunk
+///
+/// Indices count only non-history chunks. Each edit adds one history chunk, and `undo`
+/// reverts the most recent.
+pub fn record(before: &Png, after: &mut Png) -> anyhow::Result<()> {
+    let current: Vec<&Chunk> = content_chunks(after).collect();
+    let mut used = vec![false; current.len()];
+
+    let previous: Vec<&Chunk> = content_chunks(before).collect();
+    let mut entry = vec![VERSION];
+    entry.extend_from_slice(&(previous.len() as u32).to_be_bytes());
+
+    for chunk in previous {
+        let found = current.iter().enumerate().position(|(index, candidate)| {
+            !used[index]
+                && candidate.chunk_type() == chunk.chunk_type()
+                && candidate.data() == chunk.data()
+        });
+
+        match found {
+            Some(index) => {
+                used[index] = true;
+                entry.push(REFERENCE);
+                entry.extend_from_slice(&(index as u32).to_be_bytes());
+            }
+            None => {
+                entry.push(ARCHIVED);
+                chunk.write_bytes(&mut entry);
+            }
+        }
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&entry)?;
+    let data = encoder.finish()?;
+
+    after.insert_chunk(Chunk::new(ChunkType::from_str(HISTORY_CHUNK_TYPE)?, data));
+    Ok(())
+}
+
+/// Returns the number of edits that can be undone
+pub fn depth(png: &Png) -> usize {
+    png.chunks()
+        .iter()
+        .filter(|chunk| is_history(chunk))
+        .count()
+}
+
+/// Returns `png` as it was before the most recent recorded edit
+pub fn undo(png: &Png) -> anyhow::Result<Png> {
+    let (history_index, history) = png
+        .chunks()
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, chunk)| is_history(chunk))
+        .ok_or_else(|| anyhow::anyhow!("No undo history found"))?;
+
+    let mut entry = Vec::new();
+    ZlibDecoder::new(history.data()).read_to_end(&mut entry)?;
+
+    if entry.len() < 5 {
+        anyhow::bail!("Undo history is truncated");
+    }
+    if entry[0] != VERSION {
+        anyhow::bail!("Unsupported undo history version {}", entry[0]);
+    }
+
+    let current: Vec<&Chunk> = content_chunks(png).collect();
+    let count = u32::from_be_bytes(entry[1..5].try_into()?) as usize;
+    let mut position = 5;
+    let mut chunks = Vec::with_capacity(count);
+
+    for _ in 0..count {
+        let tag = *entry
+            .get(position)
+            .ok_or_else(|| anyhow::anyhow!("Undo history is truncated"))?;
+        position += 1;
+
+        match tag {
+            REFERENCE => {
+                let index = read_u32(&entry, position)? as usize;
+                position += 4;
+                let chunk = current.get(index).ok_or_else(|| {
+                    anyhow::anyhow!("Undo history refers to a chunk that no longer exists")
+                })?;
+                chunks.push((*chunk).clone());
+            }
+            ARCHIVED => {
+                let length = read_u32(&entry, position)? as usize + 12;
+                let bytes = entry
+                    .get(position..position + length)
+                    .ok_or_else(|| anyhow::anyhow!("Undo history is truncated"))?;
+                position += length;
+                chunks.push(Chunk::try_from(bytes)?);
+            }
+            _ => anyhow::bail!("Undo history is corrupted"),
+        }
+    }
+
+    let mut result = Png::from_chunks(chunks);
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        if is_history(chunk) && index != history_index {
+            result.insert_chunk(chunk.clone());
+        }
+    }
+
+    Ok(result)
+}
+
+fn is_history(chunk: &Chunk) -> bool {
+    chunk.chunk_type().to_string() == HISTORY_CHUNK_TYPE
+}
+
+fn content_chunks(png: &Png) -> impl Iterator<Item = &Chunk> {
+    png.chunks().iter().filter(|chunk| !is_history(chunk))
+}
+
+fn read_u32(bytes: &[u8], position: usize) -> anyhow::Result<u32> {
+    let bytes = bytes
+        .get(position..position + 4)
+        .ok_or_else(|| anyhow::anyhow!("Undo history is truncated"))?;
+    Ok(u32::from_be_bytes(bytes.try_into()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("tEXt", "Title\0Original").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    fn edit<F: FnOnce(&mut Png)>(png: &Png, change: F) -> Png {
+        let mut after = png.clone();
+        change(&mut after);
+        record(png, &mut after).unwrap();
+        after
+    }
+
+    #[test]
+    fn test_undo_remove() {
+        let png = testing_png();
+        let edited = edit(&png, |png| {
+            png.remove_chunk("tEXt").unwrap();
+        });
+        assert!(edited.chunk_by_type("tEXt").is_none());
+        assert_eq!(depth(&edited), 1);
+
+        let restored = undo(&edited).unwrap();
+        assert_eq!(restored.as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_undo_add_and_replace() {
+        let png = testing_png();
+        let edited = edit(&png, |png| {
+            png.remove_chunk("tEXt").unwrap();
+            png.insert_chunk(Chunk::from_strings("tEXt", "Title\0Changed").unwrap());
+            png.insert_chunk(Chunk::from_strings("ruSt", "secret").unwrap());
+        });
+
+        let restored = undo(&edited).unwrap();
+        assert_eq!(restored.as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_multiple_undos() {
+        let png = testing_png();
+        let first = edit(&png, |png| {
+            png.insert_chunk(Chunk::from_strings("ruSt", "one").unwrap());
+        });
+        let second = edit(&first, |png| {
+            png.remove_chunk("ruSt").unwrap();
+        });
+        assert_eq!(depth(&second), 2);
+
+        let restored = undo(&second).unwrap();
+        assert_eq!(depth(&restored), 1);
+        assert_eq!(restored.as_bytes(), first.as_bytes());
+
+        let restored = undo(&restored).unwrap();
+        assert_eq!(restored.as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_nothing_to_undo() {
+        assert!(undo(&testing_png()).is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use std::convert::TryInto;
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::png::{Chunk, ChunkType, Png};
+use crate::text::validate_keyword;
+
+const HEADER_LENGTH: usize = 128;
+const PROFILE_SIGNATURE: &[u8; 4] = b"acsp";
+
+/// An embedded ICC profile. See the PNG spec for more details.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.iCCP
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IccProfile {
+    /// The profile name, which follows the same rules as a text keyword
+    pub name: String,
+    /// The uncompressed profile
+    pub data: Vec<u8>,
+}
+
+impl IccProfile {
+    /// Creates a new profile after checking that `data` looks like an ICC profile
+    pub fn new(name: &str, data: Vec<u8>) -> anyhow::Result<Self> {
+        validate_keyword(name)?;
+        validate_profile(&data)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            data,
+        })
+    }
+
+    /// Reads the profile from `png`'s iCCP chunk, if it has one
+    pub fn from_png(png: &Png) -> anyhow::Result<Option<Self>> {
+        match png.chunk_by_type("iCCP") {
+            Some(chunk) => Self::from_chunk(chunk).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Parses an iCCP chunk and decompresses its profile
+    pub fn from_chunk(chunk: &Chunk) -> anyhow::Result<Self> {
+        let data = chunk.data();
+        let separator = data
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| anyhow::anyhow!("iCCP chunk is missing its profile name separator"))?;
+
+        let name: String = data[..separator].iter().map(|&byte| byte as char).collect();
+        match data.get(separator + 1) {
+            Some(0) => {}
+            Some(method) => anyhow::bail!("Unknown iCCP compression method {}", method),
+            None => anyhow::bail!("iCCP chunk is truncated"),
+        }
+
+        let mut profile = Vec::new();
+        ZlibDecoder::new(&data[separator + 2..]).read_to_end(&mut profile)?;
+
+        Self::new(&name, profile)
+    }
+
+    /// Returns this profile as an iCCP chunk.
+    /// The chunk data is laid out as follows
+    /// 1. Latin-1 profile name and a NUL separator
+    /// 2. Compression method, always zero for zlib *(1 byte)*
+    /// 3. zlib compressed profile
+    pub fn to_chunk(&self) -> anyhow::Result<Chunk> {
+        let mut data: Vec<u8> = self.name.chars().map(|c| c as u8).collect();
+        data.extend_from_slice(&[0, 0]);
+
+        let mut encoder = ZlibEncoder::new(data, Compression::best());
+        encoder.write_all(&self.data)?;
+        let data = encoder.finish()?;
+
+        Ok(Chunk::new(ChunkType::new(*b"iCCP"), data))
+    }
+}
+
+/// Returns a copy of `png` with `profile` embedded directly after IHDR. Any existing
+/// iCCP chunk is replaced, and any sRGB chunk is removed since the spec does not allow
+/// both.
+pub fn embed(png: &Png, profile: &IccProfile) -> anyhow::Result<Png> {
+    let mut result = png.clone();
+    result.retain_chunks(|chunk| {
+        let chunk_type = chunk.chunk_type().to_string();
+        chunk_type != "iCCP" && chunk_type != "sRGB"
+    });
+    result.insert_chunk_after("IHDR", profile.to_chunk()?)?;
+
+    Ok(result)
+}
+
+/// Checks the parts of the ICC profile header that identify it as a profile
+fn validate_profile(data: &[u8]) -> anyhow::Result<()> {
+    if data.len() < HEADER_LENGTH {
+        anyhow::bail!(
+            "ICC profiles are at least {} bytes, found {}",
+            HEADER_LENGTH,
+            data.len()
+        );
+    }
+
+    let declared_length = u32::from_be_bytes(data[0..4].try_into()?) as usize;
+    if declared_length != data.len() {
+        anyhow::bail!(
+            "ICC profile header declares {} bytes, found {}",
+            declared_length,
+            data.len()
+        );
+    }
+
+    if &data[36..40] != PROFILE_SIGNATURE {
+        anyhow::bail!("ICC profile is missing the 'acsp' signature");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_profile() -> Vec<u8> {
+        let mut data = vec![0; 200];
+        data[0..4].copy_from_slice(&200u32.to_be_bytes());
+        data[36..40].copy_from_slice(PROFILE_SIGNATURE);
+        data
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("sRGB", "\0").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let profile = IccProfile::new("Display P3", testing_profile()).unwrap();
+        let chunk = profile.to_chunk().unwrap();
+        assert!(chunk.data().starts_with(b"Display P3\0\0"));
+
+        assert_eq!(IccProfile::from_chunk(&chunk).unwrap(), profile);
+    }
+
+    #[test]
+    fn test_embed() {
+        let profile = IccProfile::new("ICC Profile", testing_profile()).unwrap();
+        let png = embed(&testing_png(), &profile).unwrap();
+        let png = embed(&png, &profile).unwrap();
+
+        let chunk_types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(chunk_types, vec!["IHDR", "iCCP", "IDAT", "IEND"]);
+        assert_eq!(IccProfile::from_png(&png).unwrap(), Some(profile));
+    }
+
+    #[test]
+    fn test_invalid_profiles() {
+        assert!(IccProfile::new("Profile", vec![0; 64]).is_err());
+
+        let mut wrong_length = testing_profile();
+        wrong_length.push(0);
+        assert!(IccProfile::new("Profile", wrong_length).is_err());
+
+        let mut wrong_signature = testing_profile();
+        wrong_signature[36] = b'x';
+        assert!(IccProfile::new("Profile", wrong_signature).is_err());
+
+        assert!(IccProfile::new("", testing_profile()).is_err());
+    }
+
+    #[test]
+    fn test_unknown_compression_method() {
+        let chunk = Chunk::new(
+            ChunkType::from_str("iCCP").unwrap(),
+            b"Profile\0\x01data".to_vec(),
+        );
+        assert!(IccProfile::from_chunk(&chunk).is_err());
+    }
+}
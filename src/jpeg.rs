@@ -0,0 +1,316 @@
+use std::convert::{TryFrom, TryInto};
+
+use crate::error::Locate;
+use crate::png::ChunkType;
+
+/// Start of image
+const SOI: u8 = 0xD8;
+/// End of image
+const EOI: u8 = 0xD9;
+/// Start of scan, after which the entropy coded image data follows
+const SOS: u8 = 0xDA;
+/// Comment
+const COM: u8 = 0xFE;
+/// The APPn segment used for messages
+const APP15: u8 = 0xEF;
+
+/// Identifies an APP15 segment holding a message, like `Exif\0` does for EXIF data
+const MESSAGE_IDENTIFIER: &[u8] = b"PNGME\0";
+
+/// The most data a segment can hold, since its length field counts itself
+pub const MAX_SEGMENT_DATA: usize = u16::MAX as usize - 2;
+
+/// A marker segment from the header of a JPEG file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    marker: u8,
+    data: Vec<u8>,
+}
+
+impl Segment {
+    pub fn new(marker: u8, data: Vec<u8>) -> anyhow::Result<Self> {
+        if data.len() > MAX_SEGMENT_DATA {
+            anyhow::bail!(
+                "JPEG segments hold at most {} bytes but the data is {} bytes",
+                MAX_SEGMENT_DATA,
+                data.len()
+            );
+        }
+        Ok(Self { marker, data })
+    }
+
+    /// Creates an APP15 segment holding `message` under `chunk_type`, so that messages
+    /// are found by the same four letter names as in PNG files
+    pub fn message(chunk_type: &ChunkType, message: &[u8]) -> anyhow::Result<Self> {
+        let mut data = MESSAGE_IDENTIFIER.to_vec();
+        data.extend_from_slice(&chunk_type.bytes());
+        data.extend_from_slice(message);
+        Self::new(APP15, data)
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Returns the chunk type and contents if this segment holds a message
+    pub fn as_message(&self) -> Option<(ChunkType, &[u8])> {
+        if self.marker != APP15 || !self.data.starts_with(MESSAGE_IDENTIFIER) {
+            return None;
+        }
+        let rest = &self.data[MESSAGE_IDENTIFIER.len()..];
+        let type_bytes: [u8; 4] = rest.get(..4)?.try_into().ok()?;
+        let chunk_type = ChunkType::try_from(type_bytes).ok()?;
+        Some((chunk_type, &rest[4..]))
+    }
+
+    /// The number of bytes this segment takes up in a file, including the marker
+    pub fn encoded_length(&self) -> usize {
+        4 + self.data.len()
+    }
+
+    /// A short name for the segment's marker, like `APP0` or `DQT`
+    pub fn name(&self) -> String {
+        match self.marker {
+            0xC4 => "DHT".to_string(),
+            0xCC => "DAC".to_string(),
+            0xC0..=0xCF => format!("SOF{}", self.marker - 0xC0),
+            0xDB => "DQT".to_string(),
+            0xDD => "DRI".to_string(),
+            0xE0..=0xEF => format!("APP{}", self.marker - 0xE0),
+            COM => "COM".to_string(),
+            marker => format!("0x{:02X}", marker),
+        }
+    }
+
+    /// Returns the identifier of an APPn segment, like `JFIF` or `Exif`, or the text of a
+    /// comment, shortened to fit on one line
+    pub fn label(&self) -> Option<String> {
+        let text = match self.marker {
+            0xE0..=0xEF => self.data.split(|&byte| byte == 0).next()?,
+            COM => &self.data,
+            _ => return None,
+        };
+        if text.is_empty()
+            || !text
+                .iter()
+                .all(|byte| byte.is_ascii_graphic() || *byte == b' ')
+        {
+            return None;
+        }
+
+        let text = String::from_utf8_lossy(text);
+        Some(match text.char_indices().nth(40) {
+            Some((end, _)) => format!("{}...", &text[..end]),
+            None => text.to_string(),
+        })
+    }
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        out.push(0xFF);
+        out.push(self.marker);
+        out.extend_from_slice(&(self.data.len() as u16 + 2).to_be_bytes());
+        out.extend_from_slice(&self.data);
+    }
+}
+
+/// A JPEG file split into the marker segments before the first scan, which can be
+/// edited, and everything from the first scan on, which is kept as is
+#[derive(Debug, Clone)]
+pub struct Jpeg {
+    segments: Vec<Segment>,
+    image_data: Vec<u8>,
+}
+
+impl Jpeg {
+    pub const EXPECTED_HEADER: [u8; 2] = [0xFF, SOI];
+
+    /// Returns true if `bytes` starts like a JPEG file
+    pub fn is_jpeg(bytes: &[u8]) -> bool {
+        bytes.starts_with(&Self::EXPECTED_HEADER)
+    }
+
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// The length of the image data after the segments
+    pub fn image_data_length(&self) -> usize {
+        self.image_data.len()
+    }
+
+    /// Returns the first message stored under `chunk_type`
+    pub fn message(&self, chunk_type: &str) -> Option<&[u8]> {
+        self.messages()
+            .find(|(found, _)| found.to_string() == chunk_type)
+            .map(|(_, message)| message)
+    }
+
+    /// Returns the chunk type and contents of every message
+    pub fn messages(&self) -> impl Iterator<Item = (ChunkType, &[u8])> {
+        self.segments.iter().filter_map(Segment::as_message)
+    }
+
+    /// Adds a message segment after the other segments, right before the image data
+    pub fn append_message(&mut self, chunk_type: &ChunkType, message: &[u8]) -> anyhow::Result<()> {
+        self.segments.push(Segment::message(chunk_type, message)?);
+        Ok(())
+    }
+
+    /// Removes the first message stored under `chunk_type` and returns its contents
+    pub fn remove_message(&mut self, chunk_type: &str) -> anyhow::Result<Vec<u8>> {
+        let index = self
+            .segments
+            .iter()
+            .position(|segment| {
+                segment
+                    .as_message()
+                    .is_some_and(|(found, _)| found.to_string() == chunk_type)
+            })
+            .ok_or_else(|| anyhow::anyhow!("No message of type {}", chunk_type))
+            .code("chunk_not_found")
+            .in_chunk(chunk_type)?;
+
+        let segment = self.segments.remove(index);
+        Ok(segment.data[MESSAGE_IDENTIFIER.len() + 4..].to_vec())
+    }
+
+    /// Keeps only the messages for which `keep` returns true. Other segments are untouched.
+    pub fn retain_messages<F: FnMut(&ChunkType) -> bool>(&mut self, mut keep: F) {
+        self.segments
+            .retain(|segment| segment.as_message().is_none_or(|(found, _)| keep(&found)));
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let length = 2
+            + self
+                .segments
+                .iter()
+                .map(Segment::encoded_length)
+                .sum::<usize>()
+            + self.image_data.len();
+
+        let mut bytes = Vec::with_capacity(length);
+        bytes.extend_from_slice(&Self::EXPECTED_HEADER);
+        for segment in &self.segments {
+            segment.write_bytes(&mut bytes);
+        }
+        bytes.extend_from_slice(&self.image_data);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Jpeg {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> anyhow::Result<Self> {
+        if !Self::is_jpeg(bytes) {
+            anyhow::bail!("Invalid JPEG header");
+        }
+
+        let mut segments = Vec::new();
+        let mut position = Self::EXPECTED_HEADER.len();
+
+        loop {
+            if bytes.get(position) != Some(&0xFF) {
+                anyhow::bail!("Expected a JPEG marker at offset {}", position);
+            }
+            // Any number of 0xFF fill bytes may come before a marker
+            let start = position;
+            while bytes.get(position) == Some(&0xFF) {
+                position += 1;
+            }
+            let marker = *bytes
+                .get(position)
+                .ok_or_else(|| anyhow::anyhow!("JPEG ends before the image data"))?;
+            position += 1;
+
+            if marker == SOS || marker == EOI {
+                return Ok(Self {
+                    segments,
+                    image_data: bytes[start..].to_vec(),
+                });
+            }
+
+            let length = bytes
+                .get(position..position + 2)
+                .map(|length| u16::from_be_bytes([length[0], length[1]]) as usize)
+                .filter(|&length| length >= 2)
+                .ok_or_else(|| anyhow::anyhow!("Invalid segment length at offset {}", position))?;
+            let data = bytes
+                .get(position + 2..position + length)
+                .ok_or_else(|| anyhow::anyhow!("Segment at offset {} is truncated", start))?;
+            position += length;
+
+            segments.push(Segment {
+                marker,
+                data: data.to_vec(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn testing_bytes() -> Vec<u8> {
+        let mut bytes = vec![0xFF, SOI];
+        // APP0 with a JFIF identifier
+        bytes.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x07]);
+        bytes.extend_from_slice(b"JFIF\0");
+        // A comment preceded by a fill byte
+        bytes.extend_from_slice(&[0xFF, 0xFF, COM, 0x00, 0x07]);
+        bytes.extend_from_slice(b"hello");
+        // Scan header and image data
+        bytes.extend_from_slice(&[0xFF, SOS, 0x00, 0x02, 0x12, 0x34, 0xFF, 0x00, 0x56]);
+        bytes.extend_from_slice(&[0xFF, EOI]);
+        bytes
+    }
+
+    #[test]
+    fn test_parse() {
+        let jpeg = Jpeg::try_from(testing_bytes().as_ref()).unwrap();
+        let names: Vec<String> = jpeg.segments().iter().map(Segment::name).collect();
+        assert_eq!(names, vec!["APP0", "COM"]);
+        assert_eq!(jpeg.segments()[0].label().unwrap(), "JFIF");
+        assert_eq!(jpeg.segments()[1].label().unwrap(), "hello");
+        assert_eq!(jpeg.image_data_length(), 11);
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let mut jpeg = Jpeg::try_from(testing_bytes().as_ref()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        jpeg.append_message(&chunk_type, b"secret").unwrap();
+
+        let jpeg = Jpeg::try_from(jpeg.as_bytes().as_ref()).unwrap();
+        assert_eq!(jpeg.message("ruSt"), Some(&b"secret"[..]));
+        assert_eq!(jpeg.message("otHr"), None);
+        assert_eq!(jpeg.segments().last().unwrap().name(), "APP15");
+    }
+
+    #[test]
+    fn test_remove_message() {
+        let mut jpeg = Jpeg::try_from(testing_bytes().as_ref()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        jpeg.append_message(&chunk_type, b"secret").unwrap();
+
+        assert_eq!(jpeg.remove_message("ruSt").unwrap(), b"secret");
+        assert!(jpeg.remove_message("ruSt").is_err());
+        assert_eq!(jpeg.segments().len(), 2);
+    }
+
+    #[test]
+    fn test_message_too_large() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        assert!(Segment::message(&chunk_type, &vec![0; MAX_SEGMENT_DATA]).is_err());
+    }
+
+    #[test]
+    fn test_invalid_jpeg() {
+        let bytes = testing_bytes();
+        assert!(Jpeg::try_from(&bytes[1..]).is_err());
+        assert!(Jpeg::try_from(&bytes[..12]).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use std::convert::{TryFrom, TryInto};
+
+use crate::error::Locate;
+use crate::png::{is_chunk_at, Chunk, ChunkType, Png};
+
+/// Where a chunk sits in a PNG file and whether it is intact. Unlike `Png::try_from`,
+/// reading a layout doesn't stop at bad CRCs or invalid chunk types, so it can describe
+/// damaged files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkInfo {
+    /// Offset of the chunk's length field from the start of the file
+    pub offset: usize,
+    /// Length of the chunk data
+    pub length: u32,
+    /// The raw chunk type bytes, which may not be a valid `ChunkType`
+    pub type_bytes: [u8; 4],
+    /// The CRC stored in the file
+    pub crc: u32,
+    /// Whether the stored CRC matches the chunk type and data
+    pub crc_valid: bool,
+}
+
+impl ChunkInfo {
+    /// Returns the chunk type if its bytes are valid
+    pub fn chunk_type(&self) -> Option<ChunkType> {
+        ChunkType::try_from(self.type_bytes).ok()
+    }
+}
+
+/// Reads the position and integrity of every chunk in `bytes`. Returns an error if the
+/// header is wrong or a chunk runs past the end of the file. Reading stops at bytes after
+/// IEND that aren't a complete chunk, which are the trailer; see `trailer_offset`.
+pub fn chunk_layout(bytes: &[u8]) -> anyhow::Result<Vec<ChunkInfo>> {
+    if !bytes.starts_with(&Png::SIGNATURE) {
+        return Err(anyhow::anyhow!("Invalid header"))
+            .code("invalid_signature")
+            .at_offset(0);
+    }
+
+    let mut chunks = Vec::new();
+    let mut offset = Png::SIGNATURE.len();
+    let mut after_iend = false;
+
+    while offset < bytes.len() {
+        if after_iend && !is_chunk_at(&bytes[offset..]) {
+            break;
+        }
+        let fields = bytes
+            .get(offset..offset + 8)
+            .ok_or_else(|| anyhow::anyhow!("Truncated chunk at offset {}", offset))
+            .code("truncated")
+            .at_offset(offset)?;
+        let length = u32::from_be_bytes(fields[..4].try_into()?);
+        let type_bytes: [u8; 4] = fields[4..].try_into()?;
+
+        let data_start = offset + 8;
+        let data_end = data_start + length as usize;
+        let data = bytes
+            .get(data_start..data_end)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Chunk at offset {} claims {} bytes of data but the file ends first",
+                    offset,
+                    length
+                )
+            })
+            .code("truncated")
+            .at_offset(offset)?;
+        let crc = bytes
+            .get(data_end..data_end + 4)
+            .ok_or_else(|| anyhow::anyhow!("Truncated CRC at offset {}", data_end))
+            .code("truncated")
+            .at_offset(data_end)?;
+        let crc = u32::from_be_bytes(crc.try_into()?);
+
+        let crc_valid = match ChunkType::try_from(type_bytes) {
+            Ok(chunk_type) => Chunk::calculate_crc(&chunk_type, data) == crc,
+            Err(_) => false,
+        };
+
+        after_iend |= type_bytes == *b"IEND";
+        chunks.push(ChunkInfo {
+            offset,
+            length,
+            type_bytes,
+            crc,
+            crc_valid,
+        });
+        offset = data_end + 4;
+    }
+
+    Ok(chunks)
+}
+
+/// Returns the offset of the trailer after the chunks in `layout`, if `bytes` has one
+pub fn trailer_offset(bytes: &[u8], layout: &[ChunkInfo]) -> Option<usize> {
+    let end = layout.last().map_or(Png::SIGNATURE.len(), |info| {
+        info.offset + 12 + info.length as usize
+    });
+    (end < bytes.len()).then_some(end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_bytes() -> Vec<u8> {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("ruSt", "message").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+        .as_bytes()
+    }
+
+    #[test]
+    fn test_chunk_layout() {
+        let layout = chunk_layout(&testing_bytes()).unwrap();
+
+        let offsets: Vec<usize> = layout.iter().map(|info| info.offset).collect();
+        assert_eq!(offsets, vec![8, 8 + 18, 8 + 18 + 19]);
+        assert!(layout.iter().all(|info| info.crc_valid));
+        assert_eq!(layout[1].chunk_type().unwrap().to_string(), "ruSt");
+    }
+
+    #[test]
+    fn test_bad_crc_is_reported() {
+        let mut bytes = testing_bytes();
+        // Corrupt the ruSt data
+        bytes[8 + 18 + 8] ^= 1;
+
+        let layout = chunk_layout(&bytes).unwrap();
+        assert_eq!(layout.len(), 3);
+        assert!(layout[0].crc_valid);
+        assert!(!layout[1].crc_valid);
+        assert!(layout[2].crc_valid);
+    }
+
+    #[test]
+    fn test_trailer_after_iend() {
+        let mut bytes = testing_bytes();
+        assert_eq!(trailer_offset(&bytes, &chunk_layout(&bytes).unwrap()), None);
+
+        let end = bytes.len();
+        bytes.extend_from_slice(&[0, 0, 0, 99, b'z', b'i', b'p']);
+        let layout = chunk_layout(&bytes).unwrap();
+        assert_eq!(layout.len(), 3);
+        assert_eq!(trailer_offset(&bytes, &layout), Some(end));
+    }
+
+    #[test]
+    fn test_truncated_file() {
+        let bytes = testing_bytes();
+        assert!(chunk_layout(&bytes[..bytes.len() - 2]).is_err());
+        assert!(chunk_layout(&bytes[1..]).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+//! Hiding messages in PNG files and other images. The `pngme` binary is a command line
+//! interface over this library.
+
+pub mod anonymize;
+pub mod archive;
+pub mod atomic;
+pub mod audit;
+pub mod batch;
+pub mod bitplanes;
+pub mod builder;
+pub mod camouflage;
+mod chunk;
+pub mod chunk_type;
+pub mod clone_meta;
+pub mod config;
+pub mod conformance;
+pub mod container;
+#[cfg(feature = "convert")]
+pub mod convert;
+pub mod crypto;
+pub mod data_url;
+pub mod deniable;
+pub mod diagnostics;
+pub mod envelope;
+pub mod error;
+pub mod explain;
+pub mod explode;
+pub mod generate;
+pub mod gif;
+pub mod hidden;
+pub mod history;
+pub mod icc;
+pub mod jpeg;
+pub mod keys;
+pub mod layout;
+pub mod lint;
+pub mod memory;
+pub mod messages;
+pub mod multipart;
+pub mod obfuscate;
+pub mod optimize;
+pub mod output;
+mod parser;
+pub mod pixels;
+pub mod plan;
+pub mod png;
+pub mod polyglot;
+pub mod recipients;
+pub mod report;
+pub mod scan;
+pub mod seal;
+pub mod search;
+pub mod signature;
+pub mod stats;
+pub mod text;
+pub mod tiff;
+pub mod trailer;
+pub mod xmp;
+pub mod zip_path;
+
+pub use png::Png;
@@ -0,0 +1,597 @@
+use std::fmt;
+
+use crate::diagnostics::Severity;
+use crate::envelope::BOOKKEEPING_CHUNK_TYPES;
+use crate::png::{Chunk, Png};
+use crate::scan::{self, Assessment, ByteStatistics};
+use crate::search;
+use crate::text::validate_keyword;
+use crate::trailer;
+
+/// Chunk types the PNG spec allows only one of
+const UNIQUE_CHUNK_TYPES: [&str; 22] = [
+    "IHDR", "PLTE", "IEND", "acTL", "bKGD", "cHRM", "cICP", "cLLi", "eXIf", "gAMA", "hIST", "iCCP",
+    "mDCv", "oFFs", "pCAL", "pHYs", "sBIT", "sCAL", "sRGB", "sTER", "tIME", "tRNS",
+];
+
+const TEXT_CHUNK_TYPES: [&str; 3] = ["tEXt", "zTXt", "iTXt"];
+
+/// Rules whose fix drops data someone may have put there on purpose, like a ZIP archive
+/// appended to make a polyglot. `fix` runs them only when they are named.
+const OPT_IN_FIXES: [&str; 1] = ["trailer"];
+
+/// Text longer than this is unusual for metadata, and often something else stored as text
+const MAX_TEXT_LENGTH: usize = 64 * 1024;
+
+/// A check that `lint` can run, with the severity its findings have unless told otherwise.
+/// Rules are added by writing a check function and listing it in `RULES`. Rules whose
+/// problems can be corrected automatically also have a fix, which `fix` runs.
+pub struct Rule {
+    /// The name used to turn the rule off or change its severity, like `duplicate-chunk`
+    pub name: &'static str,
+    pub description: &'static str,
+    pub severity: Severity,
+    /// Returns the offset and message of each problem found
+    check: fn(&Png) -> Vec<(usize, String)>,
+    /// Corrects what `check` finds and returns what it changed
+    fix: Option<fn(&mut Png) -> Vec<String>>,
+}
+
+impl Rule {
+    pub fn is_fixable(&self) -> bool {
+        self.fix.is_some()
+    }
+}
+
+impl fmt::Debug for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Rule")
+            .field("name", &self.name)
+            .field("severity", &self.severity)
+            .field("fixable", &self.is_fixable())
+            .finish()
+    }
+}
+
+/// Every rule, in the order they run
+pub const RULES: [Rule; 8] = [
+    Rule {
+        name: "duplicate-chunk",
+        description: "A chunk type the PNG spec allows only once appears more than once",
+        severity: Severity::Error,
+        check: duplicate_chunks,
+        fix: None,
+    },
+    Rule {
+        name: "oversized-text",
+        description: "A text chunk holds more than 64 KiB of text",
+        severity: Severity::Warning,
+        check: oversized_text,
+        fix: None,
+    },
+    Rule {
+        name: "missing-color-space",
+        description: "There is no sRGB, iCCP, cICP or gAMA chunk saying how to show the colors",
+        severity: Severity::Warning,
+        check: missing_color_space,
+        fix: None,
+    },
+    Rule {
+        name: "text-keyword",
+        description: "A text chunk's keyword breaks the PNG spec's rules for keywords",
+        severity: Severity::Warning,
+        check: text_keywords,
+        fix: None,
+    },
+    Rule {
+        name: "private-chunk",
+        description: "A private chunk that isn't pngme's own, noting data that looks encrypted",
+        severity: Severity::Warning,
+        check: private_chunks,
+        fix: None,
+    },
+    Rule {
+        name: "crc",
+        description: "A chunk's CRC doesn't match its data",
+        severity: Severity::Error,
+        check: bad_crcs,
+        fix: Some(fix_crcs),
+    },
+    Rule {
+        name: "order",
+        description: "The chunks aren't in the order the PNG spec recommends",
+        severity: Severity::Warning,
+        check: misordered_chunks,
+        fix: Some(fix_order),
+    },
+    Rule {
+        name: "trailer",
+        description: "There are bytes after IEND that aren't messages stored by pngme",
+        severity: Severity::Warning,
+        check: foreign_trailer,
+        fix: Some(fix_trailer),
+    },
+];
+
+/// A problem found by a rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub rule: &'static str,
+    /// Offset from the start of the file of the chunk the problem is about
+    pub offset: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}: {} [{}]",
+            self.offset, self.severity, self.message, self.rule
+        )
+    }
+}
+
+/// A correction made by a rule's fix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.message, self.rule)
+    }
+}
+
+/// The rules to run and the severity of each one's findings
+#[derive(Debug, Clone)]
+pub struct RuleSet {
+    rules: Vec<(&'static Rule, Severity)>,
+}
+
+impl Default for RuleSet {
+    /// Every rule, at its own severity
+    fn default() -> Self {
+        Self {
+            rules: RULES.iter().map(|rule| (rule, rule.severity)).collect(),
+        }
+    }
+}
+
+impl RuleSet {
+    /// The rules called `names` that have a fix. If `names` is empty, all of them except
+    /// those in `OPT_IN_FIXES`
+    pub fn fixable(names: &[String]) -> anyhow::Result<Self> {
+        for name in names {
+            if !find_rule(name)?.is_fixable() {
+                let fixable: Vec<&str> = RULES
+                    .iter()
+                    .filter(|rule| rule.is_fixable())
+                    .map(|rule| rule.name)
+                    .collect();
+                anyhow::bail!(
+                    "The {} rule has no automatic fix. The rules that do are {}",
+                    name,
+                    fixable.join(", ")
+                );
+            }
+        }
+        let mut rules = Self::default();
+        rules.rules.retain(|(rule, _)| rule.is_fixable());
+        match names.is_empty() {
+            true => rules
+                .rules
+                .retain(|(rule, _)| !OPT_IN_FIXES.contains(&rule.name)),
+            false => rules.only(names)?,
+        }
+        Ok(rules)
+    }
+
+    /// Stops running the rule called `name`
+    pub fn disable(&mut self, name: &str) -> anyhow::Result<()> {
+        let rule = find_rule(name)?;
+        self.rules.retain(|(kept, _)| kept.name != rule.name);
+        Ok(())
+    }
+
+    /// Runs only the rules called `names`
+    pub fn only(&mut self, names: &[String]) -> anyhow::Result<()> {
+        for name in names {
+            find_rule(name)?;
+        }
+        self.rules
+            .retain(|(rule, _)| names.iter().any(|name| name == rule.name));
+        Ok(())
+    }
+
+    /// Changes the severity of a rule's findings, given as `name=level` where the level
+    /// is `error`, `warning` or `off`
+    pub fn configure(&mut self, setting: &str) -> anyhow::Result<()> {
+        let (name, level) = setting
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Expected RULE=LEVEL, found {:?}", setting))?;
+        let severity = match level {
+            "off" => return self.disable(name),
+            "error" => Severity::Error,
+            "warning" => Severity::Warning,
+            level => anyhow::bail!("Unknown level {:?}. Use error, warning or off", level),
+        };
+        let rule = find_rule(name)?;
+        for (kept, kept_severity) in &mut self.rules {
+            if kept.name == rule.name {
+                *kept_severity = severity;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the rules over `png` and returns what they found, in the order of the rules
+    pub fn lint(&self, png: &Png) -> Vec<Finding> {
+        self.rules
+            .iter()
+            .flat_map(|&(rule, severity)| {
+                (rule.check)(png)
+                    .into_iter()
+                    .map(move |(offset, message)| Finding {
+                        rule: rule.name,
+                        offset,
+                        severity,
+                        message,
+                    })
+            })
+            .collect()
+    }
+
+    /// Runs the fixes of the rules that have one over `png`, in the order of the rules,
+    /// and returns what they changed
+    pub fn fix(&self, png: &mut Png) -> Vec<Change> {
+        let mut changes = Vec::new();
+        for (rule, _) in &self.rules {
+            if let Some(fix) = rule.fix {
+                changes.extend(fix(png).into_iter().map(|message| Change {
+                    rule: rule.name,
+                    message,
+                }));
+            }
+        }
+        changes
+    }
+}
+
+fn find_rule(name: &str) -> anyhow::Result<&'static Rule> {
+    RULES.iter().find(|rule| rule.name == name).ok_or_else(|| {
+        let names: Vec<&str> = RULES.iter().map(|rule| rule.name).collect();
+        anyhow::anyhow!(
+            "Unknown lint rule {:?}. The rules are {}",
+            name,
+            names.join(", ")
+        )
+    })
+}
+
+fn offset(chunk: &Chunk) -> usize {
+    chunk.offset().unwrap_or_default()
+}
+
+fn duplicate_chunks(png: &Png) -> Vec<(usize, String)> {
+    let mut seen = Vec::new();
+    let mut found = Vec::new();
+    for chunk in png.chunks() {
+        let name = chunk.chunk_type().to_string();
+        if !UNIQUE_CHUNK_TYPES.contains(&name.as_str()) {
+            continue;
+        }
+        if seen.contains(&name) {
+            found.push((
+                offset(chunk),
+                format!("Repeated {} chunk, which may appear only once", name),
+            ));
+        } else {
+            seen.push(name);
+        }
+    }
+    found
+}
+
+fn text_chunks(png: &Png) -> impl Iterator<Item = &Chunk> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| TEXT_CHUNK_TYPES.contains(&chunk.chunk_type().to_string().as_str()))
+}
+
+fn oversized_text(png: &Png) -> Vec<(usize, String)> {
+    text_chunks(png)
+        .filter_map(|chunk| {
+            let length = search::searchable_data(chunk).len();
+            (length > MAX_TEXT_LENGTH).then(|| {
+                (
+                    offset(chunk),
+                    format!(
+                        "{} chunk holds {} bytes of text",
+                        chunk.chunk_type(),
+                        length
+                    ),
+                )
+            })
+        })
+        .collect()
+}
+
+fn missing_color_space(png: &Png) -> Vec<(usize, String)> {
+    let described = ["sRGB", "iCCP", "cICP", "gAMA"]
+        .iter()
+        .any(|chunk_type| png.chunk_by_type(chunk_type).is_some());
+    match described {
+        true => Vec::new(),
+        false => vec![(
+            Png::SIGNATURE.len(),
+            "No sRGB, iCCP, cICP or gAMA chunk, so viewers have to guess the color space"
+                .to_string(),
+        )],
+    }
+}
+
+fn text_keywords(png: &Png) -> Vec<(usize, String)> {
+    text_chunks(png)
+        .filter_map(|chunk| {
+            let data = chunk.data();
+            let keyword = match data.iter().position(|&byte| byte == 0) {
+                Some(end) => &data[..end],
+                None => {
+                    return Some((
+                        offset(chunk),
+                        format!("{} chunk has no NUL after its keyword", chunk.chunk_type()),
+                    ))
+                }
+            };
+            let keyword: String = keyword.iter().map(|&byte| byte as char).collect();
+            validate_keyword(&keyword).err().map(|error| {
+                (
+                    offset(chunk),
+                    format!(
+                        "{} chunk keyword {:?}: {}",
+                        chunk.chunk_type(),
+                        keyword,
+                        error
+                    ),
+                )
+            })
+        })
+        .collect()
+}
+
+fn private_chunks(png: &Png) -> Vec<(usize, String)> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| {
+            let chunk_type = chunk.chunk_type();
+            !chunk_type.is_public()
+                && !BOOKKEEPING_CHUNK_TYPES.contains(&chunk_type.to_string().as_str())
+        })
+        .map(|chunk| {
+            let assessment = ByteStatistics::of(chunk.data()).assess();
+            let looks = match assessment {
+                Assessment::Random if scan::is_suspicious(chunk.chunk_type(), assessment) => {
+                    ", which looks encrypted"
+                }
+                _ => "",
+            };
+            (
+                offset(chunk),
+                format!(
+                    "Private {} chunk holding {} bytes{}",
+                    chunk.chunk_type(),
+                    chunk.data().len(),
+                    looks
+                ),
+            )
+        })
+        .collect()
+}
+
+fn bad_crcs(png: &Png) -> Vec<(usize, String)> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| !chunk.is_crc_valid())
+        .map(|chunk| {
+            (
+                offset(chunk),
+                format!("{} chunk has a bad CRC", chunk.chunk_type()),
+            )
+        })
+        .collect()
+}
+
+fn fix_crcs(png: &mut Png) -> Vec<String> {
+    let mut changes = Vec::new();
+    for chunk in png.chunks_mut() {
+        if !chunk.is_crc_valid() {
+            changes.push(format!(
+                "Recomputed the CRC of the {} chunk at offset {}",
+                chunk.chunk_type(),
+                offset(chunk)
+            ));
+            *chunk = Chunk::new(*chunk.chunk_type(), chunk.data().to_vec());
+        }
+    }
+    changes
+}
+
+fn misordered_chunks(png: &Png) -> Vec<(usize, String)> {
+    let mut canonical = png.clone();
+    if !canonical.canonicalize() {
+        return Vec::new();
+    }
+    // Report the first chunk that isn't where it belongs, since moving it usually moves
+    // the rest as well
+    let misplaced = png
+        .chunks()
+        .iter()
+        .zip(canonical.chunks())
+        .find(|(chunk, expected)| chunk.chunk_type() != expected.chunk_type())
+        .map(|(chunk, expected)| (chunk, expected.chunk_type()));
+    match misplaced {
+        Some((chunk, expected)) => vec![(
+            offset(chunk),
+            format!(
+                "{} chunk where the PNG spec puts {}",
+                chunk.chunk_type(),
+                expected
+            ),
+        )],
+        None => Vec::new(),
+    }
+}
+
+fn fix_order(png: &mut Png) -> Vec<String> {
+    match png.canonicalize() {
+        true => vec!["Moved chunks into the order the PNG spec recommends".to_string()],
+        false => Vec::new(),
+    }
+}
+
+fn foreign_trailer(png: &Png) -> Vec<(usize, String)> {
+    let trailer = png.trailer();
+    if trailer.is_empty() || trailer::is_pngme_trailer(trailer) {
+        return Vec::new();
+    }
+    vec![(
+        png.as_bytes().len() - trailer.len(),
+        format!(
+            "{} bytes after IEND: {}",
+            trailer.len(),
+            trailer::describe(trailer)
+        ),
+    )]
+}
+
+fn fix_trailer(png: &mut Png) -> Vec<String> {
+    if trailer::is_pngme_trailer(png.trailer()) {
+        return Vec::new();
+    }
+    let trailer = png.strip_trailer();
+    match trailer.is_empty() {
+        true => Vec::new(),
+        false => vec![format!("Dropped {} bytes after IEND", trailer.len())],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audit::AUDIT_CHUNK_TYPE;
+    use crate::png::ChunkType;
+
+    fn testing_png() -> Png {
+        let png = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("gAMA", "1234").unwrap(),
+            Chunk::from_strings("tEXt", " Bad  keyword\0text").unwrap(),
+            Chunk::from_strings("gAMA", "5678").unwrap(),
+            Chunk::from_strings("ruSt", "message").unwrap(),
+            Chunk::from_strings(AUDIT_CHUNK_TYPE, "records").unwrap(),
+            Chunk::new(ChunkType::new(*b"zTXt"), vec![b'x'; MAX_TEXT_LENGTH + 1]),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ]);
+        Png::from_bytes_preserving(&png.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_default_rules() {
+        let found: Vec<(&str, Severity)> = RuleSet::default()
+            .lint(&testing_png())
+            .iter()
+            .map(|finding| (finding.rule, finding.severity))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("duplicate-chunk", Severity::Error),
+                ("oversized-text", Severity::Warning),
+                ("text-keyword", Severity::Warning),
+                ("text-keyword", Severity::Warning),
+                ("private-chunk", Severity::Warning),
+                ("order", Severity::Warning),
+            ]
+        );
+
+        let minimal = RuleSet::default().lint(&Png::new_minimal());
+        assert_eq!(minimal.len(), 1);
+        assert_eq!(minimal[0].rule, "missing-color-space");
+        assert_eq!(minimal[0].offset, 8);
+    }
+
+    #[test]
+    fn test_configure_rules() {
+        let mut rules = RuleSet::default();
+        rules.disable("text-keyword").unwrap();
+        rules.configure("private-chunk=error").unwrap();
+        rules.configure("oversized-text=off").unwrap();
+        let found: Vec<(&str, Severity)> = rules
+            .lint(&testing_png())
+            .iter()
+            .map(|finding| (finding.rule, finding.severity))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("duplicate-chunk", Severity::Error),
+                ("private-chunk", Severity::Error),
+                ("order", Severity::Warning),
+            ]
+        );
+
+        let mut rules = RuleSet::default();
+        rules.only(&["duplicate-chunk".to_string()]).unwrap();
+        assert_eq!(rules.lint(&testing_png()).len(), 1);
+
+        assert!(rules.disable("no-such-rule").is_err());
+        assert!(rules.configure("duplicate-chunk").is_err());
+        assert!(rules.configure("duplicate-chunk=fatal").is_err());
+    }
+
+    #[test]
+    fn test_fix() {
+        let mut png = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IDAT", "image").unwrap(),
+            Chunk::from_strings("gAMA", "1234").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ]);
+        png.set_trailer(b"junk".to_vec());
+        let mut bytes = png.as_bytes();
+        // Break the CRC of the IDAT chunk
+        bytes[8 + 18 + 8 + 5] ^= 0xff;
+        let mut png = Png::from_bytes_preserving(&bytes).unwrap();
+
+        let found: Vec<&str> = RuleSet::fixable(&[])
+            .unwrap()
+            .lint(&png)
+            .iter()
+            .map(|finding| finding.rule)
+            .collect();
+        // The trailer is only dropped when asked for
+        assert_eq!(found, vec!["crc", "order"]);
+
+        let changes = RuleSet::fixable(&[]).unwrap().fix(&mut png);
+        let fixed: Vec<&str> = changes.iter().map(|change| change.rule).collect();
+        assert_eq!(fixed, vec!["crc", "order"]);
+        assert!(png.chunks().iter().all(|chunk| chunk.is_crc_valid()));
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "gAMA");
+        assert_eq!(png.trailer(), b"junk");
+
+        let rules = RuleSet::fixable(&["trailer".to_string()]).unwrap();
+        assert_eq!(rules.fix(&mut png).len(), 1);
+        assert!(rules.lint(&png).is_empty());
+        assert!(rules.fix(&mut png).is_empty());
+
+        // Messages stored after IEND are left alone
+        png.set_trailer(trailer::wrap(&ChunkType::new(*b"ruSt"), b"message"));
+        assert!(rules.fix(&mut png).is_empty());
+
+        assert!(RuleSet::fixable(&["private-chunk".to_string()]).is_err());
+    }
+}
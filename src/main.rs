@@ -1,23 +1,87 @@
-use clap::Clap;
+use std::process;
+
+use clap::Parser;
+
+// First, so that its outln! macro is defined in the modules after it
+#[macro_use]
+mod printer;
 
 mod args;
-mod chunk;
-mod chunk_type;
+mod clipboard;
 mod commands;
-pub mod png;
+#[cfg(feature = "gui")]
+mod gui;
 
-use crate::args::PngMeArgs;
-use crate::commands::{decode, encode, print_chunks, remove};
+use crate::args::{Cli, PngMeArgs};
+use crate::commands::{
+    analyze_bitplanes, anonymize, append, apply, canonicalize, check, clone_meta, decode, dedupe,
+    edit, encode, explain_chunks, explode, fix, generate, grep, icc, implode, keygen, lint,
+    list_messages, meta, migrate, optimize, pixelhash, polyglot, print_chunks, print_history,
+    remove, repair, report, scan, seal, sign, stats, strings, undo, verify, verify_seal,
+};
 
-pub use png::Png;
+use pngme::error::{ErrorFormat, ErrorReport};
 
-fn main() -> anyhow::Result<()> {
-    let args = PngMeArgs::parse();
+fn main() {
+    let cli = Cli::parse();
+    printer::set_format(cli.format);
+    let result = run(cli.command);
+    printer::finish();
+    if let Err(error) = result {
+        let json = match cli.error_format {
+            ErrorFormat::Json => serde_json::to_string(&ErrorReport::new(&error)).ok(),
+            ErrorFormat::Text => None,
+        };
+        match json {
+            Some(json) => eprintln!("{}", json),
+            None => eprintln!("Error: {:?}", error),
+        }
+        process::exit(1);
+    }
+}
 
+fn run(args: PngMeArgs) -> anyhow::Result<()> {
     match args {
         PngMeArgs::Encode(encode_args) => encode(encode_args),
         PngMeArgs::Decode(decode_args) => decode(decode_args),
         PngMeArgs::Remove(remove_args) => remove(remove_args),
+        PngMeArgs::Edit(edit_args) => edit(edit_args),
+        PngMeArgs::Append(append_args) => append(append_args),
+        PngMeArgs::Migrate(migrate_args) => migrate(migrate_args),
         PngMeArgs::Print(print_args) => print_chunks(print_args),
+        PngMeArgs::Explain(explain_args) => explain_chunks(explain_args),
+        PngMeArgs::Check(check_args) => check(check_args),
+        PngMeArgs::Lint(lint_args) => lint(lint_args),
+        PngMeArgs::Fix(fix_args) => fix(fix_args),
+        PngMeArgs::Keygen(keygen_args) => keygen(keygen_args),
+        PngMeArgs::Sign(sign_args) => sign(sign_args),
+        PngMeArgs::Verify(verify_args) => verify(verify_args),
+        PngMeArgs::Seal(seal_args) => seal(seal_args),
+        PngMeArgs::VerifySeal(verify_args) => verify_seal(verify_args),
+        PngMeArgs::Pixelhash(pixelhash_args) => pixelhash(pixelhash_args),
+        PngMeArgs::Canonicalize(canonicalize_args) => canonicalize(canonicalize_args),
+        PngMeArgs::Repair(repair_args) => repair(repair_args),
+        PngMeArgs::Dedupe(dedupe_args) => dedupe(dedupe_args),
+        PngMeArgs::Optimize(optimize_args) => optimize(optimize_args),
+        PngMeArgs::Report(report_args) => report(report_args),
+        PngMeArgs::Explode(explode_args) => explode(explode_args),
+        PngMeArgs::Implode(implode_args) => implode(implode_args),
+        PngMeArgs::Apply(apply_args) => apply(apply_args),
+        PngMeArgs::Generate(generate_args) => generate(generate_args),
+        PngMeArgs::Anonymize(anonymize_args) => anonymize(anonymize_args),
+        PngMeArgs::CloneMeta(clone_args) => clone_meta(clone_args),
+        PngMeArgs::Meta(meta_args) => meta(meta_args),
+        PngMeArgs::Icc(icc_args) => icc(icc_args),
+        PngMeArgs::Undo(undo_args) => undo(undo_args),
+        PngMeArgs::History(history_args) => print_history(history_args),
+        PngMeArgs::ListMessages(list_args) => list_messages(list_args),
+        PngMeArgs::Grep(grep_args) => grep(grep_args),
+        PngMeArgs::Strings(strings_args) => strings(strings_args),
+        PngMeArgs::Scan(scan_args) => scan(scan_args),
+        PngMeArgs::Stats(stats_args) => stats(stats_args),
+        PngMeArgs::AnalyzeBitplanes(analyze_args) => analyze_bitplanes(analyze_args),
+        PngMeArgs::Polyglot(polyglot_args) => polyglot(polyglot_args),
+        #[cfg(feature = "gui")]
+        PngMeArgs::Gui(gui_args) => commands::gui(gui_args),
     }
 }
@@ -0,0 +1,143 @@
+use std::io::{self, Read};
+use std::sync::{Condvar, Mutex};
+
+use bytes::Bytes;
+use memmap2::Mmap;
+
+/// Caps how many bytes of file data a batch run holds at once. Each file reserves what it
+/// needs before it's read and gives it back once it has been processed, so workers wait
+/// for memory instead of all loading large files at the same time. Files bigger than
+/// `spill_threshold` are read with `spill` instead, so one large file can't take more
+/// than the limit either.
+#[derive(Debug)]
+pub struct MemoryLimit {
+    limit: u64,
+    used: Mutex<u64>,
+    released: Condvar,
+}
+
+/// Memory reserved with `MemoryLimit::reserve`, given back when this is dropped
+#[derive(Debug)]
+pub struct Reservation<'a> {
+    owner: &'a MemoryLimit,
+    bytes: u64,
+}
+
+impl MemoryLimit {
+    pub fn new(limit: u64) -> Self {
+        Self {
+            limit,
+            used: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// The most bytes that can be reserved at once
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Files bigger than this are spilled to disk rather than read into memory. A quarter
+    /// of the limit, so that a few can be processed alongside smaller files.
+    pub fn spill_threshold(&self) -> u64 {
+        self.limit / 4
+    }
+
+    /// Waits until `bytes` are free and reserves them. More than the whole limit can't
+    /// ever be free, so such requests wait until nothing else is reserved and then take
+    /// all of it, leaving the file to be processed on its own.
+    pub fn reserve(&self, bytes: u64) -> Reservation<'_> {
+        let bytes = bytes.min(self.limit);
+        let mut used = self
+            .used
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        while *used + bytes > self.limit {
+            used = self
+                .released
+                .wait(used)
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+        *used += bytes;
+        Reservation { owner: self, bytes }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        let mut used = self
+            .owner
+            .used
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        *used -= self.bytes;
+        self.owner.released.notify_all();
+    }
+}
+
+/// Copies everything from `reader` to a temporary file and maps it into memory, so that
+/// the data is paged in from disk as it's used rather than held in memory. Chunks parsed
+/// from the result with `Png::from_shared_preserving` point into the file too. The file
+/// is deleted once the last `Bytes` pointing into it is dropped.
+pub fn spill<R: Read>(mut reader: R) -> anyhow::Result<Bytes> {
+    let mut file = tempfile::tempfile()?;
+    io::copy(&mut reader, &mut file)?;
+    if file.metadata()?.len() == 0 {
+        return Ok(Bytes::new());
+    }
+    // SAFETY: the file has no name and only this handle, so nothing else can change or
+    // truncate it while it's mapped
+    let map = unsafe { Mmap::map(&file)? };
+    Ok(Bytes::from_owner(map))
+}
+
+/// Returns how much memory reading a file of `length` bytes takes: the file itself and
+/// the copy that its chunks are parsed into
+pub fn estimate(length: u64) -> u64 {
+    length.saturating_mul(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_reserve_waits_for_room() {
+        let limit = MemoryLimit::new(100);
+        let done = AtomicBool::new(false);
+        thread::scope(|scope| {
+            let first = limit.reserve(60);
+            scope.spawn(|| {
+                let _second = limit.reserve(60);
+                assert!(done.load(Ordering::SeqCst));
+            });
+            thread::sleep(Duration::from_millis(50));
+            done.store(true, Ordering::SeqCst);
+            drop(first);
+        });
+    }
+
+    #[test]
+    fn test_spill() {
+        let data: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+        let spilled = spill(data.as_slice()).unwrap();
+        assert_eq!(spilled, data);
+        assert_eq!(spilled.slice(10..20), data[10..20]);
+        assert!(spill(io::empty()).unwrap().is_empty());
+        assert_eq!(MemoryLimit::new(100).spill_threshold(), 25);
+    }
+
+    #[test]
+    fn test_oversized_reservation_takes_everything() {
+        let limit = MemoryLimit::new(100);
+        {
+            let _huge = limit.reserve(1_000);
+            assert_eq!(*limit.used.lock().unwrap(), 100);
+        }
+        assert_eq!(*limit.used.lock().unwrap(), 0);
+        let _small = limit.reserve(10);
+    }
+}
@@ -0,0 +1,182 @@
+use std::convert::TryInto;
+
+/// Marks chunk data that holds several named messages
+pub const MAGIC: [u8; 4] = *b"PMNS";
+
+const VERSION: u8 = 1;
+const HEADER_LENGTH: usize = 4 + 1 + 2;
+
+/// Several named messages stored in the data of a single chunk, so that one chunk type
+/// can hold any number of messages
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageStore {
+    messages: Vec<(String, Vec<u8>)>,
+}
+
+impl MessageStore {
+    /// Returns true if `data` is a message store rather than a single message
+    pub fn is_message_store(data: &[u8]) -> bool {
+        data.starts_with(&MAGIC)
+    }
+
+    /// Parses the data of a chunk created by `as_bytes`
+    pub fn from_bytes(data: &[u8]) -> anyhow::Result<Self> {
+        if !Self::is_message_store(data) || data.len() < HEADER_LENGTH {
+            anyhow::bail!("Data is not a named message store");
+        }
+        if data[4] != VERSION {
+            anyhow::bail!("Unsupported message store version {}", data[4]);
+        }
+
+        let count = u16::from_be_bytes([data[5], data[6]]) as usize;
+        let mut index = Vec::with_capacity(count);
+        let mut position = HEADER_LENGTH;
+
+        for _ in 0..count {
+            let name_length = *data.get(position).ok_or_else(truncated)? as usize;
+            position += 1;
+            let name = data
+                .get(position..position + name_length)
+                .ok_or_else(truncated)?;
+            position += name_length;
+            let fields = data.get(position..position + 8).ok_or_else(truncated)?;
+            position += 8;
+
+            let offset = u32::from_be_bytes(fields[..4].try_into()?) as usize;
+            let length = u32::from_be_bytes(fields[4..].try_into()?) as usize;
+            index.push((String::from_utf8(name.to_vec())?, offset, length));
+        }
+
+        let messages = index
+            .into_iter()
+            .map(|(name, offset, length)| {
+                let start = position + offset;
+                let message = data.get(start..start + length).ok_or_else(truncated)?;
+                Ok((name, message.to_vec()))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self { messages })
+    }
+
+    /// Returns this store as chunk data.
+    /// The data is laid out as follows
+    /// 1. Magic bytes `PMNS` *(4 bytes)*
+    /// 2. Format version *(1 byte)*
+    /// 3. Number of messages *(2 bytes)*
+    /// 4. For each message: the length of its name *(1 byte)*, the UTF-8 name, and the
+    ///    offset and length of the message *(4 bytes each)*
+    /// 5. The messages themselves, with offsets counted from the end of the index
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(&MAGIC);
+        result.push(VERSION);
+        result.extend_from_slice(&(self.messages.len() as u16).to_be_bytes());
+
+        let mut offset = 0;
+        for (name, message) in &self.messages {
+            result.push(name.len() as u8);
+            result.extend_from_slice(name.as_bytes());
+            result.extend_from_slice(&(offset as u32).to_be_bytes());
+            result.extend_from_slice(&(message.len() as u32).to_be_bytes());
+            offset += message.len();
+        }
+
+        for (_, message) in &self.messages {
+            result.extend_from_slice(message);
+        }
+
+        result
+    }
+
+    /// Returns the message stored under `name`
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.messages
+            .iter()
+            .find(|(existing, _)| existing == name)
+            .map(|(_, message)| message.as_slice())
+    }
+
+    /// Stores `message` under `name`, replacing any message that already has that name
+    pub fn insert(&mut self, name: &str, message: Vec<u8>) -> anyhow::Result<()> {
+        if name.is_empty() || name.len() > u8::MAX as usize {
+            anyhow::bail!("Message names must be 1-255 bytes long");
+        }
+
+        match self
+            .messages
+            .iter_mut()
+            .find(|(existing, _)| existing == name)
+        {
+            Some((_, existing)) => *existing = message,
+            None => {
+                if self.messages.len() == u16::MAX as usize {
+                    anyhow::bail!("Too many messages");
+                }
+                self.messages.push((name.to_string(), message));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the name and contents of every message in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.messages
+            .iter()
+            .map(|(name, message)| (name.as_str(), message.as_slice()))
+    }
+}
+
+fn truncated() -> anyhow::Error {
+    anyhow::anyhow!("Message store is truncated")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut store = MessageStore::default();
+        store.insert("greeting", b"Hello".to_vec()).unwrap();
+        store
+            .insert("secret", b"This is a secret message!".to_vec())
+            .unwrap();
+
+        let bytes = store.as_bytes();
+        assert!(MessageStore::is_message_store(&bytes));
+
+        let parsed = MessageStore::from_bytes(&bytes).unwrap();
+        assert_eq!(parsed, store);
+        assert_eq!(parsed.get("greeting"), Some(&b"Hello"[..]));
+        assert_eq!(parsed.get("missing"), None);
+    }
+
+    #[test]
+    fn test_insert_replaces() {
+        let mut store = MessageStore::default();
+        store.insert("note", b"first".to_vec()).unwrap();
+        store.insert("note", b"second".to_vec()).unwrap();
+
+        assert_eq!(store.iter().count(), 1);
+        assert_eq!(store.get("note"), Some(&b"second"[..]));
+    }
+
+    #[test]
+    fn test_invalid_names() {
+        let mut store = MessageStore::default();
+        assert!(store.insert("", Vec::new()).is_err());
+        assert!(store.insert(&"a".repeat(256), Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let mut store = MessageStore::default();
+        store.insert("note", b"message".to_vec()).unwrap();
+        let bytes = store.as_bytes();
+
+        assert!(MessageStore::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(MessageStore::from_bytes(b"plain message").is_err());
+    }
+}
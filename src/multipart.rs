@@ -0,0 +1,294 @@
+use std::convert::{TryFrom, TryInto};
+
+use sha2::{Digest, Sha256};
+
+use crate::png::ChunkType;
+
+/// The chunk type used to store manifests
+pub const MANIFEST_CHUNK_TYPE: &str = "pnMf";
+
+/// Manifest version whose parts are not numbered
+const UNNUMBERED_VERSION: u8 = 1;
+const VERSION: u8 = 2;
+const HEADER_LENGTH: usize = 1 + 4 + 4 + 32;
+const PART_LENGTH: usize = 4 + 32;
+/// Length of the part number at the start of each numbered part
+const NUMBER_LENGTH: usize = 4;
+
+/// The size and digest of one part of a split payload
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub length: u32,
+    pub digest: [u8; 32],
+}
+
+/// Describes a payload split across several chunks of the same type, so that decoding
+/// can check that every part is present and report the ones that aren't
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    pub chunk_type: ChunkType,
+    /// Whether each part starts with its 4 byte part number, counting from 0. Numbered
+    /// parts can be matched to the manifest without guessing, and parts with the same
+    /// contents are still different chunks, so tools that drop duplicate chunks leave
+    /// them alone. Manifests from before part numbers were added have none.
+    pub numbered: bool,
+    pub parts: Vec<Part>,
+    /// SHA-256 of the whole payload
+    pub digest: [u8; 32],
+}
+
+impl Manifest {
+    /// Splits `payload` into numbered parts of at most `part_size` bytes, counting the
+    /// part number, and returns them with the manifest describing them
+    pub fn split(
+        chunk_type: ChunkType,
+        payload: &[u8],
+        part_size: usize,
+    ) -> anyhow::Result<(Self, Vec<Vec<u8>>)> {
+        if part_size <= NUMBER_LENGTH {
+            anyhow::bail!(
+                "The part size must be more than {} bytes, to leave room for the part number",
+                NUMBER_LENGTH
+            );
+        }
+
+        let parts: Vec<Vec<u8>> = payload
+            .chunks(part_size - NUMBER_LENGTH)
+            .enumerate()
+            .map(|(number, part)| {
+                let mut data = Vec::with_capacity(NUMBER_LENGTH + part.len());
+                data.extend_from_slice(&(number as u32).to_be_bytes());
+                data.extend_from_slice(part);
+                data
+            })
+            .collect();
+        let manifest = Self {
+            chunk_type,
+            numbered: true,
+            parts: parts
+                .iter()
+                .map(|part| Part {
+                    length: part.len() as u32,
+                    digest: Sha256::digest(part).into(),
+                })
+                .collect(),
+            digest: Sha256::digest(payload).into(),
+        };
+
+        Ok((manifest, parts))
+    }
+
+    /// Returns true if this manifest describes parts stored under `chunk_type`
+    pub fn describes(&self, chunk_type: &str) -> bool {
+        self.chunk_type.to_string() == chunk_type
+    }
+
+    /// Puts the payload back together from `candidates`, which may be in any order and
+    /// may include unrelated data. Numbered parts are put in order by their numbers, and
+    /// unnumbered ones by their digests. Returns an error listing the missing parts if
+    /// any can't be found.
+    pub fn assemble(&self, candidates: &[Vec<u8>]) -> anyhow::Result<Vec<u8>> {
+        let mut used = vec![false; candidates.len()];
+        let mut payload = Vec::new();
+        let mut missing = Vec::new();
+
+        for (number, part) in self.parts.iter().enumerate() {
+            let prefix = (number as u32).to_be_bytes();
+            let found = candidates
+                .iter()
+                .enumerate()
+                .position(|(index, candidate)| {
+                    !used[index]
+                        && (!self.numbered || candidate.starts_with(&prefix))
+                        && candidate.len() == part.length as usize
+                        && Sha256::digest(candidate).as_slice() == part.digest
+                });
+
+            match found {
+                Some(index) => {
+                    used[index] = true;
+                    let start = if self.numbered { NUMBER_LENGTH } else { 0 };
+                    payload.extend_from_slice(&candidates[index][start..]);
+                }
+                None => missing.push((number + 1).to_string()),
+            }
+        }
+
+        if !missing.is_empty() {
+            anyhow::bail!(
+                "The message is incomplete. Missing {} {} of {}",
+                if missing.len() == 1 { "part" } else { "parts" },
+                missing.join(", "),
+                self.parts.len()
+            );
+        }
+        if Sha256::digest(&payload).as_slice() != self.digest {
+            anyhow::bail!("The reassembled message does not match its checksum");
+        }
+
+        Ok(payload)
+    }
+
+    /// Returns this manifest as chunk data.
+    /// The data is laid out as follows
+    /// 1. Format version *(1 byte)*, 2 if the parts are numbered and 1 if not
+    /// 2. Chunk type of the parts *(4 bytes)*
+    /// 3. Number of parts *(4 bytes)*
+    /// 4. SHA-256 of the whole payload *(32 bytes)*
+    /// 5. For each part in order: its length *(4 bytes)* and SHA-256 *(32 bytes)*
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH + self.parts.len() * PART_LENGTH);
+        bytes.push(if self.numbered {
+            VERSION
+        } else {
+            UNNUMBERED_VERSION
+        });
+        bytes.extend_from_slice(&self.chunk_type.bytes());
+        bytes.extend_from_slice(&(self.parts.len() as u32).to_be_bytes());
+        bytes.extend_from_slice(&self.digest);
+
+        for part in &self.parts {
+            bytes.extend_from_slice(&part.length.to_be_bytes());
+            bytes.extend_from_slice(&part.digest);
+        }
+
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Manifest {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> anyhow::Result<Self> {
+        if bytes.len() < HEADER_LENGTH {
+            anyhow::bail!("Manifest is truncated");
+        }
+        let numbered = match bytes[0] {
+            VERSION => true,
+            UNNUMBERED_VERSION => false,
+            version => anyhow::bail!("Unsupported manifest version {}", version),
+        };
+
+        let type_bytes: [u8; 4] = bytes[1..5].try_into()?;
+        let count = u32::from_be_bytes(bytes[5..9].try_into()?) as usize;
+        if bytes.len() != HEADER_LENGTH + count * PART_LENGTH {
+            anyhow::bail!("Manifest length does not match its number of parts");
+        }
+
+        let parts = bytes[HEADER_LENGTH..]
+            .chunks_exact(PART_LENGTH)
+            .map(|part| {
+                Ok(Part {
+                    length: u32::from_be_bytes(part[..4].try_into()?),
+                    digest: part[4..].try_into()?,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            chunk_type: ChunkType::try_from(type_bytes)?,
+            numbered,
+            parts,
+            digest: bytes[9..HEADER_LENGTH].try_into()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_split() -> (Manifest, Vec<Vec<u8>>) {
+        let payload = b"This message is split into several parts";
+        Manifest::split(ChunkType::new(*b"ruSt"), payload, 16).unwrap()
+    }
+
+    #[test]
+    fn test_split_and_assemble() {
+        let (manifest, parts) = testing_split();
+        assert_eq!(parts.len(), 4);
+        assert_eq!(manifest.parts[3].length, 4 + 4);
+        assert_eq!(&parts[3][..4], &[0, 0, 0, 3]);
+
+        let manifest = Manifest::try_from(manifest.as_bytes().as_ref()).unwrap();
+        assert!(manifest.describes("ruSt"));
+        assert_eq!(
+            manifest.assemble(&parts).unwrap(),
+            b"This message is split into several parts"
+        );
+    }
+
+    #[test]
+    fn test_assemble_out_of_order() {
+        let (manifest, parts) = testing_split();
+        let shuffled = vec![
+            parts[2].clone(),
+            parts[3].clone(),
+            b"other".to_vec(),
+            parts[0].clone(),
+            parts[1].clone(),
+        ];
+
+        assert_eq!(
+            manifest.assemble(&shuffled).unwrap(),
+            manifest.assemble(&parts).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_identical_parts_stay_distinct() {
+        let (manifest, parts) =
+            Manifest::split(ChunkType::new(*b"ruSt"), b"abcdabcdabcd", 8).unwrap();
+        assert_ne!(parts[0], parts[1]);
+
+        let reversed: Vec<Vec<u8>> = parts.iter().rev().cloned().collect();
+        assert_eq!(manifest.assemble(&reversed).unwrap(), b"abcdabcdabcd");
+        assert!(manifest.assemble(&parts[1..]).is_err());
+    }
+
+    #[test]
+    fn test_unnumbered_manifest() {
+        let parts = [b"Split before ".to_vec(), b"part numbers".to_vec()];
+        let manifest = Manifest {
+            chunk_type: ChunkType::new(*b"ruSt"),
+            numbered: false,
+            parts: parts
+                .iter()
+                .map(|part| Part {
+                    length: part.len() as u32,
+                    digest: Sha256::digest(part).into(),
+                })
+                .collect(),
+            digest: Sha256::digest(b"Split before part numbers").into(),
+        };
+
+        let bytes = manifest.as_bytes();
+        assert_eq!(bytes[0], 1);
+        let manifest = Manifest::try_from(bytes.as_slice()).unwrap();
+        assert!(!manifest.numbered);
+        assert_eq!(
+            manifest
+                .assemble(&[parts[1].clone(), parts[0].clone()])
+                .unwrap(),
+            b"Split before part numbers"
+        );
+    }
+
+    #[test]
+    fn test_missing_parts() {
+        let (manifest, parts) = testing_split();
+        let error = manifest.assemble(&parts[..1]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The message is incomplete. Missing parts 2, 3, 4 of 4"
+        );
+    }
+
+    #[test]
+    fn test_invalid_manifest() {
+        let (manifest, _) = testing_split();
+        let bytes = manifest.as_bytes();
+        assert!(Manifest::try_from(&bytes[..bytes.len() - 1]).is_err());
+        assert!(Manifest::try_from(&bytes[..10]).is_err());
+    }
+}
@@ -0,0 +1,104 @@
+#[cfg(feature = "obfuscate")]
+use chacha20::cipher::{KeyIvInit, StreamCipher};
+#[cfg(feature = "obfuscate")]
+use chacha20::ChaCha20;
+#[cfg(feature = "obfuscate")]
+use rand::RngCore;
+#[cfg(feature = "obfuscate")]
+use sha2::{Digest, Sha256};
+
+/// Marks the start of a message scrambled by `obfuscate`
+pub const MAGIC: [u8; 4] = *b"PMEO";
+
+#[cfg(feature = "obfuscate")]
+const NONCE_LENGTH: usize = 12;
+
+/// Returns true if `data` is a message scrambled by `obfuscate`
+pub fn is_obfuscated(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Scrambles `message` with the ChaCha20 stream cipher, keyed by the SHA-256 hash of
+/// `key`, so that it isn't plainly readable in a hex dump or a `strings` listing.
+///
+/// This is obfuscation, not encryption. There is no authentication, so changes to the
+/// message go unnoticed, and the key is hashed once instead of being stretched, so short
+/// keys can be guessed quickly. Use --encrypt for messages that must stay secret.
+///
+/// The result is laid out as follows
+/// 1. Magic bytes `PMEO` *(4 bytes)*
+/// 2. A random nonce *(12 bytes)*
+/// 3. The scrambled message
+#[cfg(feature = "obfuscate")]
+pub fn obfuscate(message: &[u8], key: &str) -> anyhow::Result<Vec<u8>> {
+    let mut nonce = [0; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let mut result = Vec::with_capacity(MAGIC.len() + NONCE_LENGTH + message.len());
+    result.extend_from_slice(&MAGIC);
+    result.extend_from_slice(&nonce);
+    result.extend_from_slice(message);
+    apply_keystream(&mut result[MAGIC.len() + NONCE_LENGTH..], key, &nonce);
+    Ok(result)
+}
+
+#[cfg(not(feature = "obfuscate"))]
+pub fn obfuscate(_message: &[u8], _key: &str) -> anyhow::Result<Vec<u8>> {
+    unavailable()
+}
+
+/// Unscrambles a message created by `obfuscate`. A wrong key can't be detected, and
+/// gives back random looking bytes.
+#[cfg(feature = "obfuscate")]
+pub fn reveal(data: &[u8], key: &str) -> anyhow::Result<Vec<u8>> {
+    if !is_obfuscated(data) || data.len() < MAGIC.len() + NONCE_LENGTH {
+        anyhow::bail!("This message wasn't obfuscated with --obfuscate");
+    }
+    let (nonce, scrambled) = data[MAGIC.len()..].split_at(NONCE_LENGTH);
+    let mut message = scrambled.to_vec();
+    apply_keystream(&mut message, key, nonce);
+    Ok(message)
+}
+
+#[cfg(not(feature = "obfuscate"))]
+pub fn reveal(_data: &[u8], _key: &str) -> anyhow::Result<Vec<u8>> {
+    unavailable()
+}
+
+#[cfg(not(feature = "obfuscate"))]
+fn unavailable() -> anyhow::Result<Vec<u8>> {
+    anyhow::bail!(
+        "This pngme was built without --obfuscate support. Rebuild it with --features obfuscate"
+    )
+}
+
+#[cfg(feature = "obfuscate")]
+fn apply_keystream(data: &mut [u8], key: &str, nonce: &[u8]) {
+    let key = Sha256::digest(key.as_bytes());
+    ChaCha20::new(&key, nonce.into()).apply_keystream(data);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "obfuscate")]
+    #[test]
+    fn test_obfuscate_and_reveal() {
+        let message = b"This is a secret message!";
+        let obfuscated = obfuscate(message, "key").unwrap();
+        assert!(is_obfuscated(&obfuscated));
+        assert!(!obfuscated
+            .windows(message.len())
+            .any(|window| window == message));
+        assert_eq!(reveal(&obfuscated, "key").unwrap(), message);
+        assert_ne!(reveal(&obfuscated, "other key").unwrap(), message);
+        assert_ne!(obfuscate(message, "key").unwrap(), obfuscated);
+    }
+
+    #[test]
+    fn test_is_obfuscated() {
+        assert!(is_obfuscated(b"PMEO\0\0\0\0\0\0\0\0\0\0\0\0data"));
+        assert!(!is_obfuscated(b"plain text"));
+    }
+}
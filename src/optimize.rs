@@ -0,0 +1,319 @@
+use std::fmt;
+use std::io::{Read, Write};
+use std::num::NonZeroU64;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::pixels::{predict, unfilter, Header};
+use crate::png::{Chunk, ChunkType, Png};
+
+/// Ancillary chunk types that change how the image is displayed, which `--strip` keeps
+pub const DISPLAY_CHUNK_TYPES: [&str; 14] = [
+    "acTL", "bKGD", "cHRM", "cICP", "cLLi", "fcTL", "fdAT", "gAMA", "iCCP", "mDCv", "pHYs", "sBIT",
+    "sRGB", "tRNS",
+];
+
+/// How `optimize` should try to shrink a `Png`
+#[derive(Debug, Clone, Copy)]
+pub struct OptimizeOptions {
+    /// Refilter the image with each filter strategy and keep the one that compresses best
+    pub try_filters: bool,
+    /// Remove ancillary chunks that don't change how the image is displayed
+    pub strip: bool,
+    /// The number of zopfli iterations
+    pub iterations: NonZeroU64,
+}
+
+/// What `optimize` did to a `Png`
+#[derive(Debug)]
+pub struct OptimizeReport {
+    /// The total IDAT data length before optimizing
+    pub idat_before: usize,
+    /// The total IDAT data length after optimizing. Equal to `idat_before` if zopfli
+    /// couldn't improve on the original.
+    pub idat_after: usize,
+    /// The filter strategy used for the new IDAT data
+    pub filter: Strategy,
+    /// Chunks removed by `--strip`
+    pub stripped: Vec<Chunk>,
+}
+
+/// A way of choosing the filter type of each scanline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Keep the filter types the image already has
+    Original,
+    /// Use the same filter type for every scanline
+    Fixed(u8),
+    /// Pick the filter type with the smallest sum of absolute differences for each scanline
+    MinSum,
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Strategy::Original => "original",
+            Strategy::Fixed(0) => "none",
+            Strategy::Fixed(1) => "sub",
+            Strategy::Fixed(2) => "up",
+            Strategy::Fixed(3) => "average",
+            Strategy::Fixed(_) => "paeth",
+            Strategy::MinSum => "minsum",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Returns a copy of `png` with its IDAT data recompressed by zopfli into a single IDAT
+/// chunk, and with display-neutral chunks removed if `options.strip` is set. The
+/// original IDAT data is kept if recompressing doesn't make it smaller.
+pub fn optimize(png: &Png, options: &OptimizeOptions) -> anyhow::Result<(Png, OptimizeReport)> {
+    let compressed: Vec<u8> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+    if compressed.is_empty() {
+        anyhow::bail!("No IDAT chunks found");
+    }
+
+    let mut filtered = Vec::new();
+    ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut filtered)?;
+
+    let header = Header::from_png(png)?;
+    let (filter, filtered) = if options.try_filters && !header.interlaced {
+        best_filtering(&header, filtered)?
+    } else {
+        (Strategy::Original, filtered)
+    };
+
+    let zopfli_options = zopfli::Options {
+        iteration_count: options.iterations,
+        ..zopfli::Options::default()
+    };
+    let mut recompressed = Vec::new();
+    zopfli::compress(
+        zopfli_options,
+        zopfli::Format::Zlib,
+        filtered.as_slice(),
+        &mut recompressed,
+    )?;
+
+    let (filter, idat) = if recompressed.len() < compressed.len() {
+        (filter, recompressed)
+    } else {
+        (Strategy::Original, compressed.clone())
+    };
+
+    let mut chunks = Vec::with_capacity(png.chunks().len());
+    let mut stripped = Vec::new();
+    let mut idat = Some(idat);
+    for chunk in png.chunks() {
+        let chunk_type = chunk.chunk_type();
+        if chunk_type.to_string() == "IDAT" {
+            if let Some(data) = idat.take() {
+                chunks.push(Chunk::new(*chunk_type, data));
+            }
+        } else if options.strip && is_strippable(chunk_type) {
+            stripped.push(chunk.clone());
+        } else {
+            chunks.push(chunk.clone());
+        }
+    }
+
+    let optimized = Png::from_chunks(chunks);
+    let idat_after = optimized
+        .chunk_by_type("IDAT")
+        .map_or(0, |chunk| chunk.data().len());
+
+    let report = OptimizeReport {
+        idat_before: compressed.len(),
+        idat_after,
+        filter,
+        stripped,
+    };
+    Ok((optimized, report))
+}
+
+/// Returns true if `--strip` removes chunks of this type
+pub fn is_strippable(chunk_type: &ChunkType) -> bool {
+    !chunk_type.is_critical() && !DISPLAY_CHUNK_TYPES.contains(&chunk_type.to_string().as_str())
+}
+
+/// Refilters the scanlines with every strategy and returns the one that compresses to
+/// the fewest bytes. A fast deflate is used to compare them, since zopfli is too slow to
+/// run more than once.
+fn best_filtering(header: &Header, original: Vec<u8>) -> anyhow::Result<(Strategy, Vec<u8>)> {
+    let row_length = header.row_length()?;
+    let distance = header.filter_distance()?;
+    if original.len() != (row_length + 1) * header.height as usize {
+        anyhow::bail!("The IDAT data doesn't match the image size in IHDR");
+    }
+
+    let raw = unfilter(&original, row_length, distance)?;
+    let mut best = (Strategy::Original, deflated_length(&original)?, original);
+
+    let strategies = (0..=4).map(Strategy::Fixed).chain(Some(Strategy::MinSum));
+    for strategy in strategies {
+        let candidate = filter(&raw, row_length, distance, strategy);
+        let length = deflated_length(&candidate)?;
+        if length < best.1 {
+            best = (strategy, length, candidate);
+        }
+    }
+
+    Ok((best.0, best.2))
+}
+
+fn deflated_length(data: &[u8]) -> anyhow::Result<usize> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?.len())
+}
+
+/// Filters each raw scanline in `raw` using `strategy`, which must not be `Original`
+fn filter(raw: &[u8], row_length: usize, distance: usize, strategy: Strategy) -> Vec<u8> {
+    let mut data = Vec::with_capacity(raw.len() + raw.len() / row_length.max(1));
+    let zeros = vec![0; row_length];
+    let mut previous: &[u8] = &zeros;
+
+    for line in raw.chunks(row_length) {
+        match strategy {
+            Strategy::Fixed(filter_type) => {
+                filter_line(filter_type, line, previous, distance, &mut data)
+            }
+            _ => {
+                let mut best = Vec::new();
+                let mut best_sum = u64::MAX;
+                for filter_type in 0..=4 {
+                    let mut candidate = Vec::with_capacity(row_length + 1);
+                    filter_line(filter_type, line, previous, distance, &mut candidate);
+                    let sum = candidate[1..]
+                        .iter()
+                        .map(|&byte| (byte as i8).unsigned_abs() as u64)
+                        .sum();
+                    if sum < best_sum {
+                        best = candidate;
+                        best_sum = sum;
+                    }
+                }
+                data.extend_from_slice(&best);
+            }
+        }
+        previous = line;
+    }
+
+    data
+}
+
+fn filter_line(filter_type: u8, line: &[u8], previous: &[u8], distance: usize, out: &mut Vec<u8>) {
+    out.push(filter_type);
+    for (i, &byte) in line.iter().enumerate() {
+        let left = if i >= distance { line[i - distance] } else { 0 };
+        let upper_left = if i >= distance {
+            previous[i - distance]
+        } else {
+            0
+        };
+        // Filter types are always 0 to 4 here
+        let prediction = predict(filter_type, left, previous[i], upper_left).unwrap_or(0);
+        out.push(byte.wrapping_sub(prediction));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const WIDTH: usize = 4;
+    const HEIGHT: usize = 3;
+
+    /// The raw scanlines of a small 8-bit RGB gradient
+    fn raw_pixels() -> Vec<u8> {
+        (0..WIDTH * HEIGHT * 3).map(|i| (i * 7) as u8).collect()
+    }
+
+    fn testing_png() -> Png {
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&(WIDTH as u32).to_be_bytes());
+        ihdr.extend_from_slice(&(HEIGHT as u32).to_be_bytes());
+        ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        let filtered = filter(&raw_pixels(), WIDTH * 3, 3, Strategy::Fixed(0));
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::none());
+        encoder.write_all(&filtered).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let (first, second) = compressed.split_at(compressed.len() / 2);
+
+        let idat = ChunkType::from_str("IDAT").unwrap();
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr),
+            Chunk::from_strings("gAMA", "gama").unwrap(),
+            Chunk::new(idat, first.to_vec()),
+            Chunk::from_strings("tEXt", "Comment\0hello").unwrap(),
+            Chunk::new(idat, second.to_vec()),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    fn options(try_filters: bool, strip: bool) -> OptimizeOptions {
+        OptimizeOptions {
+            try_filters,
+            strip,
+            iterations: NonZeroU64::new(1).unwrap(),
+        }
+    }
+
+    fn decoded_pixels(png: &Png) -> Vec<u8> {
+        let mut filtered = Vec::new();
+        ZlibDecoder::new(png.chunk_by_type("IDAT").unwrap().data())
+            .read_to_end(&mut filtered)
+            .unwrap();
+        unfilter(&filtered, WIDTH * 3, 3).unwrap()
+    }
+
+    #[test]
+    fn test_filters_round_trip() {
+        let raw = raw_pixels();
+        for strategy in (0..=4).map(Strategy::Fixed).chain(Some(Strategy::MinSum)) {
+            let filtered = filter(&raw, WIDTH * 3, 3, strategy);
+            assert_eq!(unfilter(&filtered, WIDTH * 3, 3).unwrap(), raw);
+        }
+    }
+
+    #[test]
+    fn test_optimize_keeps_pixels() {
+        let png = testing_png();
+        let (optimized, report) = optimize(&png, &options(true, false)).unwrap();
+
+        assert!(report.idat_after < report.idat_before);
+        assert!(report.stripped.is_empty());
+        assert_eq!(decoded_pixels(&optimized), raw_pixels());
+
+        let chunk_types: Vec<String> = optimized
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(chunk_types, vec!["IHDR", "gAMA", "IDAT", "tEXt", "IEND"]);
+    }
+
+    #[test]
+    fn test_optimize_strip() {
+        let (optimized, report) = optimize(&testing_png(), &options(false, true)).unwrap();
+        assert_eq!(report.filter, Strategy::Original);
+        assert_eq!(report.stripped.len(), 1);
+        assert!(optimized.chunk_by_type("tEXt").is_none());
+        assert!(optimized.chunk_by_type("gAMA").is_some());
+    }
+
+    #[test]
+    fn test_no_idat() {
+        let png = Png::from_chunks(vec![Chunk::from_strings("IEND", "").unwrap()]);
+        assert!(optimize(&png, &options(false, false)).is_err());
+    }
+}
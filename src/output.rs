@@ -0,0 +1,218 @@
+use std::fmt::Write;
+
+use serde_json::Value;
+
+/// How commands print their results
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// Lines for people, as each command lays them out
+    #[default]
+    Text,
+    /// A JSON array with an object for each record
+    Json,
+    /// A header row with the name of every field, then a row for each record
+    Csv,
+    /// A YAML list with a map for each record
+    Yaml,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        match text {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "yaml" => Ok(OutputFormat::Yaml),
+            _ => anyhow::bail!(
+                "Unknown output format {:?}. Use text, json, csv or yaml",
+                text
+            ),
+        }
+    }
+}
+
+/// One result of a command, like a chunk of a file or a problem found in it, as named
+/// fields that keep the order they were added in
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Record {
+    fields: Vec<(String, Value)>,
+}
+
+impl Record {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a record with a single `message` field, for output that is just a line
+    /// of text
+    pub fn message(text: &str) -> Self {
+        Self::new().with("message", text)
+    }
+
+    /// Adds a field to the end of the record
+    pub fn with<V: Into<Value>>(mut self, name: &str, value: V) -> Self {
+        self.fields.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn fields(&self) -> &[(String, Value)] {
+        &self.fields
+    }
+
+    fn get(&self, name: &str) -> Option<&Value> {
+        self.fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Renders `records` in `format`. Text puts each record on a line with its values
+/// separated by two spaces. CSV has a column for every field name any record has, in the
+/// order they first appear, and leaves fields a record doesn't have empty.
+pub fn render(format: OutputFormat, records: &[Record]) -> String {
+    let mut output = String::new();
+    match format {
+        OutputFormat::Text => {
+            for record in records {
+                let values: Vec<String> = record
+                    .fields
+                    .iter()
+                    .map(|(_, value)| plain(value))
+                    .collect();
+                let _ = writeln!(output, "{}", values.join("  "));
+            }
+        }
+        OutputFormat::Json => {
+            output.push('[');
+            for (index, record) in records.iter().enumerate() {
+                let separator = if index == 0 { "" } else { "," };
+                let _ = write!(output, "{}\n  {{", separator);
+                for (index, (name, value)) in record.fields.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { ", " };
+                    let _ = write!(
+                        output,
+                        "{}{}: {}",
+                        separator,
+                        Value::from(name.as_str()),
+                        value
+                    );
+                }
+                output.push('}');
+            }
+            if !records.is_empty() {
+                output.push('\n');
+            }
+            output.push_str("]\n");
+        }
+        OutputFormat::Csv => {
+            let mut columns: Vec<&str> = Vec::new();
+            for (name, _) in records.iter().flat_map(|record| &record.fields) {
+                if !columns.contains(&name.as_str()) {
+                    columns.push(name);
+                }
+            }
+            let header: Vec<String> = columns.iter().map(|column| csv_field(column)).collect();
+            let _ = writeln!(output, "{}", header.join(","));
+            for record in records {
+                let row: Vec<String> = columns
+                    .iter()
+                    .map(|column| {
+                        record
+                            .get(column)
+                            .map_or_else(String::new, |value| csv_field(&plain(value)))
+                    })
+                    .collect();
+                let _ = writeln!(output, "{}", row.join(","));
+            }
+        }
+        OutputFormat::Yaml => {
+            if records.is_empty() {
+                output.push_str("[]\n");
+            }
+            for record in records {
+                if record.fields.is_empty() {
+                    output.push_str("- {}\n");
+                }
+                for (index, (name, value)) in record.fields.iter().enumerate() {
+                    let indent = if index == 0 { "- " } else { "  " };
+                    // JSON strings, numbers and booleans are valid YAML, and quoting
+                    // every string keeps values like `no` or `1e3` from changing type.
+                    // Field names are plain words, which need no quotes
+                    let _ = writeln!(output, "{}{}: {}", indent, name, value);
+                }
+            }
+        }
+    }
+    output
+}
+
+/// Returns `value` as text without the quotes JSON puts around strings
+fn plain(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        Value::Null => String::new(),
+        value => value.to_string(),
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    match text.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", text.replace('"', "\"\"")),
+        false => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_records() -> Vec<Record> {
+        vec![
+            Record::new()
+                .with("offset", 8)
+                .with("type", "IHDR")
+                .with("crc_valid", true),
+            Record::new()
+                .with("offset", 33)
+                .with("type", "tEXt")
+                .with("note", "a, \"quoted\" note"),
+        ]
+    }
+
+    #[test]
+    fn test_render_json() {
+        let json = render(OutputFormat::Json, &testing_records());
+        assert_eq!(
+            json,
+            "[\n  {\"offset\": 8, \"type\": \"IHDR\", \"crc_valid\": true},\n  \
+             {\"offset\": 33, \"type\": \"tEXt\", \"note\": \"a, \\\"quoted\\\" note\"}\n]\n"
+        );
+        let parsed: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[1]["offset"], 33);
+        assert_eq!(render(OutputFormat::Json, &[]), "[]\n");
+    }
+
+    #[test]
+    fn test_render_csv() {
+        assert_eq!(
+            render(OutputFormat::Csv, &testing_records()),
+            "offset,type,crc_valid,note\n8,IHDR,true,\n33,tEXt,,\"a, \"\"quoted\"\" note\"\n"
+        );
+    }
+
+    #[test]
+    fn test_render_yaml_and_text() {
+        assert_eq!(
+            render(OutputFormat::Yaml, &testing_records()),
+            "- offset: 8\n  type: \"IHDR\"\n  crc_valid: true\n\
+             - offset: 33\n  type: \"tEXt\"\n  note: \"a, \\\"quoted\\\" note\"\n"
+        );
+        assert_eq!(
+            render(OutputFormat::Text, &[Record::message("Wrote dice.png")]),
+            "Wrote dice.png\n"
+        );
+    }
+}
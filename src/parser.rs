@@ -0,0 +1,314 @@
+use std::convert::{TryFrom, TryInto};
+
+use bytes::{Buf, BytesMut};
+
+use crate::error::{Locate, Located};
+use crate::png::{Chunk, ChunkType, Png};
+
+/// The largest chunk data length allowed by the PNG spec
+const MAX_CHUNK_LENGTH: usize = (1 << 31) - 1;
+
+/// Something the parser found in the bytes it was fed
+#[derive(Debug)]
+pub enum ParseEvent {
+    /// The PNG signature was read and is valid
+    Header([u8; 8]),
+    /// A complete chunk was read and its CRC is valid
+    Chunk(Chunk),
+    /// The stream is not a valid PNG. No further events will be emitted.
+    Error(anyhow::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Header,
+    Chunks,
+    /// Everything after IEND that isn't a chunk, which is kept in `buffer`
+    Trailer,
+    Failed,
+}
+
+/// An incremental PNG parser for bytes that arrive a piece at a time, such as from a
+/// socket. Bytes are buffered only until the chunk they belong to is complete, so each
+/// chunk is emitted as soon as its CRC has arrived. Emitted chunks keep their data in
+/// the buffer rather than copying it out.
+///
+/// After IEND, bytes that aren't a complete chunk with a valid CRC don't make the stream
+/// invalid. They start the trailer, which is buffered to the end of the stream and
+/// returned by `trailer`.
+#[derive(Debug)]
+pub struct PngParser {
+    state: State,
+    buffer: BytesMut,
+    /// The number of bytes already consumed from the stream, which is the offset of the
+    /// start of `buffer`
+    consumed: usize,
+    after_iend: bool,
+}
+
+impl PngParser {
+    /// Creates a parser that expects to see the PNG signature first
+    pub fn new() -> Self {
+        Self {
+            state: State::Header,
+            buffer: BytesMut::new(),
+            consumed: 0,
+            after_iend: false,
+        }
+    }
+
+    /// Adds `bytes` to the stream and returns everything that could be parsed so far
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<ParseEvent> {
+        let mut events = Vec::new();
+        if self.state == State::Failed {
+            return events;
+        }
+
+        self.buffer.extend_from_slice(bytes);
+
+        loop {
+            match self.state {
+                State::Header => {
+                    if self.buffer.len() < Png::SIGNATURE.len() {
+                        break;
+                    }
+
+                    let header: [u8; 8] = self.buffer[..8].try_into().unwrap();
+                    if header != Png::SIGNATURE {
+                        let error = Located::of(anyhow::anyhow!("Invalid header: {:?}", header))
+                            .code("invalid_signature")
+                            .at_offset(0);
+                        self.fail(&mut events, error.into());
+                        break;
+                    }
+
+                    self.buffer.advance(8);
+                    self.consumed += 8;
+                    self.state = State::Chunks;
+                    events.push(ParseEvent::Header(header));
+                }
+                State::Chunks => {
+                    if self.buffer.len() < 4 {
+                        break;
+                    }
+
+                    let offset = self.consumed;
+                    let length = u32::from_be_bytes(self.buffer[..4].try_into().unwrap()) as usize;
+                    let type_bytes: Option<[u8; 4]> =
+                        self.buffer.get(4..8).map(|bytes| bytes.try_into().unwrap());
+                    let invalid_type =
+                        type_bytes.is_some_and(|bytes| ChunkType::try_from(bytes).is_err());
+                    if self.after_iend && (length > MAX_CHUNK_LENGTH || invalid_type) {
+                        self.state = State::Trailer;
+                        break;
+                    }
+                    if length > MAX_CHUNK_LENGTH {
+                        let error = Located::of(anyhow::anyhow!(
+                            "Chunk length {} at offset {} is too large",
+                            length,
+                            offset
+                        ))
+                        .code("chunk_too_large")
+                        .at_offset(offset);
+                        self.fail(&mut events, error.into());
+                        break;
+                    }
+
+                    // Length + chunk type + data + CRC
+                    let chunk_length = 12 + length;
+                    if self.buffer.len() < chunk_length {
+                        break;
+                    }
+
+                    if self.after_iend && Chunk::try_from(&self.buffer[..chunk_length]).is_err() {
+                        self.state = State::Trailer;
+                        break;
+                    }
+
+                    let bytes = self.buffer.split_to(chunk_length).freeze();
+                    match Chunk::try_from(bytes) {
+                        Ok(chunk) => {
+                            self.consumed += chunk_length;
+                            self.after_iend |= chunk.chunk_type().bytes() == *b"IEND";
+                            events.push(ParseEvent::Chunk(chunk.at_offset(offset)));
+                        }
+                        Err(error) => {
+                            let error = Located::of(
+                                error.context(format!("Invalid chunk at offset {}", offset)),
+                            )
+                            .at_offset(offset);
+                            self.fail(&mut events, error.into());
+                            break;
+                        }
+                    }
+                }
+                State::Trailer | State::Failed => break,
+            }
+        }
+
+        events
+    }
+
+    /// Checks that the stream ended on a chunk boundary, or in the trailer. Call this once
+    /// there are no more bytes to feed.
+    pub fn finish(&self) -> anyhow::Result<()> {
+        match self.state {
+            State::Chunks | State::Trailer if self.after_iend => Ok(()),
+            State::Header => Err(anyhow::anyhow!("Stream ended before the PNG header"))
+                .code("truncated")
+                .at_offset(self.consumed),
+            State::Chunks if !self.buffer.is_empty() => Err(anyhow::anyhow!(
+                "Stream ended in the middle of a chunk ({} bytes left over)",
+                self.buffer.len()
+            ))
+            .code("truncated")
+            .at_offset(self.consumed),
+            State::Chunks | State::Trailer => Ok(()),
+            State::Failed => anyhow::bail!("Stream is not a valid PNG"),
+        }
+    }
+
+    /// The bytes after IEND that aren't chunks. Only complete once `finish` succeeds,
+    /// since until then they may be the start of another chunk.
+    pub fn trailer(&self) -> &[u8] {
+        match self.state {
+            State::Chunks | State::Trailer if self.after_iend => &self.buffer,
+            _ => &[],
+        }
+    }
+
+    fn fail(&mut self, events: &mut Vec<ParseEvent>, error: anyhow::Error) {
+        self.state = State::Failed;
+        self.buffer.clear();
+        events.push(ParseEvent::Error(error));
+    }
+}
+
+impl Default for PngParser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_bytes() -> Vec<u8> {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+        .as_bytes()
+    }
+
+    fn chunk_types(events: &[ParseEvent]) -> Vec<String> {
+        events
+            .iter()
+            .filter_map(|event| match event {
+                ParseEvent::Chunk(chunk) => Some(chunk.chunk_type().to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_feed_all_at_once() {
+        let mut parser = PngParser::new();
+        let events = parser.feed(&testing_bytes());
+
+        assert!(matches!(events[0], ParseEvent::Header(_)));
+        assert_eq!(chunk_types(&events), vec!["IHDR", "IDAT", "IEND"]);
+        assert!(parser.finish().is_ok());
+    }
+
+    #[test]
+    fn test_feed_one_byte_at_a_time() {
+        let mut parser = PngParser::new();
+        let mut events = Vec::new();
+        for byte in testing_bytes() {
+            events.extend(parser.feed(&[byte]));
+        }
+
+        assert_eq!(events.len(), 4);
+        assert_eq!(chunk_types(&events), vec!["IHDR", "IDAT", "IEND"]);
+        assert!(parser.finish().is_ok());
+
+        let offsets: Vec<Option<usize>> = events
+            .iter()
+            .filter_map(|event| match event {
+                ParseEvent::Chunk(chunk) => Some(chunk.offset()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(offsets, vec![Some(8), Some(8 + 18), Some(8 + 18 + 18)]);
+    }
+
+    #[test]
+    fn test_chunks_are_emitted_when_complete() {
+        let bytes = testing_bytes();
+        let mut parser = PngParser::new();
+
+        // Header plus the whole IHDR chunk and part of IDAT
+        let events = parser.feed(&bytes[..8 + 18 + 5]);
+        assert_eq!(chunk_types(&events), vec!["IHDR"]);
+        assert!(parser.finish().is_err());
+
+        let events = parser.feed(&bytes[8 + 18 + 5..]);
+        assert_eq!(chunk_types(&events), vec!["IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let mut bytes = testing_bytes();
+        bytes[0] = 0;
+
+        let mut parser = PngParser::new();
+        let events = parser.feed(&bytes);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], ParseEvent::Error(_)));
+        assert!(parser.feed(&bytes).is_empty());
+        assert!(parser.finish().is_err());
+    }
+
+    #[test]
+    fn test_trailer_after_iend() {
+        let mut bytes = testing_bytes();
+        bytes.extend_from_slice(&Chunk::from_strings("ruSt", "after").unwrap().as_bytes());
+        bytes.extend_from_slice(b"PK\x03\x04 an appended archive");
+
+        let mut parser = PngParser::new();
+        let mut events = Vec::new();
+        for piece in bytes.chunks(5) {
+            events.extend(parser.feed(piece));
+        }
+        assert_eq!(chunk_types(&events), vec!["IHDR", "IDAT", "IEND", "ruSt"]);
+        assert!(parser.finish().is_ok());
+        assert_eq!(parser.trailer(), b"PK\x03\x04 an appended archive");
+
+        // A chunk cut short after IEND is part of the trailer too
+        let mut bytes = testing_bytes();
+        bytes.extend_from_slice(&[0, 0, 0, 9, b'r', b'u', b'S', b't']);
+        let mut parser = PngParser::new();
+        parser.feed(&bytes);
+        assert!(parser.finish().is_ok());
+        assert_eq!(parser.trailer().len(), 8);
+    }
+
+    #[test]
+    fn test_invalid_crc() {
+        let mut bytes = testing_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+
+        let events = PngParser::new().feed(&bytes);
+        assert_eq!(chunk_types(&events), vec!["IHDR", "IDAT"]);
+        match events.last() {
+            Some(ParseEvent::Error(error)) => {
+                assert_eq!(error.to_string(), "Invalid chunk at offset 44")
+            }
+            _ => panic!("Expected an error"),
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use std::convert::TryInto;
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use crate::png::Png;
+
+/// Starting column, starting row, column step and row step of the seven Adam7 passes
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// The fields of an IHDR chunk needed to walk the scanlines
+#[derive(Debug, Clone, Copy)]
+pub struct Header {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub interlaced: bool,
+}
+
+impl Header {
+    pub fn from_png(png: &Png) -> anyhow::Result<Self> {
+        let data = png
+            .chunk_by_type("IHDR")
+            .ok_or_else(|| anyhow::anyhow!("No IHDR chunk found"))?
+            .data();
+        if data.len() < 13 {
+            anyhow::bail!("IHDR chunk is truncated");
+        }
+        check_bit_depth(data[8], data[9])?;
+
+        Ok(Self {
+            width: u32::from_be_bytes(data[0..4].try_into()?),
+            height: u32::from_be_bytes(data[4..8].try_into()?),
+            bit_depth: data[8],
+            color_type: data[9],
+            interlaced: data[12] != 0,
+        })
+    }
+
+    /// The number of samples in each pixel
+    pub fn channels(&self) -> anyhow::Result<usize> {
+        Ok(match self.color_type {
+            0 | 3 => 1,
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            color_type => anyhow::bail!("Invalid color type {}", color_type),
+        })
+    }
+
+    pub fn bits_per_pixel(&self) -> anyhow::Result<usize> {
+        Ok(self.channels()? * self.bit_depth as usize)
+    }
+
+    /// The number of bytes in a scanline, not counting the filter type byte
+    pub fn row_length(&self) -> anyhow::Result<usize> {
+        let bits = (self.width as usize)
+            .checked_mul(self.bits_per_pixel()?)
+            .ok_or_else(|| anyhow::anyhow!("Image is too wide: {} pixels", self.width))?;
+        Ok(bits.div_ceil(8))
+    }
+
+    /// The distance back to the corresponding byte of the previous pixel
+    pub fn filter_distance(&self) -> anyhow::Result<usize> {
+        Ok((self.bits_per_pixel()? / 8).max(1))
+    }
+}
+
+/// Returns an error unless the PNG spec allows `bit_depth` with `color_type`
+pub fn check_bit_depth(bit_depth: u8, color_type: u8) -> anyhow::Result<()> {
+    let allowed_depths: &[u8] = match color_type {
+        0 => &[1, 2, 4, 8, 16],
+        3 => &[1, 2, 4, 8],
+        2 | 4 | 6 => &[8, 16],
+        _ => anyhow::bail!("Invalid color type {}", color_type),
+    };
+    if !allowed_depths.contains(&bit_depth) {
+        anyhow::bail!(
+            "Bit depth {} isn't allowed with color type {}",
+            bit_depth,
+            color_type
+        );
+    }
+    Ok(())
+}
+
+/// Decompresses and unfilters the image data of `png`, returning its header and the raw
+/// scanlines without their filter type bytes. Interlaced images are put back into
+/// normal row order.
+pub fn decode(png: &Png) -> anyhow::Result<(Header, Vec<u8>)> {
+    let header = Header::from_png(png)?;
+    if header.width == 0 || header.height == 0 {
+        anyhow::bail!("Invalid image size {}x{}", header.width, header.height);
+    }
+    let compressed: Vec<u8> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+    if compressed.is_empty() {
+        anyhow::bail!("No IDAT chunks found");
+    }
+
+    let mut filtered = Vec::new();
+    ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut filtered)?;
+
+    let raw = if header.interlaced {
+        deinterlace(&header, &filtered)?
+    } else {
+        let row_length = header.row_length()?;
+        let length = (row_length + 1)
+            .checked_mul(header.height as usize)
+            .filter(|&length| length <= filtered.len())
+            .ok_or_else(|| anyhow::anyhow!("The IDAT data doesn't match the image size in IHDR"))?;
+        unfilter(&filtered[..length], row_length, header.filter_distance()?)?
+    };
+
+    Ok((header, raw))
+}
+
+/// Returns true if both images show exactly the same pixels: the same size and color
+/// format, the same palette and transparency, and the same samples. How the pixel data
+/// is compressed, filtered or interlaced doesn't matter.
+pub fn same_pixels(first: &Png, second: &Png) -> anyhow::Result<bool> {
+    let (first_header, first_raw) = decode(first)?;
+    let (second_header, second_raw) = decode(second)?;
+    let format = |header: &Header| {
+        (
+            header.width,
+            header.height,
+            header.bit_depth,
+            header.color_type,
+        )
+    };
+    let color_chunks_match = ["PLTE", "tRNS"].iter().all(|chunk_type| {
+        first.chunk_by_type(chunk_type).map(|chunk| chunk.data())
+            == second.chunk_by_type(chunk_type).map(|chunk| chunk.data())
+    });
+
+    Ok(format(&first_header) == format(&second_header)
+        && color_chunks_match
+        && first_raw == second_raw)
+}
+
+/// Unfilters each Adam7 pass in `data` and copies its pixels to their places in the
+/// full image
+fn deinterlace(header: &Header, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let bits = header.bits_per_pixel()?;
+    let distance = header.filter_distance()?;
+    let row_length = header.row_length()?;
+    let (width, height) = (header.width as usize, header.height as usize);
+    // No pass has longer rows than the full image, so this bounds the sizes below as well
+    let size = (row_length + 1)
+        .checked_mul(height)
+        .ok_or_else(|| anyhow::anyhow!("The IDAT data doesn't match the image size in IHDR"))?;
+
+    // Passes with no pixels have no scanlines at all, not even filter type bytes
+    let passes: Vec<_> = ADAM7
+        .iter()
+        .map(|&(x0, y0, dx, dy)| {
+            let pass_width = width.saturating_sub(x0).div_ceil(dx);
+            let pass_height = height.saturating_sub(y0).div_ceil(dy);
+            (x0, y0, dx, dy, pass_width, pass_height)
+        })
+        .filter(|pass| pass.4 > 0 && pass.5 > 0)
+        .collect();
+    let expected: usize = passes
+        .iter()
+        .map(|pass| ((pass.4 * bits).div_ceil(8) + 1) * pass.5)
+        .sum();
+    if data.len() < expected {
+        anyhow::bail!("The IDAT data doesn't match the image size in IHDR");
+    }
+
+    let mut raw = vec![0; size - height];
+    let mut offset = 0;
+    for (x0, y0, dx, dy, pass_width, pass_height) in passes {
+        let pass_row_length = (pass_width * bits).div_ceil(8);
+        let length = (pass_row_length + 1) * pass_height;
+        let pass = unfilter(&data[offset..offset + length], pass_row_length, distance)?;
+        offset += length;
+
+        for (row, line) in pass.chunks(pass_row_length).enumerate() {
+            let y = y0 + row * dy;
+            let target = &mut raw[y * row_length..(y + 1) * row_length];
+            for column in 0..pass_width {
+                copy_bits(line, column * bits, target, (x0 + column * dx) * bits, bits);
+            }
+        }
+    }
+
+    Ok(raw)
+}
+
+/// Copies `count` bits starting at bit `from` of `source` to bit `to` of `target`, which
+/// must be zero there. Bits are counted from the most significant bit of the first byte.
+fn copy_bits(source: &[u8], from: usize, target: &mut [u8], to: usize, count: usize) {
+    if from.is_multiple_of(8) && to.is_multiple_of(8) && count.is_multiple_of(8) {
+        target[to / 8..(to + count) / 8].copy_from_slice(&source[from / 8..(from + count) / 8]);
+        return;
+    }
+    for i in 0..count {
+        let bit = (source[(from + i) / 8] >> (7 - (from + i) % 8)) & 1;
+        target[(to + i) / 8] |= bit << (7 - (to + i) % 8);
+    }
+}
+
+/// Reverses the filter on each scanline of `data`, returning the raw scanlines without
+/// their filter type bytes
+pub fn unfilter(data: &[u8], row_length: usize, distance: usize) -> anyhow::Result<Vec<u8>> {
+    let mut raw = Vec::with_capacity(data.len());
+    let mut previous = vec![0; row_length];
+
+    for line in data.chunks(row_length + 1) {
+        let (filter_type, line) = (line[0], &line[1..]);
+        let start = raw.len();
+        for (i, &byte) in line.iter().enumerate() {
+            let left = if i >= distance {
+                raw[start + i - distance]
+            } else {
+                0
+            };
+            let upper_left = if i >= distance {
+                previous[i - distance]
+            } else {
+                0
+            };
+            let prediction = predict(filter_type, left, previous[i], upper_left)?;
+            raw.push(byte.wrapping_add(prediction));
+        }
+        previous.copy_from_slice(&raw[start..]);
+    }
+
+    Ok(raw)
+}
+
+/// The value a filter type predicts for a byte from its left, upper and upper left
+/// neighbours
+pub fn predict(filter_type: u8, left: u8, up: u8, upper_left: u8) -> anyhow::Result<u8> {
+    Ok(match filter_type {
+        0 => 0,
+        1 => left,
+        2 => up,
+        3 => ((left as u16 + up as u16) / 2) as u8,
+        4 => {
+            let estimate = left as i16 + up as i16 - upper_left as i16;
+            let to_left = (estimate - left as i16).abs();
+            let to_up = (estimate - up as i16).abs();
+            let to_upper_left = (estimate - upper_left as i16).abs();
+            if to_left <= to_up && to_left <= to_upper_left {
+                left
+            } else if to_up <= to_upper_left {
+                up
+            } else {
+                upper_left
+            }
+        }
+        filter_type => anyhow::bail!("Invalid filter type {}", filter_type),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::{Chunk, ChunkType};
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    fn testing_png(width: u32, height: u32, bit_depth: u8, color_type: u8, raw: &[u8]) -> Png {
+        let header = Header {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            interlaced: true,
+        };
+        let bits = header.bits_per_pixel().unwrap();
+        let row_length = header.row_length().unwrap();
+
+        // Splits the raw image into Adam7 passes, with no filtering
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        for &(x0, y0, dx, dy) in ADAM7.iter() {
+            let pass_width = (width as usize).saturating_sub(x0).div_ceil(dx);
+            for y in (y0..height as usize).step_by(dy) {
+                if pass_width == 0 {
+                    break;
+                }
+                let mut line = vec![0; (pass_width * bits).div_ceil(8)];
+                let source = &raw[y * row_length..(y + 1) * row_length];
+                for column in 0..pass_width {
+                    copy_bits(
+                        source,
+                        (x0 + column * dx) * bits,
+                        &mut line,
+                        column * bits,
+                        bits,
+                    );
+                }
+                encoder.write_all(&[0]).unwrap();
+                encoder.write_all(&line).unwrap();
+            }
+        }
+
+        let mut data = Vec::new();
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        data.extend_from_slice(&[bit_depth, color_type, 0, 0, 1]);
+        Png::from_chunks(vec![
+            Chunk::new(ChunkType::new(*b"IHDR"), data),
+            Chunk::new(ChunkType::new(*b"IDAT"), encoder.finish().unwrap()),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_decode() {
+        let png = crate::generate::generate("9x6".parse().unwrap(), crate::generate::Style::Noise)
+            .unwrap();
+        let (header, raw) = decode(&png).unwrap();
+        assert_eq!((header.width, header.height), (9, 6));
+        assert_eq!(raw.len(), 9 * 6 * 3);
+
+        let no_idat = Png::from_chunks(vec![png.chunks()[0].clone()]);
+        assert!(decode(&no_idat).is_err());
+
+        // Bit depths that don't exist, or aren't allowed with the color type
+        for (bit_depth, color_type) in [(0, 0), (3, 0), (4, 2), (16, 3), (8, 5)] {
+            let mut header = png.chunks()[0].data().to_vec();
+            header[8..10].copy_from_slice(&[bit_depth, color_type]);
+            let mut chunks = png.chunks().to_vec();
+            chunks[0] = Chunk::new(ChunkType::new(*b"IHDR"), header);
+            assert!(decode(&Png::from_chunks(chunks)).is_err());
+        }
+        assert!(check_bit_depth(16, 6).is_ok());
+    }
+
+    #[test]
+    fn test_deinterlace() {
+        let rgb: Vec<u8> = (0..9 * 9 * 3).map(|i| (i * 7) as u8).collect();
+        let (_, raw) = decode(&testing_png(9, 9, 8, 2, &rgb)).unwrap();
+        assert_eq!(raw, rgb);
+
+        // 1 bit grayscale, 5 pixels to a row padded out to a byte
+        let bits = vec![0b1011_0000, 0b0100_1000, 0b1111_1000];
+        let (_, raw) = decode(&testing_png(5, 3, 1, 0, &bits)).unwrap();
+        assert_eq!(raw, bits);
+    }
+
+    #[test]
+    fn test_same_pixels() {
+        let png = crate::generate::generate("9x6".parse().unwrap(), crate::generate::Style::Noise)
+            .unwrap();
+        let (_, raw) = decode(&png).unwrap();
+
+        let mut with_message = png.clone();
+        with_message.insert_chunk(Chunk::from_strings("ruSt", "hidden").unwrap());
+        assert!(same_pixels(&png, &with_message).unwrap());
+        assert!(same_pixels(&png, &testing_png(9, 6, 8, 2, &raw)).unwrap());
+
+        let mut changed = raw.clone();
+        changed[0] ^= 1;
+        assert!(!same_pixels(&png, &testing_png(9, 6, 8, 2, &changed)).unwrap());
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::container::Container;
+use crate::envelope;
+use crate::png::ChunkType;
+use crate::text::TextChunk;
+
+/// A batch of edits applied to many files by the `apply` command, read from TOML like
+///
+/// ```toml
+/// files = ["assets/**/*.png", "logo.png"]
+/// out_dir = "dist"
+///
+/// [[step]]
+/// op = "encode"
+/// chunk = "ruSt"
+/// message = "Release 1.2.0"
+///
+/// [[step]]
+/// op = "remove"
+/// chunk = "tEXt"
+///
+/// [[step]]
+/// op = "meta"
+/// set = { Author = "The release team" }
+/// ```
+///
+/// Paths are relative to the plan file. Without `out_dir` the files are edited in place.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Plan {
+    /// Glob patterns naming the files to edit. `*` and `?` match within a path component
+    /// and `**` matches any number of directories.
+    pub files: Vec<String>,
+    /// Where to write the edited files, keeping their paths relative to the plan
+    pub out_dir: Option<PathBuf>,
+    #[serde(rename = "step", default)]
+    pub steps: Vec<Step>,
+}
+
+/// One edit made to every file in a `Plan`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Step {
+    /// Adds `message` as a new message of type `chunk`
+    Encode { chunk: String, message: String },
+    /// Removes every message of type `chunk`
+    Remove { chunk: String },
+    /// Sets tEXt keywords in PNG files, replacing any existing values
+    Meta { set: BTreeMap<String, String> },
+}
+
+impl Plan {
+    /// Reads and checks the plan in `path`
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        Self::from_str(&text).map_err(|e| anyhow::anyhow!("Invalid plan {:?}: {}", path, e))
+    }
+
+    /// Returns every file matched by the plan's patterns, sorted and without repeats.
+    /// Files in `out_dir` are left out so that running a plan again doesn't pick up its
+    /// own output. It is an error for a pattern to match nothing, since that usually
+    /// means a typo.
+    pub fn matching_files(&self, base: &Path) -> anyhow::Result<Vec<PathBuf>> {
+        let out_dir = self.out_dir.as_ref().map(|out_dir| base.join(out_dir));
+        let mut files = Vec::new();
+        for pattern in &self.files {
+            let mut matches = glob(base, pattern)?;
+            matches.retain(|file| {
+                out_dir
+                    .as_ref()
+                    .is_none_or(|out_dir| !file.starts_with(out_dir))
+            });
+            if matches.is_empty() {
+                anyhow::bail!("No files match {:?}", pattern);
+            }
+            files.extend(matches);
+        }
+
+        files.sort();
+        files.dedup();
+        Ok(files)
+    }
+
+    /// Where the edited copy of `file` goes
+    pub fn output_path(&self, base: &Path, file: &Path) -> anyhow::Result<PathBuf> {
+        let out_dir = match &self.out_dir {
+            Some(out_dir) => base.join(out_dir),
+            None => return Ok(file.to_path_buf()),
+        };
+        match file.strip_prefix(base) {
+            Ok(relative) => Ok(out_dir.join(relative)),
+            Err(_) => anyhow::bail!(
+                "{:?} is outside the plan's directory, so it has no place in out_dir",
+                file
+            ),
+        }
+    }
+}
+
+impl FromStr for Plan {
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> anyhow::Result<Self> {
+        let plan: Self = toml::from_str(text)?;
+        if plan.steps.is_empty() {
+            anyhow::bail!("The plan has no steps");
+        }
+        for step in &plan.steps {
+            match step {
+                Step::Encode { chunk, .. } | Step::Remove { chunk } => {
+                    ChunkType::from_str(chunk)?;
+                }
+                Step::Meta { set } => {
+                    for (keyword, text) in set {
+                        TextChunk::new(keyword, text)?;
+                    }
+                }
+            }
+        }
+        Ok(plan)
+    }
+}
+
+impl Step {
+    /// Makes this edit to `container` and returns a short description of what changed
+    pub fn apply(&self, container: &mut Container) -> anyhow::Result<String> {
+        match self {
+            Step::Encode { chunk, message } => {
+                let chunk_type = ChunkType::from_str(chunk)?;
+                container.append_message(
+                    &chunk_type,
+                    envelope::frame(&chunk_type, message.as_bytes()),
+                )?;
+                Ok(format!("encoded {}", chunk))
+            }
+            Step::Remove { chunk } => {
+                let removed = container.messages(chunk)?.len();
+                container.remove_messages(&ChunkType::from_str(chunk)?)?;
+                Ok(format!("removed {} {}", removed, chunk))
+            }
+            Step::Meta { set } => {
+                let png = match container {
+                    Container::Png(png) => png,
+                    _ => {
+                        anyhow::bail!("meta only works with PNG files, not {}", container.format())
+                    }
+                };
+                for (keyword, text) in set {
+                    png.retain_chunks(|chunk| match TextChunk::try_from(chunk) {
+                        Ok(existing) => existing.keyword() != keyword,
+                        Err(_) => true,
+                    });
+                    png.insert_chunk(TextChunk::new(keyword, text)?.to_chunk());
+                }
+                let keywords: Vec<&str> = set.keys().map(String::as_str).collect();
+                Ok(format!("set {}", keywords.join(", ")))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Encode { chunk, .. } => write!(f, "encode {}", chunk),
+            Step::Remove { chunk } => write!(f, "remove {}", chunk),
+            Step::Meta { .. } => write!(f, "meta"),
+        }
+    }
+}
+
+/// Returns the files under `base` matching `pattern`. Components before the first
+/// wildcard are taken literally, so only the directory they name is searched.
+fn glob(base: &Path, pattern: &str) -> anyhow::Result<Vec<PathBuf>> {
+    let is_wildcard = |component: &&str| component.contains(['*', '?']);
+    let components: Vec<&str> = pattern.split('/').collect();
+    let literal = components
+        .iter()
+        .take_while(|component| !is_wildcard(component))
+        .count();
+
+    let root = base.join(components[..literal].join("/"));
+    if literal == components.len() {
+        return Ok(if root.is_file() { vec![root] } else { vec![] });
+    }
+
+    let regex = Regex::new(&glob_regex(&components[literal..].join("/")))?;
+    let mut files = Vec::new();
+    let mut directories = vec![root.clone()];
+    while let Some(directory) = directories.pop() {
+        let entries = match fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(_) if directory == root => continue,
+            Err(error) => return Err(error.into()),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            if path.is_dir() {
+                directories.push(path);
+                continue;
+            }
+
+            let relative: Vec<String> = path
+                .strip_prefix(&root)?
+                .components()
+                .filter_map(|component| match component {
+                    Component::Normal(name) => Some(name.to_string_lossy().into_owned()),
+                    _ => None,
+                })
+                .collect();
+            if regex.is_match(&relative.join("/")) {
+                files.push(path);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Translates a glob pattern into an anchored regular expression over `/` separated paths
+fn glob_regex(pattern: &str) -> String {
+    let mut regex = String::from("^");
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("**/") {
+            regex.push_str("(?:.*/)?");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("**") {
+            regex.push_str(".*");
+            rest = after;
+        } else {
+            match c {
+                '*' => regex.push_str("[^/]*"),
+                '?' => regex.push_str("[^/]"),
+                c => regex.push_str(&regex::escape(&c.to_string())),
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    regex.push('$');
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::{Chunk, Png};
+
+    fn testing_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("pngme-plan-{}-{}", std::process::id(), name))
+    }
+
+    const PLAN: &str = "files = [\"*.png\"]\n\
+                        out_dir = \"dist\"\n\n\
+                        [[step]]\nop = \"encode\"\nchunk = \"ruSt\"\nmessage = \"hello\"\n\n\
+                        [[step]]\nop = \"remove\"\nchunk = \"tEXt\"\n\n\
+                        [[step]]\nop = \"meta\"\nset = { Author = \"Me\" }\n";
+
+    #[test]
+    fn test_parse_plan() {
+        let plan = Plan::from_str(PLAN).unwrap();
+        assert_eq!(plan.out_dir, Some(PathBuf::from("dist")));
+        assert_eq!(
+            plan.steps[1],
+            Step::Remove {
+                chunk: "tEXt".to_string()
+            }
+        );
+
+        assert!(Plan::from_str("files = [\"*.png\"]\n").is_err());
+        assert!(Plan::from_str(&PLAN.replace("ruSt", "ru5t")).is_err());
+        assert!(Plan::from_str(&PLAN.replace("\"remove\"", "\"delete\"")).is_err());
+        assert!(Plan::from_str(&PLAN.replace("message", "mesage")).is_err());
+    }
+
+    #[test]
+    fn test_apply_steps() {
+        let plan = Plan::from_str(PLAN).unwrap();
+        let mut png = Png::new_minimal();
+        png.insert_chunk(Chunk::from_strings("tEXt", "Comment\0old").unwrap());
+        let mut container = Container::Png(png);
+
+        let done: Vec<String> = plan
+            .steps
+            .iter()
+            .map(|step| step.apply(&mut container).unwrap())
+            .collect();
+        assert_eq!(done, vec!["encoded ruSt", "removed 1 tEXt", "set Author"]);
+        assert_eq!(
+            container.messages("ruSt").unwrap(),
+            vec![envelope::wrap(b"hello")]
+        );
+        assert_eq!(
+            container.messages("tEXt").unwrap(),
+            vec![b"Author\0Me".to_vec()]
+        );
+    }
+
+    #[test]
+    fn test_glob() {
+        assert_eq!(glob_regex("*.png"), "^[^/]*\\.png$");
+        assert_eq!(glob_regex("**/a?.png"), "^(?:.*/)?a[^/]\\.png$");
+
+        let base = testing_path("glob");
+        fs::create_dir_all(base.join("assets").join("icons")).unwrap();
+        for file in [
+            "logo.png",
+            "assets/a.png",
+            "assets/icons/b.png",
+            "assets/c.jpg",
+        ] {
+            fs::write(base.join(file), b"").unwrap();
+        }
+
+        let matches = |pattern: &str| {
+            let mut files: Vec<String> = glob(&base, pattern)
+                .unwrap()
+                .iter()
+                .map(|path| path.strip_prefix(&base).unwrap().display().to_string())
+                .collect();
+            files.sort();
+            files
+        };
+        assert_eq!(matches("*.png"), vec!["logo.png"]);
+        assert_eq!(
+            matches("assets/**/*.png"),
+            vec!["assets/a.png", "assets/icons/b.png"]
+        );
+        assert_eq!(matches("assets/*"), vec!["assets/a.png", "assets/c.jpg"]);
+        assert_eq!(matches("logo.png"), vec!["logo.png"]);
+        assert!(matches("missing/*.png").is_empty());
+
+        let plan = Plan::from_str(PLAN).unwrap();
+        assert_eq!(
+            plan.output_path(&base, &base.join("assets/a.png")).unwrap(),
+            base.join("dist").join("assets/a.png")
+        );
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
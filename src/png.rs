@@ -1,38 +1,153 @@
 use std::convert::TryFrom;
 use std::fmt;
-use std::fs;
-use std::io::{BufReader, Read};
-use std::path::Path;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
+use bytes::Bytes;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+
+use crate::atomic;
 pub use crate::chunk::Chunk;
 pub use crate::chunk_type::ChunkType;
+use crate::error::Locate;
+use crate::parser::{ParseEvent, PngParser};
+use crate::pixels::Header;
 
 /// A PNG container as described by the PNG spec
 /// http://www.libpng.org/pub/png/spec/1.2/PNG-Contents.html
 /// A `Png` struct contains a header with the bytes `[137, 80, 78, 71, 13, 10, 26, 10]`
 /// and a list of `Chunk` structs.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Png {
     header: [u8; 8],
     chunks: Vec<Chunk>,
+    /// Bytes after IEND that aren't chunks, or after the last chunk that could be read
+    /// by `from_bytes_preserving`. Viewers stop at IEND, so they never see these.
+    trailer: Vec<u8>,
+    /// The file this was read from by `from_file`, which `save_in_place` writes back to
+    path: Option<PathBuf>,
 }
 
 impl Png {
-    pub const EXPECTED_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    /// The 8 bytes every PNG file starts with, which the PNG spec calls its signature
+    pub const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+
+    #[deprecated(note = "Use Png::SIGNATURE")]
+    pub const EXPECTED_HEADER: [u8; 8] = Png::SIGNATURE;
 
     /// Creates a `Png` from a list of chunks using the correct header
     pub fn from_chunks(chunks: Vec<Chunk>) -> Self {
         Self {
-            header: Png::EXPECTED_HEADER,
+            header: Png::SIGNATURE,
             chunks,
+            trailer: Vec::new(),
+            path: None,
         }
     }
 
+    /// Creates the smallest useful PNG: a single fully transparent pixel. It makes a
+    /// carrier or test fixture without needing an image file.
+    pub fn new_minimal() -> Self {
+        // 1x1, 8 bit RGBA, no interlacing
+        let header = vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 6, 0, 0, 0];
+
+        // A single scanline: filter type None followed by one RGBA pixel of zeros
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(&[0; 5])
+            .expect("writing to a Vec can't fail");
+        let pixels = encoder.finish().expect("writing to a Vec can't fail");
+
+        Self::from_chunks(vec![
+            Chunk::new(ChunkType::new(*b"IHDR"), header),
+            Chunk::new(ChunkType::new(*b"IDAT"), pixels),
+            Chunk::new(ChunkType::new(*b"IEND"), Vec::new()),
+        ])
+    }
+
     /// Creates a `Png` from a file path
     pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
-        let bytes = fs::read(path)?;
-        Ok(Self::try_from(bytes.as_ref())?)
+        let path = path.as_ref();
+        let bytes = fs::read(path).in_file(path)?;
+        let mut png = Self::try_from(bytes.as_ref()).in_file(path)?;
+        png.path = Some(path.to_path_buf());
+        Ok(png)
+    }
+
+    /// Writes this `Png` to `path`. The bytes go to a temporary file that is renamed
+    /// over `path`, so a crash or full disk never leaves a half-written image behind.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        atomic::write(path, &self.checked_bytes()?).in_file(path)
+    }
+
+    /// Writes this `Png` back to the file it was read from with `from_file`, like `save`
+    pub fn save_in_place(&self) -> anyhow::Result<()> {
+        match &self.path {
+            Some(path) => self.save(path),
+            None => anyhow::bail!("This PNG wasn't read from a file, so save it with `save`"),
+        }
+    }
+
+    /// Like `TryFrom<&[u8]>`, but keeps damaged data instead of refusing it, so that saving
+    /// the result changes only the chunks that were edited. Chunks with a bad CRC keep the
+    /// CRC they were read with, and everything from the first chunk that can't be read
+    /// (an invalid chunk type, a truncated chunk or bytes after the last chunk) is kept
+    /// as the trailer. After IEND only complete chunks with valid CRCs are read, like
+    /// `TryFrom<&[u8]>` does.
+    pub fn from_bytes_preserving(bytes: &[u8]) -> anyhow::Result<Self> {
+        Self::from_shared_preserving(Bytes::copy_from_slice(bytes))
+    }
+
+    /// Like `from_bytes_preserving`, but the chunks' data points into `shared` instead of
+    /// a copy of it
+    pub fn from_shared_preserving(shared: Bytes) -> anyhow::Result<Self> {
+        let bytes: &[u8] = &shared;
+        if !bytes.starts_with(&Png::SIGNATURE) {
+            return Err(anyhow::anyhow!(
+                "Invalid header: {:?}",
+                &bytes[..bytes.len().min(8)]
+            ))
+            .code("invalid_signature")
+            .at_offset(0);
+        }
+
+        let mut chunks = Vec::new();
+        let mut offset = Png::SIGNATURE.len();
+        let mut after_iend = false;
+        while let Some(fields) = bytes.get(offset..offset + 8) {
+            if after_iend && !is_chunk_at(&bytes[offset..]) {
+                break;
+            }
+            let length = u32::from_be_bytes([fields[0], fields[1], fields[2], fields[3]]);
+            let type_bytes = [fields[4], fields[5], fields[6], fields[7]];
+            let chunk_type = match ChunkType::try_from(type_bytes) {
+                Ok(chunk_type) => chunk_type,
+                Err(_) => break,
+            };
+
+            let data_end = offset + 8 + length as usize;
+            let crc = match bytes.get(data_end..data_end + 4) {
+                Some(crc) => u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]),
+                None => break,
+            };
+            let data = shared.slice(offset + 8..data_end);
+
+            after_iend |= chunk_type.bytes() == *b"IEND";
+            chunks.push(Chunk::with_crc(chunk_type, data, crc).at_offset(offset));
+            offset = data_end + 4;
+        }
+
+        Ok(Self {
+            header: Png::SIGNATURE,
+            chunks,
+            trailer: bytes[offset..].to_vec(),
+            path: None,
+        })
     }
 
     /// Appends a chunk to the end of this `Png` file's `Chunk` list.
@@ -40,6 +155,111 @@ impl Png {
         self.chunks.push(chunk);
     }
 
+    /// Adds a chunk to a PNG file on disk without reading or rewriting the rest of it.
+    /// If the file ends with an IEND chunk, the new chunk overwrites it and IEND is
+    /// written again after it. Otherwise the chunk is appended to the end of the file,
+    /// which matches `append_chunk`.
+    pub fn append_chunk_to_file<P: AsRef<Path>>(path: P, chunk: &Chunk) -> anyhow::Result<()> {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+
+        let mut header = [0; 8];
+        file.read_exact(&mut header)?;
+        if header != Png::SIGNATURE {
+            anyhow::bail!("Invalid header: {:?}", header);
+        }
+
+        let iend = Chunk::new(ChunkType::new(*b"IEND"), Vec::new()).as_bytes();
+        let length = file.seek(SeekFrom::End(0))?;
+
+        let mut trailer = vec![0; iend.len()];
+        let ends_with_iend = length >= (header.len() + iend.len()) as u64 && {
+            file.seek(SeekFrom::End(-(iend.len() as i64)))?;
+            file.read_exact(&mut trailer)?;
+            trailer == iend
+        };
+
+        let mut bytes = chunk.as_bytes();
+        let position = if ends_with_iend {
+            bytes.extend_from_slice(&iend);
+            length - iend.len() as u64
+        } else {
+            length
+        };
+
+        file.seek(SeekFrom::Start(position))?;
+        file.write_all(&bytes)?;
+        file.set_len(position + bytes.len() as u64)?;
+        file.sync_all()?;
+
+        Ok(())
+    }
+
+    /// Inserts a chunk just before the IEND chunk, or at the end of the `Chunk` list
+    /// if there is no IEND chunk.
+    pub fn insert_chunk(&mut self, chunk: Chunk) {
+        let index = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == "IEND")
+            .unwrap_or(self.chunks.len());
+        self.chunks.insert(index, chunk);
+    }
+
+    /// Inserts a chunk directly before the first chunk of type `anchor`. Returns an error
+    /// if there is no such chunk.
+    pub fn insert_chunk_before(&mut self, anchor: &str, chunk: Chunk) -> anyhow::Result<()> {
+        let anchor = ChunkType::from_str(anchor)?;
+        let index = self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type() == &anchor)
+            .ok_or_else(|| anyhow::anyhow!("No {} chunk to insert before", anchor))?;
+        self.chunks.insert(index, chunk);
+        Ok(())
+    }
+
+    /// Inserts a chunk directly after the last chunk of type `anchor`, so that a chunk
+    /// inserted after IDAT never splits the image data. Returns an error if there is no
+    /// such chunk.
+    pub fn insert_chunk_after(&mut self, anchor: &str, chunk: Chunk) -> anyhow::Result<()> {
+        let anchor = ChunkType::from_str(anchor)?;
+        let index = self
+            .chunks
+            .iter()
+            .rposition(|chunk| chunk.chunk_type() == &anchor)
+            .ok_or_else(|| anyhow::anyhow!("No {} chunk to insert after", anchor))?;
+        self.chunks.insert(index + 1, chunk);
+        Ok(())
+    }
+
+    /// Removes every `Chunk` for which `keep` returns false
+    pub fn retain_chunks<F: FnMut(&Chunk) -> bool>(&mut self, keep: F) {
+        self.chunks.retain(keep);
+    }
+
+    /// Removes ancillary chunks that have the same type and data as an earlier chunk,
+    /// keeping the first copy. Critical chunks are never removed, since repeated IDAT
+    /// data is part of the image. Returns the removed chunks.
+    pub fn dedupe_chunks(&mut self) -> Vec<Chunk> {
+        let mut kept: Vec<Chunk> = Vec::with_capacity(self.chunks.len());
+        let mut removed = Vec::new();
+
+        for chunk in self.chunks.drain(..) {
+            let duplicate = !chunk.chunk_type().is_critical()
+                && kept.iter().any(|existing| {
+                    existing.chunk_type() == chunk.chunk_type() && existing.data() == chunk.data()
+                });
+            if duplicate {
+                removed.push(chunk);
+            } else {
+                kept.push(chunk);
+            }
+        }
+
+        self.chunks = kept;
+        removed
+    }
+
     /// Searches for a `Chunk` with the specified `chunk_type` and removes the first
     /// matching `Chunk` from this `Png` list of chunks.
     pub fn remove_chunk(&mut self, chunk_type: &str) -> anyhow::Result<Chunk> {
@@ -54,15 +274,60 @@ impl Png {
 
         match target_index {
             Some(index) => Ok(self.chunks.remove(index)),
-            None => anyhow::bail!("Chunk not found"),
+            None => Err(anyhow::anyhow!("Chunk not found"))
+                .code("chunk_not_found")
+                .in_chunk(&chunk_type.to_string()),
         }
     }
 
+    /// Compares two `Png`s like `==`, except that the CRCs of their chunks are ignored
+    pub fn eq_ignoring_crc(&self, other: &Png) -> bool {
+        self.header == other.header
+            && self.trailer == other.trailer
+            && self.chunks.len() == other.chunks.len()
+            && self
+                .chunks
+                .iter()
+                .zip(&other.chunks)
+                .all(|(chunk, other)| chunk.eq_ignoring_crc(other))
+    }
+
+    /// The signature this `Png` starts with, which is always `Png::SIGNATURE`
+    pub fn header(&self) -> &[u8; 8] {
+        &self.header
+    }
+
+    /// The width of the image in pixels, from its IHDR chunk
+    pub fn width(&self) -> anyhow::Result<u32> {
+        Ok(Header::from_png(self)?.width)
+    }
+
+    /// The height of the image in pixels, from its IHDR chunk
+    pub fn height(&self) -> anyhow::Result<u32> {
+        Ok(Header::from_png(self)?.height)
+    }
+
+    /// The color type from the IHDR chunk: 0 for grayscale, 2 for RGB, 3 for a palette,
+    /// 4 for grayscale with alpha and 6 for RGBA
+    pub fn color_type(&self) -> anyhow::Result<u8> {
+        Ok(Header::from_png(self)?.color_type)
+    }
+
     /// Lists the `Chunk`s stored in this `Png`
     pub fn chunks(&self) -> &[Chunk] {
         &self.chunks
     }
 
+    /// The `Chunk`s stored in this `Png`, for editing them where they are
+    pub fn chunks_mut(&mut self) -> &mut [Chunk] {
+        &mut self.chunks
+    }
+
+    /// Takes the `Chunk`s out of this `Png`, dropping the header and the trailer
+    pub fn into_chunks(self) -> Vec<Chunk> {
+        self.chunks
+    }
+
     /// Searches for a `Chunk` with the specified `chunk_type` and returns the first
     /// matching `Chunk` from this `Png` list of chunks.
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
@@ -70,7 +335,7 @@ impl Png {
             Ok(chunk_type) => {
                 for chunk in &self.chunks {
                     if chunk.chunk_type() == &chunk_type {
-                        return Some(&chunk);
+                        return Some(chunk);
                     }
                 }
                 None
@@ -79,55 +344,307 @@ impl Png {
         }
     }
 
+    /// Reorders the chunks of this `Png` into the order recommended by the PNG spec:
+    /// IHDR first, color space chunks before PLTE, palette dependent and other pre-IDAT
+    /// chunks after PLTE, the IDAT chunks in one contiguous run and IEND last. Chunks that
+    /// may appear anywhere keep their position relative to the image data, and the relative
+    /// order of chunks within each group is preserved.
+    /// Returns true if any chunk was moved.
+    pub fn canonicalize(&mut self) -> bool {
+        const BEFORE_PLTE: [&str; 5] = ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB"];
+        const BEFORE_IDAT: [&str; 9] = [
+            "bKGD", "hIST", "tRNS", "pHYs", "sPLT", "oFFs", "pCAL", "sCAL", "eXIf",
+        ];
+
+        let mut seen_idat = false;
+        let ranks: Vec<u8> = self
+            .chunks
+            .iter()
+            .map(|chunk| {
+                let chunk_type = chunk.chunk_type().to_string();
+                match chunk_type.as_str() {
+                    "IHDR" => 0,
+                    t if BEFORE_PLTE.contains(&t) => 1,
+                    "PLTE" => 2,
+                    t if BEFORE_IDAT.contains(&t) => 3,
+                    "IDAT" => {
+                        seen_idat = true;
+                        4
+                    }
+                    "IEND" => 6,
+                    _ if seen_idat => 5,
+                    _ => 3,
+                }
+            })
+            .collect();
+
+        let mut order: Vec<usize> = (0..self.chunks.len()).collect();
+        order.sort_by_key(|&index| ranks[index]);
+
+        let moved = order.iter().enumerate().any(|(i, &index)| i != index);
+        if moved {
+            let mut chunks: Vec<Option<Chunk>> = self.chunks.drain(..).map(Some).collect();
+            self.chunks = order
+                .into_iter()
+                .map(|index| chunks[index].take().expect("each index appears once"))
+                .collect();
+        }
+
+        moved
+    }
+
+    /// Fixes the overall structure: appends IEND if it's missing and removes any chunks
+    /// after it. With `merge_idat`, each run of consecutive IDAT chunks becomes a single
+    /// chunk. Chunk order is otherwise left alone, so call `canonicalize` first to keep
+    /// chunks that were appended after IEND.
+    pub fn normalize(&mut self, merge_idat: bool) -> Normalization {
+        let mut normalization = Normalization::default();
+
+        match self
+            .chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().to_string() == "IEND")
+        {
+            Some(iend) => normalization.removed = self.chunks.split_off(iend + 1),
+            None => {
+                self.chunks
+                    .push(Chunk::new(ChunkType::new(*b"IEND"), Vec::new()));
+                normalization.added_iend = true;
+            }
+        }
+
+        if merge_idat {
+            let mut chunks: Vec<Chunk> = Vec::with_capacity(self.chunks.len());
+            for chunk in self.chunks.drain(..) {
+                let is_idat = |chunk: &Chunk| chunk.chunk_type().to_string() == "IDAT";
+                match chunks.last_mut() {
+                    Some(last) if is_idat(last) && is_idat(&chunk) => {
+                        last.append_data(chunk.data());
+                        normalization.merged_idat += 1;
+                    }
+                    _ => chunks.push(chunk),
+                }
+            }
+            self.chunks = chunks;
+        }
+
+        normalization
+    }
+
+    /// Returns a SHA-256 digest of the image content: the IHDR and PLTE chunks followed by
+    /// the concatenated IDAT data. Ancillary chunks are ignored, so two files which only
+    /// differ in metadata or hidden messages produce the same digest. IDAT data is hashed
+    /// as a single stream, so splitting it differently doesn't change the digest either.
+    pub fn content_hash(&self) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+
+        for chunk_type in &["IHDR", "PLTE"] {
+            if let Some(chunk) = self.chunk_by_type(chunk_type) {
+                hasher.update(chunk.chunk_type().bytes());
+                hasher.update(chunk.length().to_be_bytes());
+                hasher.update(chunk.data());
+            }
+        }
+
+        hasher.update(b"IDAT");
+        for chunk in &self.chunks {
+            if chunk.chunk_type().to_string() == "IDAT" {
+                hasher.update(chunk.data());
+            }
+        }
+
+        hasher.finalize().into()
+    }
+
+    /// Bytes after IEND that aren't chunks, like an archive appended to the image, or
+    /// the bytes after the last chunk `from_bytes_preserving` could read. They are
+    /// written back unchanged after the chunks.
+    pub fn trailer(&self) -> &[u8] {
+        &self.trailer
+    }
+
+    /// Replaces the bytes written after the chunks
+    pub fn set_trailer(&mut self, trailer: Vec<u8>) {
+        self.trailer = trailer;
+    }
+
+    /// Removes the trailer and returns it
+    pub fn strip_trailer(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.trailer)
+    }
+
     /// Returns this `Png` as a byte sequence.
-    /// These bytes will contain the header followed by the bytes of all of the chunks.
+    /// These bytes will contain the header followed by the bytes of all of the chunks,
+    /// and the trailer.
     pub fn as_bytes(&self) -> Vec<u8> {
-        let mut result = Vec::new();
+        let length = self.header.len()
+            + self.chunks.iter().map(Chunk::encoded_length).sum::<usize>()
+            + self.trailer.len();
+        let mut result = Vec::with_capacity(length);
 
-        result.extend(&self.header);
+        result.extend_from_slice(&self.header);
         for chunk in &self.chunks {
-            result.extend(chunk.as_bytes());
+            chunk.write_bytes(&mut result);
         }
+        result.extend_from_slice(&self.trailer);
 
         result
     }
+
+    /// Like `as_bytes`, but reads the bytes back first and checks that they hold exactly
+    /// these chunks, each with the CRC it had, followed by the same trailer. Files
+    /// are saved through this, so a bug in writing chunks out fails the save instead of
+    /// replacing a good file with a broken one.
+    pub fn checked_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let bytes = self.as_bytes();
+        let parsed = match Png::from_bytes_preserving(&bytes) {
+            Ok(parsed) => parsed,
+            Err(error) => return self_check_failed(error),
+        };
+
+        let mismatch = self
+            .chunks
+            .iter()
+            .zip(&parsed.chunks)
+            .position(|(chunk, read)| {
+                chunk.chunk_type() != read.chunk_type()
+                    || chunk.data() != read.data()
+                    || chunk.crc() != read.crc()
+            });
+        if let Some(index) = mismatch {
+            let problem = anyhow::anyhow!(
+                "chunk {} ({}) reads back differently",
+                index,
+                self.chunks[index].chunk_type()
+            );
+            return self_check_failed(problem);
+        }
+        if parsed.chunks.len() != self.chunks.len() || parsed.trailer != self.trailer {
+            let problem = anyhow::anyhow!(
+                "{} chunk(s) read back where {} were written",
+                parsed.chunks.len(),
+                self.chunks.len()
+            );
+            return self_check_failed(problem);
+        }
+        Ok(bytes)
+    }
+
+    /// Adds what the parser found to this `Png`, or returns the error it found
+    fn add_events(&mut self, events: Vec<ParseEvent>) -> anyhow::Result<()> {
+        for event in events {
+            match event {
+                ParseEvent::Header(header) => self.header = header,
+                ParseEvent::Chunk(chunk) => self.chunks.push(chunk),
+                ParseEvent::Error(error) => return Err(error),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns true if `bytes` start with a complete chunk with a valid type and CRC. Bytes
+/// after IEND are only read as chunks if they pass this, and are the trailer otherwise.
+pub(crate) fn is_chunk_at(bytes: &[u8]) -> bool {
+    let length = match bytes.get(..4) {
+        Some(length) => u32::from_be_bytes([length[0], length[1], length[2], length[3]]) as usize,
+        None => return false,
+    };
+    match bytes.get(..length.saturating_add(12)) {
+        Some(chunk) => Chunk::try_from(chunk).is_ok(),
+        None => false,
+    }
+}
+
+/// The error for bytes that didn't read back as what was written, which means the file
+/// about to be saved would be damaged
+pub(crate) fn self_check_failed<T>(problem: anyhow::Error) -> anyhow::Result<T> {
+    Err(anyhow::anyhow!(
+        "The file didn't read back the way it was written ({}), so it was not saved and the \
+         original is unchanged. This is a bug in pngme",
+        problem
+    ))
+    .code("self_check_failed")
+}
+
+/// What `Png::normalize` changed
+#[derive(Debug, Default)]
+pub struct Normalization {
+    /// Whether IEND was missing and had to be added
+    pub added_iend: bool,
+    /// The chunks that were after IEND
+    pub removed: Vec<Chunk>,
+    /// The number of IDAT chunks merged into the one before them
+    pub merged_idat: usize,
+}
+
+impl Normalization {
+    /// Returns true if `normalize` didn't change anything
+    pub fn is_empty(&self) -> bool {
+        !self.added_iend && self.removed.is_empty() && self.merged_idat == 0
+    }
 }
 
 impl TryFrom<&[u8]> for Png {
     type Error = anyhow::Error;
 
     fn try_from(bytes: &[u8]) -> anyhow::Result<Png> {
-        let mut reader = BufReader::new(bytes);
-        let mut header: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 0];
-        let mut chunks = Vec::new();
+        let mut png = Png::from_chunks(Vec::new());
+        let mut parser = PngParser::new();
+        png.add_events(parser.feed(bytes))?;
+        parser.finish()?;
+        png.trailer = parser.trailer().to_vec();
+        Ok(png)
+    }
+}
 
-        reader.read_exact(&mut header)?;
+/// Like `TryFrom<&[u8]>`, but the chunks' data points into `bytes` instead of a copy of it
+impl TryFrom<Bytes> for Png {
+    type Error = anyhow::Error;
 
-        if header != Png::EXPECTED_HEADER {
-            anyhow::bail!("Invalid header: {:?}", header);
+    fn try_from(bytes: Bytes) -> anyhow::Result<Png> {
+        let png = Png::from_shared_preserving(bytes.clone())?;
+        // A preserving read keeps what a strict one refuses: chunks with bad CRCs, and the
+        // unreadable remains of a file without IEND
+        let damaged = png.chunks.iter().any(|chunk| !chunk.is_crc_valid())
+            || (png.chunk_by_type("IEND").is_none() && !png.trailer.is_empty());
+        if !damaged {
+            return Ok(png);
         }
 
-        let mut length_buffer: [u8; 4] = [0, 0, 0, 0];
-        while let Ok(()) = reader.read_exact(&mut length_buffer) {
-            let length = u32::from_be_bytes(length_buffer);
-
-            // Data length + 4 byte chunk type + 4 byte crc
-            let chunk_length = (length + 8) as usize;
-
-            let mut chunk_data: Vec<u8> = vec![0; chunk_length];
-            reader.read_exact(&mut chunk_data)?;
+        // Find the error a strict read gives, dropping the chunks before it as they come
+        let mut parser = PngParser::new();
+        for block in bytes.chunks(64 * 1024) {
+            for event in parser.feed(block) {
+                if let ParseEvent::Error(error) = event {
+                    return Err(error);
+                }
+            }
+        }
+        parser.finish()?;
+        Png::try_from(bytes.as_ref())
+    }
+}
 
-            let chunk_bytes: Vec<u8> = length_buffer
-                .iter()
-                .copied()
-                .chain(chunk_data.into_iter())
-                .collect();
+/// Reads a `Png` from a file a block at a time, so the whole file is never held in
+/// memory next to the parsed chunks
+impl TryFrom<File> for Png {
+    type Error = anyhow::Error;
 
-            let chunk = Chunk::try_from(chunk_bytes.as_ref())?;
-            chunks.push(chunk);
+    fn try_from(mut file: File) -> anyhow::Result<Png> {
+        let mut png = Png::from_chunks(Vec::new());
+        let mut parser = PngParser::new();
+        let mut block = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut block)?;
+            if read == 0 {
+                break;
+            }
+            png.add_events(parser.feed(&block[..read]))?;
         }
-
-        Ok(Self { header, chunks })
+        parser.finish()?;
+        png.trailer = parser.trailer().to_vec();
+        Ok(png)
     }
 }
 
@@ -141,10 +658,45 @@ impl fmt::Display for Png {
     }
 }
 
+/// `Png`s are equal if they have the same header, chunks and trailer
+impl PartialEq for Png {
+    fn eq(&self, other: &Png) -> bool {
+        self.header == other.header && self.chunks == other.chunks && self.trailer == other.trailer
+    }
+}
+
+impl Eq for Png {}
+
+impl IntoIterator for Png {
+    type Item = Chunk;
+    type IntoIter = std::vec::IntoIter<Chunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Png {
+    type Item = &'a Chunk;
+    type IntoIter = std::slice::Iter<'a, Chunk>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.chunks.iter()
+    }
+}
+
+/// Collects chunks into a `Png` with the correct header, like `from_chunks`
+impl std::iter::FromIterator<Chunk> for Png {
+    fn from_iter<I: IntoIterator<Item = Chunk>>(chunks: I) -> Self {
+        Self::from_chunks(chunks.into_iter().collect())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[allow(clippy::vec_init_then_push)]
     fn testing_chunks() -> Vec<Chunk> {
         let mut chunks = Vec::new();
 
@@ -160,6 +712,20 @@ mod tests {
         Png::from_chunks(chunks)
     }
 
+    #[test]
+    fn test_new_minimal() {
+        let png = Png::new_minimal();
+        let bytes = png.as_bytes();
+        assert!(crate::diagnostics::diagnose(&bytes).is_empty());
+        assert!(Png::try_from(bytes.as_slice()).is_ok());
+
+        let mut pixels = Vec::new();
+        flate2::read::ZlibDecoder::new(png.chunk_by_type("IDAT").unwrap().data())
+            .read_to_end(&mut pixels)
+            .unwrap();
+        assert_eq!(pixels, vec![0; 5]);
+    }
+
     #[test]
     fn test_from_chunks() {
         let chunks = testing_chunks();
@@ -175,7 +741,7 @@ mod tests {
             .flat_map(|chunk| chunk.as_bytes())
             .collect();
 
-        let bytes: Vec<u8> = Png::EXPECTED_HEADER
+        let bytes: Vec<u8> = Png::SIGNATURE
             .iter()
             .chain(chunk_bytes.iter())
             .copied()
@@ -233,6 +799,116 @@ mod tests {
         assert_eq!(chunks.len(), 3);
     }
 
+    #[test]
+    fn test_into_chunks() {
+        let chunks = testing_png().into_chunks();
+        let types: Vec<String> = chunks.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(types, vec!["FrSt", "miDl", "LASt"]);
+    }
+
+    #[test]
+    fn test_chunks_mut() {
+        let mut png = testing_png();
+        png.chunks_mut()[1].set_data(b"Edited".to_vec());
+
+        let png = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(png.chunks()[1].chunk_type().to_string(), "miDl");
+        assert_eq!(png.chunks()[1].data(), b"Edited");
+    }
+
+    #[test]
+    fn test_collect_chunks() {
+        let png = testing_png();
+        let filtered: Png = png
+            .into_iter()
+            .filter(|chunk| chunk.chunk_type().to_string() != "miDl")
+            .collect();
+
+        assert_eq!(filtered.as_bytes()[..8], Png::SIGNATURE);
+        let types: Vec<String> = (&filtered)
+            .into_iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["FrSt", "LASt"]);
+    }
+
+    #[test]
+    fn test_png_equality() {
+        let png = testing_png();
+        let parsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(parsed, png);
+
+        let mut damaged = png.clone();
+        let chunk = &mut damaged.chunks_mut()[0];
+        *chunk = Chunk::with_crc(*chunk.chunk_type(), chunk.data_bytes(), 0);
+        assert_ne!(damaged, png);
+        assert!(damaged.eq_ignoring_crc(&png));
+
+        let mut edited = png.clone();
+        edited.chunks_mut()[0].set_data(b"Edited".to_vec());
+        assert!(!edited.eq_ignoring_crc(&png));
+    }
+
+    #[test]
+    fn test_png_equality_matches_bytes() {
+        let png = testing_png();
+        let mut trailing = png.clone();
+        trailing.set_trailer(b"after IEND".to_vec());
+        assert_ne!(trailing, png);
+        assert!(!trailing.eq_ignoring_crc(&png));
+
+        let mut reordered = png.clone();
+        reordered.chunks_mut().swap(0, 1);
+        assert_ne!(reordered, png);
+
+        let mut restored = reordered.clone();
+        restored.chunks_mut().swap(0, 1);
+        assert_eq!(restored, png);
+        assert_eq!(restored.as_bytes(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_try_from_file() {
+        let png = testing_png();
+        let path = std::env::temp_dir().join(format!("pngme-file-{}.png", std::process::id()));
+        fs::write(&path, png.as_bytes()).unwrap();
+
+        let parsed = Png::try_from(File::open(&path).unwrap());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(parsed.unwrap(), png);
+    }
+
+    #[test]
+    fn test_try_from_shared_bytes() {
+        let png = testing_png();
+        let bytes = Bytes::from(png.as_bytes());
+        assert_eq!(Png::try_from(bytes.clone()).unwrap(), png);
+
+        let mut damaged = bytes.to_vec();
+        damaged[8 + 8 + 13] ^= 0xFF;
+        let strict = Png::try_from(damaged.as_ref()).unwrap_err();
+        let shared = Png::try_from(Bytes::from(damaged)).unwrap_err();
+        assert_eq!(shared.to_string(), strict.to_string());
+
+        let truncated = bytes.slice(..bytes.len() - 4);
+        assert!(Png::try_from(truncated).is_err());
+    }
+
+    #[test]
+    fn test_save_in_place() {
+        let path = std::env::temp_dir().join(format!("pngme-save-{}.png", std::process::id()));
+        testing_png().save(&path).unwrap();
+
+        let mut png = Png::from_file(&path).unwrap();
+        png.append_chunk(Chunk::from_strings("ruSt", "Saved").unwrap());
+        png.save_in_place().unwrap();
+
+        let saved = Png::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved.unwrap(), png);
+        assert!(testing_png().save_in_place().is_err());
+    }
+
     #[test]
     fn test_chunk_by_type() {
         let png = testing_png();
@@ -241,6 +917,16 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "I am the first chunk");
     }
 
+    #[test]
+    fn test_header_and_dimensions() {
+        let png = Png::new_minimal();
+        assert_eq!(png.header(), &Png::SIGNATURE);
+        assert_eq!(png.width().unwrap(), 1);
+        assert_eq!(png.height().unwrap(), 1);
+        assert_eq!(png.color_type().unwrap(), 6);
+        assert!(testing_png().width().is_err());
+    }
+
     #[test]
     fn test_append_chunk() {
         let mut png = testing_png();
@@ -250,6 +936,110 @@ mod tests {
         assert_eq!(&chunk.data_as_string().unwrap(), "Message");
     }
 
+    #[test]
+    fn test_append_chunk_to_file() {
+        let path = std::env::temp_dir().join(format!("pngme-append-{}.png", std::process::id()));
+        fs::write(&path, PNG_FILE).unwrap();
+
+        let chunk = Chunk::from_strings("TeSt", "Message").unwrap();
+        Png::append_chunk_to_file(&path, &chunk).unwrap();
+
+        let png = Png::from_file(&path).unwrap();
+        let mut expected = Png::try_from(&PNG_FILE[..]).unwrap();
+        expected.insert_chunk(chunk.clone());
+        assert_eq!(png.as_bytes(), expected.as_bytes());
+
+        // Once something follows IEND, new chunks go at the very end
+        Png::append_chunk_to_file(&path, &chunk).unwrap();
+        let trailing = Chunk::from_strings("TeSt", "Trailing").unwrap();
+        let mut png = Png::from_file(&path).unwrap();
+        png.append_chunk(trailing.clone());
+        fs::write(&path, png.as_bytes()).unwrap();
+
+        Png::append_chunk_to_file(&path, &chunk).unwrap();
+        let png = Png::from_file(&path).unwrap();
+        let last = png.chunks().last().unwrap();
+        assert_eq!(last.data(), chunk.data());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = testing_png();
+        png.append_chunk(Chunk::from_strings("IEND", "").unwrap());
+        png.insert_chunk(Chunk::from_strings("TeSt", "Message").unwrap());
+
+        let chunks = png.chunks();
+        assert_eq!(&chunks[3].chunk_type().to_string(), "TeSt");
+        assert_eq!(&chunks[4].chunk_type().to_string(), "IEND");
+
+        let mut png = Png::from_chunks(Vec::new());
+        png.insert_chunk(Chunk::from_strings("TeSt", "Message").unwrap());
+        assert_eq!(png.chunks().len(), 1);
+    }
+
+    #[test]
+    fn test_insert_chunk_before_and_after() {
+        let mut png = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IDAT", "one").unwrap(),
+            Chunk::from_strings("IDAT", "two").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ]);
+
+        png.insert_chunk_after("IHDR", Chunk::from_strings("gAMA", "").unwrap())
+            .unwrap();
+        png.insert_chunk_before("IDAT", Chunk::from_strings("tEXt", "").unwrap())
+            .unwrap();
+        png.insert_chunk_after("IDAT", Chunk::from_strings("ruSt", "").unwrap())
+            .unwrap();
+
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "gAMA", "tEXt", "IDAT", "IDAT", "ruSt", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_insert_chunk_missing_anchor() {
+        let mut png = Png::from_chunks(Vec::new());
+        let chunk = Chunk::from_strings("ruSt", "").unwrap();
+
+        assert!(png.insert_chunk_after("IHDR", chunk.clone()).is_err());
+        assert!(png.insert_chunk_before("IEND", chunk).is_err());
+        assert!(png.chunks().is_empty());
+    }
+
+    #[test]
+    fn test_retain_chunks() {
+        let mut png = testing_png();
+        png.retain_chunks(|chunk| chunk.chunk_type().is_critical());
+        assert_eq!(png.chunks().len(), 2);
+    }
+
+    #[test]
+    fn test_dedupe_chunks() {
+        let mut png = testing_png();
+        png.insert_chunk(Chunk::from_strings("tEXt", "Title\0Same").unwrap());
+        png.insert_chunk(Chunk::from_strings("tEXt", "Title\0Different").unwrap());
+        png.insert_chunk(Chunk::from_strings("tEXt", "Title\0Same").unwrap());
+        png.insert_chunk(Chunk::from_strings("IDAT", "pixels").unwrap());
+        png.insert_chunk(Chunk::from_strings("IDAT", "pixels").unwrap());
+
+        let removed = png.dedupe_chunks();
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].data_as_string().unwrap(), "Title\0Same");
+        assert_eq!(png.dedupe_chunks().len(), 0);
+        assert_eq!(
+            png.chunks()
+                .iter()
+                .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+                .count(),
+            2
+        );
+    }
+
     #[test]
     fn test_remove_chunk() {
         let mut png = testing_png();
@@ -259,6 +1049,73 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    #[test]
+    fn test_content_hash_ignores_ancillary_chunks() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut with_message = Png::try_from(&PNG_FILE[..]).unwrap();
+        with_message.append_chunk(Chunk::from_strings("ruSt", "Message").unwrap());
+
+        assert_eq!(png.content_hash(), with_message.content_hash());
+    }
+
+    #[test]
+    fn test_content_hash_ignores_idat_splits() {
+        let whole = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+        ]);
+        let split = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IDAT", "pix").unwrap(),
+            Chunk::from_strings("IDAT", "els").unwrap(),
+        ]);
+        let different = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IDAT", "other pixels").unwrap(),
+        ]);
+
+        assert_eq!(whole.content_hash(), split.content_hash());
+        assert_ne!(whole.content_hash(), different.content_hash());
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let mut png = Png::from_chunks(vec![
+            Chunk::from_strings("tEXt", "Comment\0early").unwrap(),
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("PLTE", "palette").unwrap(),
+            Chunk::from_strings("gAMA", "gamma").unwrap(),
+            Chunk::from_strings("IDAT", "pix").unwrap(),
+            Chunk::from_strings("ruSt", "late message").unwrap(),
+            Chunk::from_strings("pHYs", "physical").unwrap(),
+            Chunk::from_strings("IDAT", "els").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+            Chunk::from_strings("tIME", "time").unwrap(),
+        ]);
+
+        assert!(png.canonicalize());
+        assert_eq!(
+            chunk_types(&png),
+            vec!["IHDR", "gAMA", "PLTE", "tEXt", "pHYs", "IDAT", "IDAT", "ruSt", "tIME", "IEND"]
+        );
+
+        assert!(!png.canonicalize());
+    }
+
+    #[test]
+    fn test_canonicalize_image_file() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert!(!png.canonicalize());
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -266,6 +1123,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::iter_cloned_collect)]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
@@ -273,6 +1131,16 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_checked_bytes() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.checked_bytes().unwrap(), PNG_FILE.to_vec());
+
+        // Trailing data that reads back as a chunk changes the file's chunks
+        png.trailer = Chunk::from_strings("RuSt", "extra").unwrap().as_bytes();
+        assert!(png.checked_bytes().is_err());
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -280,7 +1148,7 @@ mod tests {
             .flat_map(|chunk| chunk.as_bytes())
             .collect();
 
-        let bytes: Vec<u8> = Png::EXPECTED_HEADER
+        let bytes: Vec<u8> = Png::SIGNATURE
             .iter()
             .chain(chunk_bytes.iter())
             .copied()
@@ -537,4 +1405,94 @@ mod tests {
         202, 28, 31, 66, 176, 235, 16, 0, 0, 0, 3, 82, 117, 83, 116, 104, 101, 121, 158, 176, 245,
         160, 0, 0, 0, 0, 73, 69, 78, 68, 174, 66, 96, 130,
     ];
+
+    #[test]
+    fn test_preserving_keeps_damaged_bytes() {
+        let mut bytes = testing_png().as_bytes();
+        // Break the CRC of the second chunk, then add bytes that aren't a chunk
+        let second_crc = 8 + testing_chunks()[0].encoded_length() + 8 + 18;
+        bytes[second_crc] ^= 0xFF;
+        bytes.extend_from_slice(&[0, 0, 0, 1, b'1', b'2', b'3', b'4', 0xAB]);
+        assert!(Png::try_from(bytes.as_ref()).is_err());
+
+        let png = Png::from_bytes_preserving(&bytes).unwrap();
+        assert_eq!(png.chunks().len(), 3);
+        assert!(!png.chunks()[1].is_crc_valid());
+        assert_eq!(png.trailer().len(), 9);
+        assert_eq!(png.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_trailer_after_iend() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.append_chunk(Chunk::from_strings("IEND", "").unwrap());
+        let mut bytes = png.as_bytes();
+        bytes.extend_from_slice(b"PK\x03\x04 appended");
+
+        let mut png = Png::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(png.trailer(), b"PK\x03\x04 appended");
+        assert_eq!(png.checked_bytes().unwrap(), bytes);
+        assert_eq!(Png::from_bytes_preserving(&bytes).unwrap(), png);
+
+        assert_eq!(png.strip_trailer(), b"PK\x03\x04 appended");
+        assert_eq!(png.as_bytes().len(), bytes.len() - 13);
+    }
+
+    #[test]
+    fn test_preserving_edit_changes_only_edited_bytes() {
+        let mut bytes = testing_png().as_bytes();
+        bytes.extend_from_slice(b"trailing");
+
+        let mut png = Png::from_bytes_preserving(&bytes).unwrap();
+        png.remove_chunk("miDl").unwrap();
+
+        let removed = testing_chunks()[1].as_bytes();
+        let start = 8 + testing_chunks()[0].encoded_length();
+        let mut expected = bytes[..start].to_vec();
+        expected.extend_from_slice(&bytes[start + removed.len()..]);
+        assert_eq!(png.as_bytes(), expected);
+    }
+
+    #[test]
+    fn test_normalize_adds_iend() {
+        let mut png = testing_png();
+        let normalization = png.normalize(false);
+
+        assert!(normalization.added_iend);
+        assert_eq!(
+            png.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+        assert!(png.normalize(false).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_removes_chunks_after_iend() {
+        let mut png = testing_png();
+        png.insert_chunk_after("miDl", Chunk::from_strings("IEND", "").unwrap())
+            .unwrap();
+
+        let normalization = png.normalize(false);
+        assert!(!normalization.added_iend);
+        assert_eq!(normalization.removed.len(), 1);
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_normalize_merges_idat() {
+        let mut png = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IDAT", "one").unwrap(),
+            Chunk::from_strings("IDAT", "two").unwrap(),
+            Chunk::from_strings("IDAT", "three").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ]);
+        let content_hash = png.content_hash();
+
+        assert!(png.clone().normalize(false).is_empty());
+        assert_eq!(png.normalize(true).merged_idat, 2);
+        assert_eq!(png.chunks().len(), 3);
+        assert_eq!(png.chunks()[1].data(), b"onetwothree");
+        assert_eq!(png.content_hash(), content_hash);
+    }
 }
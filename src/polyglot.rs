@@ -0,0 +1,281 @@
+use std::convert::{TryFrom, TryInto};
+
+use crate::png::Png;
+
+/// Formats that are commonly hidden in PNG files to make polyglots, which open as an
+/// image in a viewer and as something else in the right program: their signature, name
+/// and file extension
+const SIGNATURES: [(&[u8], &str, &str); 4] = [
+    (b"PK\x03\x04", "ZIP archive", "zip"),
+    (b"%PDF-", "PDF document", "pdf"),
+    (b"Rar!\x1a\x07", "RAR archive", "rar"),
+    (b"7z\xbc\xaf\x27\x1c", "7-Zip archive", "7z"),
+];
+
+/// Chunks with less data than this are too small to hold a file worth reporting
+const MIN_CHUNK_LENGTH: usize = 64;
+
+const END_OF_CENTRAL_DIRECTORY: [u8; 4] = *b"PK\x05\x06";
+const CENTRAL_DIRECTORY_ENTRY: [u8; 4] = *b"PK\x01\x02";
+/// The end of central directory record without its comment
+const END_OF_CENTRAL_DIRECTORY_LENGTH: usize = 22;
+/// A central directory entry without its name, extra field and comment
+const CENTRAL_DIRECTORY_ENTRY_LENGTH: usize = 46;
+
+/// A file of another format found inside a PNG file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedFile {
+    /// Offset of the file's signature from the start of the PNG file
+    pub offset: usize,
+    /// What kind of file it is, like `ZIP archive`
+    pub kind: &'static str,
+    /// The usual extension for files of this kind, without the dot
+    pub extension: &'static str,
+    /// Where the file was found, like `the trailer` or `a ruSt chunk`
+    pub location: String,
+    /// The file, from its signature to its end, or to the end of the trailer or chunk
+    /// data if its end can't be found
+    pub bytes: Vec<u8>,
+}
+
+/// Looks for ZIP, PDF, RAR and 7-Zip files in the trailer and in the data of chunk types
+/// the PNG spec doesn't define. Their signatures can be anywhere in the data, not just at
+/// the start, since polyglots often put a little padding first.
+pub fn find(png: &Png) -> Vec<EmbeddedFile> {
+    let mut found = Vec::new();
+    for chunk in png.chunks() {
+        if chunk.chunk_type().is_registered() || chunk.data().len() < MIN_CHUNK_LENGTH {
+            continue;
+        }
+        let start = chunk.offset().unwrap_or_default() + 8;
+        let location = format!("a {} chunk", chunk.chunk_type());
+        found.extend(find_in(chunk.data(), start, &location));
+    }
+
+    let trailer = png.trailer();
+    if !trailer.is_empty() {
+        let start = png.as_bytes().len() - trailer.len();
+        found.extend(find_in(trailer, start, "the trailer"));
+    }
+    found
+}
+
+/// Puts `zip` after IEND in `png` so that the file is both an image and a ZIP archive.
+/// ZIP readers find the archive from the end of the file, where its central directory
+/// says where each entry starts, so those offsets are moved past the image. Strict
+/// readers would otherwise look for the entries inside the PNG data.
+pub fn append_zip(png: &mut Png, zip: &[u8]) -> anyhow::Result<()> {
+    if png.chunk_by_type("IEND").is_none() {
+        anyhow::bail!(
+            "This image has no IEND chunk to put the archive after. Fix it with `repair`"
+        );
+    }
+    if !png.trailer().is_empty() {
+        anyhow::bail!(
+            "This image already has {} bytes after IEND. Remove them with `repair --strip-trailer` first",
+            png.trailer().len()
+        );
+    }
+    let shift = png.as_bytes().len();
+    png.set_trailer(shift_zip(zip, shift)?);
+    Ok(())
+}
+
+/// Returns `zip` with the offsets in its central directory increased by `shift`, for
+/// when `shift` bytes are put in front of it
+fn shift_zip(zip: &[u8], shift: usize) -> anyhow::Result<Vec<u8>> {
+    // The record is at the very end, unless the archive has a comment of up to 64 KiB
+    let search_start = zip
+        .len()
+        .saturating_sub(END_OF_CENTRAL_DIRECTORY_LENGTH + u16::MAX as usize);
+    let end = zip[search_start..]
+        .windows(4)
+        .rposition(|window| window == END_OF_CENTRAL_DIRECTORY)
+        .map(|index| search_start + index)
+        .filter(|end| zip.len() >= end + END_OF_CENTRAL_DIRECTORY_LENGTH)
+        .ok_or_else(|| {
+            anyhow::anyhow!("This isn't a ZIP archive: it has no end of central directory record")
+        })?;
+
+    let read_u16 = |at: usize| u16::from_le_bytes(zip[at..at + 2].try_into().unwrap());
+    let read_u32 = |at: usize| u32::from_le_bytes(zip[at..at + 4].try_into().unwrap());
+    let shifted = |offset: u32| {
+        if offset == u32::MAX {
+            anyhow::bail!("ZIP64 archives aren't supported");
+        }
+        u32::try_from(offset as usize + shift).map_err(|_| {
+            anyhow::anyhow!("The image and archive together are larger than a ZIP archive can be")
+        })
+    };
+
+    let mut result = zip.to_vec();
+    let entries = read_u16(end + 10);
+    let directory = read_u32(end + 16);
+    result[end + 16..end + 20].copy_from_slice(&shifted(directory)?.to_le_bytes());
+
+    let mut position = directory as usize;
+    for _ in 0..entries {
+        let entry = zip
+            .get(position..position + CENTRAL_DIRECTORY_ENTRY_LENGTH)
+            .filter(|entry| entry.starts_with(&CENTRAL_DIRECTORY_ENTRY))
+            .ok_or_else(|| anyhow::anyhow!("The ZIP archive's central directory is damaged"))?;
+        let local_header = u32::from_le_bytes(entry[42..46].try_into().unwrap());
+        result[position + 42..position + 46].copy_from_slice(&shifted(local_header)?.to_le_bytes());
+        position += CENTRAL_DIRECTORY_ENTRY_LENGTH
+            + read_u16(position + 28) as usize
+            + read_u16(position + 30) as usize
+            + read_u16(position + 32) as usize;
+    }
+    Ok(result)
+}
+
+/// Finds the embedded files in `data`, which starts at `start` in the PNG file. Each
+/// file's bytes are skipped once found, so the entries of a ZIP archive aren't reported
+/// as archives of their own.
+fn find_in(data: &[u8], start: usize, location: &str) -> Vec<EmbeddedFile> {
+    let mut found = Vec::new();
+    let mut position = 0;
+    while position < data.len() {
+        let rest = &data[position..];
+        let next = SIGNATURES
+            .iter()
+            .filter_map(|signature| {
+                rest.windows(signature.0.len())
+                    .position(|window| window == signature.0)
+                    .map(|index| (index, signature))
+            })
+            .min_by_key(|(index, _)| *index);
+        let (index, (_, kind, extension)) = match next {
+            Some(next) => next,
+            None => break,
+        };
+
+        let file = &rest[index..];
+        let length = file_length(extension, file);
+        found.push(EmbeddedFile {
+            offset: start + position + index,
+            kind,
+            extension,
+            location: location.to_string(),
+            bytes: file[..length].to_vec(),
+        });
+        position += index + length;
+    }
+    found
+}
+
+/// Returns where the file at the start of `bytes` ends, if its format says so, or the
+/// length of `bytes` otherwise
+fn file_length(extension: &str, bytes: &[u8]) -> usize {
+    let find = |needle: &[u8]| {
+        bytes
+            .windows(needle.len())
+            .position(|window| window == needle)
+    };
+    let end = match extension {
+        // The end of central directory record is 22 bytes and a comment
+        "zip" => find(b"PK\x05\x06").and_then(|index| {
+            let comment = bytes.get(index + 20..index + 22)?;
+            Some(index + 22 + u16::from_le_bytes([comment[0], comment[1]]) as usize)
+        }),
+        // Updated PDFs have an %%EOF for each revision, and the last one ends the file
+        "pdf" => bytes
+            .windows(5)
+            .rposition(|window| window == b"%%EOF")
+            .map(|index| index + 5),
+        _ => None,
+    };
+    end.map_or(bytes.len(), |end| end.min(bytes.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::{Chunk, ChunkType};
+    use std::io::{Read, Write};
+
+    fn zip_bytes() -> Vec<u8> {
+        let mut zip = b"PK\x03\x04 first entry PK\x03\x04 second entry".to_vec();
+        zip.extend_from_slice(b"PK\x05\x06");
+        zip.extend_from_slice(&[0; 16]);
+        zip.extend_from_slice(&[3, 0]);
+        zip.extend_from_slice(b"hi!");
+        zip
+    }
+
+    #[test]
+    fn test_find_in_trailer_and_chunks() {
+        let mut pdf = b"padding %PDF-1.4 objects %%EOF update %%EOF".to_vec();
+        pdf.resize(MIN_CHUNK_LENGTH, b' ');
+        let mut png = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::new(ChunkType::new(*b"ruSt"), pdf),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ]);
+        let mut trailer = b"junk".to_vec();
+        trailer.extend_from_slice(&zip_bytes());
+        trailer.extend_from_slice(b" after");
+        png.set_trailer(trailer);
+        let png = Png::from_bytes_preserving(&png.as_bytes()).unwrap();
+
+        let found = find(&png);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].kind, "PDF document");
+        assert_eq!(found[0].location, "a ruSt chunk");
+        assert_eq!(found[0].offset, 8 + 18 + 8 + 8);
+        assert_eq!(found[0].bytes, b"%PDF-1.4 objects %%EOF update %%EOF");
+
+        assert_eq!(found[1].kind, "ZIP archive");
+        assert_eq!(found[1].location, "the trailer");
+        assert_eq!(found[1].bytes, zip_bytes());
+        let bytes = png.as_bytes();
+        assert!(bytes[found[1].offset..].starts_with(&zip_bytes()));
+    }
+
+    #[test]
+    fn test_append_zip() {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .compression_method(zip::CompressionMethod::Stored);
+        for (name, contents) in [("a.txt", "first"), ("b.txt", "second")] {
+            zip.start_file(name, options).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        let zip = zip.finish().unwrap().into_inner();
+
+        let mut png = Png::new_minimal();
+        let image_length = png.as_bytes().len();
+        append_zip(&mut png, &zip).unwrap();
+        let bytes = png.as_bytes();
+        assert_eq!(Png::try_from(bytes.as_ref()).unwrap(), png);
+
+        // The central directory offset now points at the directory in the combined file
+        let directory = |bytes: &[u8]| {
+            let end = bytes.len() - END_OF_CENTRAL_DIRECTORY_LENGTH;
+            u32::from_le_bytes(bytes[end + 16..end + 20].try_into().unwrap()) as usize
+        };
+        assert_eq!(directory(&bytes), image_length + directory(&zip));
+        assert!(bytes[directory(&bytes)..].starts_with(&CENTRAL_DIRECTORY_ENTRY));
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let mut contents = String::new();
+        archive
+            .by_name("b.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "second");
+
+        assert!(append_zip(&mut png, &zip).is_err());
+        assert!(shift_zip(b"not a zip", 10).is_err());
+    }
+
+    #[test]
+    fn test_small_and_registered_chunks_are_skipped() {
+        let png = Png::from_chunks(vec![
+            Chunk::from_strings("ruSt", "PK\x03\x04 too small").unwrap(),
+            Chunk::new(ChunkType::new(*b"tEXt"), zip_bytes().repeat(4)),
+        ]);
+        assert!(find(&png).is_empty());
+    }
+}
@@ -0,0 +1,74 @@
+use std::sync::{Mutex, OnceLock};
+
+use pngme::output::{self, OutputFormat, Record};
+
+/// The --format everything is printed in, set once when the command line is parsed
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Records kept to be printed together by `finish`, since JSON, CSV and YAML can't be
+/// written a line at a time
+static RECORDS: Mutex<Vec<Record>> = Mutex::new(Vec::new());
+
+/// Prints a line of output like `println!`, or keeps it as a record with a `message`
+/// field when the output is structured. Commands print through this so that every one
+/// of them follows --format without handling it.
+macro_rules! outln {
+    () => {
+        $crate::printer::line(String::new())
+    };
+    ($($arg:tt)*) => {
+        $crate::printer::line(format!($($arg)*))
+    };
+}
+
+pub fn set_format(format: OutputFormat) {
+    let _ = FORMAT.set(format);
+}
+
+pub fn format() -> OutputFormat {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// Prints `text` in text output, or keeps it as a message record. Blank lines only
+/// space out text output, so they aren't kept.
+pub fn line(text: String) {
+    match format() {
+        OutputFormat::Text => println!("{}", text),
+        _ if text.is_empty() => {}
+        _ => push(Record::message(&text)),
+    }
+}
+
+/// Prints `text` only in text output, for lines that label the lines after them, like
+/// column headings
+pub fn heading(text: String) {
+    if format() == OutputFormat::Text {
+        println!("{}", text);
+    }
+}
+
+/// Prints `text` in text output, or keeps `record`, which has the same information as
+/// separate fields
+pub fn record(text: String, record: Record) {
+    match format() {
+        OutputFormat::Text => println!("{}", text),
+        _ => push(record),
+    }
+}
+
+/// Prints the records kept for structured output
+pub fn finish() {
+    let format = format();
+    if format == OutputFormat::Text {
+        return;
+    }
+    let records = std::mem::take(&mut *RECORDS.lock().unwrap_or_else(|e| e.into_inner()));
+    print!("{}", output::render(format, &records));
+}
+
+fn push(record: Record) {
+    RECORDS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(record);
+}
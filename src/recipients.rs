@@ -0,0 +1,208 @@
+use std::convert::TryInto;
+
+use hkdf::Hkdf;
+use rand::RngCore;
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::crypto;
+
+/// Marks the start of a payload encrypted to one or more X25519 public keys
+pub const MAGIC: [u8; 4] = *b"PMER";
+
+const VERSION: u8 = 1;
+const NONCE_LENGTH: usize = 12;
+const WRAPPED_KEY_LENGTH: usize = 32 + 16;
+const WRAP_INFO: &[u8] = b"pngme recipient key wrap v1";
+
+/// An X25519 key pair used to receive multi-recipient payloads
+pub struct KeyPair {
+    pub private_key: [u8; 32],
+    pub public_key: [u8; 32],
+}
+
+impl KeyPair {
+    /// Generates a new random key pair
+    pub fn generate() -> Self {
+        let secret = StaticSecret::random_from_rng(rand::thread_rng());
+        Self::from_private_key(secret.to_bytes())
+    }
+
+    /// Recomputes the public half of an existing private key
+    pub fn from_private_key(private_key: [u8; 32]) -> Self {
+        let public_key = PublicKey::from(&StaticSecret::from(private_key));
+        Self {
+            private_key,
+            public_key: public_key.to_bytes(),
+        }
+    }
+}
+
+/// Returns true if `data` is a multi-recipient payload
+pub fn is_multi_recipient(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Encrypts `plaintext` so that it can be decrypted by the private key matching any of
+/// the given public keys. A random content key encrypts the message once and is then
+/// wrapped separately for every recipient.
+///
+/// The result is laid out as follows
+/// 1. Magic bytes `PMER` *(4 bytes)*
+/// 2. Format version *(1 byte)*
+/// 3. Ephemeral X25519 public key *(32 bytes)*
+/// 4. Number of recipients *(2 bytes)*
+/// 5. The wrapped content key for each recipient *(48 bytes each)*
+/// 6. Nonce *(12 bytes)*
+/// 7. ChaCha20-Poly1305 ciphertext and tag
+///
+/// The whole header is authenticated along with the ciphertext.
+pub fn encrypt(plaintext: &[u8], public_keys: &[[u8; 32]]) -> anyhow::Result<Vec<u8>> {
+    if public_keys.is_empty() {
+        anyhow::bail!("At least one recipient is required");
+    }
+    if public_keys.len() > u16::MAX as usize {
+        anyhow::bail!("Too many recipients");
+    }
+
+    let mut content_key = [0; 32];
+    let mut nonce = [0; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut content_key);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let ephemeral = StaticSecret::random_from_rng(rand::thread_rng());
+    let ephemeral_public = PublicKey::from(&ephemeral).to_bytes();
+
+    let mut result = Vec::with_capacity(
+        MAGIC.len() + 35 + public_keys.len() * WRAPPED_KEY_LENGTH + NONCE_LENGTH + plaintext.len(),
+    );
+    result.extend_from_slice(&MAGIC);
+    result.push(VERSION);
+    result.extend_from_slice(&ephemeral_public);
+    result.extend_from_slice(&(public_keys.len() as u16).to_be_bytes());
+
+    for public_key in public_keys {
+        let shared = ephemeral.diffie_hellman(&PublicKey::from(*public_key));
+        let wrapping_key = wrapping_key(shared.as_bytes(), &ephemeral_public, public_key);
+        let wrapped = crypto::seal(&wrapping_key, &[0; NONCE_LENGTH], &[], &content_key)?;
+        result.extend(wrapped);
+    }
+
+    result.extend_from_slice(&nonce);
+    let ciphertext = crypto::seal(&content_key, &nonce, &result, plaintext)?;
+    result.extend(ciphertext);
+
+    Ok(result)
+}
+
+/// Decrypts a payload created by `encrypt` with one recipient's private key
+pub fn decrypt(data: &[u8], private_key: &[u8; 32]) -> anyhow::Result<Vec<u8>> {
+    if !is_multi_recipient(data) || data.len() < MAGIC.len() + 35 {
+        anyhow::bail!("Data is not a multi-recipient payload");
+    }
+    if data[4] != VERSION {
+        anyhow::bail!("Unsupported multi-recipient format version {}", data[4]);
+    }
+
+    let ephemeral_public: [u8; 32] = data[5..37].try_into().unwrap();
+    let recipient_count = u16::from_be_bytes([data[37], data[38]]) as usize;
+
+    let wrapped_start = 39;
+    let nonce_start = wrapped_start + recipient_count * WRAPPED_KEY_LENGTH;
+    let header_length = nonce_start + NONCE_LENGTH;
+    if data.len() < header_length {
+        anyhow::bail!("Multi-recipient payload is truncated");
+    }
+
+    let key_pair = KeyPair::from_private_key(*private_key);
+    let shared =
+        StaticSecret::from(*private_key).diffie_hellman(&PublicKey::from(ephemeral_public));
+    let wrapping_key = wrapping_key(shared.as_bytes(), &ephemeral_public, &key_pair.public_key);
+
+    let content_key = data[wrapped_start..nonce_start]
+        .chunks(WRAPPED_KEY_LENGTH)
+        .find_map(|wrapped| crypto::open(&wrapping_key, &[0; NONCE_LENGTH], &[], wrapped).ok())
+        .ok_or_else(|| anyhow::anyhow!("This key is not one of the payload's recipients"))?;
+    let content_key: [u8; 32] = content_key.as_slice().try_into()?;
+
+    let nonce = &data[nonce_start..header_length];
+    let (header, ciphertext) = data.split_at(header_length);
+
+    crypto::open(&content_key, nonce, header, ciphertext)
+        .map_err(|_| anyhow::anyhow!("Decryption failed: the payload is corrupted"))
+}
+
+/// Derives the key which wraps the content key for a single recipient
+fn wrapping_key(
+    shared_secret: &[u8],
+    ephemeral_public: &[u8; 32],
+    public_key: &[u8; 32],
+) -> [u8; 32] {
+    let salt: Vec<u8> = ephemeral_public
+        .iter()
+        .chain(public_key.iter())
+        .copied()
+        .collect();
+    let hkdf = Hkdf::<Sha256>::new(Some(&salt), shared_secret);
+
+    let mut key = [0; 32];
+    hkdf.expand(WRAP_INFO, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_recipient_can_decrypt() {
+        let alice = KeyPair::generate();
+        let bob = KeyPair::generate();
+
+        let encrypted = encrypt(
+            b"This is a secret message!",
+            &[alice.public_key, bob.public_key],
+        )
+        .unwrap();
+        assert!(is_multi_recipient(&encrypted));
+
+        for key_pair in &[alice, bob] {
+            let decrypted = decrypt(&encrypted, &key_pair.private_key).unwrap();
+            assert_eq!(decrypted, b"This is a secret message!");
+        }
+    }
+
+    #[test]
+    fn test_non_recipient_cannot_decrypt() {
+        let alice = KeyPair::generate();
+        let eve = KeyPair::generate();
+
+        let encrypted = encrypt(b"This is a secret message!", &[alice.public_key]).unwrap();
+        assert!(decrypt(&encrypted, &eve.private_key).is_err());
+    }
+
+    #[test]
+    fn test_tampered_recipient_list() {
+        let alice = KeyPair::generate();
+        let bob = KeyPair::generate();
+
+        let mut encrypted = encrypt(b"message", &[alice.public_key, bob.public_key]).unwrap();
+        // Corrupt Bob's wrapped key. Alice can still unwrap hers, but the header no
+        // longer authenticates.
+        encrypted[39 + WRAPPED_KEY_LENGTH] ^= 1;
+        assert!(decrypt(&encrypted, &alice.private_key).is_err());
+    }
+
+    #[test]
+    fn test_no_recipients() {
+        assert!(encrypt(b"message", &[]).is_err());
+    }
+
+    #[test]
+    fn test_key_pair_from_private_key() {
+        let key_pair = KeyPair::generate();
+        let restored = KeyPair::from_private_key(key_pair.private_key);
+        assert_eq!(key_pair.public_key, restored.public_key);
+    }
+}
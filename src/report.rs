@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+use std::convert::{TryFrom, TryInto};
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::anonymize;
+use crate::optimize;
+use crate::png::{ChunkType, Png};
+
+/// Bytes used by chunks of one type
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkUsage {
+    pub chunk_type: ChunkType,
+    pub count: usize,
+    pub bytes: usize,
+}
+
+/// Where a PNG file spends bytes that don't contribute to the displayed image. Each byte
+/// is counted in at most one category, so the categories can be added up.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WasteReport {
+    pub file_size: usize,
+    /// Ancillary chunks that don't change how the image is displayed, including messages
+    pub non_essential: Vec<ChunkUsage>,
+    /// Bytes past the end of standard chunks with a fixed size
+    pub padding: Vec<ChunkUsage>,
+    /// Repeated copies of the remaining ancillary chunks
+    pub duplicates: Vec<ChunkUsage>,
+    /// Chunk headers and CRCs of IDAT chunks beyond the first
+    pub idat_overhead: usize,
+    /// How much smaller the IDAT data gets when recompressed at the best zlib level
+    pub recompression: usize,
+    /// Bytes after the last chunk
+    pub trailing: usize,
+}
+
+impl WasteReport {
+    /// The total number of bytes that could be reclaimed
+    pub fn total(&self) -> usize {
+        let usage = |list: &[ChunkUsage]| list.iter().map(|usage| usage.bytes).sum::<usize>();
+        usage(&self.non_essential)
+            + usage(&self.padding)
+            + usage(&self.duplicates)
+            + self.idat_overhead
+            + self.recompression
+            + self.trailing
+    }
+}
+
+/// Works out where the PNG file in `bytes` wastes space
+pub fn waste_report(bytes: &[u8]) -> anyhow::Result<WasteReport> {
+    let end = chunks_end(bytes);
+    let png = Png::try_from(&bytes[..end])?;
+
+    let mut non_essential = BTreeMap::new();
+    let mut essential = Vec::new();
+    for chunk in png.chunks() {
+        if optimize::is_strippable(chunk.chunk_type()) {
+            add_usage(
+                &mut non_essential,
+                *chunk.chunk_type(),
+                chunk.encoded_length(),
+            );
+        } else {
+            essential.push(chunk.clone());
+        }
+    }
+
+    let mut essential = Png::from_chunks(essential);
+    let mut duplicates = BTreeMap::new();
+    for chunk in essential.dedupe_chunks() {
+        add_usage(&mut duplicates, *chunk.chunk_type(), chunk.encoded_length());
+    }
+
+    let mut padding = BTreeMap::new();
+    for chunk in essential.chunks() {
+        let length = chunk.data().len();
+        match anonymize::allowed_length(&chunk.chunk_type().to_string(), length) {
+            Some(allowed) if allowed < length => {
+                add_usage(&mut padding, *chunk.chunk_type(), length - allowed)
+            }
+            _ => {}
+        }
+    }
+
+    let idat: Vec<&[u8]> = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+        .map(|chunk| chunk.data())
+        .collect();
+    let compressed = idat.concat();
+
+    Ok(WasteReport {
+        file_size: bytes.len(),
+        non_essential: into_usage(non_essential),
+        padding: into_usage(padding),
+        duplicates: into_usage(duplicates),
+        idat_overhead: idat.len().saturating_sub(1) * 12,
+        recompression: compressed
+            .len()
+            .saturating_sub(best_zlib_length(&compressed)?),
+        trailing: bytes.len() - end,
+    })
+}
+
+/// Returns the offset just past the last well-formed chunk. Messages are often stored
+/// after IEND, so only bytes that don't look like chunks count as trailing data.
+fn chunks_end(bytes: &[u8]) -> usize {
+    let mut offset = Png::SIGNATURE.len();
+    loop {
+        let next = bytes.get(offset..offset + 8).and_then(|fields| {
+            let length = u32::from_be_bytes(fields[..4].try_into().ok()?) as usize;
+            ChunkType::try_from(<[u8; 4]>::try_from(&fields[4..]).ok()?).ok()?;
+            Some(offset + 12 + length).filter(|&end| end <= bytes.len())
+        });
+        match next {
+            Some(end) => offset = end,
+            None => return offset.min(bytes.len()),
+        }
+    }
+}
+
+/// The length of `compressed` after decompressing it and compressing it again at the
+/// best level. Empty or undecodable data is left as it is.
+fn best_zlib_length(compressed: &[u8]) -> anyhow::Result<usize> {
+    let mut raw = Vec::new();
+    if compressed.is_empty() || ZlibDecoder::new(compressed).read_to_end(&mut raw).is_err() {
+        return Ok(compressed.len());
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(&raw)?;
+    Ok(encoder.finish()?.len())
+}
+
+fn add_usage(usage: &mut BTreeMap<ChunkType, (usize, usize)>, chunk_type: ChunkType, bytes: usize) {
+    let entry = usage.entry(chunk_type).or_insert((0, 0));
+    entry.0 += 1;
+    entry.1 += bytes;
+}
+
+fn into_usage(usage: BTreeMap<ChunkType, (usize, usize)>) -> Vec<ChunkUsage> {
+    usage
+        .into_iter()
+        .map(|(chunk_type, (count, bytes))| ChunkUsage {
+            chunk_type,
+            count,
+            bytes,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Chunk;
+
+    fn testing_bytes() -> Vec<u8> {
+        let comment = Chunk::from_strings("tEXt", "Comment\0hello").unwrap();
+        let gamma = Chunk::from_strings("gAMA", "gamma").unwrap();
+        let mut bytes = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            gamma.clone(),
+            gamma,
+            comment.clone(),
+            comment,
+            Chunk::from_strings("IDAT", "").unwrap(),
+            Chunk::from_strings("IDAT", "").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+            Chunk::from_strings("ruSt", "message").unwrap(),
+        ])
+        .as_bytes();
+        bytes.extend_from_slice(b"garbage");
+        bytes
+    }
+
+    #[test]
+    fn test_waste_report() {
+        let report = waste_report(&testing_bytes()).unwrap();
+
+        assert_eq!(report.non_essential.len(), 2);
+        assert_eq!(report.non_essential[0].chunk_type.to_string(), "ruSt");
+        assert_eq!(report.non_essential[0].bytes, 12 + 7);
+        assert_eq!(report.non_essential[1].count, 2);
+        assert_eq!(report.non_essential[1].bytes, 2 * (12 + 13));
+
+        // gAMA holds 4 bytes, so the copy that's kept has one byte of padding
+        assert_eq!(report.padding[0].bytes, 1);
+        assert_eq!(report.duplicates[0].chunk_type.to_string(), "gAMA");
+        assert_eq!(report.duplicates[0].bytes, 12 + 5);
+
+        assert_eq!(report.idat_overhead, 12);
+        assert_eq!(report.recompression, 0);
+        assert_eq!(report.trailing, 7);
+        assert_eq!(report.total(), 19 + 50 + 1 + 17 + 12 + 7);
+    }
+
+    #[test]
+    fn test_clean_file() {
+        let bytes = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+        .as_bytes();
+
+        let report = waste_report(&bytes).unwrap();
+        assert_eq!(report.total(), 0);
+        assert_eq!(report.file_size, bytes.len());
+    }
+}
@@ -0,0 +1,473 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use base64::Engine;
+
+use crate::archive;
+use crate::audit::AUDIT_CHUNK_TYPE;
+use crate::crypto;
+use crate::deniable;
+use crate::envelope;
+use crate::history::HISTORY_CHUNK_TYPE;
+use crate::messages::MessageStore;
+use crate::multipart::{Manifest, MANIFEST_CHUNK_TYPE};
+use crate::obfuscate;
+use crate::png::{Chunk, ChunkType, Png};
+use crate::polyglot;
+use crate::recipients;
+use crate::seal::SEAL_CHUNK_TYPE;
+use crate::signature::SIGNATURE_CHUNK_TYPE;
+use crate::text::{InternationalTextChunk, TextChunk};
+use crate::trailer;
+
+/// Chunks shorter than this don't have enough bytes for the statistics to mean anything
+const MIN_STATISTICS_LENGTH: usize = 64;
+
+/// Entropy, relative to the most that data of its length can have, above which data is
+/// taken to be compressed or encrypted
+const HIGH_ENTROPY: f64 = 0.9;
+
+/// The chi-square value for 255 degrees of freedom that uniformly random bytes stay
+/// under 99.9% of the time. Compressed data is high entropy too, but its byte counts are
+/// far less even, so it lands well above this.
+const RANDOM_CHI_SQUARE: f64 = 330.5;
+
+/// Chunk types whose data is normally compressed, so high entropy is expected
+const COMPRESSED_CHUNK_TYPES: [&str; 5] = ["IDAT", "fdAT", "iCCP", "zTXt", "iTXt"];
+
+/// Chunk types used in pngme's documentation and examples, which are the ones people
+/// trying it out most often leave behind
+const EXAMPLE_CHUNK_TYPES: [&str; 2] = ["ruSt", "RuSt"];
+
+/// Statistics about how the byte values in some data are distributed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteStatistics {
+    pub length: usize,
+    /// Shannon entropy in bits per byte, from 0 to 8
+    pub entropy: f64,
+    /// Pearson's chi-square statistic of the byte counts against a uniform distribution
+    pub chi_square: f64,
+}
+
+/// What the byte statistics of some data suggest it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Assessment {
+    /// Too short to tell
+    Short,
+    /// Structured data such as text
+    Plain,
+    /// High entropy with uneven byte counts, like deflate output
+    Compressed,
+    /// Indistinguishable from random bytes, like encrypted data
+    Random,
+}
+
+impl fmt::Display for Assessment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Assessment::Short => write!(f, "too short to tell"),
+            Assessment::Plain => write!(f, "plain"),
+            Assessment::Compressed => write!(f, "compressed"),
+            Assessment::Random => write!(f, "random, like encrypted data"),
+        }
+    }
+}
+
+impl ByteStatistics {
+    pub fn of(data: &[u8]) -> Self {
+        let mut counts = [0usize; 256];
+        for &byte in data {
+            counts[byte as usize] += 1;
+        }
+
+        let length = data.len() as f64;
+        let expected = length / 256.0;
+        let mut entropy = 0.0;
+        let mut chi_square = 0.0;
+        for &count in counts.iter() {
+            if count > 0 {
+                let p = count as f64 / length;
+                entropy -= p * p.log2();
+            }
+            chi_square += (count as f64 - expected).powi(2) / expected;
+        }
+
+        Self {
+            length: data.len(),
+            entropy,
+            chi_square: if data.is_empty() { 0.0 } else { chi_square },
+        }
+    }
+
+    pub fn assess(&self) -> Assessment {
+        if self.length < MIN_STATISTICS_LENGTH {
+            return Assessment::Short;
+        }
+
+        // Data shorter than 256 bytes can't use every byte value, which caps its entropy
+        let max_entropy = (self.length as f64).log2().min(8.0);
+        if self.entropy < HIGH_ENTROPY * max_entropy {
+            Assessment::Plain
+        } else if self.length >= 256 && self.chi_square > RANDOM_CHI_SQUARE {
+            Assessment::Compressed
+        } else {
+            Assessment::Random
+        }
+    }
+}
+
+/// Returns true if high entropy data in a chunk of this type is a sign of a hidden
+/// payload rather than normal compression. Short deflate streams often pass the
+/// chi-square test, so high entropy in chunk types that are normally compressed isn't
+/// flagged.
+pub fn is_suspicious(chunk_type: &ChunkType, assessment: Assessment) -> bool {
+    let expected = COMPRESSED_CHUNK_TYPES.contains(&chunk_type.to_string().as_str());
+    match assessment {
+        Assessment::Random | Assessment::Compressed => !expected,
+        Assessment::Short | Assessment::Plain => false,
+    }
+}
+
+/// Something in a file that could be hiding data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// Offset from the start of the file
+    pub offset: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.offset, self.message)
+    }
+}
+
+/// Lists the places where data is commonly hidden: chunk types the PNG spec doesn't
+/// define, chunks after IEND, bytes after the last chunk and files of other formats
+/// embedded in either, which make the image a polyglot
+pub fn findings(png: &Png) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut after_iend = false;
+    for chunk in png.chunks() {
+        let offset = chunk.offset().unwrap_or_default();
+        let chunk_type = chunk.chunk_type();
+        if after_iend {
+            findings.push(Finding {
+                offset,
+                message: format!("{} chunk after IEND", chunk_type),
+            });
+        } else if !chunk_type.is_registered() {
+            findings.push(Finding {
+                offset,
+                message: format!(
+                    "Unknown {} chunk type {} holding {} bytes",
+                    if chunk_type.is_public() {
+                        "public"
+                    } else {
+                        "private"
+                    },
+                    chunk_type,
+                    chunk.data().len()
+                ),
+            });
+        }
+        after_iend |= chunk_type.to_string() == "IEND";
+    }
+
+    if !png.trailer().is_empty() {
+        let end = png.as_bytes().len() - png.trailer().len();
+        let position = match after_iend {
+            true => "IEND",
+            false => "the last chunk",
+        };
+        findings.push(Finding {
+            offset: end,
+            message: format!(
+                "{} bytes of trailing data after {}, which look like {}",
+                png.trailer().len(),
+                position,
+                trailer::describe(png.trailer())
+            ),
+        });
+    }
+
+    for embedded in polyglot::find(png) {
+        findings.push(Finding {
+            offset: embedded.offset,
+            message: format!(
+                "{} of {} bytes embedded in {}",
+                embedded.kind,
+                embedded.bytes.len(),
+                embedded.location
+            ),
+        });
+    }
+
+    findings
+}
+
+/// Lists what pngme itself has left in a file: messages in the formats it writes, in
+/// any chunk, after IEND or camouflaged as text, the manifests of split messages, its
+/// own records, and chunk types from its examples. Messages stored without encryption
+/// or an envelope under other chunk types look like any other private chunk, so they
+/// are only found by `findings`.
+pub fn pngme_findings(png: &Png) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let mut split_types = Vec::new();
+    for chunk in png.chunks() {
+        if chunk.chunk_type().to_string() == MANIFEST_CHUNK_TYPE {
+            if let Ok(manifest) = Manifest::try_from(chunk.data()) {
+                split_types.push(manifest.chunk_type);
+            }
+        }
+    }
+
+    for chunk in png.chunks() {
+        let offset = chunk.offset().unwrap_or_default();
+        let chunk_type = chunk.chunk_type();
+        let name = chunk_type.to_string();
+        let message = match name.as_str() {
+            AUDIT_CHUNK_TYPE => Some("pngme audit trail".to_string()),
+            HISTORY_CHUNK_TYPE => Some("pngme edit history".to_string()),
+            SEAL_CHUNK_TYPE => Some("pngme seal".to_string()),
+            SIGNATURE_CHUNK_TYPE => Some("pngme signature".to_string()),
+            MANIFEST_CHUNK_TYPE => Manifest::try_from(chunk.data()).ok().map(|manifest| {
+                let present = png
+                    .chunks()
+                    .iter()
+                    .filter(|part| part.chunk_type() == &manifest.chunk_type)
+                    .count();
+                format!(
+                    "pngme manifest of a message split into {} {} chunks, {} of them present",
+                    manifest.parts.len(),
+                    manifest.chunk_type,
+                    present
+                )
+            }),
+            _ => match message_kind(chunk.data()) {
+                Some(kind) => Some(format!(
+                    "pngme {} of {} bytes in a {} chunk",
+                    kind,
+                    chunk.data().len(),
+                    chunk_type
+                )),
+                None if EXAMPLE_CHUNK_TYPES.contains(&name.as_str())
+                    && !split_types.contains(chunk_type) =>
+                {
+                    Some(format!(
+                        "{} chunk holding {} bytes, a type pngme's examples use",
+                        chunk_type,
+                        chunk.data().len()
+                    ))
+                }
+                None => camouflaged(chunk),
+            },
+        };
+        if let Some(message) = message {
+            findings.push(Finding { offset, message });
+        }
+    }
+
+    let stored = trailer::messages(png.trailer());
+    if !stored.is_empty() {
+        let types: Vec<String> = stored
+            .iter()
+            .map(|(chunk_type, _)| chunk_type.to_string())
+            .collect();
+        findings.push(Finding {
+            offset: png.as_bytes().len() - png.trailer().len(),
+            message: format!(
+                "{} pngme {} stored after IEND under {}",
+                stored.len(),
+                if stored.len() == 1 {
+                    "message"
+                } else {
+                    "messages"
+                },
+                types.join(", ")
+            ),
+        });
+    }
+
+    findings
+}
+
+/// Names the kind of message `data` is, if it has one of the headers pngme writes.
+/// Archives and age files are only taken to be pngme's inside its envelope, since other
+/// tools write them too.
+fn message_kind(data: &[u8]) -> Option<&'static str> {
+    let enveloped = envelope::is_envelope(data);
+    // An envelope from a newer version can't be unwrapped, but is still pngme's
+    let payload = envelope::unwrap(data).unwrap_or_default();
+    let kind = if recipients::is_multi_recipient(payload) {
+        "message encrypted for several recipients"
+    } else if deniable::is_deniable(payload) {
+        "deniable encrypted message"
+    } else if crypto::is_encrypted(payload) {
+        "encrypted message"
+    } else if obfuscate::is_obfuscated(payload) {
+        "obfuscated message"
+    } else if MessageStore::is_message_store(payload) {
+        "store of named messages"
+    } else if enveloped && crypto::is_age_encrypted(payload) {
+        "age encrypted message"
+    } else if enveloped && archive::is_archive(payload) {
+        "directory archive"
+    } else if enveloped {
+        "message"
+    } else {
+        return None;
+    };
+    Some(kind)
+}
+
+/// Describes the message hidden in a text chunk by `encode --camouflage`, which stores
+/// binary messages in base64
+fn camouflaged(chunk: &Chunk) -> Option<String> {
+    let (keyword, text) = match chunk.chunk_type().to_string().as_str() {
+        "tEXt" => TextChunk::try_from(chunk)
+            .ok()
+            .map(|text| (text.keyword().to_string(), text.text().to_string()))?,
+        "iTXt" => InternationalTextChunk::try_from(chunk)
+            .ok()
+            .map(|text| (text.keyword().to_string(), text.text().to_string()))?,
+        _ => return None,
+    };
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(text.trim())
+        .ok()?;
+    message_kind(&decoded).map(|kind| {
+        format!(
+            "pngme {} of {} bytes camouflaged as {} text with the keyword {:?}",
+            kind,
+            decoded.len(),
+            chunk.chunk_type(),
+            keyword
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Chunk;
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use sha2::{Digest, Sha256};
+
+    #[test]
+    fn test_statistics() {
+        let stats = ByteStatistics::of(&[7; 1000]);
+        assert_eq!(stats.entropy, 0.0);
+        assert_eq!(stats.assess(), Assessment::Plain);
+
+        let every_byte: Vec<u8> = (0..=255).collect();
+        let stats = ByteStatistics::of(&every_byte);
+        assert!((stats.entropy - 8.0).abs() < 1e-9);
+        assert_eq!(stats.chi_square, 0.0);
+
+        assert_eq!(ByteStatistics::of(b"short").assess(), Assessment::Short);
+        assert_eq!(ByteStatistics::of(&[]).chi_square, 0.0);
+    }
+
+    #[test]
+    fn test_assess() {
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(40);
+        assert_eq!(
+            ByteStatistics::of(text.as_bytes()).assess(),
+            Assessment::Plain
+        );
+
+        // Hash output stands in for encrypted data without making the test random
+        let random: Vec<u8> = (0..128u32)
+            .flat_map(|i| Sha256::digest(i.to_be_bytes()))
+            .collect();
+        assert_eq!(ByteStatistics::of(&random).assess(), Assessment::Random);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        for i in 0..2000u32 {
+            write!(encoder, "{} ", i * i).unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(
+            ByteStatistics::of(&compressed).assess(),
+            Assessment::Compressed
+        );
+
+        let idat = ChunkType::new(*b"IDAT");
+        let private = ChunkType::new(*b"ruSt");
+        assert!(!is_suspicious(&idat, Assessment::Compressed));
+        assert!(is_suspicious(&private, Assessment::Compressed));
+        assert!(!is_suspicious(&idat, Assessment::Random));
+        assert!(is_suspicious(&private, Assessment::Random));
+        assert!(!is_suspicious(&private, Assessment::Plain));
+    }
+
+    #[test]
+    fn test_findings() {
+        let mut bytes = Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("ruSt", "hidden").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+            Chunk::from_strings("tEXt", "Comment\0late").unwrap(),
+        ])
+        .as_bytes();
+        bytes.extend_from_slice(b"garbage%PDF-1.7 %%EOF");
+
+        let png = Png::from_bytes_preserving(&bytes).unwrap();
+        let found: Vec<String> = findings(&png).iter().map(Finding::to_string).collect();
+        assert_eq!(
+            found,
+            vec![
+                "26: Unknown private chunk type ruSt holding 6 bytes",
+                "74: tEXt chunk after IEND",
+                "98: 21 bytes of trailing data after IEND, which look like text",
+                "105: PDF document of 14 bytes embedded in the trailer",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pngme_findings() {
+        let (manifest, parts) =
+            Manifest::split(ChunkType::new(*b"spLt"), b"split message", 8).unwrap();
+        let mut chunks = vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::new(ChunkType::new(*b"seCr"), envelope::wrap(b"hidden")),
+            Chunk::from_strings("ruSt", "old message").unwrap(),
+            Chunk::from_strings("prVt", "someone else's").unwrap(),
+            TextChunk::new("Comment", "UE1OUwAAAAA=")
+                .unwrap()
+                .to_chunk(),
+            Chunk::new(ChunkType::new(*b"pnMf"), manifest.as_bytes()),
+        ];
+        chunks.extend(
+            parts
+                .into_iter()
+                .skip(1)
+                .map(|part| Chunk::new(ChunkType::new(*b"spLt"), part)),
+        );
+        chunks.push(Chunk::from_strings("IEND", "").unwrap());
+        let mut png = Png::from_chunks(chunks);
+        png.set_trailer(trailer::wrap(&ChunkType::new(*b"taIl"), b"after"));
+
+        let found: Vec<String> = pngme_findings(&png)
+            .iter()
+            .map(|finding| finding.message.clone())
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                "pngme message of 11 bytes in a seCr chunk",
+                "ruSt chunk holding 11 bytes, a type pngme's examples use",
+                "pngme store of named messages of 8 bytes camouflaged as tEXt text with the \
+                 keyword \"Comment\"",
+                "pngme manifest of a message split into 4 spLt chunks, 3 of them present",
+                "1 pngme message stored after IEND under taIl",
+            ]
+        );
+    }
+}
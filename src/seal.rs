@@ -0,0 +1,129 @@
+use std::convert::{TryFrom, TryInto};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::png::{Chunk, ChunkType, Png};
+use crate::signature;
+
+/// The chunk type used to store seals. Like signatures, seals are marked unsafe to copy
+/// because they describe the critical chunks.
+pub const SEAL_CHUNK_TYPE: &str = "pnSL";
+
+const VERSION: u8 = 1;
+const SEAL_DATA_LENGTH: usize = 1 + 8 + 32;
+
+/// A SHA-256 digest of the critical chunks of a PNG, stored in the PNG so that later
+/// changes to the image can be detected. Unlike a signature, a seal doesn't prove who
+/// made it, since anyone can recompute it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seal {
+    /// Seconds since the UNIX epoch when the seal was made
+    pub timestamp: u64,
+    digest: [u8; 32],
+}
+
+impl Seal {
+    /// Seals the current critical chunks of `png`
+    pub fn new(png: &Png) -> anyhow::Result<Self> {
+        Ok(Self {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+            digest: signature::critical_hash(png),
+        })
+    }
+
+    /// Returns the most recent seal in `png`, if there is one
+    pub fn from_png(png: &Png) -> anyhow::Result<Option<Self>> {
+        png.chunks()
+            .iter()
+            .rev()
+            .find(|chunk| chunk.chunk_type().to_string() == SEAL_CHUNK_TYPE)
+            .map(Self::try_from)
+            .transpose()
+    }
+
+    /// Returns true if the critical chunks of `png` are the ones that were sealed
+    pub fn matches(&self, png: &Png) -> bool {
+        signature::critical_hash(png) == self.digest
+    }
+
+    /// Returns this seal as a chunk.
+    /// The data is laid out as follows
+    /// 1. Format version *(1 byte)*
+    /// 2. Timestamp in seconds since the UNIX epoch *(8 bytes)*
+    /// 3. SHA-256 of the critical chunks *(32 bytes)*
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data = Vec::with_capacity(SEAL_DATA_LENGTH);
+        data.push(VERSION);
+        data.extend_from_slice(&self.timestamp.to_be_bytes());
+        data.extend_from_slice(&self.digest);
+
+        Chunk::new(ChunkType::new(*b"pnSL"), data)
+    }
+}
+
+impl TryFrom<&Chunk> for Seal {
+    type Error = anyhow::Error;
+
+    fn try_from(chunk: &Chunk) -> anyhow::Result<Self> {
+        let data = chunk.data();
+        if data.len() != SEAL_DATA_LENGTH {
+            anyhow::bail!("Invalid seal length {}", data.len());
+        }
+        if data[0] != VERSION {
+            anyhow::bail!("Unsupported seal version {}", data[0]);
+        }
+
+        Ok(Self {
+            timestamp: u64::from_be_bytes(data[1..9].try_into()?),
+            digest: data[9..].try_into()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_seal_round_trip() {
+        let mut png = testing_png();
+        let seal = Seal::new(&png).unwrap();
+        png.insert_chunk(seal.to_chunk());
+
+        let found = Seal::from_png(&png).unwrap().unwrap();
+        assert_eq!(found, seal);
+        assert!(found.matches(&png));
+    }
+
+    #[test]
+    fn test_metadata_changes_keep_seal() {
+        let mut png = testing_png();
+        png.insert_chunk(Seal::new(&png).unwrap().to_chunk());
+        png.insert_chunk(Chunk::from_strings("tEXt", "Comment\0Added later").unwrap());
+
+        assert!(Seal::from_png(&png).unwrap().unwrap().matches(&png));
+    }
+
+    #[test]
+    fn test_pixel_changes_break_seal() {
+        let mut png = testing_png();
+        png.insert_chunk(Seal::new(&png).unwrap().to_chunk());
+        png.remove_chunk("IDAT").unwrap();
+        png.insert_chunk_after("IHDR", Chunk::from_strings("IDAT", "pixelz").unwrap())
+            .unwrap();
+
+        assert!(!Seal::from_png(&png).unwrap().unwrap().matches(&png));
+    }
+
+    #[test]
+    fn test_no_seal() {
+        assert_eq!(Seal::from_png(&testing_png()).unwrap(), None);
+    }
+}
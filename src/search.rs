@@ -0,0 +1,356 @@
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use flate2::read::ZlibDecoder;
+use regex::bytes::Regex;
+
+use crate::png::{Chunk, ChunkType, Png};
+use crate::zip_path;
+
+/// What to look for in chunk data
+#[derive(Debug)]
+pub enum Pattern {
+    Literal(Vec<u8>),
+    Regex(Regex),
+}
+
+impl Pattern {
+    /// Creates a pattern that matches `pattern` literally, or as a regular expression
+    /// if `regex` is set
+    pub fn new(pattern: &str, regex: bool) -> anyhow::Result<Self> {
+        if pattern.is_empty() {
+            anyhow::bail!("The search pattern must not be empty");
+        }
+
+        if regex {
+            Ok(Pattern::Regex(Regex::new(pattern)?))
+        } else {
+            Ok(Pattern::Literal(pattern.as_bytes().to_vec()))
+        }
+    }
+
+    /// Returns the start and end of every non-overlapping match in `haystack`
+    pub fn find_all(&self, haystack: &[u8]) -> Vec<(usize, usize)> {
+        match self {
+            Pattern::Literal(needle) => {
+                let mut matches = Vec::new();
+                let mut start = 0;
+                while start + needle.len() <= haystack.len() {
+                    if haystack[start..].starts_with(needle) {
+                        matches.push((start, start + needle.len()));
+                        start += needle.len();
+                    } else {
+                        start += 1;
+                    }
+                }
+                matches
+            }
+            Pattern::Regex(regex) => regex
+                .find_iter(haystack)
+                .filter(|found| !found.as_bytes().is_empty())
+                .map(|found| (found.start(), found.end()))
+                .collect(),
+        }
+    }
+}
+
+/// A match inside one chunk of a PNG
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    pub chunk_type: ChunkType,
+    /// Offset of the match in the searched data, which is decompressed for zTXt, iCCP
+    /// and compressed iTXt chunks
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+}
+
+/// Finds every match of `pattern` in the chunks of `png` whose type passes `include`
+pub fn search<F>(png: &Png, pattern: &Pattern, include: F) -> Vec<Match>
+where
+    F: Fn(&ChunkType) -> bool,
+{
+    let mut matches = Vec::new();
+
+    for chunk in png.chunks() {
+        if !include(chunk.chunk_type()) {
+            continue;
+        }
+
+        let data = searchable_data(chunk);
+        for (start, end) in pattern.find_all(&data) {
+            matches.push(Match {
+                chunk_type: *chunk.chunk_type(),
+                offset: start,
+                bytes: data[start..end].to_vec(),
+            });
+        }
+    }
+
+    matches
+}
+
+/// Finds every run of at least `min_length` printable characters in the data of each
+/// chunk, like the `strings` tool. Text can be ASCII or any other UTF-8, and tabs count
+/// as printable. With `decompress`, zTXt, iCCP and compressed iTXt chunks are searched
+/// inflated, and so is the image data, as one stream across all IDAT chunks with
+/// offsets into the inflated pixels, reported under the first IDAT chunk.
+pub fn strings(png: &Png, min_length: usize, decompress: bool) -> Vec<Match> {
+    let mut matches = Vec::new();
+    let mut image_data_searched = false;
+
+    for chunk in png.chunks() {
+        let is_image_data = chunk.chunk_type().to_string() == "IDAT";
+        let data = match (decompress, is_image_data) {
+            (false, _) => chunk.data().to_vec(),
+            (true, false) => searchable_data(chunk),
+            (true, true) if image_data_searched => continue,
+            (true, true) => {
+                image_data_searched = true;
+                let compressed: Vec<u8> = png
+                    .chunks()
+                    .iter()
+                    .filter(|chunk| chunk.chunk_type().to_string() == "IDAT")
+                    .flat_map(|chunk| chunk.data().iter().copied())
+                    .collect();
+                // A damaged stream still gives up whatever inflated before the damage
+                let mut inflated = Vec::new();
+                let _ = ZlibDecoder::new(compressed.as_slice()).read_to_end(&mut inflated);
+                inflated
+            }
+        };
+
+        for (start, end) in printable_runs(&data, min_length) {
+            matches.push(Match {
+                chunk_type: *chunk.chunk_type(),
+                offset: start,
+                bytes: data[start..end].to_vec(),
+            });
+        }
+    }
+
+    matches
+}
+
+/// Returns the start and end of every run of at least `min_length` printable characters
+/// in `data`
+fn printable_runs(data: &[u8], min_length: usize) -> Vec<(usize, usize)> {
+    let mut runs = Vec::new();
+    let mut start = 0;
+    let mut characters = 0;
+    let mut position = 0;
+    let mut end_run = |start: usize, end: usize, characters: usize| {
+        if characters >= min_length.max(1) {
+            runs.push((start, end));
+        }
+    };
+
+    for piece in data.utf8_chunks() {
+        for (index, character) in piece.valid().char_indices() {
+            if character.is_control() && character != '\t' {
+                end_run(start, position + index, characters);
+                characters = 0;
+            } else {
+                if characters == 0 {
+                    start = position + index;
+                }
+                characters += 1;
+            }
+        }
+        position += piece.valid().len();
+        if !piece.invalid().is_empty() {
+            end_run(start, position, characters);
+            characters = 0;
+            position += piece.invalid().len();
+        }
+    }
+    end_run(start, position, characters);
+
+    runs
+}
+
+/// Returns the data of `chunk` with any compressed part inflated. The uncompressed
+/// fields at the start, like the keyword of a zTXt chunk, are kept so that they can be
+/// searched too. If the data can't be inflated it is returned as is.
+pub fn searchable_data(chunk: &Chunk) -> Vec<u8> {
+    let data = chunk.data();
+    let compressed_start = match chunk.chunk_type().to_string().as_str() {
+        // Keyword, NUL and the compression method
+        "zTXt" | "iCCP" => data.iter().position(|&byte| byte == 0).map(|i| i + 2),
+        // Keyword, NUL, compression flag and method, language tag, NUL, translated
+        // keyword and NUL
+        "iTXt" => data
+            .iter()
+            .position(|&byte| byte == 0)
+            .filter(|&i| data.get(i + 1) == Some(&1))
+            .and_then(|i| nth_nul(data, i + 3, 2)),
+        _ => None,
+    };
+
+    if let Some(start) = compressed_start.filter(|&start| start <= data.len()) {
+        let mut inflated = data[..start].to_vec();
+        if ZlibDecoder::new(&data[start..])
+            .read_to_end(&mut inflated)
+            .is_ok()
+        {
+            return inflated;
+        }
+    }
+
+    data.to_vec()
+}
+
+/// Returns every PNG file under `path`, sorted. A path to a file is returned as is.
+/// Directories are searched recursively for files with a `.png` extension. ZIP archives,
+/// whether given or found, stand for the PNG files inside them, as paths like
+/// `bundle.zip!assets/icon.png`.
+pub fn png_files(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if zip_path::has_zip_extension(path) && path.is_file() {
+        return zip_path::png_entries(path);
+    }
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    let mut files = Vec::new();
+    let mut directories = vec![path.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                directories.push(path);
+            } else if zip_path::has_zip_extension(&path) {
+                files.extend(zip_path::png_entries(&path)?);
+            } else if path
+                .extension()
+                .is_some_and(|extension| extension.eq_ignore_ascii_case("png"))
+            {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// The index just past the `n`th NUL byte at or after `start`
+fn nth_nul(data: &[u8], start: usize, n: usize) -> Option<usize> {
+    data.iter()
+        .enumerate()
+        .skip(start)
+        .filter(|(_, &byte)| byte == 0)
+        .nth(n - 1)
+        .map(|(i, _)| i + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use std::str::FromStr;
+
+    fn compressed_text_chunk(keyword: &str, text: &str) -> Chunk {
+        let mut data = keyword.as_bytes().to_vec();
+        data.extend_from_slice(&[0, 0]);
+        let mut encoder = ZlibEncoder::new(data, Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+
+        Chunk::new(
+            ChunkType::from_str("zTXt").unwrap(),
+            encoder.finish().unwrap(),
+        )
+    }
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("tEXt", "Author\0Ferris the crab").unwrap(),
+            compressed_text_chunk("Comment", "Made by a crab"),
+            Chunk::from_strings("ruSt", "crab crab").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_literal_search() {
+        let pattern = Pattern::new("crab", false).unwrap();
+        let matches = search(&testing_png(), &pattern, |_| true);
+
+        let found: Vec<(String, usize)> = matches
+            .iter()
+            .map(|m| (m.chunk_type.to_string(), m.offset))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("tEXt".to_string(), 18),
+                ("zTXt".to_string(), 19),
+                ("ruSt".to_string(), 0),
+                ("ruSt".to_string(), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_regex_search_with_types() {
+        let pattern = Pattern::new("(Ferris|Made) \\w+", true).unwrap();
+        let matches = search(&testing_png(), &pattern, |chunk_type| {
+            chunk_type.to_string() == "zTXt"
+        });
+
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].bytes, b"Made by");
+    }
+
+    #[test]
+    fn test_searchable_data_inflates_ztxt() {
+        let chunk = compressed_text_chunk("Comment", "Hello");
+        assert_eq!(searchable_data(&chunk), b"Comment\0\0Hello");
+    }
+
+    #[test]
+    fn test_empty_pattern() {
+        assert!(Pattern::new("", false).is_err());
+    }
+
+    #[test]
+    fn test_strings() {
+        let mut png = testing_png();
+        png.append_chunk(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            b"\x01\xffshort\x00a longer run\tof t\xc3\xa9xt\x02".to_vec(),
+        ));
+
+        // The compressed zTXt text is left out, since what it happens to hold depends on
+        // the compressor
+        let found: Vec<(String, usize, String)> = strings(&png, 6, false)
+            .iter()
+            .filter(|m| m.chunk_type.to_string() != "zTXt")
+            .map(|m| {
+                let text = String::from_utf8(m.bytes.clone()).unwrap();
+                (m.chunk_type.to_string(), m.offset, text)
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                ("IHDR".to_string(), 0, "header".to_string()),
+                ("tEXt".to_string(), 0, "Author".to_string()),
+                ("tEXt".to_string(), 7, "Ferris the crab".to_string()),
+                ("ruSt".to_string(), 0, "crab crab".to_string()),
+                (
+                    "ruSt".to_string(),
+                    8,
+                    "a longer run\tof t\u{e9}xt".to_string()
+                ),
+            ]
+        );
+
+        let inflated = strings(&png, 6, true);
+        assert_eq!(inflated[3].bytes, b"Comment");
+        assert_eq!(inflated[4].bytes, b"Made by a crab");
+    }
+}
@@ -0,0 +1,373 @@
+use std::convert::{TryFrom, TryInto};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use ed25519_dalek::{Signer, SigningKey, Verifier, VerifyingKey};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+
+use crate::keys;
+use crate::png::{Chunk, ChunkType, Png};
+
+/// The chunk type used to store signatures. It is ancillary and private, and marked
+/// unsafe to copy because the signature covers the image's critical chunks.
+pub const SIGNATURE_CHUNK_TYPE: &str = "pmSG";
+
+const VERSION: u8 = 1;
+const DOMAIN: &[u8] = b"pngme signature v1";
+const SIGNATURE_DATA_LENGTH: usize = 1 + 4 + 8 + 32 + 32 + 32 + 64;
+const PEM_LABEL: &str = "PNGME SIGNATURE";
+
+/// An Ed25519 signature embedded in a PNG. A signature either covers the data of a single
+/// chunk, or every chunk in the file apart from other signatures. A chunk signature
+/// records only the chunk's type, so it checks out as long as some chunk of that type
+/// still holds the signed data. In both cases it also
+/// records a hash of the critical chunks so that changes to the image itself can be
+/// detected.
+#[derive(Debug, Clone)]
+pub struct EmbeddedSignature {
+    /// The signed chunk type, or `None` if this signature covers the whole file
+    pub target: Option<ChunkType>,
+    /// Seconds since the UNIX epoch at signing time
+    pub timestamp: u64,
+    /// The Ed25519 public key of the signer
+    pub signer: [u8; 32],
+    content_hash: [u8; 32],
+    critical_hash: [u8; 32],
+    signature: [u8; 64],
+}
+
+/// The result of checking an `EmbeddedSignature` against a `Png`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Verification {
+    /// The signature matches the signer's key and the recorded hashes
+    pub signature_valid: bool,
+    /// The signed content still hashes to the recorded value
+    pub content_unchanged: bool,
+    /// The critical chunks still hash to the recorded value
+    pub critical_unchanged: bool,
+}
+
+impl Verification {
+    /// Returns true if every check passed
+    pub fn passed(&self) -> bool {
+        self.signature_valid && self.content_unchanged && self.critical_unchanged
+    }
+}
+
+impl EmbeddedSignature {
+    /// Signs `target`, which should be one of the chunks of `png`, or the whole file if
+    /// `target` is `None`
+    pub fn sign(png: &Png, target: Option<&Chunk>, signing_key: &[u8; 32]) -> anyhow::Result<Self> {
+        let signing_key = SigningKey::from_bytes(signing_key);
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+        let content_hash = match target {
+            Some(chunk) => Sha256::digest(chunk.data()).into(),
+            None => file_hash(png),
+        };
+        let mut result = Self {
+            target: target.map(|chunk| *chunk.chunk_type()),
+            timestamp,
+            signer: signing_key.verifying_key().to_bytes(),
+            content_hash,
+            critical_hash: critical_hash(png),
+            signature: [0; 64],
+        };
+        result.signature = signing_key.sign(&result.signed_message()).to_bytes();
+
+        Ok(result)
+    }
+
+    /// Checks this signature against the current contents of `png`
+    pub fn verify(&self, png: &Png) -> Verification {
+        let signature_valid = match VerifyingKey::from_bytes(&self.signer) {
+            Ok(key) => {
+                let signature = ed25519_dalek::Signature::from_bytes(&self.signature);
+                key.verify(&self.signed_message(), &signature).is_ok()
+            }
+            Err(_) => false,
+        };
+
+        let content_unchanged = match &self.target {
+            Some(chunk_type) => png
+                .chunks()
+                .iter()
+                .filter(|chunk| chunk.chunk_type() == chunk_type)
+                .any(|chunk| Sha256::digest(chunk.data()).as_slice() == self.content_hash),
+            None => file_hash(png) == self.content_hash,
+        };
+
+        Verification {
+            signature_valid,
+            content_unchanged,
+            critical_unchanged: critical_hash(png) == self.critical_hash,
+        }
+    }
+
+    /// Returns this signature as a chunk of type `SIGNATURE_CHUNK_TYPE`.
+    /// The chunk data is laid out as follows
+    /// 1. Format version *(1 byte)*
+    /// 2. Signed chunk type, or zeros for a whole file signature *(4 bytes)*
+    /// 3. Timestamp in seconds since the UNIX epoch *(8 bytes)*
+    /// 4. Signer's Ed25519 public key *(32 bytes)*
+    /// 5. SHA-256 of the signed content *(32 bytes)*
+    /// 6. SHA-256 of the critical chunks *(32 bytes)*
+    /// 7. Ed25519 signature over all of the above *(64 bytes)*
+    pub fn to_chunk(&self) -> Chunk {
+        let chunk_type = SIGNATURE_CHUNK_TYPE.parse().expect("valid chunk type");
+        Chunk::new(chunk_type, self.to_data())
+    }
+
+    /// Returns this signature as a PEM document for storing next to the file it signs,
+    /// which is left untouched. The body holds the same data as `to_chunk`.
+    pub fn to_pem(&self) -> String {
+        let body = base64::engine::general_purpose::STANDARD.encode(self.to_data());
+        format!(
+            "-----BEGIN {}-----\n{}\n-----END {}-----\n",
+            PEM_LABEL, body, PEM_LABEL
+        )
+    }
+
+    /// Parses a detached signature written by `to_pem`
+    pub fn from_pem(bytes: &[u8]) -> anyhow::Result<Self> {
+        Self::from_data(&keys::parse_pem(bytes)?)
+    }
+
+    fn to_data(&self) -> Vec<u8> {
+        let mut data = self.signed_fields();
+        data.extend_from_slice(&self.signature);
+        data
+    }
+
+    fn from_data(data: &[u8]) -> anyhow::Result<Self> {
+        if data.len() != SIGNATURE_DATA_LENGTH {
+            anyhow::bail!("Invalid signature length {}", data.len());
+        }
+        if data[0] != VERSION {
+            anyhow::bail!("Unsupported signature version {}", data[0]);
+        }
+
+        let target: [u8; 4] = data[1..5].try_into()?;
+        let target = if target == [0; 4] {
+            None
+        } else {
+            Some(ChunkType::try_from(target)?)
+        };
+
+        Ok(Self {
+            target,
+            timestamp: u64::from_be_bytes(data[5..13].try_into()?),
+            signer: data[13..45].try_into()?,
+            content_hash: data[45..77].try_into()?,
+            critical_hash: data[77..109].try_into()?,
+            signature: data[109..173].try_into()?,
+        })
+    }
+
+    fn signed_fields(&self) -> Vec<u8> {
+        let target = self.target.as_ref().map(ChunkType::bytes).unwrap_or([0; 4]);
+
+        let mut fields = Vec::with_capacity(SIGNATURE_DATA_LENGTH);
+        fields.push(VERSION);
+        fields.extend_from_slice(&target);
+        fields.extend_from_slice(&self.timestamp.to_be_bytes());
+        fields.extend_from_slice(&self.signer);
+        fields.extend_from_slice(&self.content_hash);
+        fields.extend_from_slice(&self.critical_hash);
+        fields
+    }
+
+    fn signed_message(&self) -> Vec<u8> {
+        DOMAIN.iter().copied().chain(self.signed_fields()).collect()
+    }
+}
+
+impl TryFrom<&Chunk> for EmbeddedSignature {
+    type Error = anyhow::Error;
+
+    fn try_from(chunk: &Chunk) -> anyhow::Result<Self> {
+        if chunk.chunk_type().to_string() != SIGNATURE_CHUNK_TYPE {
+            anyhow::bail!("Not a signature chunk");
+        }
+
+        Self::from_data(chunk.data())
+    }
+}
+
+/// Returns all of the signatures embedded in `png`
+pub fn signatures(png: &Png) -> anyhow::Result<Vec<EmbeddedSignature>> {
+    png.chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().to_string() == SIGNATURE_CHUNK_TYPE)
+        .map(EmbeddedSignature::try_from)
+        .collect()
+}
+
+/// Generates a random Ed25519 signing key and returns it with its public key
+pub fn generate_signing_key() -> ([u8; 32], [u8; 32]) {
+    let mut secret = [0; 32];
+    rand::thread_rng().fill_bytes(&mut secret);
+    let public = SigningKey::from_bytes(&secret).verifying_key().to_bytes();
+
+    (secret, public)
+}
+
+/// Hashes every chunk that isn't a signature, then any data after IEND, so that nothing
+/// can be added to the file without changing the hash
+fn file_hash(png: &Png) -> [u8; 32] {
+    let mut hasher = chunk_hasher(
+        png.chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().to_string() != SIGNATURE_CHUNK_TYPE),
+    );
+    hasher.update(png.trailer());
+    hasher.finalize().into()
+}
+
+/// Hashes every critical chunk in order
+pub fn critical_hash(png: &Png) -> [u8; 32] {
+    hash_chunks(
+        png.chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().is_critical()),
+    )
+}
+
+fn hash_chunks<'a>(chunks: impl Iterator<Item = &'a Chunk>) -> [u8; 32] {
+    chunk_hasher(chunks).finalize().into()
+}
+
+fn chunk_hasher<'a>(chunks: impl Iterator<Item = &'a Chunk>) -> Sha256 {
+    let mut hasher = Sha256::new();
+    for chunk in chunks {
+        hasher.update(chunk.length().to_be_bytes());
+        hasher.update(chunk.chunk_type().bytes());
+        hasher.update(chunk.data());
+    }
+    hasher
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("ruSt", "This is a secret message!").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_sign_chunk() {
+        let (secret, public) = generate_signing_key();
+        let mut png = testing_png();
+        let target = ChunkType::try_from(*b"ruSt").unwrap();
+
+        let signature = EmbeddedSignature::sign(&png, Some(&png.chunks()[2]), &secret).unwrap();
+        assert_eq!(signature.signer, public);
+        png.append_chunk(signature.to_chunk());
+
+        let signatures = signatures(&png).unwrap();
+        assert_eq!(signatures.len(), 1);
+        assert_eq!(signatures[0].target, Some(target));
+        assert!(signatures[0].verify(&png).passed());
+    }
+
+    #[test]
+    fn test_modified_chunk() {
+        let (secret, _) = generate_signing_key();
+        let png = testing_png();
+        let signature = EmbeddedSignature::sign(&png, Some(&png.chunks()[2]), &secret).unwrap();
+
+        let mut modified = testing_png();
+        modified.remove_chunk("ruSt").unwrap();
+        modified.append_chunk(Chunk::from_strings("ruSt", "Something else").unwrap());
+
+        let verification = signature.verify(&modified);
+        assert!(verification.signature_valid);
+        assert!(!verification.content_unchanged);
+        assert!(verification.critical_unchanged);
+    }
+
+    #[test]
+    fn test_sign_later_chunk_of_same_type() {
+        let (secret, _) = generate_signing_key();
+        let mut png = testing_png();
+        png.append_chunk(Chunk::from_strings("ruSt", "A newer message").unwrap());
+        let signature = EmbeddedSignature::sign(&png, Some(&png.chunks()[3]), &secret).unwrap();
+        assert!(signature.verify(&png).passed());
+
+        // Only the older message is left, which wasn't signed
+        assert!(!signature.verify(&testing_png()).content_unchanged);
+    }
+
+    #[test]
+    fn test_whole_file_detects_critical_change() {
+        let (secret, _) = generate_signing_key();
+        let png = testing_png();
+        let signature = EmbeddedSignature::sign(&png, None, &secret).unwrap();
+        assert!(signature.verify(&png).passed());
+
+        let mut modified = testing_png();
+        modified.remove_chunk("IDAT").unwrap();
+        modified.append_chunk(Chunk::from_strings("IDAT", "other pixels").unwrap());
+
+        let verification = signature.verify(&modified);
+        assert!(!verification.content_unchanged);
+        assert!(!verification.critical_unchanged);
+    }
+
+    #[test]
+    fn test_forged_signature() {
+        let (secret, _) = generate_signing_key();
+        let png = testing_png();
+        let mut signature = EmbeddedSignature::sign(&png, None, &secret).unwrap();
+        signature.timestamp += 1;
+
+        assert!(!signature.verify(&png).signature_valid);
+    }
+
+    #[test]
+    fn test_chunk_round_trip() {
+        let (secret, _) = generate_signing_key();
+        let signature = EmbeddedSignature::sign(&testing_png(), None, &secret).unwrap();
+
+        let parsed = EmbeddedSignature::try_from(&signature.to_chunk()).unwrap();
+        assert_eq!(parsed.target, None);
+        assert_eq!(parsed.timestamp, signature.timestamp);
+        assert!(parsed.verify(&testing_png()).passed());
+    }
+
+    #[test]
+    fn test_detached_signature() {
+        let (secret, public) = generate_signing_key();
+        let signature = EmbeddedSignature::sign(&testing_png(), None, &secret).unwrap();
+
+        let pem = signature.to_pem();
+        assert!(pem.starts_with("-----BEGIN PNGME SIGNATURE-----\n"));
+
+        let parsed = EmbeddedSignature::from_pem(pem.as_bytes()).unwrap();
+        assert_eq!(parsed.signer, public);
+        assert!(parsed.verify(&testing_png()).passed());
+
+        let mut modified = testing_png();
+        modified.append_chunk(Chunk::from_strings("ruSt", "Another message").unwrap());
+        assert!(!parsed.verify(&modified).content_unchanged);
+        assert!(EmbeddedSignature::from_pem(b"not a signature").is_err());
+    }
+
+    #[test]
+    fn test_whole_file_covers_trailer() {
+        let (secret, _) = generate_signing_key();
+        let signature = EmbeddedSignature::sign(&testing_png(), None, &secret).unwrap();
+
+        let mut trailed = testing_png();
+        trailed.set_trailer(b"EVIL PAYLOAD".to_vec());
+        let verification = signature.verify(&trailed);
+        assert!(!verification.content_unchanged);
+        assert!(!verification.passed());
+    }
+}
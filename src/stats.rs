@@ -0,0 +1,212 @@
+use std::collections::BTreeMap;
+
+use crate::png::{ChunkType, Png};
+
+/// The chunks of one file, for comparing it with others
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStats {
+    /// The length of the whole file
+    pub length: u64,
+    /// The type and data length of each chunk, in file order
+    pub chunks: Vec<(ChunkType, usize)>,
+}
+
+impl FileStats {
+    pub fn of(png: &Png, length: u64) -> Self {
+        Self {
+            length,
+            chunks: png
+                .chunks()
+                .iter()
+                .map(|chunk| (*chunk.chunk_type(), chunk.data().len()))
+                .collect(),
+        }
+    }
+
+    /// The number of bytes of ancillary chunk data, which is everything but the image
+    /// itself: text, color information, timestamps and hidden messages
+    pub fn metadata_bytes(&self) -> u64 {
+        self.chunks
+            .iter()
+            .filter(|(chunk_type, _)| !chunk_type.is_critical())
+            .map(|&(_, length)| length as u64)
+            .sum()
+    }
+}
+
+/// How a chunk type is used across many files
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeStats {
+    pub chunk_type: ChunkType,
+    /// How many files have at least one chunk of this type
+    pub files: usize,
+    pub chunks: usize,
+    /// The data length of all of these chunks together
+    pub bytes: u64,
+}
+
+impl TypeStats {
+    pub fn average_length(&self) -> f64 {
+        self.bytes as f64 / self.chunks as f64
+    }
+}
+
+/// A file that stands out from the rest, by its index in the files given to `aggregate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outlier {
+    pub index: usize,
+    pub reason: String,
+}
+
+/// What a set of files has in common and where they differ
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregate {
+    pub files: usize,
+    /// How many files have any ancillary chunks
+    pub with_metadata: usize,
+    /// Every chunk type found, the most widespread first
+    pub types: Vec<TypeStats>,
+    pub outliers: Vec<Outlier>,
+}
+
+/// Combines the statistics of many files. A file is an outlier if it has far more
+/// metadata than most, going by the usual rule of more than one and a half interquartile
+/// ranges above the upper quartile, or if it is the only one with some chunk type. Sets
+/// of fewer than four files are too small to say what is usual, so they have no outliers.
+pub fn aggregate(files: &[FileStats]) -> Aggregate {
+    let mut types: BTreeMap<ChunkType, TypeStats> = BTreeMap::new();
+    for file in files {
+        let mut seen = Vec::new();
+        for &(chunk_type, length) in &file.chunks {
+            let stats = types.entry(chunk_type).or_insert(TypeStats {
+                chunk_type,
+                files: 0,
+                chunks: 0,
+                bytes: 0,
+            });
+            if !seen.contains(&chunk_type) {
+                seen.push(chunk_type);
+                stats.files += 1;
+            }
+            stats.chunks += 1;
+            stats.bytes += length as u64;
+        }
+    }
+    let mut types: Vec<TypeStats> = types.into_values().collect();
+    types.sort_by(|a, b| b.files.cmp(&a.files).then(a.chunk_type.cmp(&b.chunk_type)));
+
+    let mut outliers = Vec::new();
+    if files.len() >= 4 {
+        let mut metadata: Vec<u64> = files.iter().map(FileStats::metadata_bytes).collect();
+        metadata.sort_unstable();
+        let quartile = |fraction: f64| metadata[((metadata.len() - 1) as f64 * fraction) as usize];
+        let (lower, upper) = (quartile(0.25), quartile(0.75));
+        let limit = upper as f64 + 1.5 * (upper - lower) as f64;
+
+        for (index, file) in files.iter().enumerate() {
+            let bytes = file.metadata_bytes();
+            if bytes as f64 > limit {
+                outliers.push(Outlier {
+                    index,
+                    reason: format!(
+                        "{} bytes of metadata, where most files have at most {}",
+                        bytes, upper
+                    ),
+                });
+            }
+            for stats in types.iter().filter(|stats| stats.files == 1) {
+                if file
+                    .chunks
+                    .iter()
+                    .any(|(chunk_type, _)| chunk_type == &stats.chunk_type)
+                {
+                    outliers.push(Outlier {
+                        index,
+                        reason: format!("the only file with a {} chunk", stats.chunk_type),
+                    });
+                }
+            }
+        }
+    }
+
+    Aggregate {
+        files: files.len(),
+        with_metadata: files
+            .iter()
+            .filter(|file| file.metadata_bytes() > 0)
+            .count(),
+        types,
+        outliers,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn file_stats(chunks: &[(&str, usize)]) -> FileStats {
+        FileStats {
+            length: chunks.iter().map(|(_, length)| *length as u64 + 12).sum(),
+            chunks: chunks
+                .iter()
+                .map(|&(chunk_type, length)| (ChunkType::from_str(chunk_type).unwrap(), length))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let mut files: Vec<FileStats> = (0..5)
+            .map(|_| file_stats(&[("IHDR", 13), ("tEXt", 20), ("IDAT", 100), ("IEND", 0)]))
+            .collect();
+        files.push(file_stats(&[
+            ("IHDR", 13),
+            ("IDAT", 50),
+            ("IDAT", 50),
+            ("IEND", 0),
+        ]));
+        files.push(file_stats(&[
+            ("IHDR", 13),
+            ("IDAT", 100),
+            ("ruSt", 5000),
+            ("IEND", 0),
+        ]));
+
+        let aggregate = aggregate(&files);
+        assert_eq!(aggregate.files, 7);
+        assert_eq!(aggregate.with_metadata, 6);
+
+        let names: Vec<String> = aggregate
+            .types
+            .iter()
+            .map(|stats| stats.chunk_type.to_string())
+            .collect();
+        assert_eq!(names, vec!["IDAT", "IEND", "IHDR", "tEXt", "ruSt"]);
+        assert_eq!(aggregate.types[0].chunks, 8);
+        assert_eq!(aggregate.types[0].average_length(), 87.5);
+
+        assert_eq!(
+            aggregate.outliers,
+            vec![
+                Outlier {
+                    index: 6,
+                    reason: "5000 bytes of metadata, where most files have at most 20".to_string(),
+                },
+                Outlier {
+                    index: 6,
+                    reason: "the only file with a ruSt chunk".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_small_sets_have_no_outliers() {
+        let files = vec![
+            file_stats(&[("IHDR", 13), ("IEND", 0)]),
+            file_stats(&[("IHDR", 13), ("ruSt", 5000), ("IEND", 0)]),
+        ];
+        assert!(aggregate(&files).outliers.is_empty());
+    }
+}
@@ -0,0 +1,293 @@
+use std::convert::TryFrom;
+use std::fmt;
+
+use crate::png::{Chunk, ChunkType};
+
+/// The keywords predefined by the PNG spec for tEXt, zTXt and iTXt chunks
+pub const STANDARD_KEYWORDS: [&str; 10] = [
+    "Title",
+    "Author",
+    "Description",
+    "Copyright",
+    "Creation Time",
+    "Software",
+    "Disclaimer",
+    "Warning",
+    "Source",
+    "Comment",
+];
+
+/// A validated tEXt chunk. See the PNG spec for more details.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.tEXt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    keyword: String,
+    text: String,
+}
+
+impl TextChunk {
+    /// Creates a new text chunk. Both the keyword and the text must be representable in
+    /// Latin-1. The keyword must be 1-79 printable characters with no leading, trailing
+    /// or consecutive spaces, and the text must not contain NUL characters.
+    pub fn new(keyword: &str, text: &str) -> anyhow::Result<Self> {
+        validate_keyword(keyword)?;
+
+        if let Some(c) = text.chars().find(|&c| c == '\0' || c as u32 > 0xff) {
+            anyhow::bail!(
+                "Text contains {:?}, which is not allowed in a tEXt chunk",
+                c
+            );
+        }
+
+        Ok(Self {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        })
+    }
+
+    /// The keyword describing the text
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    /// The text itself
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns this as a tEXt chunk: the Latin-1 keyword, a NUL separator and the Latin-1 text
+    pub fn to_chunk(&self) -> Chunk {
+        let data: Vec<u8> = self
+            .keyword
+            .chars()
+            .chain(std::iter::once('\0'))
+            .chain(self.text.chars())
+            .map(|c| c as u8)
+            .collect();
+
+        Chunk::new(ChunkType::new(*b"tEXt"), data)
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = anyhow::Error;
+
+    fn try_from(chunk: &Chunk) -> anyhow::Result<Self> {
+        if chunk.chunk_type().to_string() != "tEXt" {
+            anyhow::bail!("Expected a tEXt chunk, found {}", chunk.chunk_type());
+        }
+
+        let data = chunk.data();
+        let separator = data
+            .iter()
+            .position(|&byte| byte == 0)
+            .ok_or_else(|| anyhow::anyhow!("tEXt chunk is missing its keyword separator"))?;
+
+        let keyword = latin1_to_string(&data[..separator]);
+        let text = latin1_to_string(&data[separator + 1..]);
+
+        Self::new(&keyword, &text)
+    }
+}
+
+impl fmt::Display for TextChunk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.keyword, self.text)
+    }
+}
+
+/// An uncompressed iTXt chunk. See the PNG spec for more details.
+/// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.iTXt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalTextChunk {
+    keyword: String,
+    language_tag: String,
+    translated_keyword: String,
+    text: String,
+}
+
+impl InternationalTextChunk {
+    /// Creates a new international text chunk with no language tag or translated keyword
+    pub fn new(keyword: &str, text: &str) -> anyhow::Result<Self> {
+        validate_keyword(keyword)?;
+
+        if text.contains('\0') {
+            anyhow::bail!("Text must not contain NUL characters");
+        }
+
+        Ok(Self {
+            keyword: keyword.to_string(),
+            language_tag: String::new(),
+            translated_keyword: String::new(),
+            text: text.to_string(),
+        })
+    }
+
+    /// The keyword describing the text
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    /// The UTF-8 text
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Returns this as an uncompressed iTXt chunk.
+    /// The chunk data is laid out as follows
+    /// 1. Latin-1 keyword and a NUL separator
+    /// 2. Compression flag and compression method, both zero *(2 bytes)*
+    /// 3. Language tag and a NUL separator
+    /// 4. UTF-8 translated keyword and a NUL separator
+    /// 5. UTF-8 text
+    pub fn to_chunk(&self) -> Chunk {
+        let mut data: Vec<u8> = self.keyword.chars().map(|c| c as u8).collect();
+        data.extend_from_slice(&[0, 0, 0]);
+        data.extend_from_slice(self.language_tag.as_bytes());
+        data.push(0);
+        data.extend_from_slice(self.translated_keyword.as_bytes());
+        data.push(0);
+        data.extend_from_slice(self.text.as_bytes());
+
+        Chunk::new(ChunkType::new(*b"iTXt"), data)
+    }
+}
+
+impl TryFrom<&Chunk> for InternationalTextChunk {
+    type Error = anyhow::Error;
+
+    fn try_from(chunk: &Chunk) -> anyhow::Result<Self> {
+        if chunk.chunk_type().to_string() != "iTXt" {
+            anyhow::bail!("Expected an iTXt chunk, found {}", chunk.chunk_type());
+        }
+
+        let mut fields = chunk.data().splitn(2, |&byte| byte == 0);
+        let keyword = latin1_to_string(fields.next().unwrap_or_default());
+        let rest = fields
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("iTXt chunk is missing its keyword separator"))?;
+        validate_keyword(&keyword)?;
+
+        if rest.len() < 2 {
+            anyhow::bail!("iTXt chunk is truncated");
+        }
+        if rest[0] != 0 {
+            anyhow::bail!("Compressed iTXt chunks are not supported");
+        }
+
+        let mut fields = rest[2..].splitn(3, |&byte| byte == 0);
+        let language_tag = fields.next().unwrap_or_default();
+        let translated_keyword = fields.next();
+        let text = fields.next();
+
+        match (translated_keyword, text) {
+            (Some(translated_keyword), Some(text)) => Ok(Self {
+                keyword,
+                language_tag: String::from_utf8(language_tag.to_vec())?,
+                translated_keyword: String::from_utf8(translated_keyword.to_vec())?,
+                text: String::from_utf8(text.to_vec())?,
+            }),
+            _ => anyhow::bail!("iTXt chunk is missing a separator"),
+        }
+    }
+}
+
+/// Checks a keyword against the rules shared by tEXt, zTXt and iTXt chunks
+pub fn validate_keyword(keyword: &str) -> anyhow::Result<()> {
+    let length = keyword.chars().count();
+    if length == 0 || length > 79 {
+        anyhow::bail!("Keywords must be 1-79 characters long, found {}", length);
+    }
+
+    if let Some(c) = keyword
+        .chars()
+        .find(|&c| !((' '..='~').contains(&c) || ('\u{a1}'..='\u{ff}').contains(&c)))
+    {
+        anyhow::bail!("Keyword contains {:?}, which is not printable Latin-1", c);
+    }
+
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        anyhow::bail!("Keywords must not have leading, trailing or consecutive spaces");
+    }
+
+    Ok(())
+}
+
+fn latin1_to_string(bytes: &[u8]) -> String {
+    bytes.iter().map(|&byte| byte as char).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_round_trip() {
+        let text = TextChunk::new("Author", "Zoë").unwrap();
+        let chunk = text.to_chunk();
+        assert_eq!(chunk.data(), b"Author\0Zo\xeb");
+
+        let parsed = TextChunk::try_from(&chunk).unwrap();
+        assert_eq!(parsed, text);
+    }
+
+    #[test]
+    fn test_standard_keywords_are_valid() {
+        for keyword in STANDARD_KEYWORDS.iter() {
+            assert!(validate_keyword(keyword).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_invalid_keywords() {
+        assert!(validate_keyword("").is_err());
+        assert!(validate_keyword(&"a".repeat(80)).is_err());
+        assert!(validate_keyword(" Author").is_err());
+        assert!(validate_keyword("Creation  Time").is_err());
+        assert!(validate_keyword("Tab\there").is_err());
+    }
+
+    #[test]
+    fn test_invalid_text() {
+        assert!(TextChunk::new("Title", "Emoji 🦀").is_err());
+        assert!(TextChunk::new("Title", "Nul\0byte").is_err());
+        assert!(TextChunk::new("Title", "Line\nbreaks are fine").is_ok());
+    }
+
+    #[test]
+    fn test_international_round_trip() {
+        let text = InternationalTextChunk::new("Comment", "Ferris 🦀").unwrap();
+        let chunk = text.to_chunk();
+        assert_eq!(chunk.data(), "Comment\0\0\0\0\0Ferris 🦀".as_bytes());
+
+        let parsed = InternationalTextChunk::try_from(&chunk).unwrap();
+        assert_eq!(parsed, text);
+    }
+
+    #[test]
+    fn test_international_with_language() {
+        let chunk = Chunk::new(
+            ChunkType::from_str("iTXt").unwrap(),
+            "Title\0\0\0fr\0Titre\0Bonjour".as_bytes().to_vec(),
+        );
+        let parsed = InternationalTextChunk::try_from(&chunk).unwrap();
+        assert_eq!(parsed.keyword(), "Title");
+        assert_eq!(parsed.text(), "Bonjour");
+    }
+
+    #[test]
+    fn test_compressed_international_text() {
+        let chunk = Chunk::new(
+            ChunkType::from_str("iTXt").unwrap(),
+            b"Title\0\x01\0\0\0x\x9c".to_vec(),
+        );
+        assert!(InternationalTextChunk::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_missing_separator() {
+        let chunk = Chunk::from_strings("tEXt", "no separator").unwrap();
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+}
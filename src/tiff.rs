@@ -0,0 +1,570 @@
+use std::convert::{TryFrom, TryInto};
+use std::ops::Range;
+
+use crate::error::Locate;
+use crate::png::ChunkType;
+
+/// Private tags used for messages, one tag per message
+const MESSAGE_TAGS: Range<u16> = 65000..65100;
+
+const EXIF_IFD_TAG: u16 = 0x8769;
+const GPS_IFD_TAG: u16 = 0x8825;
+const INTEROPERABILITY_IFD_TAG: u16 = 0xA005;
+
+const BYTE: u16 = 1;
+const ASCII: u16 = 2;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const RATIONAL: u16 = 5;
+const SBYTE: u16 = 6;
+const UNDEFINED: u16 = 7;
+const SSHORT: u16 = 8;
+const SLONG: u16 = 9;
+const SRATIONAL: u16 = 10;
+
+/// The order of the bytes in every number of a TIFF structure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    LittleEndian,
+    BigEndian,
+}
+
+impl ByteOrder {
+    fn u16(self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        match self {
+            ByteOrder::LittleEndian => u16::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u16::from_be_bytes(bytes),
+        }
+    }
+
+    fn u32(self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        match self {
+            ByteOrder::LittleEndian => u32::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u32::from_be_bytes(bytes),
+        }
+    }
+
+    fn u16_bytes(self, value: u16) -> [u8; 2] {
+        match self {
+            ByteOrder::LittleEndian => value.to_le_bytes(),
+            ByteOrder::BigEndian => value.to_be_bytes(),
+        }
+    }
+
+    fn u32_bytes(self, value: u32) -> [u8; 4] {
+        match self {
+            ByteOrder::LittleEndian => value.to_le_bytes(),
+            ByteOrder::BigEndian => value.to_be_bytes(),
+        }
+    }
+}
+
+/// A tag in an image file directory, with its value bytes in the file's byte order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub tag: u16,
+    pub field_type: u16,
+    pub count: u32,
+    pub value: Vec<u8>,
+}
+
+impl Entry {
+    /// The tag's name, like `Make` or `DateTimeOriginal`, or its number if it isn't known
+    pub fn name(&self) -> String {
+        match tag_name(self.tag) {
+            Some(name) => name.to_string(),
+            None => format!("0x{:04X}", self.tag),
+        }
+    }
+
+    /// Formats the value for people to read. Text is shown as is, numbers are listed and
+    /// binary data is summarized by its length.
+    pub fn display(&self, order: ByteOrder) -> String {
+        let numbers = |size: usize, format: &dyn Fn(&[u8]) -> String| {
+            let values: Vec<String> = self.value.chunks_exact(size).map(format).collect();
+            values.join(", ")
+        };
+
+        match self.field_type {
+            ASCII => String::from_utf8_lossy(&self.value)
+                .trim_end_matches('\0')
+                .to_string(),
+            SHORT => numbers(2, &|bytes| order.u16(bytes).to_string()),
+            SSHORT => numbers(2, &|bytes| (order.u16(bytes) as i16).to_string()),
+            LONG => numbers(4, &|bytes| order.u32(bytes).to_string()),
+            SLONG => numbers(4, &|bytes| (order.u32(bytes) as i32).to_string()),
+            RATIONAL => numbers(8, &|bytes| {
+                format!("{}/{}", order.u32(bytes), order.u32(&bytes[4..]))
+            }),
+            SRATIONAL => numbers(8, &|bytes| {
+                format!(
+                    "{}/{}",
+                    order.u32(bytes) as i32,
+                    order.u32(&bytes[4..]) as i32
+                )
+            }),
+            SBYTE => numbers(1, &|bytes| (bytes[0] as i8).to_string()),
+            BYTE | UNDEFINED if self.value.len() <= 8 => numbers(1, &|bytes| bytes[0].to_string()),
+            _ => format!("{} bytes", self.value.len()),
+        }
+    }
+
+    /// Returns the chunk type and contents if this entry holds a message
+    pub fn as_message(&self) -> Option<(ChunkType, &[u8])> {
+        if !MESSAGE_TAGS.contains(&self.tag) || self.field_type != UNDEFINED {
+            return None;
+        }
+        let type_bytes: [u8; 4] = self.value.get(..4)?.try_into().ok()?;
+        let chunk_type = ChunkType::try_from(type_bytes).ok()?;
+        Some((chunk_type, &self.value[4..]))
+    }
+
+    fn offset(&self, order: ByteOrder) -> Option<u32> {
+        match self.field_type {
+            LONG | 13 if self.count == 1 => Some(order.u32(&self.value)),
+            _ => None,
+        }
+    }
+}
+
+/// A TIFF file or an EXIF block, which uses the same structure. Only the first image
+/// file directory (IFD0) can be changed.
+///
+/// Edits never move existing data, since other tags point at it by offset. Instead a new
+/// IFD0 is added to the end and the header is pointed at it, leaving the old one unused.
+#[derive(Debug, Clone)]
+pub struct Tiff {
+    bytes: Vec<u8>,
+    order: ByteOrder,
+}
+
+impl Tiff {
+    /// Returns true if `bytes` starts like a TIFF file
+    pub fn is_tiff(bytes: &[u8]) -> bool {
+        bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*")
+    }
+
+    pub fn byte_order(&self) -> ByteOrder {
+        self.order
+    }
+
+    /// Returns the entries of IFD0
+    pub fn entries(&self) -> anyhow::Result<Vec<Entry>> {
+        self.read_ifd(self.first_ifd_offset())
+            .map(|(entries, _)| entries)
+    }
+
+    /// Returns the entries of IFD0 followed by those of the EXIF and GPS directories it
+    /// points to
+    pub fn all_entries(&self) -> anyhow::Result<Vec<Entry>> {
+        let mut entries = self.entries()?;
+        let mut index = 0;
+
+        while index < entries.len() {
+            let entry = &entries[index];
+            let is_directory = entry.tag == EXIF_IFD_TAG || entry.tag == GPS_IFD_TAG;
+            if let Some(offset) = entry.offset(self.order).filter(|_| is_directory) {
+                let (directory, _) = self.read_ifd(offset as usize)?;
+                entries.extend(directory);
+            }
+            index += 1;
+            // Guards against directories that point at each other
+            if entries.len() > 10_000 {
+                anyhow::bail!("TIFF has too many entries");
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Returns the first message stored under `chunk_type`
+    pub fn message(&self, chunk_type: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        Ok(self
+            .messages()?
+            .into_iter()
+            .find(|(found, _)| found.to_string() == chunk_type)
+            .map(|(_, message)| message))
+    }
+
+    /// Returns the chunk type and contents of every message
+    pub fn messages(&self) -> anyhow::Result<Vec<(ChunkType, Vec<u8>)>> {
+        Ok(self
+            .entries()?
+            .iter()
+            .filter_map(|entry| entry.as_message())
+            .map(|(chunk_type, message)| (chunk_type, message.to_vec()))
+            .collect())
+    }
+
+    /// Stores `message` under `chunk_type` in the first unused private message tag
+    pub fn append_message(&mut self, chunk_type: &ChunkType, message: &[u8]) -> anyhow::Result<()> {
+        let mut entries = self.entries()?;
+        let tag = MESSAGE_TAGS
+            .clone()
+            .find(|tag| entries.iter().all(|entry| entry.tag != *tag))
+            .ok_or_else(|| anyhow::anyhow!("No free message tags left"))?;
+
+        let mut value = chunk_type.bytes().to_vec();
+        value.extend_from_slice(message);
+        entries.push(Entry {
+            tag,
+            field_type: UNDEFINED,
+            count: value.len() as u32,
+            value,
+        });
+
+        self.write_first_ifd(entries)
+    }
+
+    /// Removes the first message stored under `chunk_type` and returns its contents
+    pub fn remove_message(&mut self, chunk_type: &str) -> anyhow::Result<Vec<u8>> {
+        let mut entries = self.entries()?;
+        let (index, message) = entries
+            .iter()
+            .enumerate()
+            .find_map(|(index, entry)| match entry.as_message() {
+                Some((found, message)) if found.to_string() == chunk_type => {
+                    Some((index, message.to_vec()))
+                }
+                _ => None,
+            })
+            .ok_or_else(|| anyhow::anyhow!("No message of type {}", chunk_type))
+            .code("chunk_not_found")
+            .in_chunk(chunk_type)?;
+
+        entries.remove(index);
+        self.write_first_ifd(entries)?;
+        Ok(message)
+    }
+
+    /// Keeps only the messages for which `keep` returns true. Other entries are untouched.
+    pub fn retain_messages<F: FnMut(&ChunkType) -> bool>(
+        &mut self,
+        mut keep: F,
+    ) -> anyhow::Result<()> {
+        let entries = self.entries()?;
+        let count = entries.len();
+        let entries: Vec<Entry> = entries
+            .into_iter()
+            .filter(|entry| entry.as_message().is_none_or(|(found, _)| keep(&found)))
+            .collect();
+
+        if entries.len() != count {
+            self.write_first_ifd(entries)?;
+        }
+        Ok(())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    fn first_ifd_offset(&self) -> usize {
+        self.order.u32(&self.bytes[4..8]) as usize
+    }
+
+    /// Reads the entries of the directory at `offset` and the offset of the next one
+    fn read_ifd(&self, offset: usize) -> anyhow::Result<(Vec<Entry>, u32)> {
+        let truncated = || anyhow::anyhow!("Directory at offset {} is truncated", offset);
+        let count = self
+            .order
+            .u16(self.bytes.get(offset..offset + 2).ok_or_else(truncated)?);
+        let end = offset + 2 + count as usize * 12;
+        let fields = self.bytes.get(offset + 2..end).ok_or_else(truncated)?;
+
+        let entries = fields
+            .chunks_exact(12)
+            .map(|field| {
+                let tag = self.order.u16(field);
+                let field_type = self.order.u16(&field[2..]);
+                let count = self.order.u32(&field[4..]);
+                let length = type_size(field_type) * count as usize;
+
+                let value = if length <= 4 {
+                    field[8..8 + length].to_vec()
+                } else {
+                    let start = self.order.u32(&field[8..]) as usize;
+                    self.bytes
+                        .get(start..start + length)
+                        .ok_or_else(|| anyhow::anyhow!("Value of tag 0x{:04X} is truncated", tag))?
+                        .to_vec()
+                };
+
+                Ok(Entry {
+                    tag,
+                    field_type,
+                    count,
+                    value,
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let next = self
+            .bytes
+            .get(end..end + 4)
+            .map(|next| self.order.u32(next))
+            .unwrap_or(0);
+
+        Ok((entries, next))
+    }
+
+    /// Appends a new IFD0 holding `entries`, followed by any values too long to fit in
+    /// their entry, and points the header at it
+    fn write_first_ifd(&mut self, mut entries: Vec<Entry>) -> anyhow::Result<()> {
+        let (_, next) = self.read_ifd(self.first_ifd_offset())?;
+        entries.sort_by_key(|entry| entry.tag);
+
+        // Offsets must be even
+        if self.bytes.len() % 2 == 1 {
+            self.bytes.push(0);
+        }
+        let ifd_offset = self.bytes.len();
+        let ifd_length = 2 + entries.len() * 12 + 4;
+        let values_length: usize = entries.iter().map(|entry| entry.value.len() + 1).sum();
+        if u32::try_from(ifd_offset + ifd_length + values_length).is_err() {
+            anyhow::bail!("TIFF files can't be larger than 4 GiB");
+        }
+
+        let order = self.order;
+        let mut ifd = order.u16_bytes(entries.len() as u16).to_vec();
+        let mut values = Vec::new();
+
+        for entry in &entries {
+            ifd.extend_from_slice(&order.u16_bytes(entry.tag));
+            ifd.extend_from_slice(&order.u16_bytes(entry.field_type));
+            ifd.extend_from_slice(&order.u32_bytes(entry.count));
+
+            if entry.value.len() <= 4 {
+                let mut inline = [0; 4];
+                inline[..entry.value.len()].copy_from_slice(&entry.value);
+                ifd.extend_from_slice(&inline);
+            } else {
+                let value_offset = ifd_offset + ifd_length + values.len();
+                ifd.extend_from_slice(&order.u32_bytes(value_offset as u32));
+                values.extend_from_slice(&entry.value);
+                if entry.value.len() % 2 == 1 {
+                    values.push(0);
+                }
+            }
+        }
+        ifd.extend_from_slice(&order.u32_bytes(next));
+
+        self.bytes.extend_from_slice(&ifd);
+        self.bytes.extend_from_slice(&values);
+        let header_offset = order.u32_bytes(ifd_offset as u32);
+        self.bytes[4..8].copy_from_slice(&header_offset);
+
+        Ok(())
+    }
+}
+
+impl TryFrom<&[u8]> for Tiff {
+    type Error = anyhow::Error;
+
+    fn try_from(bytes: &[u8]) -> anyhow::Result<Self> {
+        let order = match bytes.get(..4) {
+            Some(b"II*\0") => ByteOrder::LittleEndian,
+            Some(b"MM\0*") => ByteOrder::BigEndian,
+            _ => anyhow::bail!("Invalid TIFF header"),
+        };
+        if bytes.len() < 8 {
+            anyhow::bail!("TIFF is truncated");
+        }
+
+        let tiff = Self {
+            bytes: bytes.to_vec(),
+            order,
+        };
+        tiff.entries()?;
+        Ok(tiff)
+    }
+}
+
+/// The size in bytes of one value of a TIFF field type
+fn type_size(field_type: u16) -> usize {
+    match field_type {
+        SHORT | SSHORT => 2,
+        LONG | SLONG | 11 | 13 => 4,
+        RATIONAL | SRATIONAL | 12 => 8,
+        _ => 1,
+    }
+}
+
+/// The names of common TIFF, EXIF and GPS tags
+fn tag_name(tag: u16) -> Option<&'static str> {
+    let name = match tag {
+        0x0100 => "ImageWidth",
+        0x0101 => "ImageLength",
+        0x0102 => "BitsPerSample",
+        0x0103 => "Compression",
+        0x0106 => "PhotometricInterpretation",
+        0x010A => "FillOrder",
+        0x010D => "DocumentName",
+        0x010E => "ImageDescription",
+        0x010F => "Make",
+        0x0110 => "Model",
+        0x0111 => "StripOffsets",
+        0x0112 => "Orientation",
+        0x0115 => "SamplesPerPixel",
+        0x0116 => "RowsPerStrip",
+        0x0117 => "StripByteCounts",
+        0x011A => "XResolution",
+        0x011B => "YResolution",
+        0x011C => "PlanarConfiguration",
+        0x0128 => "ResolutionUnit",
+        0x0129 => "PageNumber",
+        0x0131 => "Software",
+        0x0132 => "DateTime",
+        0x013B => "Artist",
+        0x013E => "WhitePoint",
+        0x013F => "PrimaryChromaticities",
+        0x0152 => "ExtraSamples",
+        0x0213 => "YCbCrPositioning",
+        0x8298 => "Copyright",
+        EXIF_IFD_TAG => "ExifIFD",
+        GPS_IFD_TAG => "GPSIFD",
+        0x829A => "ExposureTime",
+        0x829D => "FNumber",
+        0x8822 => "ExposureProgram",
+        0x8827 => "ISOSpeedRatings",
+        0x9000 => "ExifVersion",
+        0x9003 => "DateTimeOriginal",
+        0x9004 => "DateTimeDigitized",
+        0x9201 => "ShutterSpeedValue",
+        0x9202 => "ApertureValue",
+        0x9204 => "ExposureBiasValue",
+        0x9207 => "MeteringMode",
+        0x9209 => "Flash",
+        0x920A => "FocalLength",
+        0x927C => "MakerNote",
+        0x9286 => "UserComment",
+        0xA000 => "FlashpixVersion",
+        0xA001 => "ColorSpace",
+        0xA002 => "PixelXDimension",
+        0xA003 => "PixelYDimension",
+        INTEROPERABILITY_IFD_TAG => "InteroperabilityIFD",
+        0xA402 => "ExposureMode",
+        0xA403 => "WhiteBalance",
+        0xA405 => "FocalLengthIn35mmFilm",
+        0xA406 => "SceneCaptureType",
+        0xA430 => "CameraOwnerName",
+        0xA431 => "BodySerialNumber",
+        0xA433 => "LensMake",
+        0xA434 => "LensModel",
+        0xA435 => "LensSerialNumber",
+        0x0000 => "GPSVersionID",
+        0x0001 => "GPSLatitudeRef",
+        0x0002 => "GPSLatitude",
+        0x0003 => "GPSLongitudeRef",
+        0x0004 => "GPSLongitude",
+        0x0005 => "GPSAltitudeRef",
+        0x0006 => "GPSAltitude",
+        0x0007 => "GPSTimeStamp",
+        0x001D => "GPSDateStamp",
+        _ => return None,
+    };
+    Some(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    /// A little endian EXIF block with Make in IFD0 and an EXIF directory holding
+    /// DateTimeOriginal
+    fn testing_bytes() -> Vec<u8> {
+        let mut bytes = b"II*\0".to_vec();
+        bytes.extend_from_slice(&8u32.to_le_bytes());
+        // IFD0 at 8: two entries, ending at 8 + 2 + 24 + 4 = 38
+        bytes.extend_from_slice(&2u16.to_le_bytes());
+        bytes.extend_from_slice(&[0x0F, 0x01, 2, 0, 4, 0, 0, 0]);
+        bytes.extend_from_slice(b"Crb\0");
+        bytes.extend_from_slice(&[0x69, 0x87, 4, 0, 1, 0, 0, 0]);
+        bytes.extend_from_slice(&38u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        // EXIF IFD at 38: one entry, with its value at 38 + 2 + 12 + 4 = 56
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&[0x03, 0x90, 2, 0, 20, 0, 0, 0]);
+        bytes.extend_from_slice(&56u32.to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(b"2020:01:02 03:04:05\0");
+        bytes
+    }
+
+    #[test]
+    fn test_read_entries() {
+        let tiff = Tiff::try_from(testing_bytes().as_ref()).unwrap();
+        let described: Vec<(String, String)> = tiff
+            .all_entries()
+            .unwrap()
+            .iter()
+            .map(|entry| (entry.name(), entry.display(tiff.byte_order())))
+            .collect();
+
+        assert_eq!(
+            described,
+            vec![
+                ("Make".to_string(), "Crb".to_string()),
+                ("ExifIFD".to_string(), "38".to_string()),
+                (
+                    "DateTimeOriginal".to_string(),
+                    "2020:01:02 03:04:05".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_message_round_trip() {
+        let mut tiff = Tiff::try_from(testing_bytes().as_ref()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        tiff.append_message(&chunk_type, b"hidden in a tag")
+            .unwrap();
+
+        let tiff = Tiff::try_from(tiff.as_bytes()).unwrap();
+        assert_eq!(
+            tiff.message("ruSt").unwrap(),
+            Some(b"hidden in a tag".to_vec())
+        );
+        // Existing entries and the directories they point to still work
+        assert_eq!(tiff.all_entries().unwrap().len(), 4);
+        assert_eq!(tiff.entries().unwrap()[0].display(tiff.byte_order()), "Crb");
+    }
+
+    #[test]
+    fn test_remove_message() {
+        let mut tiff = Tiff::try_from(testing_bytes().as_ref()).unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        tiff.append_message(&chunk_type, b"one").unwrap();
+        tiff.append_message(&chunk_type, b"two").unwrap();
+
+        assert_eq!(tiff.remove_message("ruSt").unwrap(), b"one");
+        assert_eq!(tiff.message("ruSt").unwrap(), Some(b"two".to_vec()));
+        tiff.retain_messages(|_| false).unwrap();
+        assert_eq!(tiff.message("ruSt").unwrap(), None);
+    }
+
+    #[test]
+    fn test_big_endian() {
+        let mut bytes = b"MM\0*".to_vec();
+        bytes.extend_from_slice(&8u32.to_be_bytes());
+        bytes.extend_from_slice(&1u16.to_be_bytes());
+        bytes.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0]);
+        bytes.extend_from_slice(&0u32.to_be_bytes());
+
+        let tiff = Tiff::try_from(bytes.as_ref()).unwrap();
+        let entries = tiff.entries().unwrap();
+        assert_eq!(entries[0].name(), "Orientation");
+        assert_eq!(entries[0].display(tiff.byte_order()), "6");
+    }
+
+    #[test]
+    fn test_invalid_tiff() {
+        let bytes = testing_bytes();
+        assert!(Tiff::try_from(&bytes[1..]).is_err());
+        assert!(Tiff::try_from(&bytes[..20]).is_err());
+    }
+}
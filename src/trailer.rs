@@ -0,0 +1,149 @@
+use std::convert::TryFrom;
+
+use crate::png::ChunkType;
+
+/// Marks a message stored after IEND by `wrap`
+pub const MAGIC: [u8; 4] = *b"PMET";
+
+const HEADER_LENGTH: usize = MAGIC.len() + 4 + 4;
+
+/// What the trailers that other tools leave behind start with, and what that makes them
+const SIGNATURES: [(&[u8], &str); 11] = [
+    (b"PK\x03\x04", "a ZIP archive"),
+    (b"Rar!\x1a\x07", "a RAR archive"),
+    (b"7z\xbc\xaf\x27\x1c", "a 7-Zip archive"),
+    (b"\x1f\x8b", "gzip data"),
+    (b"%PDF", "a PDF document"),
+    (b"\x89PNG\r\n\x1a\n", "another PNG image"),
+    (b"\xff\xd8\xff", "a JPEG image"),
+    (b"GIF8", "a GIF image"),
+    (b"-----BEGIN PGP", "a PGP message"),
+    (b"age-encryption.org/", "an age encrypted file"),
+    (b"#!", "a script"),
+];
+
+/// Frames `message` to be stored after IEND, where viewers never look, under
+/// `chunk_type` so that it can be found again like a message in a chunk. A trailer can
+/// hold several of these one after the other.
+///
+/// The result is laid out as follows
+/// 1. Magic bytes `PMET` *(4 bytes)*
+/// 2. The chunk type *(4 bytes)*
+/// 3. The length of the message *(4 bytes)*
+/// 4. The message
+pub fn wrap(chunk_type: &ChunkType, message: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(HEADER_LENGTH + message.len());
+    result.extend_from_slice(&MAGIC);
+    result.extend_from_slice(&chunk_type.bytes());
+    result.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    result.extend_from_slice(message);
+    result
+}
+
+/// Returns the messages framed by `wrap` at the start of `trailer`, in order. Reading
+/// stops at the first bytes that aren't one.
+pub fn messages(trailer: &[u8]) -> Vec<(ChunkType, &[u8])> {
+    parse(trailer).0
+}
+
+/// Returns the last message of type `chunk_type` in `trailer`, which is the one stored
+/// most recently
+pub fn message<'a>(trailer: &'a [u8], chunk_type: &ChunkType) -> Option<&'a [u8]> {
+    messages(trailer)
+        .into_iter()
+        .rev()
+        .find(|(found, _)| found == chunk_type)
+        .map(|(_, message)| message)
+}
+
+/// Returns true if `trailer` is nothing but messages framed by `wrap`, so that another
+/// can be added after them
+pub fn is_pngme_trailer(trailer: &[u8]) -> bool {
+    !trailer.is_empty() && parse(trailer).1 == trailer.len()
+}
+
+/// Says what a trailer most likely is: messages stored by pngme, a file another tool
+/// appended, text, or unknown data
+pub fn describe(trailer: &[u8]) -> String {
+    if is_pngme_trailer(trailer) {
+        let types: Vec<String> = messages(trailer)
+            .iter()
+            .map(|(chunk_type, _)| chunk_type.to_string())
+            .collect();
+        return format!("pngme message(s) of type {}", types.join(", "));
+    }
+    if let Some((_, kind)) = SIGNATURES
+        .iter()
+        .find(|(signature, _)| trailer.starts_with(signature))
+    {
+        return kind.to_string();
+    }
+    match std::str::from_utf8(trailer) {
+        Ok(text) if !text.contains(|c: char| c.is_control() && !c.is_whitespace()) => {
+            "text".to_string()
+        }
+        _ => "unknown data".to_string(),
+    }
+}
+
+/// Reads the messages at the start of `trailer` and returns them with the number of
+/// bytes they take up
+fn parse(trailer: &[u8]) -> (Vec<(ChunkType, &[u8])>, usize) {
+    let mut messages = Vec::new();
+    let mut offset = 0;
+    while let Some(header) = trailer.get(offset..offset + HEADER_LENGTH) {
+        if !header.starts_with(&MAGIC) {
+            break;
+        }
+        let chunk_type = match ChunkType::try_from([header[4], header[5], header[6], header[7]]) {
+            Ok(chunk_type) => chunk_type,
+            Err(_) => break,
+        };
+        let length = u32::from_be_bytes([header[8], header[9], header[10], header[11]]) as usize;
+        let start = offset + HEADER_LENGTH;
+        let message = match trailer.get(start..start + length) {
+            Some(message) => message,
+            None => break,
+        };
+        messages.push((chunk_type, message));
+        offset = start + length;
+    }
+    (messages, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_wrap_and_find() {
+        let rust = ChunkType::from_str("ruSt").unwrap();
+        let other = ChunkType::from_str("otHr").unwrap();
+        let mut trailer = wrap(&rust, b"first");
+        trailer.extend_from_slice(&wrap(&other, b"other"));
+        trailer.extend_from_slice(&wrap(&rust, b"second"));
+
+        assert!(is_pngme_trailer(&trailer));
+        assert_eq!(messages(&trailer).len(), 3);
+        assert_eq!(message(&trailer, &rust), Some(&b"second"[..]));
+        assert_eq!(message(&trailer, &other), Some(&b"other"[..]));
+        assert_eq!(
+            describe(&trailer),
+            "pngme message(s) of type ruSt, otHr, ruSt"
+        );
+
+        trailer.extend_from_slice(b"junk");
+        assert!(!is_pngme_trailer(&trailer));
+        assert_eq!(messages(&trailer).len(), 3);
+    }
+
+    #[test]
+    fn test_describe_foreign_trailers() {
+        assert_eq!(describe(b"PK\x03\x04\x14\x00"), "a ZIP archive");
+        assert_eq!(describe(b"%PDF-1.7"), "a PDF document");
+        assert_eq!(describe(b"hidden note\n"), "text");
+        assert_eq!(describe(&[0, 159, 3, 250]), "unknown data");
+        assert!(!is_pngme_trailer(b""));
+    }
+}
@@ -0,0 +1,209 @@
+use std::convert::TryFrom;
+
+use crate::png::Png;
+use crate::text::InternationalTextChunk;
+
+/// The iTXt keyword that identifies an XMP packet
+pub const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+
+/// Returns the XMP packet stored in `png`, if there is one
+pub fn packet(png: &Png) -> anyhow::Result<Option<String>> {
+    for chunk in png.chunks() {
+        if chunk.chunk_type().to_string() != "iTXt" {
+            continue;
+        }
+
+        let text = InternationalTextChunk::try_from(chunk)?;
+        if text.keyword() == XMP_KEYWORD {
+            return Ok(Some(text.text().to_string()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Stores `packet` in `png`, replacing any existing XMP packet
+pub fn set_packet(png: &mut Png, packet: &str) -> anyhow::Result<()> {
+    if !packet.contains("x:xmpmeta") && !packet.contains("rdf:RDF") {
+        anyhow::bail!("This does not look like an XMP packet");
+    }
+
+    let text = InternationalTextChunk::new(XMP_KEYWORD, packet)?;
+
+    png.retain_chunks(|chunk| match InternationalTextChunk::try_from(chunk) {
+        Ok(existing) => existing.keyword() != XMP_KEYWORD,
+        Err(_) => true,
+    });
+    png.insert_chunk(text.to_chunk());
+
+    Ok(())
+}
+
+/// Returns the values of a property such as `dc:creator` or `xmp:CreatorTool`.
+/// Properties may be written either as attributes of `rdf:Description` or as elements.
+/// Array properties (`rdf:Seq`, `rdf:Bag` and `rdf:Alt`) return one value per item.
+/// This is a simple text scan rather than a full RDF parser.
+pub fn property(packet: &str, name: &str) -> Vec<String> {
+    let mut values = Vec::new();
+
+    let attribute = format!("{}=", name);
+    let mut rest = packet;
+    while let Some(index) = rest.find(&attribute) {
+        let preceded_by_space = rest[..index]
+            .chars()
+            .last()
+            .is_some_and(char::is_whitespace);
+        rest = &rest[index + attribute.len()..];
+
+        let quote = match rest.chars().next() {
+            Some(quote @ '"') | Some(quote @ '\'') => quote,
+            _ => continue,
+        };
+        if let Some(end) = rest[1..].find(quote) {
+            if preceded_by_space {
+                values.push(unescape(&rest[1..end + 1]));
+            }
+            rest = &rest[end + 2..];
+        }
+    }
+
+    let open = format!("<{}", name);
+    let close = format!("</{}>", name);
+    let mut rest = packet;
+    while let Some(index) = rest.find(&open) {
+        rest = &rest[index + open.len()..];
+
+        // Make sure this is the whole element name and not a prefix of a longer one
+        match rest.chars().next() {
+            Some('>') | Some('/') => {}
+            Some(c) if c.is_whitespace() => {}
+            _ => continue,
+        }
+
+        let start = match rest.find('>') {
+            Some(start) => start,
+            None => break,
+        };
+        if rest[..start].ends_with('/') {
+            rest = &rest[start + 1..];
+            continue;
+        }
+
+        let content = &rest[start + 1..];
+        let end = match content.find(&close) {
+            Some(end) => end,
+            None => break,
+        };
+
+        let content = &content[..end];
+        if content.contains("<rdf:li") {
+            values.extend(list_items(content));
+        } else {
+            values.push(unescape(content.trim()));
+        }
+        rest = &rest[start + 1 + end + close.len()..];
+    }
+
+    values
+}
+
+/// Returns the text of every `rdf:li` element in `content`
+fn list_items(content: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut rest = content;
+
+    while let Some(index) = rest.find("<rdf:li") {
+        rest = &rest[index..];
+        let start = match rest.find('>') {
+            Some(start) => start,
+            None => break,
+        };
+        let end = match rest.find("</rdf:li>") {
+            Some(end) => end,
+            None => break,
+        };
+        if start < end {
+            items.push(unescape(rest[start + 1..end].trim()));
+        }
+        rest = &rest[end + "</rdf:li>".len()..];
+    }
+
+    items
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::Chunk;
+
+    const PACKET: &str = r#"<?xpacket begin="" id="W5M0MpCehiHzreSzNTczkc9d"?>
+<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description rdf:about=""
+        xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+        xmlns:dc="http://purl.org/dc/elements/1.1/"
+        xmp:CreatorTool="pngme &amp; friends">
+      <dc:creator>
+        <rdf:Seq>
+          <rdf:li>Alice</rdf:li>
+          <rdf:li>Bob</rdf:li>
+        </rdf:Seq>
+      </dc:creator>
+      <dc:title>
+        <rdf:Alt>
+          <rdf:li xml:lang="x-default">Dice</rdf:li>
+        </rdf:Alt>
+      </dc:title>
+      <dc:format>image/png</dc:format>
+    </rdf:Description>
+  </rdf:RDF>
+</x:xmpmeta>
+<?xpacket end="w"?>"#;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(vec![
+            Chunk::from_strings("IHDR", "header").unwrap(),
+            Chunk::from_strings("IDAT", "pixels").unwrap(),
+            Chunk::from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_set_and_read_packet() {
+        let mut png = testing_png();
+        assert_eq!(packet(&png).unwrap(), None);
+
+        set_packet(&mut png, PACKET).unwrap();
+        set_packet(&mut png, PACKET).unwrap();
+
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(&png.chunks()[2].chunk_type().to_string(), "iTXt");
+        assert_eq!(packet(&png).unwrap().as_deref(), Some(PACKET));
+    }
+
+    #[test]
+    fn test_rejects_non_xmp() {
+        assert!(set_packet(&mut testing_png(), "hello").is_err());
+    }
+
+    #[test]
+    fn test_attribute_property() {
+        assert_eq!(property(PACKET, "xmp:CreatorTool"), vec!["pngme & friends"]);
+    }
+
+    #[test]
+    fn test_element_properties() {
+        assert_eq!(property(PACKET, "dc:creator"), vec!["Alice", "Bob"]);
+        assert_eq!(property(PACKET, "dc:title"), vec!["Dice"]);
+        assert_eq!(property(PACKET, "dc:format"), vec!["image/png"]);
+        assert!(property(PACKET, "dc:rights").is_empty());
+    }
+}
@@ -0,0 +1,219 @@
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+
+use zip::write::SimpleFileOptions;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::atomic;
+use crate::error::Locate;
+
+/// Separates the path of a ZIP archive from the path of a file inside it
+pub const SEPARATOR: char = '!';
+
+/// Extensions of files that are ZIP archives under another name
+const ZIP_EXTENSIONS: [&str; 5] = ["zip", "jar", "epub", "apk", "docx"];
+
+const ZIP_MAGIC: [u8; 4] = *b"PK\x03\x04";
+
+/// A file inside a ZIP archive, written as `bundle.zip!assets/icon.png`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipPath {
+    pub archive: PathBuf,
+    pub entry: String,
+}
+
+impl ZipPath {
+    /// Splits `path` at the first `!` that follows the path of an existing file. Returns
+    /// `None` for paths that exist as they are, so that files with a `!` in their name
+    /// keep working.
+    pub fn parse(path: &Path) -> Option<Self> {
+        if path.exists() {
+            return None;
+        }
+
+        let text = path.to_str()?;
+        text.match_indices(SEPARATOR).find_map(|(index, _)| {
+            let archive = Path::new(&text[..index]);
+            let entry = &text[index + 1..];
+            match archive.is_file() && !entry.is_empty() {
+                true => Some(ZipPath {
+                    archive: archive.to_path_buf(),
+                    entry: entry.to_string(),
+                }),
+                false => None,
+            }
+        })
+    }
+
+    /// Returns the contents of the file inside the archive
+    pub fn read(&self) -> anyhow::Result<Vec<u8>> {
+        let bytes = fs::read(&self.archive).in_file(&self.archive)?;
+        let mut archive = ZipArchive::new(Cursor::new(bytes)).in_file(&self.archive)?;
+        let mut file = archive
+            .by_name(&self.entry)
+            .map_err(|_| anyhow::anyhow!("The archive has no file named {:?}", self.entry))
+            .in_file(&self.archive)?;
+
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    /// Replaces the file inside the archive with `bytes` and saves the archive. Every
+    /// other file is copied over without being decompressed, so the rest of the archive
+    /// stays exactly as it was.
+    pub fn write(&self, bytes: &[u8]) -> anyhow::Result<()> {
+        let original = fs::read(&self.archive).in_file(&self.archive)?;
+        let mut archive = ZipArchive::new(Cursor::new(original)).in_file(&self.archive)?;
+        if archive.index_for_name(&self.entry).is_none() {
+            return Err(anyhow::anyhow!(
+                "The archive has no file named {:?}",
+                self.entry
+            ))
+            .in_file(&self.archive);
+        }
+
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        for index in 0..archive.len() {
+            let file = archive.by_index_raw(index)?;
+            if file.name() != self.entry {
+                writer.raw_copy_file(file)?;
+                continue;
+            }
+
+            let mut options = SimpleFileOptions::default()
+                .compression_method(file.compression())
+                .last_modified_time(file.last_modified().unwrap_or_default());
+            if let Some(mode) = file.unix_mode() {
+                options = options.unix_permissions(mode);
+            }
+            drop(file);
+            writer.start_file(self.entry.as_str(), options)?;
+            writer.write_all(bytes)?;
+        }
+        writer.set_raw_comment(archive.comment().into());
+
+        let rewritten = writer.finish()?.into_inner();
+        atomic::write(&self.archive, &rewritten)
+    }
+}
+
+/// Returns true if `path` has the extension of a ZIP archive, like `.zip`, `.jar` or
+/// `.epub`
+pub fn has_zip_extension(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        ZIP_EXTENSIONS
+            .iter()
+            .any(|zip| extension.eq_ignore_ascii_case(zip))
+    })
+}
+
+/// Returns the paths of the PNG files inside the ZIP archive at `path`, in the form
+/// `ZipPath::parse` reads. Files that aren't ZIP archives have none.
+pub fn png_entries(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let bytes = fs::read(path).in_file(path)?;
+    if !bytes.starts_with(&ZIP_MAGIC) {
+        return Ok(Vec::new());
+    }
+
+    let archive = ZipArchive::new(Cursor::new(bytes)).in_file(path)?;
+    let mut entries: Vec<PathBuf> = archive
+        .file_names()
+        .filter(|name| name.to_ascii_lowercase().ends_with(".png"))
+        .map(|name| {
+            let mut entry = path.as_os_str().to_owned();
+            entry.push(format!("{}{}", SEPARATOR, name));
+            PathBuf::from(entry)
+        })
+        .collect();
+    entries.sort();
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_archive(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("pngme-zip-{}-{}.zip", name, std::process::id()));
+        let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let stored =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        writer.start_file("mimetype", stored).unwrap();
+        writer.write_all(b"application/epub+zip").unwrap();
+        writer
+            .start_file("assets/icon.png", SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(b"original").unwrap();
+        writer.start_file("notes.txt", stored).unwrap();
+        writer.write_all(b"notes").unwrap();
+        fs::write(&path, writer.finish().unwrap().into_inner()).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_parse() {
+        let archive = testing_archive("parse");
+        let path = PathBuf::from(format!("{}!assets/icon.png", archive.display()));
+        assert_eq!(
+            ZipPath::parse(&path),
+            Some(ZipPath {
+                archive: archive.clone(),
+                entry: "assets/icon.png".to_string(),
+            })
+        );
+        assert_eq!(ZipPath::parse(&archive), None);
+        assert_eq!(ZipPath::parse(Path::new("missing.zip!icon.png")), None);
+        fs::remove_file(&archive).unwrap();
+    }
+
+    #[test]
+    fn test_read_and_write() {
+        let archive = testing_archive("write");
+        let zip_path = ZipPath {
+            archive: archive.clone(),
+            entry: "assets/icon.png".to_string(),
+        };
+        assert_eq!(zip_path.read().unwrap(), b"original");
+
+        zip_path.write(b"changed").unwrap();
+        assert_eq!(zip_path.read().unwrap(), b"changed");
+
+        // The other files are kept, in their original order
+        let mut rewritten = ZipArchive::new(fs::File::open(&archive).unwrap()).unwrap();
+        let names: Vec<&str> = rewritten.file_names().collect();
+        assert_eq!(names.len(), 3);
+        assert_eq!(rewritten.by_index(0).unwrap().name(), "mimetype");
+        let mut notes = String::new();
+        rewritten
+            .by_name("notes.txt")
+            .unwrap()
+            .read_to_string(&mut notes)
+            .unwrap();
+        assert_eq!(notes, "notes");
+
+        let missing = ZipPath {
+            archive: archive.clone(),
+            entry: "missing.png".to_string(),
+        };
+        assert!(missing.read().is_err());
+        assert!(missing.write(b"data").is_err());
+        fs::remove_file(&archive).unwrap();
+    }
+
+    #[test]
+    fn test_png_entries() {
+        let archive = testing_archive("entries");
+        assert!(has_zip_extension(&archive));
+        assert_eq!(
+            png_entries(&archive).unwrap(),
+            vec![PathBuf::from(format!(
+                "{}!assets/icon.png",
+                archive.display()
+            ))]
+        );
+        fs::remove_file(&archive).unwrap();
+    }
+}